@@ -0,0 +1,27 @@
+// Marks the twelve hours of an analog clock face by rotating one point
+// around the center, the fourth chapter's exercise. Writes clock.ppm, or
+// the path given.
+use ray_tracer::canvas::Canvas;
+use ray_tracer::color::WHITE;
+use ray_tracer::point::Point;
+use ray_tracer::scalar::consts::PI;
+use ray_tracer::scalar::Scalar;
+use ray_tracer::transform::rotation_y;
+use std::env;
+use std::fs;
+
+fn main() -> std::io::Result<()> {
+    let path = env::args().nth(1).unwrap_or("clock.ppm".to_string());
+    let size = 200;
+    let radius = 3.0 / 8.0 * size as Scalar;
+    let mut canvas = Canvas::new(size, size);
+    // the clock lies in the xz plane with twelve o'clock along z
+    let twelve = Point::new(0.0, 0.0, 1.0);
+    for hour in 0..12 {
+        let p = rotation_y(hour as Scalar * PI / 6.0) * &twelve;
+        let x = size as Scalar / 2.0 + p.x * radius;
+        let y = size as Scalar / 2.0 - p.z * radius;
+        canvas.write_pixel(x.round() as usize, y.round() as usize, WHITE);
+    }
+    fs::write(path, canvas.to_ppm())
+}
@@ -0,0 +1,53 @@
+// Plots the trajectory of a projectile fired through gravity and wind, the
+// first chapter's exercise. Writes projectile.ppm, or the path given.
+use ray_tracer::canvas::Canvas;
+use ray_tracer::color::Color;
+use ray_tracer::point::Point;
+use ray_tracer::scalar::Scalar;
+use ray_tracer::vector::Vector;
+use std::env;
+use std::fs;
+
+struct Projectile {
+    position: Point,
+    velocity: Vector,
+}
+
+struct Environment {
+    gravity: Vector,
+    wind: Vector,
+}
+
+fn tick(env: &Environment, proj: Projectile) -> Projectile {
+    Projectile {
+        position: proj.position + &proj.velocity,
+        velocity: &(proj.velocity + &env.gravity) + &env.wind,
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let path = env::args().nth(1).unwrap_or("projectile.ppm".to_string());
+    let env = Environment {
+        gravity: Vector::new(0.0, -0.1, 0.0),
+        wind: Vector::new(-0.01, 0.0, 0.0),
+    };
+    let mut proj = Projectile {
+        position: Point::new(0.0, 1.0, 0.0),
+        velocity: &Vector::new(1.0, 1.8, 0.0).normalize() * 11.25,
+    };
+    let mut canvas = Canvas::new(900, 550);
+    let color = Color::new(1.0, 0.5, 0.25);
+    let mut ticks = 0;
+    while proj.position.y > 0.0 {
+        let x = proj.position.x.round();
+        // canvas rows grow downwards
+        let y = canvas.height() as Scalar - proj.position.y.round();
+        if x >= 0.0 && y >= 0.0 && (x as usize) < canvas.width() && (y as usize) < canvas.height() {
+            canvas.write_pixel(x as usize, y as usize, color);
+        }
+        proj = tick(&env, proj);
+        ticks += 1;
+    }
+    println!("landed at x = {:.2} after {} ticks", proj.position.x, ticks);
+    fs::write(path, canvas.to_ppm())
+}
@@ -0,0 +1,62 @@
+/* C interface to the ray tracer, built with `cargo build --release --features ffi`.
+ *
+ * Shapes are referred to by the ids the rt_world_add_* functions return.
+ * Transforms are the top three rows of a row-major 4x4 matrix, twelve
+ * doubles; a null transform is the identity. Functions returning an int
+ * or shape id report failures as negative values. */
+
+#ifndef RAY_TRACER_H
+#define RAY_TRACER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RT_OK 0
+#define RT_INVALID_ARGUMENT (-1)
+#define RT_RENDER_ERROR (-2)
+
+typedef struct RtWorld RtWorld;
+
+typedef struct {
+    double color[3];
+    double ambient;
+    double diffuse;
+    double specular;
+    double shininess;
+    double reflective;
+    double transparency;
+    double refractive_index;
+} RtMaterial;
+
+typedef struct {
+    uint32_t width;
+    uint32_t height;
+    double field_of_view;
+    double from[3];
+    double to[3];
+    double up[3];
+} RtCamera;
+
+RtWorld *rt_world_new(void);
+void rt_world_free(RtWorld *world);
+
+int rt_world_add_light(RtWorld *world, const double position[3], const double intensity[3]);
+int64_t rt_world_add_sphere(RtWorld *world, const double transform[12]);
+int64_t rt_world_add_plane(RtWorld *world, const double transform[12]);
+int64_t rt_world_add_cube(RtWorld *world, const double transform[12]);
+
+RtMaterial rt_material_default(void);
+int rt_shape_set_material(RtWorld *world, int64_t shape, const RtMaterial *material);
+
+/* Writes 4 * width * height bytes of RGBA, row by row, into pixels. */
+int rt_render(const RtWorld *world, const RtCamera *camera, uint8_t *pixels, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
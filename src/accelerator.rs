@@ -0,0 +1,142 @@
+use crate::bounds::Bounds;
+use crate::bvh::{Bvh, BvhBuilder};
+use crate::kdtree::KdTree;
+use crate::packet::{LaneMask, RayPacket};
+use crate::ray::Ray;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AcceleratorKind {
+    #[default]
+    Bvh,
+    KdTree,
+}
+
+// Work done finding ray hits: the rays traced, the tree nodes they entered
+// and the shapes they had to be intersected with.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraversalStats {
+    pub rays: usize,
+    pub nodes: usize,
+    pub candidates: usize,
+}
+
+// Totals of `TraversalStats` gathered across render threads.
+#[derive(Default)]
+pub(crate) struct TraversalCounters {
+    rays: AtomicUsize,
+    nodes: AtomicUsize,
+    candidates: AtomicUsize,
+}
+
+impl TraversalCounters {
+    pub(crate) fn add(&self, stats: &TraversalStats) {
+        self.rays.fetch_add(stats.rays, Ordering::Relaxed);
+        self.nodes.fetch_add(stats.nodes, Ordering::Relaxed);
+        self.candidates
+            .fetch_add(stats.candidates, Ordering::Relaxed);
+    }
+    pub(crate) fn get(&self) -> TraversalStats {
+        TraversalStats {
+            rays: self.rays.load(Ordering::Relaxed),
+            nodes: self.nodes.load(Ordering::Relaxed),
+            candidates: self.candidates.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub enum Accelerator {
+    Bvh(Bvh),
+    KdTree(KdTree),
+}
+
+impl Accelerator {
+    // `bvh` configures the BVH's construction, if that is the kind chosen.
+    pub fn new(
+        kind: AcceleratorKind,
+        bvh: &BvhBuilder,
+        items: impl IntoIterator<Item = (usize, Bounds)>,
+    ) -> Self {
+        match kind {
+            AcceleratorKind::Bvh => Accelerator::Bvh(bvh.build(items)),
+            AcceleratorKind::KdTree => Accelerator::KdTree(KdTree::new(items)),
+        }
+    }
+    // Updates the structure for items that moved, see `Bvh::refit`. Returns
+    // false if it has to be built anew instead, as a kd-tree always does.
+    pub fn refit(&mut self, bounds: impl Fn(usize) -> Bounds) -> bool {
+        match self {
+            Accelerator::Bvh(bvh) => bvh.refit(bounds),
+            Accelerator::KdTree(_) => false,
+        }
+    }
+    pub fn any(&self, ray: &Ray, stats: &mut TraversalStats, f: impl FnMut(usize) -> bool) -> bool {
+        match self {
+            Accelerator::Bvh(bvh) => bvh.traverse(ray, stats, f),
+            Accelerator::KdTree(tree) => tree.traverse(ray, stats, f),
+        }
+    }
+    pub fn for_each(&self, ray: &Ray, stats: &mut TraversalStats, mut f: impl FnMut(usize)) {
+        self.any(ray, stats, |id| {
+            f(id);
+            false
+        });
+    }
+    // Like `for_each` for all the packet's rays, passing the lanes that may
+    // hit each item. The kd-tree traces the rays one by one.
+    pub fn for_each_packet(
+        &self,
+        packet: &RayPacket,
+        stats: &mut TraversalStats,
+        mut f: impl FnMut(usize, LaneMask),
+    ) {
+        match self {
+            Accelerator::Bvh(bvh) => bvh.traverse_packet(packet, stats, f),
+            Accelerator::KdTree(tree) => {
+                for (lane, ray) in packet.rays().iter().enumerate() {
+                    tree.traverse(ray, stats, |id| {
+                        f(id, 1 << lane);
+                        false
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::point::Point;
+    use crate::scalar::Scalar;
+    use crate::vector::Vector;
+
+    #[test]
+    fn test_both_accelerators_find_the_same_candidates() {
+        let boxes = (0..50).map(|i| {
+            let p = Point::new((i % 7) as Scalar, (i % 5) as Scalar, (i % 3) as Scalar);
+            (
+                i,
+                Bounds::new(p, Point::new(p.x + 0.8, p.y + 0.8, p.z + 0.8)),
+            )
+        });
+        let boxes: Vec<_> = boxes.collect();
+        let r = Ray::new(Point::new(-1.0, 0.5, 0.25), Vector::new(1.0, 0.3, 0.2));
+        let candidates = |kind: AcceleratorKind| {
+            let mut ids = vec![];
+            let mut stats = TraversalStats::default();
+            Accelerator::new(kind, &BvhBuilder::new(), boxes.iter().copied()).for_each(
+                &r,
+                &mut stats,
+                |id| ids.push(id),
+            );
+            assert_eq!(stats.candidates, ids.len());
+            ids.sort();
+            ids
+        };
+        let ids = candidates(AcceleratorKind::Bvh);
+        assert!(!ids.is_empty());
+        assert_eq!(candidates(AcceleratorKind::KdTree), ids);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::canvas::Canvas;
+use crate::color::{Color, BLACK};
+use crate::scalar::Scalar;
+use std::ops::{Add, Mul};
+
+// The components that add up to the color of a shaded point. `shadow` is
+// the direct light that occluders blocked, so it isn't part of the total.
+#[derive(Copy, Clone, Debug)]
+pub struct Aovs {
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+    pub rim: Color,
+    pub reflection: Color,
+    pub refraction: Color,
+    pub shadow: Color,
+}
+
+impl Aovs {
+    pub fn new() -> Self {
+        Self {
+            ambient: BLACK,
+            diffuse: BLACK,
+            specular: BLACK,
+            rim: BLACK,
+            reflection: BLACK,
+            refraction: BLACK,
+            shadow: BLACK,
+        }
+    }
+    pub fn total(&self) -> Color {
+        self.ambient + self.diffuse + self.specular + self.rim + self.reflection + self.refraction
+    }
+}
+
+impl Default for Aovs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Add for Aovs {
+    type Output = Aovs;
+
+    fn add(self, other: Aovs) -> Self::Output {
+        Aovs {
+            ambient: self.ambient + other.ambient,
+            diffuse: self.diffuse + other.diffuse,
+            specular: self.specular + other.specular,
+            rim: self.rim + other.rim,
+            reflection: self.reflection + other.reflection,
+            refraction: self.refraction + other.refraction,
+            shadow: self.shadow + other.shadow,
+        }
+    }
+}
+
+impl Mul<Scalar> for Aovs {
+    type Output = Aovs;
+
+    fn mul(self, other: Scalar) -> Self::Output {
+        Aovs {
+            ambient: self.ambient * other,
+            diffuse: self.diffuse * other,
+            specular: self.specular * other,
+            rim: self.rim * other,
+            reflection: self.reflection * other,
+            refraction: self.refraction * other,
+            shadow: self.shadow * other,
+        }
+    }
+}
+
+// One canvas per render pass, plus the combined image.
+pub struct AovCanvases {
+    pub beauty: Canvas,
+    pub ambient: Canvas,
+    pub diffuse: Canvas,
+    pub specular: Canvas,
+    pub rim: Canvas,
+    pub reflection: Canvas,
+    pub refraction: Canvas,
+    pub shadow: Canvas,
+}
+
+impl AovCanvases {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            beauty: Canvas::new(width, height),
+            ambient: Canvas::new(width, height),
+            diffuse: Canvas::new(width, height),
+            specular: Canvas::new(width, height),
+            rim: Canvas::new(width, height),
+            reflection: Canvas::new(width, height),
+            refraction: Canvas::new(width, height),
+            shadow: Canvas::new(width, height),
+        }
+    }
+    pub fn write(&mut self, x: usize, y: usize, aovs: &Aovs) {
+        self.beauty.write_pixel(x, y, aovs.total());
+        self.ambient.write_pixel(x, y, aovs.ambient);
+        self.diffuse.write_pixel(x, y, aovs.diffuse);
+        self.specular.write_pixel(x, y, aovs.specular);
+        self.rim.write_pixel(x, y, aovs.rim);
+        self.reflection.write_pixel(x, y, aovs.reflection);
+        self.refraction.write_pixel(x, y, aovs.refraction);
+        self.shadow.write_pixel(x, y, aovs.shadow);
+    }
+    pub fn write_alpha(&mut self, x: usize, y: usize, a: Scalar) {
+        for canvas in self.canvases_mut() {
+            canvas.write_alpha(x, y, a);
+        }
+    }
+    // The passes by name, e.g. for writing each to its own file.
+    pub fn named(&self) -> [(&'static str, &Canvas); 8] {
+        [
+            ("beauty", &self.beauty),
+            ("ambient", &self.ambient),
+            ("diffuse", &self.diffuse),
+            ("specular", &self.specular),
+            ("rim", &self.rim),
+            ("reflection", &self.reflection),
+            ("refraction", &self.refraction),
+            ("shadow", &self.shadow),
+        ]
+    }
+    fn canvases_mut(&mut self) -> [&mut Canvas; 8] {
+        [
+            &mut self.beauty,
+            &mut self.ambient,
+            &mut self.diffuse,
+            &mut self.specular,
+            &mut self.rim,
+            &mut self.reflection,
+            &mut self.refraction,
+            &mut self.shadow,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    #[test]
+    fn test_the_total_excludes_the_shadow_pass() {
+        let aovs = Aovs {
+            diffuse: Color::new(0.5, 0.0, 0.0),
+            reflection: Color::new(0.0, 0.25, 0.0),
+            shadow: Color::new(1.0, 1.0, 1.0),
+            ..Aovs::new()
+        };
+        assert_approx_eq!(aovs.total(), Color::new(0.5, 0.25, 0.0));
+    }
+
+    #[test]
+    fn test_writing_aovs_fills_every_pass() {
+        let mut c = AovCanvases::new(2, 2);
+        let aovs = Aovs {
+            ambient: Color::new(0.1, 0.1, 0.1),
+            specular: Color::new(0.2, 0.2, 0.2),
+            ..Aovs::new()
+        };
+        c.write(1, 0, &aovs);
+        assert_approx_eq!(c.beauty.pixel_at(1, 0), Color::new(0.3, 0.3, 0.3));
+        assert_approx_eq!(c.specular.pixel_at(1, 0), Color::new(0.2, 0.2, 0.2));
+        assert_approx_eq!(c.diffuse.pixel_at(1, 0), BLACK);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::scalar::Scalar;
+
 pub trait ApproxEq<Rhs = Self>
 where
     Rhs: ?Sized,
@@ -5,14 +7,24 @@ where
     fn approx_eq(&self, other: &Rhs) -> bool;
 }
 
-pub const EPSILON: f64 = 0.00001;
+#[cfg(not(feature = "f32"))]
+pub const EPSILON: Scalar = 0.00001;
+// f32 only carries about seven significant digits
+#[cfg(feature = "f32")]
+pub const EPSILON: Scalar = 0.0001;
 
-impl ApproxEq for f64 {
-    fn approx_eq(&self, other: &f64) -> bool {
-        (self - other).abs() < EPSILON
-    }
+macro_rules! impl_approx_eq_for_float {
+    ($($t:ty),*) => {
+        $(impl ApproxEq for $t {
+            fn approx_eq(&self, other: &$t) -> bool {
+                (self - other).abs() < EPSILON as $t
+            }
+        })*
+    };
 }
 
+impl_approx_eq_for_float!(f32, f64);
+
 impl<T: ApproxEq> ApproxEq for [T] {
     fn approx_eq(&self, other: &[T]) -> bool {
         self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| a.approx_eq(b))
@@ -0,0 +1,127 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::scalar::Scalar;
+
+// Glow around highlights: the light of each pixel brighter than `threshold`
+// (by luminance) is blurred with a Gaussian reaching `radius` pixels out and
+// added back, scaled by `strength`. Alpha is kept as it was.
+pub fn bloom(canvas: &Canvas, threshold: Scalar, radius: usize, strength: Scalar) -> Canvas {
+    let (width, height) = (canvas.width(), canvas.height());
+    let mut bright = Canvas::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            bright.write_pixel(x, y, bright_pass(canvas.pixel_at(x, y), threshold));
+        }
+    }
+    let kernel = gaussian_kernel(radius);
+    let glow = blur(&blur(&bright, &kernel, true), &kernel, false);
+
+    let mut image = Canvas::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            image.write_pixel(x, y, canvas.pixel_at(x, y) + glow.pixel_at(x, y) * strength);
+            if canvas.has_alpha() {
+                image.write_alpha(x, y, canvas.alpha_at(x, y));
+            }
+        }
+    }
+    image
+}
+
+// Only the part of the color above the threshold, keeping its hue.
+fn bright_pass(c: Color, threshold: Scalar) -> Color {
+    let luminance = c.luminance();
+    if luminance <= threshold {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    c * ((luminance - threshold) / luminance)
+}
+
+// Weights for offsets 0..=radius, with the radius at three standard
+// deviations, normalized so the whole kernel sums to one.
+fn gaussian_kernel(radius: usize) -> Vec<Scalar> {
+    let sigma = (radius as Scalar / 3.0).max(Scalar::EPSILON);
+    let weights: Vec<Scalar> = (0..=radius)
+        .map(|i| (-((i * i) as Scalar) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total = weights[0] + 2.0 * weights[1..].iter().sum::<Scalar>();
+    weights.iter().map(|w| w / total).collect()
+}
+
+// One pass of the separable blur. Edge pixels are repeated beyond the
+// canvas so light doesn't drain away at the borders.
+fn blur(canvas: &Canvas, kernel: &[Scalar], horizontal: bool) -> Canvas {
+    let (width, height) = (canvas.width(), canvas.height());
+    let mut image = Canvas::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = canvas.pixel_at(x, y) * kernel[0];
+            for (i, weight) in kernel.iter().enumerate().skip(1) {
+                let (before, after) = if horizontal {
+                    (
+                        canvas.pixel_at(x.saturating_sub(i), y),
+                        canvas.pixel_at((x + i).min(width - 1), y),
+                    )
+                } else {
+                    (
+                        canvas.pixel_at(x, y.saturating_sub(i)),
+                        canvas.pixel_at(x, (y + i).min(height - 1)),
+                    )
+                };
+                sum = sum + (before + after) * *weight;
+            }
+            image.write_pixel(x, y, sum);
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::color::WHITE;
+
+    // A single bright pixel in the middle of a dim canvas.
+    fn highlight(brightness: Scalar) -> Canvas {
+        let mut canvas = Canvas::new(9, 9);
+        for y in 0..9 {
+            for x in 0..9 {
+                canvas.write_pixel(x, y, Color::new(0.1, 0.1, 0.1));
+            }
+        }
+        canvas.write_pixel(4, 4, WHITE * brightness);
+        canvas
+    }
+
+    fn total(canvas: &Canvas) -> Color {
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for y in 0..canvas.height() {
+            for x in 0..canvas.width() {
+                sum = sum + canvas.pixel_at(x, y);
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn test_pixels_below_the_threshold_are_left_alone() {
+        let canvas = highlight(0.9);
+        let image = bloom(&canvas, 1.0, 3, 1.0);
+        assert_approx_eq!(total(&image), total(&canvas));
+        assert_approx_eq!(image.pixel_at(4, 4), WHITE * 0.9);
+    }
+
+    #[test]
+    fn test_a_highlight_spreads_its_excess_light() {
+        let canvas = highlight(5.0);
+        let image = bloom(&canvas, 1.0, 3, 0.5);
+        // half of the 4 over the threshold is added, spread around
+        assert_approx_eq!(total(&image), total(&canvas) + WHITE * 2.0);
+        assert!(image.pixel_at(3, 4).red > 0.1);
+        assert!(image.pixel_at(4, 2).red > 0.1);
+        assert!(image.pixel_at(3, 4).red > image.pixel_at(2, 4).red);
+        assert_approx_eq!(image.pixel_at(0, 0), Color::new(0.1, 0.1, 0.1));
+    }
+}
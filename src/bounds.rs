@@ -0,0 +1,168 @@
+use crate::approx_eq::EPSILON;
+use crate::point::Point;
+use crate::ray::Ray;
+use crate::scalar::Scalar;
+use crate::transform::Affine;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Bounds {
+    pub fn new(min: Point, max: Point) -> Self {
+        Self { min, max }
+    }
+    pub fn empty() -> Self {
+        Self {
+            min: Point::new(Scalar::INFINITY, Scalar::INFINITY, Scalar::INFINITY),
+            max: Point::new(
+                Scalar::NEG_INFINITY,
+                Scalar::NEG_INFINITY,
+                Scalar::NEG_INFINITY,
+            ),
+        }
+    }
+    pub fn infinite() -> Self {
+        Self {
+            min: Point::new(
+                Scalar::NEG_INFINITY,
+                Scalar::NEG_INFINITY,
+                Scalar::NEG_INFINITY,
+            ),
+            max: Point::new(Scalar::INFINITY, Scalar::INFINITY, Scalar::INFINITY),
+        }
+    }
+    pub fn is_finite(&self) -> bool {
+        (0..3).all(|i| self.min[i].is_finite() && self.max[i].is_finite())
+    }
+    pub fn add_point(&mut self, p: &Point) {
+        for i in 0..3 {
+            self.min[i] = self.min[i].min(p[i]);
+            self.max[i] = self.max[i].max(p[i]);
+        }
+    }
+    pub fn merge(&self, other: &Self) -> Self {
+        let mut bounds = *self;
+        bounds.add_point(&other.min);
+        bounds.add_point(&other.max);
+        bounds
+    }
+    pub fn contains(&self, p: &Point) -> bool {
+        (0..3).all(|i| self.min[i] - EPSILON <= p[i] && p[i] <= self.max[i] + EPSILON)
+    }
+    pub fn centroid(&self) -> Point {
+        Point::new(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+    pub fn surface_area(&self) -> Scalar {
+        let d = self.max - &self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+    pub fn transform(&self, trans: &Affine) -> Self {
+        if !self.is_finite() {
+            return Self::infinite();
+        }
+        let mut bounds = Self::empty();
+        for corner in 0..8 {
+            let pick = |i: usize| {
+                if corner & (1 << i) == 0 {
+                    self.min[i]
+                } else {
+                    self.max[i]
+                }
+            };
+            bounds.add_point(&(trans * &Point::new(pick(0), pick(1), pick(2))));
+        }
+        bounds
+    }
+    // Slab test; a zero direction component divides to an infinity with the
+    // proper sign, which the min/max comparisons handle.
+    // The range of t over which the ray is inside the box; empty (with
+    // tmin > tmax) when it misses.
+    pub fn ray_interval(&self, ray: &Ray) -> (Scalar, Scalar) {
+        let mut tmin = Scalar::NEG_INFINITY;
+        let mut tmax = Scalar::INFINITY;
+        for i in 0..3 {
+            let t0 = (self.min[i] - ray.origin[i]) / ray.direction[i];
+            let t1 = (self.max[i] - ray.origin[i]) / ray.direction[i];
+            let (t0, t1) = if t0 > t1 { (t1, t0) } else { (t0, t1) };
+            tmin = tmin.max(t0);
+            tmax = tmax.min(t1);
+        }
+        (tmin, tmax)
+    }
+    pub fn intersects(&self, ray: &Ray) -> bool {
+        let (tmin, tmax) = self.ray_interval(ray);
+        tmin <= tmax && tmax >= 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::scalar::consts::PI;
+    use crate::transform::{rotation_y, translation};
+    use crate::vector::Vector;
+
+    fn unit_cube() -> Bounds {
+        Bounds::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn test_adding_points_to_empty_bounds() {
+        let mut b = Bounds::empty();
+        b.add_point(&Point::new(-5.0, 2.0, 0.0));
+        b.add_point(&Point::new(7.0, 0.0, -3.0));
+        assert_approx_eq!(b.min, Point::new(-5.0, 0.0, -3.0));
+        assert_approx_eq!(b.max, Point::new(7.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn test_merging_bounds() {
+        let b = unit_cube().merge(&Bounds::new(
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(3.0, 0.5, 2.0),
+        ));
+        assert_approx_eq!(b.min, Point::new(-1.0, -1.0, -1.0));
+        assert_approx_eq!(b.max, Point::new(3.0, 1.0, 2.0));
+    }
+
+    #[test]
+    fn test_transforming_bounds() {
+        let b = unit_cube().transform(&(translation(1.0, 0.0, 0.0) * &rotation_y(PI / 4.0)));
+        let s = Scalar::sqrt(2.0);
+        assert_approx_eq!(b.min, Point::new(1.0 - s, -1.0, -s));
+        assert_approx_eq!(b.max, Point::new(1.0 + s, 1.0, s));
+    }
+
+    #[test]
+    fn test_transforming_infinite_bounds_stays_infinite() {
+        let b = Bounds::infinite().transform(&translation(1.0, 2.0, 3.0));
+        assert!(!b.is_finite());
+    }
+
+    #[test]
+    fn test_bounds_containing_points() {
+        let b = unit_cube();
+        assert!(b.contains(&Point::new(0.5, -1.0, 1.0)));
+        assert!(!b.contains(&Point::new(0.5, -1.5, 0.0)));
+    }
+
+    #[test]
+    fn test_a_ray_intersecting_bounds() {
+        let b = unit_cube();
+        let hit = Ray::new(Point::new(0.5, 0.5, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let miss = Ray::new(Point::new(2.0, 0.5, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let behind = Ray::new(Point::new(0.0, 0.0, 5.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(b.intersects(&hit));
+        assert!(!b.intersects(&miss));
+        assert!(!b.intersects(&behind));
+    }
+}
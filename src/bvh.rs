@@ -0,0 +1,582 @@
+use crate::accelerator::TraversalStats;
+use crate::bounds::Bounds;
+use crate::packet::{LaneMask, RayPacket};
+use crate::point::Point;
+use crate::ray::Ray;
+
+use crate::scalar::Scalar;
+use std::thread;
+
+// Buckets the surface area heuristic sorts centroids into along an axis.
+const SAH_BUCKETS: usize = 12;
+// The cost of visiting a node relative to intersecting one item.
+const TRAVERSAL_COST: Scalar = 0.125;
+// Smaller subtrees aren't worth a thread of their own.
+const PARALLEL_MIN_ITEMS: usize = 4096;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SplitMethod {
+    // halve the items at the median centroid of the widest axis
+    Median,
+    // split where the surface area heuristic estimates rays are cheapest
+    Sah,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BvhBuilder {
+    split: SplitMethod,
+    max_leaf_size: usize,
+    threads: usize,
+}
+
+enum Node {
+    Leaf {
+        bounds: Bounds,
+        start: usize,
+        end: usize,
+    },
+    Interior {
+        bounds: Bounds,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &Bounds {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+// Bounding volume hierarchy over opaque item ids. Items with infinite bounds
+// (e.g. planes) can't be partitioned and are always reported as candidates.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    items: Vec<(usize, Bounds)>,
+    unbounded: Vec<usize>,
+}
+
+// A part of the tree built on its own, with indices local to it.
+struct Subtree {
+    nodes: Vec<Node>,
+    items: Vec<(usize, Bounds)>,
+}
+
+impl Subtree {
+    fn leaf(bounds: Bounds, items: &[(usize, Bounds)]) -> Self {
+        Self {
+            nodes: vec![Node::Leaf {
+                bounds,
+                start: 0,
+                end: items.len(),
+            }],
+            items: items.to_vec(),
+        }
+    }
+    // Moves `other` in after this subtree's nodes and returns where its
+    // root ended up.
+    fn append(&mut self, other: Subtree) -> usize {
+        let node_offset = self.nodes.len();
+        let item_offset = self.items.len();
+        self.nodes
+            .extend(other.nodes.into_iter().map(|node| match node {
+                Node::Leaf { bounds, start, end } => Node::Leaf {
+                    bounds,
+                    start: start + item_offset,
+                    end: end + item_offset,
+                },
+                Node::Interior {
+                    bounds,
+                    left,
+                    right,
+                } => Node::Interior {
+                    bounds,
+                    left: left + node_offset,
+                    right: right + node_offset,
+                },
+            }));
+        self.items.extend(other.items);
+        node_offset
+    }
+}
+
+impl Default for BvhBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BvhBuilder {
+    pub fn new() -> Self {
+        Self {
+            split: SplitMethod::Sah,
+            max_leaf_size: 4,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+    pub fn set_split(self, split: SplitMethod) -> Self {
+        Self { split, ..self }
+    }
+    // With the surface area heuristic, nodes up to this size become leaves
+    // when that is estimated to be cheaper than splitting them; larger
+    // nodes are always split.
+    pub fn set_max_leaf_size(self, max_leaf_size: usize) -> Self {
+        Self {
+            max_leaf_size: max_leaf_size.max(1),
+            ..self
+        }
+    }
+    // Large subtrees are built on separate threads, up to this many.
+    pub fn set_threads(self, threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            ..self
+        }
+    }
+    pub fn build(&self, items: impl IntoIterator<Item = (usize, Bounds)>) -> Bvh {
+        let (mut bounded, unbounded): (Vec<_>, Vec<_>) = items
+            .into_iter()
+            .partition(|(_, bounds)| bounds.is_finite());
+        let tree = if bounded.is_empty() {
+            Subtree {
+                nodes: vec![],
+                items: vec![],
+            }
+        } else {
+            self.build_subtree(&mut bounded, self.threads)
+        };
+        Bvh {
+            nodes: tree.nodes,
+            items: tree.items,
+            unbounded: unbounded.into_iter().map(|(id, _)| id).collect(),
+        }
+    }
+    fn build_subtree(&self, items: &mut [(usize, Bounds)], threads: usize) -> Subtree {
+        let bounds = items
+            .iter()
+            .fold(Bounds::empty(), |acc, (_, b)| acc.merge(b));
+        if items.len() <= 1 {
+            return Subtree::leaf(bounds, items);
+        }
+        let mid = match self.split {
+            SplitMethod::Median if items.len() <= self.max_leaf_size => None,
+            SplitMethod::Median => Some(median_split(items)),
+            SplitMethod::Sah => self.sah_split(items, &bounds),
+        };
+        let Some(mid) = mid else {
+            return Subtree::leaf(bounds, items);
+        };
+
+        let large = items.len() >= PARALLEL_MIN_ITEMS;
+        let (left_items, right_items) = items.split_at_mut(mid);
+        let (left, right) = if threads > 1 && large {
+            thread::scope(|scope| {
+                let left = scope.spawn(|| self.build_subtree(left_items, threads / 2));
+                let right = self.build_subtree(right_items, threads - threads / 2);
+                (left.join().unwrap(), right)
+            })
+        } else {
+            (
+                self.build_subtree(left_items, 1),
+                self.build_subtree(right_items, 1),
+            )
+        };
+        // reserve the root's slot so the children can refer to it
+        let mut tree = Subtree {
+            nodes: vec![Node::Leaf {
+                bounds,
+                start: 0,
+                end: 0,
+            }],
+            items: vec![],
+        };
+        let left = tree.append(left);
+        let right = tree.append(right);
+        tree.nodes[0] = Node::Interior {
+            bounds,
+            left,
+            right,
+        };
+        tree
+    }
+    // Where to split `items`, after moving those going left to the front,
+    // or None if a leaf is cheaper.
+    fn sah_split(&self, items: &mut [(usize, Bounds)], bounds: &Bounds) -> Option<usize> {
+        let mut centroids = Bounds::empty();
+        for (_, b) in items.iter() {
+            centroids.add_point(&b.centroid());
+        }
+        let axis = widest_axis(&centroids);
+        let (min, extent) = (
+            centroids.min[axis],
+            centroids.max[axis] - centroids.min[axis],
+        );
+        if extent <= 0.0 {
+            // every centroid coincides, so only an arbitrary split is left
+            return (items.len() > self.max_leaf_size).then(|| median_split(items));
+        }
+        let bucket = |b: &Bounds| {
+            let k = ((b.centroid()[axis] - min) / extent * SAH_BUCKETS as Scalar) as usize;
+            k.min(SAH_BUCKETS - 1)
+        };
+        let mut counts = [0; SAH_BUCKETS];
+        let mut bucket_bounds = [Bounds::empty(); SAH_BUCKETS];
+        for (_, b) in items.iter() {
+            let k = bucket(b);
+            counts[k] += 1;
+            bucket_bounds[k] = bucket_bounds[k].merge(b);
+        }
+        // the cost of splitting after each bucket but the last
+        let area = |count: usize, b: &Bounds| match count {
+            0 => 0.0,
+            _ => count as Scalar * b.surface_area(),
+        };
+        let mut costs = [0.0; SAH_BUCKETS - 1];
+        let (mut count, mut below) = (0, Bounds::empty());
+        for k in 0..SAH_BUCKETS - 1 {
+            count += counts[k];
+            below = below.merge(&bucket_bounds[k]);
+            costs[k] = area(count, &below);
+        }
+        let (mut count, mut above) = (0, Bounds::empty());
+        for k in (1..SAH_BUCKETS).rev() {
+            count += counts[k];
+            above = above.merge(&bucket_bounds[k]);
+            costs[k - 1] += area(count, &above);
+        }
+        let (best, cost) = costs
+            .iter()
+            .map(|c| TRAVERSAL_COST + c / bounds.surface_area())
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .unwrap();
+        if items.len() <= self.max_leaf_size && cost >= items.len() as Scalar {
+            return None;
+        }
+        let mut mid = 0;
+        for i in 0..items.len() {
+            if bucket(&items[i].1) <= best {
+                items.swap(i, mid);
+                mid += 1;
+            }
+        }
+        Some(mid)
+    }
+}
+
+fn widest_axis(bounds: &Bounds) -> usize {
+    (0..3)
+        .max_by(|&a, &b| {
+            let da = bounds.max[a] - bounds.min[a];
+            let db = bounds.max[b] - bounds.min[b];
+            da.partial_cmp(&db).unwrap()
+        })
+        .unwrap()
+}
+
+// Moves the half of `items` with the smaller centroids along the axis with
+// the widest spread to the front.
+fn median_split(items: &mut [(usize, Bounds)]) -> usize {
+    let mut centroids = Bounds::empty();
+    for (_, b) in items.iter() {
+        centroids.add_point(&b.centroid());
+    }
+    let axis = widest_axis(&centroids);
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |(_, a), (_, b)| {
+        a.centroid()[axis].partial_cmp(&b.centroid()[axis]).unwrap()
+    });
+    mid
+}
+
+impl Bvh {
+    pub fn new(items: impl IntoIterator<Item = (usize, Bounds)>) -> Self {
+        BvhBuilder::new().build(items)
+    }
+    // Updates the tree for items that moved, keeping its structure: every
+    // item gets its bounds from `bounds` and the nodes are grown or shrunk
+    // to fit. Much cheaper than building anew, though the tree gets worse
+    // the further items move from where it was built. Returns false, with
+    // the tree left unusable, if a bounded item became unbounded.
+    pub fn refit(&mut self, bounds: impl Fn(usize) -> Bounds) -> bool {
+        for (id, b) in &mut self.items {
+            *b = bounds(*id);
+            if !b.is_finite() {
+                return false;
+            }
+        }
+        // children always come after their parents
+        for i in (0..self.nodes.len()).rev() {
+            let fitted = match self.nodes[i] {
+                Node::Leaf { start, end, .. } => self.items[start..end]
+                    .iter()
+                    .fold(Bounds::empty(), |acc, (_, b)| acc.merge(b)),
+                Node::Interior { left, right, .. } => {
+                    self.nodes[left].bounds().merge(self.nodes[right].bounds())
+                }
+            };
+            match &mut self.nodes[i] {
+                Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => *bounds = fitted,
+            }
+        }
+        true
+    }
+    // Calls `f` with every item whose bounds the ray may hit, stopping early
+    // when `f` returns true. Returns whether it stopped early.
+    pub fn any(&self, ray: &Ray, f: impl FnMut(usize) -> bool) -> bool {
+        self.traverse(ray, &mut TraversalStats::default(), f)
+    }
+    // As `any`, counting the work done into `stats`.
+    pub fn traverse(
+        &self,
+        ray: &Ray,
+        stats: &mut TraversalStats,
+        f: impl FnMut(usize) -> bool,
+    ) -> bool {
+        self.visit(|bounds| bounds.intersects(ray), stats, f)
+    }
+    pub fn for_each(&self, ray: &Ray, mut f: impl FnMut(usize)) {
+        self.any(ray, |id| {
+            f(id);
+            false
+        });
+    }
+    // Calls `f` with every item the packet's rays may hit, along with the
+    // lanes whose rays may hit it. Items are seen in the order `traverse`
+    // would see them for each ray.
+    pub fn traverse_packet(
+        &self,
+        packet: &RayPacket,
+        stats: &mut TraversalStats,
+        mut f: impl FnMut(usize, LaneMask),
+    ) {
+        let all = packet.mask();
+        for id in &self.unbounded {
+            stats.candidates += packet.rays().len();
+            f(*id, all);
+        }
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![(0, all)];
+        while let Some((index, active)) = stack.pop() {
+            let node = &self.nodes[index];
+            stats.nodes += 1;
+            let active = packet.hits(node.bounds(), active);
+            if active == 0 {
+                continue;
+            }
+            match node {
+                Node::Leaf { start, end, .. } => {
+                    for (id, bounds) in &self.items[*start..*end] {
+                        let lanes = packet.hits(bounds, active);
+                        if lanes != 0 {
+                            stats.candidates += lanes.count_ones() as usize;
+                            f(*id, lanes);
+                        }
+                    }
+                }
+                Node::Interior { left, right, .. } => {
+                    stack.push((*right, active));
+                    stack.push((*left, active));
+                }
+            }
+        }
+    }
+    pub fn for_each_containing(&self, p: &Point, mut f: impl FnMut(usize)) {
+        self.visit(
+            |bounds| bounds.contains(p),
+            &mut TraversalStats::default(),
+            |id| {
+                f(id);
+                false
+            },
+        );
+    }
+    fn visit(
+        &self,
+        enter: impl Fn(&Bounds) -> bool,
+        stats: &mut TraversalStats,
+        mut f: impl FnMut(usize) -> bool,
+    ) -> bool {
+        stats.candidates += self.unbounded.len();
+        if self.unbounded.iter().any(|id| f(*id)) {
+            return true;
+        }
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            stats.nodes += 1;
+            if !enter(node.bounds()) {
+                continue;
+            }
+            match node {
+                Node::Leaf { start, end, .. } => {
+                    let items = &self.items[*start..*end];
+                    for (id, bounds) in items {
+                        if !enter(bounds) {
+                            continue;
+                        }
+                        stats.candidates += 1;
+                        if f(*id) {
+                            return true;
+                        }
+                    }
+                }
+                Node::Interior { left, right, .. } => {
+                    stack.push(*right);
+                    stack.push(*left);
+                }
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::scalar::Scalar;
+    use crate::vector::Vector;
+
+    fn unit_box_at(x: Scalar) -> Bounds {
+        Bounds::new(
+            Point::new(x - 0.5, -0.5, -0.5),
+            Point::new(x + 0.5, 0.5, 0.5),
+        )
+    }
+
+    fn candidates(bvh: &Bvh, ray: &Ray) -> Vec<usize> {
+        let mut ids = vec![];
+        bvh.for_each(ray, |id| ids.push(id));
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_an_empty_bvh_has_no_candidates() {
+        let bvh = Bvh::new([]);
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(candidates(&bvh, &r).is_empty());
+    }
+
+    #[test]
+    fn test_a_ray_only_visits_the_boxes_it_passes_through() {
+        let bvh = Bvh::new((0..20).map(|i| (i, unit_box_at(2.0 * i as Scalar))));
+        let r = Ray::new(Point::new(10.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(candidates(&bvh, &r), vec![5]);
+    }
+
+    #[test]
+    fn test_a_ray_along_the_row_visits_every_box() {
+        let bvh = Bvh::new((0..20).map(|i| (i, unit_box_at(2.0 * i as Scalar))));
+        let r = Ray::new(Point::new(-5.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        assert_eq!(candidates(&bvh, &r), (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_unbounded_items_are_always_candidates() {
+        let bvh = Bvh::new([(0, unit_box_at(0.0)), (1, Bounds::infinite())]);
+        let r = Ray::new(Point::new(10.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(candidates(&bvh, &r), vec![1]);
+    }
+
+    #[test]
+    fn test_finding_the_boxes_containing_a_point() {
+        let bvh = Bvh::new((0..20).map(|i| (i, unit_box_at(2.0 * i as Scalar))));
+        let mut ids = vec![];
+        bvh.for_each_containing(&Point::new(10.25, 0.0, 0.0), |id| ids.push(id));
+        assert_eq!(ids, vec![5]);
+    }
+
+    #[test]
+    fn test_any_stops_at_the_first_accepted_item() {
+        let bvh = Bvh::new((0..20).map(|i| (i, unit_box_at(2.0 * i as Scalar))));
+        let r = Ray::new(Point::new(-5.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let mut visited = 0;
+        assert!(bvh.any(&r, |_| {
+            visited += 1;
+            true
+        }));
+        assert_eq!(visited, 1);
+    }
+
+    #[test]
+    fn test_refitting_follows_moved_boxes() {
+        let mut bvh = Bvh::new((0..20).map(|i| (i, unit_box_at(2.0 * i as Scalar))));
+        // box 3 jumps from one end of the row to beyond the other
+        let moved = |id: usize| match id {
+            3 => unit_box_at(50.0),
+            _ => unit_box_at(2.0 * id as Scalar),
+        };
+        assert!(bvh.refit(moved));
+        let r = Ray::new(Point::new(50.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(candidates(&bvh, &r), vec![3]);
+        let r = Ray::new(Point::new(6.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(candidates(&bvh, &r).is_empty());
+        assert!(!bvh.refit(|_| Bounds::infinite()));
+    }
+
+    fn leaf_sizes(bvh: &Bvh) -> Vec<usize> {
+        bvh.nodes
+            .iter()
+            .filter_map(|node| match node {
+                Node::Leaf { start, end, .. } => Some(end - start),
+                Node::Interior { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_the_leaf_size_is_configurable() {
+        let items = (0..20).map(|i| (i, unit_box_at(2.0 * i as Scalar)));
+        for split in [SplitMethod::Median, SplitMethod::Sah] {
+            let bvh = BvhBuilder::new()
+                .set_split(split)
+                .set_max_leaf_size(1)
+                .build(items.clone());
+            assert_eq!(leaf_sizes(&bvh), vec![1; 20]);
+        }
+    }
+
+    #[test]
+    fn test_the_surface_area_heuristic_splits_off_an_outlier() {
+        // a median split would cut the cluster in half and leave the
+        // outlier in a box reaching all the way back to it
+        let items = (0..16)
+            .map(|i| (i, unit_box_at(0.1 * i as Scalar)))
+            .chain([(16, unit_box_at(1000.0))]);
+        let bvh = BvhBuilder::new().build(items);
+        let Node::Interior { left, right, .. } = bvh.nodes[0] else {
+            panic!("expected the root to be split");
+        };
+        let far = [left, right]
+            .into_iter()
+            .find(|i| bvh.nodes[*i].bounds().max.x > 1000.0)
+            .unwrap();
+        assert!(matches!(bvh.nodes[far], Node::Leaf { start, end, .. } if end - start == 1));
+    }
+
+    #[test]
+    fn test_a_parallel_build_matches_a_serial_one() {
+        let items: Vec<_> = (0..10000)
+            .map(|i| {
+                let x = (i * 7919 % 10007) as Scalar;
+                (i, unit_box_at(x))
+            })
+            .collect();
+        let serial = BvhBuilder::new().set_threads(1).build(items.clone());
+        let parallel = BvhBuilder::new().set_threads(4).build(items);
+        let ids = |bvh: &Bvh| bvh.items.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        assert_eq!(ids(&parallel), ids(&serial));
+        assert_eq!(leaf_sizes(&parallel), leaf_sizes(&serial));
+        let r = Ray::new(Point::new(5000.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(candidates(&parallel, &r), candidates(&serial, &r));
+    }
+}
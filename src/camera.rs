@@ -1,24 +1,197 @@
-use crate::canvas::Canvas;
-use crate::point::{Point, ORIGIN};
+use crate::aov::{AovCanvases, Aovs};
+use crate::approx_eq::EPSILON;
+use crate::canvas::{Canvas, PixelFormat};
+#[cfg(feature = "fs")]
+use crate::checkpoint::Checkpoint;
+use crate::color::{Color, ColorSpace, WHITE};
+use crate::denoise::{Denoiser, GuideBuffers};
+use crate::depth::DepthBuffer;
+use crate::error::{RayTracerError, Result};
+#[cfg(feature = "mmap")]
+use crate::mapped::MappedCanvas;
+use crate::matte::IdMatte;
+use crate::packet::PACKET_SIZE;
+use crate::point::Point;
 use crate::ray::Ray;
-use crate::transform::{Affine, IDENTITY_AFFINE};
-use crate::world::{World, RECURSION_LIMIT};
+use crate::sampler::{SampleSequence, Sampler};
+use crate::scalar::consts::PI;
+use crate::scalar::Scalar;
+#[cfg(feature = "spectral")]
+use crate::spectrum::{band_wavelength, Spectrum, BANDS};
+use crate::tile::{tile_order, tile_rects, Rect, TileOrder, TiledCanvas, DEFAULT_TILE_SIZE};
+use crate::timing::RenderTimes;
+use crate::transform::{view_transform, Affine, IDENTITY_AFFINE};
+use crate::vector::Vector;
+use crate::world::{Fresnel, ShadingInfo, World};
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::io;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
+// How much room `Camera::frame` leaves around the scene.
+const FRAME_MARGIN: Scalar = 1.1;
+
+// The longest side of the preview `Camera::auto_expose` meters.
+const AUTO_EXPOSURE_SIZE: usize = 64;
+
+// How canvas points map to ray directions. Fisheye is an equidistant 180°
+// projection inscribed in the canvas; Equirectangular covers the full sphere
+// (longitude across, latitude down) for environment maps.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CameraProjection {
+    #[default]
+    Perspective,
+    Fisheye,
+    Equirectangular,
+}
+
+// What a pixel shows. Everything but Shaded is a debugging view: a flat
+// color per object, the normal mapped to RGB, the distance as gray fading
+// to black at `max`, or mesh edges in white over dimly shaded faces.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenderMode {
+    #[default]
+    Shaded,
+    ObjectId,
+    Normals,
+    Depth {
+        max: Scalar,
+    },
+    Wireframe,
+}
+
+// Edges are drawn where the smallest barycentric weight is below this.
+const WIREFRAME_WIDTH: Scalar = 0.03;
+
+// Side-by-side stereo: eyes `interocular` apart whose views converge at
+// `convergence` distance in front of the camera.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stereo {
+    pub interocular: Scalar,
+    pub convergence: Scalar,
+}
+
+// How to render, apart from where the camera is and what it sees: the
+// knobs of the camera's setters in one place, to keep with a scene file or
+// pass to `Camera::render_with`. `threads` of None uses every core, and
+// `aovs` renders the separate passes as well.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct RenderSettings {
+    pub samples: usize,
+    pub threads: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    pub pixel_format: PixelFormat,
+    pub sequence: SampleSequence,
+    pub seed: u64,
+    pub exposure: Scalar,
+    pub render_mode: RenderMode,
+    pub alpha: bool,
+    pub aovs: bool,
+    pub fresnel: Fresnel,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            samples: 1,
+            threads: None,
+            max_depth: None,
+            tile_size: DEFAULT_TILE_SIZE,
+            tile_order: TileOrder::Scanline,
+            pixel_format: PixelFormat::default(),
+            sequence: SampleSequence::Halton,
+            seed: 0,
+            exposure: 0.0,
+            render_mode: RenderMode::Shaded,
+            alpha: false,
+            aovs: false,
+            fresnel: Fresnel::Schlick,
+        }
+    }
+}
+
+// How often `Camera::render_with_snapshots` hands over the image so far:
+// once this much time has passed since the last snapshot, or every so many
+// finished tiles.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SnapshotInterval {
+    Time(Duration),
+    Tiles(usize),
+}
+
+// The image `Camera::render_with` renders, with its passes if the settings
+// asked for them.
+pub struct RenderOutput {
+    pub image: Canvas,
+    pub aovs: Option<AovCanvases>,
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "CameraSettings", into = "CameraSettings")
+)]
 pub struct Camera {
     hsize: usize,
     vsize: usize,
-    half_width: f64,
-    half_height: f64,
-    pixel_size: f64,
+    field_of_view: Scalar,
+    half_width: Scalar,
+    half_height: Scalar,
+    pixel_size: Scalar,
     transform: Affine,
+    inverse_transform: Affine,
+    projection: CameraProjection,
+    stereo: Option<Stereo>,
+    samples: usize,
+    threads: usize,
+    frame: u64,
+    alpha: bool,
+    render_mode: RenderMode,
+    sequence: SampleSequence,
+    tile_order: TileOrder,
+    tile_size: usize,
+    max_depth: Option<usize>,
+    pixel_format: PixelFormat,
+    color_space: ColorSpace,
+    exposure: Scalar,
+    fresnel: Fresnel,
 }
 
 impl Camera {
-    pub fn new(hsize: usize, vsize: usize, field_of_view: f64) -> Self {
+    pub fn new(hsize: usize, vsize: usize, field_of_view: Scalar) -> Result<Self> {
+        if hsize == 0 || vsize == 0 {
+            return Err(RayTracerError::InvalidCamera(format!(
+                "image size must be positive, got {}x{}",
+                hsize, vsize
+            )));
+        }
+        if !(field_of_view > 0.0 && field_of_view < PI) {
+            return Err(RayTracerError::InvalidCamera(format!(
+                "field of view must be between 0 and pi, got {}",
+                field_of_view
+            )));
+        }
         let half_view = (field_of_view / 2.0).tan();
-        let aspect = hsize as f64 / vsize as f64;
-        let half_width: f64;
-        let half_height: f64;
+        let aspect = hsize as Scalar / vsize as Scalar;
+        let half_width: Scalar;
+        let half_height: Scalar;
         if aspect >= 1.0 {
             half_width = half_view;
             half_height = half_view / aspect;
@@ -26,50 +199,1055 @@ impl Camera {
             half_width = half_view * aspect;
             half_height = half_view;
         }
-        let pixel_size = (half_width * 2.0) / hsize as f64;
-        Self {
+        let pixel_size = (half_width * 2.0) / hsize as Scalar;
+        Ok(Self {
             hsize,
             vsize,
+            field_of_view,
             half_width,
             half_height,
             pixel_size,
             transform: IDENTITY_AFFINE,
+            inverse_transform: IDENTITY_AFFINE,
+            projection: CameraProjection::Perspective,
+            stereo: None,
+            samples: 1,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            frame: 0,
+            alpha: false,
+            render_mode: RenderMode::Shaded,
+            sequence: SampleSequence::Halton,
+            tile_order: TileOrder::Scanline,
+            tile_size: DEFAULT_TILE_SIZE,
+            max_depth: None,
+            pixel_format: PixelFormat::default(),
+            color_space: ColorSpace::Linear,
+            exposure: 0.0,
+            fresnel: Fresnel::Schlick,
+        })
+    }
+    pub fn get_hsize(&self) -> usize {
+        self.hsize
+    }
+    pub fn get_vsize(&self) -> usize {
+        self.vsize
+    }
+    // Changes the image size, keeping the field of view and all settings.
+    pub fn set_size(&self, hsize: usize, vsize: usize) -> Result<Self> {
+        let resized = Camera::new(hsize, vsize, self.field_of_view)?;
+        Ok(Self {
+            hsize,
+            vsize,
+            half_width: resized.half_width,
+            half_height: resized.half_height,
+            pixel_size: resized.pixel_size,
+            ..*self
+        })
+    }
+    pub fn get_field_of_view(&self) -> Scalar {
+        self.field_of_view
+    }
+    pub fn set_field_of_view(&self, field_of_view: Scalar) -> Result<Self> {
+        let resized = Camera::new(self.hsize, self.vsize, field_of_view)?;
+        Ok(Self {
+            field_of_view,
+            half_width: resized.half_width,
+            half_height: resized.half_height,
+            pixel_size: resized.pixel_size,
+            ..*self
+        })
+    }
+    pub fn get_transform(&self) -> Affine {
+        self.transform
+    }
+    pub fn set_transform(&self, transform: Affine) -> Result<Self> {
+        let inverse_transform = transform
+            .inverse()
+            .ok_or(RayTracerError::NonInvertibleTransform)?;
+        Ok(Self {
+            transform,
+            inverse_transform,
+            ..*self
+        })
+    }
+    // Looks along `direction` at the center of the world's bounds, backed
+    // off until their bounding sphere fits the narrower field of view.
+    pub fn frame(&self, world: &World, direction: &Vector) -> Result<Self> {
+        let bounds = world.bounds();
+        if !bounds.is_finite() {
+            return Err(RayTracerError::InvalidCamera(
+                "the world has no bounded shapes to frame".to_string(),
+            ));
+        }
+        if direction.magnitude() < EPSILON {
+            return Err(RayTracerError::InvalidCamera(
+                "the framing direction must not be zero".to_string(),
+            ));
+        }
+        let direction = direction.normalize();
+        let center = bounds.centroid();
+        let radius = (bounds.max - &bounds.min).magnitude() / 2.0;
+        let half_angle = self.half_width.min(self.half_height).atan();
+        let distance = FRAME_MARGIN * radius.max(EPSILON) / half_angle.sin();
+        let from = center - &(&direction * distance);
+        let up = Vector::new(0.0, 1.0, 0.0);
+        self.set_transform(view_transform(&from, &center, &up)?)
+    }
+    pub fn set_projection(&self, projection: CameraProjection) -> Self {
+        Self {
+            projection,
+            ..*self
+        }
+    }
+    // Renders the left and right eye next to each other, so the canvas is
+    // twice as wide as the camera's hsize.
+    pub fn set_stereo(&self, stereo: Option<Stereo>) -> Result<Self> {
+        if let Some(s) = stereo {
+            if !(s.interocular >= 0.0 && s.convergence > 0.0) {
+                return Err(RayTracerError::InvalidCamera(format!(
+                    "invalid stereo settings: interocular {}, convergence {}",
+                    s.interocular, s.convergence
+                )));
+            }
+        }
+        Ok(Self { stereo, ..*self })
+    }
+    pub(crate) fn canvas_width(&self) -> usize {
+        match self.stereo {
+            Some(_) => 2 * self.hsize,
+            None => self.hsize,
+        }
+    }
+    // Number of jittered samples per pixel; a single sample goes through the
+    // pixel's center.
+    pub fn set_samples(&self, samples: usize) -> Self {
+        Self {
+            samples: samples.max(1),
+            ..*self
+        }
+    }
+    pub fn set_threads(&self, threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            ..*self
+        }
+    }
+    pub fn set_frame(&self, frame: u64) -> Self {
+        Self { frame, ..*self }
+    }
+    // Records the fraction of each pixel covered by geometry in the
+    // canvas's alpha channel, so misses render transparent.
+    pub fn set_alpha(&self, alpha: bool) -> Self {
+        Self { alpha, ..*self }
+    }
+    pub fn set_render_mode(&self, render_mode: RenderMode) -> Self {
+        Self {
+            render_mode,
+            ..*self
+        }
+    }
+    // Where anti-aliasing and area light samples come from, Halton points
+    // unless set otherwise.
+    pub fn set_sequence(&self, sequence: SampleSequence) -> Self {
+        Self { sequence, ..*self }
+    }
+    // The order tiles are handed out to the render threads in; it only
+    // changes how an image fills in, never the finished image.
+    pub fn set_tile_order(&self, tile_order: TileOrder) -> Self {
+        Self {
+            tile_order,
+            ..*self
+        }
+    }
+    // The side of the square tiles the image is split into for rendering.
+    // Like the tile order, it never changes the finished image.
+    pub fn set_tile_size(&self, tile_size: usize) -> Result<Self> {
+        if tile_size == 0 {
+            return Err(RayTracerError::InvalidCamera(
+                "tile size must be positive".to_string(),
+            ));
+        }
+        Ok(Self { tile_size, ..*self })
+    }
+    pub fn get_tile_size(&self) -> usize {
+        self.tile_size
+    }
+    // How the rendered image stores its colors; the smaller formats save
+    // memory for previews, see `PixelFormat`.
+    pub fn set_pixel_format(&self, pixel_format: PixelFormat) -> Self {
+        Self {
+            pixel_format,
+            ..*self
+        }
+    }
+    pub fn get_pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+    // The color space rendered images are written out in, see
+    // `Canvas::set_color_space`.
+    pub fn set_color_space(&self, color_space: ColorSpace) -> Self {
+        Self {
+            color_space,
+            ..*self
+        }
+    }
+    pub fn get_color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+    // How many times rays may bounce off or pass through surfaces, instead
+    // of `World::recursion_limit`.
+    pub fn set_max_depth(&self, max_depth: Option<usize>) -> Self {
+        Self { max_depth, ..*self }
+    }
+    // Brightens (or, if negative, darkens) shaded renders by this many
+    // stops, doubling the brightness per stop, so physically lit scenes can
+    // be brought into display range without touching the lights.
+    pub fn set_exposure(&self, exposure: Scalar) -> Self {
+        Self { exposure, ..*self }
+    }
+    // The exposure of a photographic camera with these settings, relative
+    // to ISO 100, a one second shutter and f/1: brightness grows with the
+    // sensitivity and the shutter time and falls with the square of the
+    // f-number.
+    pub fn set_exposure_settings(
+        &self,
+        iso: Scalar,
+        shutter: Scalar,
+        f_stop: Scalar,
+    ) -> Result<Self> {
+        if !(iso > 0.0 && shutter > 0.0 && f_stop > 0.0) {
+            return Err(RayTracerError::InvalidCamera(format!(
+                "exposure settings must be positive, got ISO {}, {}s at f/{}",
+                iso, shutter, f_stop
+            )));
         }
+        Ok(self.set_exposure((iso / 100.0 * shutter / (f_stop * f_stop)).log2()))
+    }
+    pub fn get_exposure(&self) -> Scalar {
+        self.exposure
+    }
+    // How reflective, transparent surfaces split their light; see `Fresnel`.
+    pub fn set_fresnel(&self, fresnel: Fresnel) -> Self {
+        Self { fresnel, ..*self }
+    }
+    pub fn get_fresnel(&self) -> Fresnel {
+        self.fresnel
     }
-    pub fn set_transform(&self, transform: Affine) -> Self {
-        Self { transform, ..*self }
+    // Sets the exposure from a quick render at no more than AUTO_EXPOSURE_SIZE
+    // pixels across, so that `fraction` of the pixels hitting something are
+    // darker than mid-gray (see `LuminanceHistogram::auto_exposure`).
+    pub fn auto_expose(&self, world: &World, fraction: Scalar) -> Result<Self> {
+        let scale = (AUTO_EXPOSURE_SIZE as Scalar / self.hsize.max(self.vsize) as Scalar).min(1.0);
+        let size = |n: usize| ((n as Scalar * scale).round() as usize).max(1);
+        let preview = self
+            .set_size(size(self.hsize), size(self.vsize))?
+            .set_samples(1)
+            .set_exposure(0.0);
+        let histogram = preview.render(world).luminance_histogram();
+        Ok(self.set_exposure(histogram.auto_exposure(fraction)))
     }
-    fn ray_for_pixel(&self, px: usize, py: usize) -> Ray {
-        // the offset from the edge of the canvas to the pixel's center
-        let xoffset = (px as f64 + 0.5) * self.pixel_size;
-        let yoffset = (py as f64 + 0.5) * self.pixel_size;
-        // the untransformed coordinates of the pixel in world space.
-        // (remember that the camera looks toward -z, so +x is to the *left*.)
-        let world_x = self.half_width - xoffset;
-        let world_y = self.half_height - yoffset;
-        // using the camera matrix, transform the canvas point and the origin,
-        // and then compute the ray's direction vector.
-        // (remember that the canvas is at z=-1)
-        let inv_transform = self.transform.inverse().unwrap();
-        let pixel = inv_transform * &Point::new(world_x, world_y, -1.0);
-        let origin = inv_transform * &ORIGIN;
-        let direction = (pixel - &origin).normalize();
+    fn ray_for_pixel(&self, px: usize, py: usize) -> Option<Ray> {
+        self.ray_for_canvas_point(px as Scalar + 0.5, py as Scalar + 0.5)
+    }
+    // The id of the object seen through the center of a pixel, e.g. to
+    // select objects by clicking on a rendered image.
+    pub fn pick(&self, world: &World, px: usize, py: usize) -> Option<usize> {
+        let ray = self.ray_for_pixel(px, py)?;
+        Some(world.shade_ray(&ray)?.object_id)
+    }
+    // None for canvas points outside the projection (the fisheye's corners).
+    fn ray_for_canvas_point(&self, cx: Scalar, cy: Scalar) -> Option<Ray> {
+        // the offset of the eye along the camera's x axis (+x is to the left)
+        let (cx, eye) = match self.stereo {
+            Some(s) if cx >= self.hsize as Scalar => {
+                (cx - self.hsize as Scalar, -s.interocular / 2.0)
+            }
+            Some(s) => (cx, s.interocular / 2.0),
+            None => (cx, 0.0),
+        };
+        let mut direction = match self.projection {
+            CameraProjection::Perspective => {
+                // the offset from the edge of the canvas to the point
+                let xoffset = cx * self.pixel_size;
+                let yoffset = cy * self.pixel_size;
+                // the untransformed coordinates of the pixel in world space.
+                // (remember that the camera looks toward -z, so +x is to the *left*.)
+                let world_x = self.half_width - xoffset;
+                let world_y = self.half_height - yoffset;
+                // (remember that the canvas is at z=-1)
+                Vector::new(world_x, world_y, -1.0)
+            }
+            CameraProjection::Fisheye => {
+                let radius = self.hsize.min(self.vsize) as Scalar / 2.0;
+                let nx = (cx - self.hsize as Scalar / 2.0) / radius;
+                let ny = (cy - self.vsize as Scalar / 2.0) / radius;
+                let r = (nx * nx + ny * ny).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                // the distance from the center is proportional to the angle
+                // from the view direction
+                let theta = r * PI / 2.0;
+                let s = if r > 0.0 { theta.sin() / r } else { 0.0 };
+                Vector::new(-nx * s, -ny * s, -theta.cos())
+            }
+            CameraProjection::Equirectangular => {
+                let longitude = (cx / self.hsize as Scalar - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - cy / self.vsize as Scalar) * PI;
+                Vector::new(
+                    -longitude.sin() * latitude.cos(),
+                    latitude.sin(),
+                    -longitude.cos() * latitude.cos(),
+                )
+            }
+        };
+        if let Some(s) = self.stereo {
+            // skew towards the other eye so both views meet at the convergence distance
+            direction.x += eye * direction.z / s.convergence;
+        }
+        // using the camera matrix, transform the origin and the direction
+        let origin = self.inverse_transform * &Point::new(eye, 0.0, 0.0);
+        let direction = (self.inverse_transform * &direction).normalize();
 
-        return Ray::new(origin, direction);
+        Some(Ray::new(origin, direction))
+    }
+    // The debugging views go through the same pixel and AOV code as shaded
+    // renders, with their color in the diffuse pass.
+    fn trace(&self, world: &World, ray: &Ray, sampler: &mut Sampler) -> Option<Aovs> {
+        let comps = world.shade_ray(ray)?;
+        Some(self.shade(world, &comps, sampler))
+    }
+    fn shade(&self, world: &World, comps: &ShadingInfo, sampler: &mut Sampler) -> Aovs {
+        let color = match self.render_mode {
+            RenderMode::Shaded => {
+                let depth = self
+                    .max_depth
+                    .map_or(world.recursion_limit(), |depth| depth as isize);
+                let aovs = world.hit_aovs(comps, depth, sampler);
+                return aovs * self.exposure.exp2();
+            }
+            RenderMode::ObjectId => object_color(comps.object_id),
+            RenderMode::Normals => {
+                let n = comps.normalv;
+                Color::new((n.x + 1.0) / 2.0, (n.y + 1.0) / 2.0, (n.z + 1.0) / 2.0)
+            }
+            RenderMode::Depth { max } => {
+                let gray = (1.0 - comps.t / max).clamp(0.0, 1.0);
+                Color::new(gray, gray, gray)
+            }
+            RenderMode::Wireframe => {
+                let shape = world.get_shape(comps.object_id);
+                match shape.edge_distance(&comps.point) {
+                    Some(d) if d < WIREFRAME_WIDTH => WHITE,
+                    _ => {
+                        let gray = 0.25 * comps.normalv.dot(&comps.eyev);
+                        Color::new(gray, gray, gray)
+                    }
+                }
+            }
+        };
+        Aovs {
+            diffuse: color,
+            ..Aovs::new()
+        }
+    }
+    // The pixel's components and the fraction of its samples that hit
+    // something.
+    fn aovs_for_pixel(&self, world: &World, px: usize, py: usize) -> (Aovs, Scalar) {
+        let mut sampler = self.sampler(px, py);
+        if self.samples == 1 {
+            return match self
+                .ray_for_pixel(px, py)
+                .and_then(|ray| self.trace(world, &ray, &mut sampler))
+            {
+                Some(aovs) => (aovs, 1.0),
+                None => (Aovs::new(), 0.0),
+            };
+        }
+        let mut sum = Aovs::new();
+        let mut hits = 0;
+        let set = sampler.point_set();
+        for i in 0..self.samples {
+            let [dx, dy] = set.point(i as u64, &mut sampler);
+            let (cx, cy) = (px as Scalar + dx, py as Scalar + dy);
+            let Some(ray) = self.ray_for_canvas_point(cx, cy) else {
+                continue;
+            };
+            if let Some(aovs) = self.trace(world, &ray, &mut sampler) {
+                sum = sum + aovs;
+                hits += 1;
+            }
+        }
+        let scale = 1.0 / self.samples as Scalar;
+        (sum * scale, hits as Scalar * scale)
+    }
+    // With one sample per pixel the camera rays of a few pixels are traced
+    // as a packet. Only their first hits are found together; everything
+    // after is traced ray by ray, so the image is the same either way.
+    fn packet_aovs(&self, world: &World, pixels: &[(usize, usize)]) -> Vec<(Aovs, Scalar)> {
+        // fisheye corners have no ray
+        let mut rays = vec![];
+        let mut has_ray = vec![];
+        for (x, y) in pixels {
+            let ray = self.ray_for_pixel(*x, *y);
+            has_ray.push(ray.is_some());
+            rays.extend(ray);
+        }
+        let mut hits = world.shade_packet(&rays).into_iter();
+        pixels
+            .iter()
+            .zip(has_ray)
+            .map(|((x, y), has_ray)| {
+                let Some(comps) = has_ray.then(|| hits.next().unwrap()).flatten() else {
+                    return (Aovs::new(), 0.0);
+                };
+                let mut sampler = self.sampler(*x, *y);
+                (self.shade(world, &comps, &mut sampler), 1.0)
+            })
+            .collect()
+    }
+    fn tile_aovs(&self, world: &World, tile: &Rect) -> Vec<(Aovs, Scalar)> {
+        let pixels: Vec<(usize, usize)> = (tile.y..tile.y + tile.height)
+            .flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+            .collect();
+        if self.samples == 1 {
+            return pixels
+                .chunks(PACKET_SIZE)
+                .flat_map(|pixels| self.packet_aovs(world, pixels))
+                .collect();
+        }
+        pixels
+            .into_iter()
+            .map(|(x, y)| self.aovs_for_pixel(world, x, y))
+            .collect()
+    }
+    pub(crate) fn tile_colors(&self, world: &World, tile: &Rect) -> Vec<(Color, Scalar)> {
+        self.tile_aovs(world, tile)
+            .into_iter()
+            .map(|(aovs, alpha)| (aovs.total(), alpha))
+            .collect()
+    }
+    pub(crate) fn write_tile(
+        &self,
+        image: &mut TiledCanvas,
+        tile: &Rect,
+        colors: &[(Color, Scalar)],
+    ) {
+        for (i, (color, alpha)) in colors.iter().enumerate() {
+            let (x, y) = (tile.x + i % tile.width, tile.y + i / tile.width);
+            image.write_pixel(x, y, *color);
+            if self.alpha {
+                image.write_alpha(x, y, *alpha);
+            }
+        }
+    }
+    // The camera with all its render settings replaced by `settings`.
+    pub fn with_settings(&self, settings: &RenderSettings) -> Result<Self> {
+        let threads = settings
+            .threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        Ok(self
+            .set_samples(settings.samples)
+            .set_threads(threads)
+            .set_max_depth(settings.max_depth)
+            .set_tile_size(settings.tile_size)?
+            .set_tile_order(settings.tile_order)
+            .set_pixel_format(settings.pixel_format)
+            .set_sequence(settings.sequence)
+            .set_frame(settings.seed)
+            .set_exposure(settings.exposure)
+            .set_render_mode(settings.render_mode)
+            .set_alpha(settings.alpha)
+            .set_fresnel(settings.fresnel))
+    }
+    pub fn get_settings(&self) -> RenderSettings {
+        RenderSettings {
+            samples: self.samples,
+            threads: Some(self.threads),
+            max_depth: self.max_depth,
+            tile_size: self.tile_size,
+            tile_order: self.tile_order,
+            pixel_format: self.pixel_format,
+            sequence: self.sequence,
+            seed: self.frame,
+            exposure: self.exposure,
+            render_mode: self.render_mode,
+            alpha: self.alpha,
+            aovs: false,
+            fresnel: self.fresnel,
+        }
+    }
+    // Renders with `settings` in place of the camera's own.
+    pub fn render_with(&self, settings: &RenderSettings, world: &World) -> Result<RenderOutput> {
+        let camera = self.with_settings(settings)?;
+        if !settings.aovs {
+            return Ok(RenderOutput {
+                image: camera.render(world),
+                aovs: None,
+            });
+        }
+        let aovs = camera.render_aovs(world);
+        let mut image = aovs.beauty.to_format(camera.pixel_format);
+        image.set_color_space(camera.color_space);
+        Ok(RenderOutput {
+            image,
+            aovs: Some(aovs),
+        })
     }
+    // Renders on the configured number of threads. Each pixel is seeded from
+    // its coordinates and the frame, so every thread count gives the same image.
     pub fn render(&self, world: &World) -> Canvas {
-        let mut image = Canvas::new(self.hsize, self.vsize);
-
-        for y in 0..self.vsize {
-            for x in 0..self.hsize {
-                let ray = self.ray_for_pixel(x, y);
-                let color = world.color_at(&ray, RECURSION_LIMIT);
-                image.write_pixel(x, y, color);
+        let image = Mutex::new(self.tiled_canvas());
+        self.render_into(world, &image, &AtomicBool::new(false));
+        image.into_inner().unwrap().into_canvas()
+    }
+    // Writes each tile into `image` as soon as it is done, for showing the
+    // render while it progresses. No new tiles are started once `cancel` is
+    // set; returns whether every tile was rendered.
+    pub(crate) fn render_into(
+        &self,
+        world: &World,
+        image: &Mutex<TiledCanvas>,
+        cancel: &AtomicBool,
+    ) -> bool {
+        self.for_each_tile(|tile| {
+            if !cancel.load(Ordering::Relaxed) {
+                let colors = self.tile_colors(world, tile);
+                self.write_tile(&mut image.lock().unwrap(), tile, &colors);
+            }
+        });
+        !cancel.load(Ordering::Relaxed)
+    }
+    // Renders like `render`, but traces every sample once per band of a
+    // `Spectrum` with the band's wavelength, so that materials with
+    // dispersion split white light into its colors. Each band keeps its
+    // share of the color it traced, and the spectrum is turned back into a
+    // color. Without dispersion the image is the same as `render`'s, at
+    // `BANDS` times the cost.
+    #[cfg(feature = "spectral")]
+    pub fn render_spectral(&self, world: &World) -> Canvas {
+        let image = Mutex::new(self.tiled_canvas());
+        self.for_each_tile(|tile| {
+            let colors: Vec<(Color, Scalar)> = (tile.y..tile.y + tile.height)
+                .flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+                .map(|(x, y)| self.spectral_pixel(world, x, y))
+                .collect();
+            self.write_tile(&mut image.lock().unwrap(), tile, &colors);
+        });
+        image.into_inner().unwrap().into_canvas()
+    }
+    // The bands of a sample see the same random numbers, so noise from
+    // soft shadows and the like doesn't turn into colored speckles.
+    #[cfg(feature = "spectral")]
+    fn spectral_pixel(&self, world: &World, px: usize, py: usize) -> (Color, Scalar) {
+        let mut sampler = self.sampler(px, py);
+        let set = sampler.point_set();
+        let mut sum = Spectrum::default();
+        let mut hits = 0;
+        for i in 0..self.samples {
+            let [dx, dy] = if self.samples == 1 {
+                [0.5, 0.5]
+            } else {
+                set.point(i as u64, &mut sampler)
+            };
+            let Some(ray) = self.ray_for_canvas_point(px as Scalar + dx, py as Scalar + dy) else {
+                continue;
+            };
+            let mut spectrum = Spectrum::default();
+            let mut hit = false;
+            let mut band_sampler = sampler.clone();
+            for band in 0..BANDS {
+                band_sampler = sampler.clone();
+                let ray =
+                    Ray::new(ray.origin, ray.direction).set_wavelength(Some(band_wavelength(band)));
+                if let Some(aovs) = self.trace(world, &ray, &mut band_sampler) {
+                    spectrum.bands[band] = Spectrum::from_color(aovs.total()).bands[band];
+                    hit = true;
+                }
+            }
+            sampler = band_sampler;
+            if hit {
+                sum = sum + spectrum;
+                hits += 1;
             }
         }
+        let scale = 1.0 / self.samples as Scalar;
+        ((sum * scale).to_color(), hits as Scalar * scale)
+    }
+    // Renders every pass in one go; the beauty pass matches `render`.
+    pub fn render_aovs(&self, world: &World) -> AovCanvases {
+        let images = Mutex::new(AovCanvases::new(self.canvas_width(), self.vsize));
+        self.for_each_tile(|tile| {
+            let aovs = self.tile_aovs(world, tile);
+            let mut images = images.lock().unwrap();
+            for (i, (aovs, alpha)) in aovs.iter().enumerate() {
+                let (x, y) = (tile.x + i % tile.width, tile.y + i / tile.width);
+                images.write(x, y, aovs);
+                if self.alpha {
+                    images.write_alpha(x, y, *alpha);
+                }
+            }
+        });
+        images.into_inner().unwrap()
+    }
+    // The distance in meters (see `World::set_units`) to the first hit
+    // through the center of every pixel, for the post effects in `depth`.
+    // Only one ray is traced per pixel.
+    pub fn render_depth(&self, world: &World) -> DepthBuffer {
+        let meters = world.meters_per_unit();
+        let depth = Mutex::new(DepthBuffer::new(self.canvas_width(), self.vsize));
+        self.for_each_tile(|tile| {
+            let depths: Vec<Scalar> = (tile.y..tile.y + tile.height)
+                .flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    self.ray_for_pixel(x, y)
+                        .and_then(|ray| world.shade_ray(&ray))
+                        .map_or(Scalar::INFINITY, |comps| comps.t * meters)
+                })
+                .collect();
+            let mut depth = depth.lock().unwrap();
+            for (i, d) in depths.iter().enumerate() {
+                depth.write_depth(tile.x + i % tile.width, tile.y + i / tile.width, *d);
+            }
+        });
+        depth.into_inner().unwrap()
+    }
+    // The albedo, normal and depth through the center of every pixel, for
+    // guiding a denoiser. Pixels that hit nothing are black with infinite
+    // depth.
+    pub fn render_guides(&self, world: &World) -> GuideBuffers {
+        let meters = world.meters_per_unit();
+        let guides = Mutex::new(GuideBuffers::new(self.canvas_width(), self.vsize));
+        self.for_each_tile(|tile| {
+            let hits: Vec<(usize, usize, Option<ShadingInfo>)> = (tile.y..tile.y + tile.height)
+                .flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let comps = self
+                        .ray_for_pixel(x, y)
+                        .and_then(|ray| world.shade_ray(&ray));
+                    (x, y, comps)
+                })
+                .collect();
+            let mut guides = guides.lock().unwrap();
+            for (x, y, comps) in hits {
+                let Some(comps) = comps else {
+                    continue;
+                };
+                let n = comps.normalv;
+                guides.albedo.write_pixel(x, y, world.albedo_at(&comps));
+                guides.normal.write_pixel(x, y, Color::new(n.x, n.y, n.z));
+                guides.depth.write_depth(x, y, comps.t * meters);
+            }
+        });
+        guides.into_inner().unwrap()
+    }
+    // The objects seen in every pixel with the share of its samples that
+    // hit each, see `IdMatte`. Samples are placed as in `render`.
+    pub fn render_id_matte(&self, world: &World) -> IdMatte {
+        let matte = Mutex::new(IdMatte::new(self.canvas_width(), self.vsize));
+        let weight = 1.0 / self.samples as Scalar;
+        self.for_each_tile(|tile| {
+            let mut hits = vec![];
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    let mut sampler = self.sampler(x, y);
+                    let set = sampler.point_set();
+                    for i in 0..self.samples {
+                        let [dx, dy] = if self.samples == 1 {
+                            [0.5, 0.5]
+                        } else {
+                            set.point(i as u64, &mut sampler)
+                        };
+                        let hit = self
+                            .ray_for_canvas_point(x as Scalar + dx, y as Scalar + dy)
+                            .and_then(|ray| world.shade_ray(&ray));
+                        if let Some(comps) = hit {
+                            hits.push((x, y, comps.object_id));
+                        }
+                    }
+                }
+            }
+            let mut matte = matte.lock().unwrap();
+            for (x, y, id) in hits {
+                let name = match world.get_shape(id).get_name() {
+                    Some(name) => name.to_string(),
+                    None => format!("shape {}", id),
+                };
+                matte.add(x, y, &name, weight);
+            }
+        });
+        matte.into_inner().unwrap()
+    }
+    // Renders like `render`, timing every pixel, to see where the time
+    // goes. Pixels are traced one by one even with a single sample, as
+    // packets would share their time, so the render itself is a little
+    // slower.
+    pub fn render_timed(&self, world: &World) -> (Canvas, RenderTimes) {
+        let image = Mutex::new(self.tiled_canvas());
+        let times = Mutex::new(RenderTimes::new(self.canvas_width(), self.vsize));
+        self.for_each_tile(|tile| {
+            let pixels = (tile.y..tile.y + tile.height)
+                .flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| (x, y)));
+            let (colors, durations): (Vec<(Color, Scalar)>, Vec<Duration>) = pixels
+                .map(|(x, y)| {
+                    let start = Instant::now();
+                    let (aovs, alpha) = self.aovs_for_pixel(world, x, y);
+                    ((aovs.total(), alpha), start.elapsed())
+                })
+                .unzip();
+            self.write_tile(&mut image.lock().unwrap(), tile, &colors);
+            let mut times = times.lock().unwrap();
+            for (i, time) in durations.into_iter().enumerate() {
+                times.write_time(tile.x + i % tile.width, tile.y + i / tile.width, time);
+            }
+        });
+        (
+            image.into_inner().unwrap().into_canvas(),
+            times.into_inner().unwrap(),
+        )
+    }
+    // Renders like `render` and cleans the image up with `denoiser`, guided
+    // by `render_guides`.
+    pub fn render_denoised(&self, world: &World, denoiser: &dyn Denoiser) -> Canvas {
+        denoiser.denoise(&self.render(world), &self.render_guides(world))
+    }
+    // Renders like `render`, passing the image with the tiles finished so
+    // far (the rest black) to `snapshot` every `interval`, for keeping an
+    // eye on long renders. Once `snapshot` fails no more are taken, and its
+    // error is returned after the render completes.
+    pub fn render_with_snapshots(
+        &self,
+        world: &World,
+        interval: SnapshotInterval,
+        snapshot: impl FnMut(&Canvas) -> Result<()> + Send,
+    ) -> Result<Canvas> {
+        let image = self.tiled_canvas();
+        let state = Mutex::new((image, snapshot, 0, Instant::now(), Ok(())));
+        self.for_each_tile(|tile| {
+            let colors = self.tile_colors(world, tile);
+            let mut state = state.lock().unwrap();
+            let (image, snapshot, finished, last_snapshot, result) = &mut *state;
+            self.write_tile(image, tile, &colors);
+            *finished += 1;
+            let due = match interval {
+                SnapshotInterval::Time(time) => last_snapshot.elapsed() >= time,
+                SnapshotInterval::Tiles(tiles) => *finished % tiles.max(1) == 0,
+            };
+            // the finished image is returned instead
+            if result.is_ok() && due && *finished < image.tile_count() {
+                *result = snapshot(image.canvas());
+                *last_snapshot = Instant::now();
+            }
+        });
+        let (image, _, _, _, result) = state.into_inner().unwrap();
+        result?;
+        Ok(image.into_canvas())
+    }
+    // Renders like `render` into a canvas kept in a memory-mapped file at
+    // `path`, for images too large to hold in memory.
+    #[cfg(feature = "mmap")]
+    pub fn render_mapped(&self, world: &World, path: &Path) -> Result<MappedCanvas> {
+        let image = Mutex::new(MappedCanvas::create(path, self.canvas_width(), self.vsize)?);
+        self.for_each_tile(|tile| {
+            let colors = self.tile_colors(world, tile);
+            let colors: Vec<(Color, Scalar)> = if self.alpha {
+                colors
+            } else {
+                colors.into_iter().map(|(color, _)| (color, 1.0)).collect()
+            };
+            image.lock().unwrap().write_rect(tile, &colors);
+        });
+        let image = image.into_inner().unwrap();
+        image.flush()?;
+        Ok(image)
+    }
+    // Renders like `render`, saving the finished tiles to `path` at most
+    // every `interval` and resuming from any saved there before; the
+    // checkpoint is removed once done.
+    #[cfg(feature = "fs")]
+    pub fn render_resumable(
+        &self,
+        world: &World,
+        path: &Path,
+        interval: Duration,
+    ) -> Result<Canvas> {
+        let tiles = tile_rects(self.canvas_width(), self.vsize, self.tile_size);
+        let mut image = self.tiled_canvas();
+        let mut checkpoint = Checkpoint {
+            width: self.canvas_width(),
+            height: self.vsize,
+            tile_size: self.tile_size,
+            samples: self.samples,
+            frame: self.frame,
+            fingerprint: self.fingerprint(),
+            tiles: vec![],
+        };
+        let mut done = vec![false; tiles.len()];
+        if let Some(saved) = Checkpoint::load(path)? {
+            let settings = |c: &Checkpoint| {
+                (
+                    c.width,
+                    c.height,
+                    c.tile_size,
+                    c.samples,
+                    c.frame,
+                    c.fingerprint,
+                )
+            };
+            if settings(&saved) != settings(&checkpoint) {
+                return Err(RayTracerError::InvalidCamera(
+                    "the checkpoint was saved by a render with different settings".to_string(),
+                ));
+            }
+            for (index, colors) in &saved.tiles {
+                let tile = tiles
+                    .get(*index)
+                    .filter(|tile| tile.width * tile.height == colors.len())
+                    .ok_or_else(|| {
+                        RayTracerError::InvalidCamera(format!("invalid checkpoint tile {}", index))
+                    })?;
+                self.write_tile(&mut image, tile, colors);
+                done[*index] = true;
+            }
+            checkpoint = saved;
+        }
 
+        let remaining: Vec<(usize, Rect)> = self
+            .tile_order()
+            .into_iter()
+            .filter(|index| !done[*index])
+            .map(|index| (index, tiles[index]))
+            .collect();
+        let state = Mutex::new((image, checkpoint, Instant::now(), Ok(())));
+        self.for_each_in_parallel(&remaining, |(index, tile)| {
+            let colors = self.tile_colors(world, tile);
+            let mut state = state.lock().unwrap();
+            let (image, checkpoint, last_save, result) = &mut *state;
+            self.write_tile(image, tile, &colors);
+            checkpoint.tiles.push((*index, colors));
+            if result.is_ok() && last_save.elapsed() >= interval {
+                *result = checkpoint.save(path);
+                *last_save = Instant::now();
+            }
+        });
+        let (image, _, _, result) = state.into_inner().unwrap();
+        result?;
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        Ok(image.into_canvas())
+    }
+    // A hash of every setting that changes the pixels, so that a checkpoint
+    // of another render isn't resumed. FNV-1a over the settings' Debug text
+    // keeps it the same between builds.
+    #[cfg(feature = "fs")]
+    pub(crate) fn fingerprint(&self) -> u64 {
+        let view = (
+            self.hsize,
+            self.vsize,
+            self.field_of_view,
+            self.transform,
+            self.projection,
+            self.stereo,
+        );
+        let settings = (
+            self.samples,
+            self.frame,
+            self.alpha,
+            self.render_mode,
+            self.sequence,
+            self.max_depth,
+            self.pixel_format,
+            self.color_space,
+            self.exposure,
+            self.fresnel,
+        );
+        format!("{:?}", (view, settings))
+            .bytes()
+            .fold(0xcbf29ce484222325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+    // The pixel's sampler, seeded by its coordinates and the frame.
+    fn sampler(&self, x: usize, y: usize) -> Sampler {
+        Sampler::for_pixel(x, y, self.frame)
+            .set_sequence(self.sequence)
+            .set_fresnel(self.fresnel)
+    }
+    // An empty image to render into, in the camera's tiles and format.
+    pub(crate) fn tiled_canvas(&self) -> TiledCanvas {
+        let mut image = TiledCanvas::with_format(
+            self.canvas_width(),
+            self.vsize,
+            self.tile_size,
+            self.pixel_format,
+        );
+        image.set_color_space(self.color_space);
         image
     }
+    fn tile_order(&self) -> Vec<usize> {
+        tile_order(
+            self.canvas_width(),
+            self.vsize,
+            self.tile_size,
+            self.tile_order,
+        )
+    }
+    // Hands the tiles out to the worker threads.
+    fn for_each_tile(&self, f: impl Fn(&Rect) + Sync) {
+        let tiles = tile_rects(self.canvas_width(), self.vsize, self.tile_size);
+        let tiles: Vec<Rect> = self.tile_order().into_iter().map(|i| tiles[i]).collect();
+        self.for_each_in_parallel(&tiles, f);
+    }
+    // With a single thread nothing is spawned, which also keeps rendering
+    // working where threads aren't available, such as in WebAssembly.
+    fn for_each_in_parallel<T: Sync>(&self, items: &[T], f: impl Fn(&T) + Sync) {
+        if self.threads == 1 {
+            items.iter().for_each(f);
+            return;
+        }
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(items.len()) {
+                scope.spawn(|| {
+                    while let Some(item) = items.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        f(item);
+                    }
+                });
+            }
+        });
+    }
+    pub fn render_tile(&self, world: &World, tile: &Rect, image: &mut TiledCanvas) {
+        let colors = self.tile_colors(world, tile);
+        self.write_tile(image, tile, &colors);
+    }
+}
+
+// Fully saturated colors with hues a golden ratio turn apart, so objects
+// with consecutive ids look different.
+fn object_color(id: usize) -> Color {
+    let h = (id as Scalar * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as usize {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    Color::new(r, g, b)
+}
+
+// The serialized form of a camera: what was passed to its constructor and
+// setters, not the quantities derived from them.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CameraSettings {
+    hsize: usize,
+    vsize: usize,
+    field_of_view: Scalar,
+    #[serde(default = "default_transform")]
+    transform: Affine,
+    #[serde(default)]
+    projection: CameraProjection,
+    #[serde(default)]
+    stereo: Option<Stereo>,
+    #[serde(default = "default_samples")]
+    samples: usize,
+    #[serde(default)]
+    frame: u64,
+    #[serde(default)]
+    alpha: bool,
+    #[serde(default)]
+    render_mode: RenderMode,
+    #[serde(default = "default_sequence")]
+    sequence: SampleSequence,
+    #[serde(default)]
+    tile_order: TileOrder,
+    #[serde(default = "default_tile_size")]
+    tile_size: usize,
+    #[serde(default)]
+    max_depth: Option<usize>,
+    #[serde(default)]
+    pixel_format: PixelFormat,
+    #[serde(default)]
+    color_space: ColorSpace,
+    #[serde(default)]
+    exposure: Scalar,
+    #[serde(default)]
+    fresnel: Fresnel,
+}
+
+#[cfg(feature = "serde")]
+fn default_transform() -> Affine {
+    IDENTITY_AFFINE
+}
+
+#[cfg(feature = "serde")]
+fn default_samples() -> usize {
+    1
+}
+
+#[cfg(feature = "serde")]
+fn default_sequence() -> SampleSequence {
+    SampleSequence::Halton
+}
+
+#[cfg(feature = "serde")]
+fn default_tile_size() -> usize {
+    DEFAULT_TILE_SIZE
+}
+
+#[cfg(feature = "serde")]
+impl From<Camera> for CameraSettings {
+    fn from(c: Camera) -> Self {
+        Self {
+            hsize: c.hsize,
+            vsize: c.vsize,
+            field_of_view: c.field_of_view,
+            transform: c.transform,
+            projection: c.projection,
+            stereo: c.stereo,
+            samples: c.samples,
+            frame: c.frame,
+            alpha: c.alpha,
+            render_mode: c.render_mode,
+            sequence: c.sequence,
+            tile_order: c.tile_order,
+            tile_size: c.tile_size,
+            max_depth: c.max_depth,
+            pixel_format: c.pixel_format,
+            color_space: c.color_space,
+            exposure: c.exposure,
+            fresnel: c.fresnel,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<CameraSettings> for Camera {
+    type Error = RayTracerError;
+
+    fn try_from(s: CameraSettings) -> Result<Self> {
+        Ok(Camera::new(s.hsize, s.vsize, s.field_of_view)?
+            .set_transform(s.transform)?
+            .set_projection(s.projection)
+            .set_stereo(s.stereo)?
+            .set_samples(s.samples)
+            .set_frame(s.frame)
+            .set_alpha(s.alpha)
+            .set_render_mode(s.render_mode)
+            .set_sequence(s.sequence)
+            .set_tile_order(s.tile_order)
+            .set_tile_size(s.tile_size)?
+            .set_max_depth(s.max_depth)
+            .set_pixel_format(s.pixel_format)
+            .set_color_space(s.color_space)
+            .set_exposure(s.exposure)
+            .set_fresnel(s.fresnel))
+    }
 }
 
 #[cfg(test)]
@@ -77,34 +1255,99 @@ mod tests {
 
     use super::*;
     use crate::approx_eq::{assert_approx_eq, ApproxEq};
-    use crate::transform::{rotation_y, translation};
-    use crate::vector::Vector;
-    use std::f64::consts::PI;
+    use crate::color::BLACK;
+    use crate::light::PointLight;
+    use crate::material::Material;
+    use crate::matte::name_hash;
+    use crate::mesh::Mesh;
+    use crate::pattern::CheckersPattern;
+    use crate::plane::Plane;
+    use crate::point::ORIGIN;
+    use crate::scalar::consts::{FRAC_1_SQRT_2, PI};
+    use crate::shape::Shape;
+    use crate::sphere::Sphere;
+    use crate::transform::{rotation_y, scaling, translation};
+    use crate::units::Units;
 
     #[test]
     fn test_the_pixel_size_for_a_horizontal_canvas() {
-        let c = Camera::new(200, 125, PI / 2.0);
+        let c = Camera::new(200, 125, PI / 2.0).unwrap();
         assert_approx_eq!(c.pixel_size, 0.01);
     }
 
     #[test]
     fn test_the_pixel_size_for_a_vertical_canvas() {
-        let c = Camera::new(125, 200, PI / 2.0);
+        let c = Camera::new(125, 200, PI / 2.0).unwrap();
         assert_approx_eq!(c.pixel_size, 0.01);
     }
 
     #[test]
     fn test_constructing_a_ray_through_the_center_of_the_canvas() {
-        let c = Camera::new(201, 101, PI / 2.0);
-        let r = c.ray_for_pixel(100, 50);
+        let c = Camera::new(201, 101, PI / 2.0).unwrap();
+        let r = c.ray_for_pixel(100, 50).unwrap();
         assert_approx_eq!(r.origin, ORIGIN);
         assert_approx_eq!(r.direction, Vector::new(0.0, 0.0, -1.0));
     }
 
+    #[test]
+    fn test_fisheye_rays() {
+        let c = Camera::new(100, 100, PI / 2.0)
+            .unwrap()
+            .set_projection(CameraProjection::Fisheye);
+        let center = c.ray_for_canvas_point(50.0, 50.0).unwrap();
+        assert_approx_eq!(center.direction, Vector::new(0.0, 0.0, -1.0));
+        let right = c.ray_for_canvas_point(100.0, 50.0).unwrap();
+        assert_approx_eq!(right.direction, Vector::new(-1.0, 0.0, 0.0));
+        let top = c.ray_for_canvas_point(50.0, 25.0).unwrap();
+        assert_approx_eq!(
+            top.direction,
+            Vector::new(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2)
+        );
+        assert!(c.ray_for_canvas_point(0.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn test_equirectangular_rays() {
+        let c = Camera::new(200, 100, PI / 2.0)
+            .unwrap()
+            .set_projection(CameraProjection::Equirectangular);
+        let center = c.ray_for_canvas_point(100.0, 50.0).unwrap();
+        assert_approx_eq!(center.direction, Vector::new(0.0, 0.0, -1.0));
+        let behind = c.ray_for_canvas_point(0.0, 50.0).unwrap();
+        assert_approx_eq!(behind.direction, Vector::new(0.0, 0.0, 1.0));
+        let right = c.ray_for_canvas_point(150.0, 50.0).unwrap();
+        assert_approx_eq!(right.direction, Vector::new(-1.0, 0.0, 0.0));
+        let up = c.ray_for_canvas_point(100.0, 0.0).unwrap();
+        assert_approx_eq!(up.direction, Vector::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_stereo_eyes_converge() {
+        let stereo = Stereo {
+            interocular: 0.2,
+            convergence: 5.0,
+        };
+        let c = Camera::new(100, 50, PI / 2.0)
+            .unwrap()
+            .set_stereo(Some(stereo))
+            .unwrap();
+        let left = c.ray_for_canvas_point(50.0, 25.0).unwrap();
+        let right = c.ray_for_canvas_point(150.0, 25.0).unwrap();
+        assert_approx_eq!(left.origin, Point::new(0.1, 0.0, 0.0));
+        assert_approx_eq!(right.origin, Point::new(-0.1, 0.0, 0.0));
+        for r in [left, right] {
+            let t = 5.0 / -r.direction.z;
+            assert_approx_eq!(r.position(t), Point::new(0.0, 0.0, -5.0));
+        }
+        let image = c.render(&World::new());
+        assert_eq!(image.width(), 200);
+        assert_eq!(image.height(), 50);
+    }
+
     #[test]
     fn test_constructing_a_ray_through_a_corner_of_the_canvas() {
-        let c = Camera::new(201, 101, PI / 2.0);
-        let r = c.ray_for_pixel(0, 0);
+        let c = Camera::new(201, 101, PI / 2.0).unwrap();
+        let r = c.ray_for_pixel(0, 0).unwrap();
         assert_approx_eq!(r.origin, ORIGIN);
         assert_approx_eq!(r.direction, Vector::new(0.66519, 0.33259, -0.66851));
     }
@@ -112,12 +1355,645 @@ mod tests {
     #[test]
     fn test_constructing_a_ray_when_the_camera_is_transformed() {
         let c = Camera::new(201, 101, PI / 2.0)
-            .set_transform(rotation_y(PI / 4.0) * &translation(0.0, -2.0, 5.0));
-        let r = c.ray_for_pixel(100, 50);
+            .unwrap()
+            .set_transform(rotation_y(PI / 4.0) * &translation(0.0, -2.0, 5.0))
+            .unwrap();
+        let r = c.ray_for_pixel(100, 50).unwrap();
         assert_approx_eq!(r.origin, Point::new(0.0, 2.0, -5.0));
         assert_approx_eq!(
             r.direction,
-            Vector::new(2f64.sqrt() / 2.0, 0.0, -2f64.sqrt() / 2.0)
+            Vector::new(Scalar::sqrt(2.0) / 2.0, 0.0, -Scalar::sqrt(2.0) / 2.0)
+        );
+    }
+    #[test]
+    fn test_framing_a_world() {
+        let mut world = World::new();
+        world.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(translation(2.0, 0.0, 0.0))
+                .unwrap(),
         );
+        let c = Camera::new(101, 51, PI / 3.0)
+            .unwrap()
+            .frame(&world, &Vector::new(0.0, 0.0, 2.0))
+            .unwrap();
+        let r = c.ray_for_pixel(50, 25).unwrap();
+        assert_approx_eq!(r.direction, Vector::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(r.origin.x, 2.0);
+        assert!(world.trace(&r, 0, &mut Sampler::new(0)).is_some());
+        // the sphere is inside the frame, with a margin at the top and bottom
+        for py in [0, 50] {
+            let r = c.ray_for_pixel(50, py).unwrap();
+            assert!(world.trace(&r, 0, &mut Sampler::new(0)).is_none());
+        }
+    }
+
+    #[test]
+    fn test_an_empty_world_cannot_be_framed() {
+        let c = Camera::new(101, 51, PI / 3.0).unwrap();
+        assert!(matches!(
+            c.frame(&World::new(), &Vector::new(0.0, 0.0, 1.0)),
+            Err(RayTracerError::InvalidCamera(_))
+        ));
+    }
+    #[test]
+    fn test_a_tile_size_of_zero_is_an_error() {
+        let c = Camera::new(101, 51, PI / 3.0).unwrap();
+        assert!(matches!(
+            c.set_tile_size(0),
+            Err(RayTracerError::InvalidCamera(_))
+        ));
+        let settings = RenderSettings {
+            tile_size: 0,
+            ..RenderSettings::default()
+        };
+        assert!(c.with_settings(&settings).is_err());
+    }
+    #[test]
+    fn test_rendering_a_tile_only_dirties_that_tile() {
+        let c = Camera::new(8, 8, PI / 2.0).unwrap();
+        let mut image = TiledCanvas::new(8, 8, 4);
+        let tile = image.tile_rect(3);
+        c.render_tile(&World::new(), &tile, &mut image);
+        assert_eq!(image.take_dirty_rects(), vec![tile]);
+    }
+    #[test]
+    fn test_a_cancelled_render_starts_no_tiles() {
+        let c = Camera::new(40, 40, PI / 2.0).unwrap();
+        let image = Mutex::new(TiledCanvas::new(40, 40, DEFAULT_TILE_SIZE));
+        assert!(!c.render_into(&World::new(), &image, &AtomicBool::new(true)));
+        assert!(image.lock().unwrap().take_dirty_rects().is_empty());
+        assert!(c.render_into(&World::new(), &image, &AtomicBool::new(false)));
+        assert_eq!(image.lock().unwrap().take_dirty_rects().len(), 9);
+    }
+    #[test]
+    fn test_the_beauty_pass_matches_a_plain_render() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, 10.0), WHITE));
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(11, 11, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap()
+            .set_samples(2);
+        let image = camera.render(&world);
+        let passes = camera.render_aovs(&world);
+        assert_approx_eq!(passes.beauty.pixel_at(5, 5), image.pixel_at(5, 5));
+        assert_approx_eq!(passes.beauty.pixel_at(3, 4), image.pixel_at(3, 4));
+        assert!(passes.diffuse.pixel_at(5, 5).red > 0.0);
+        assert_approx_eq!(passes.reflection.pixel_at(5, 5), BLACK);
+    }
+    #[test]
+    fn test_jittered_renders_are_identical_for_any_thread_count() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        world.add_shape(
+            Shape::new(Sphere::new()).set_material(
+                Material::new()
+                    .set_pattern(CheckersPattern::new(WHITE, BLACK), scaling(0.2, 0.2, 0.2))
+                    .unwrap(),
+            ),
+        );
+        let camera = Camera::new(40, 30, PI / 3.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -3.0))
+            .unwrap()
+            .set_samples(4);
+        let single = camera.set_threads(1).render(&world);
+        let multi = camera.set_threads(4).render(&world);
+        for y in 0..30 {
+            for x in 0..40 {
+                let (a, b) = (single.pixel_at(x, y), multi.pixel_at(x, y));
+                assert_eq!((a.red, a.green, a.blue), (b.red, b.green, b.blue));
+            }
+        }
+    }
+
+    #[test]
+    fn test_the_tile_order_does_not_change_the_image() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(40, 30, PI / 3.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -3.0))
+            .unwrap();
+        let scanline = camera.render(&world);
+        for order in [TileOrder::Spiral, TileOrder::Hilbert] {
+            let image = camera.set_tile_order(order).render(&world);
+            for (x, y) in [(0, 0), (20, 15), (17, 3), (39, 29)] {
+                let color = |image: &Canvas| <[Scalar; 3]>::from(image.pixel_at(x, y));
+                assert_eq!(color(&image), color(&scanline));
+            }
+        }
+    }
+    #[test]
+    fn test_the_camera_picks_the_fresnel_term() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        let glass = Material::new()
+            .set_reflective(1.0)
+            .unwrap()
+            .set_transparency(1.0)
+            .unwrap()
+            .set_refractive_index(2.5)
+            .unwrap();
+        world.add_shape(Shape::new(Sphere::new()).set_material(glass));
+        let camera = Camera::new(11, 11, PI / 3.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -3.0))
+            .unwrap();
+        let schlick = camera.render(&world);
+        let exact = camera.set_fresnel(Fresnel::Exact).render(&world);
+        let color = |image: &Canvas, x: usize| <[Scalar; 3]>::from(image.pixel_at(x, 5));
+        assert!((0..11).any(|x| color(&schlick, x) != color(&exact, x)));
+    }
+
+    #[test]
+    fn test_rendering_with_settings() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(40, 30, PI / 3.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -3.0))
+            .unwrap();
+        let settings = RenderSettings {
+            samples: 2,
+            tile_size: 7,
+            max_depth: Some(1),
+            pixel_format: PixelFormat::Half,
+            aovs: true,
+            fresnel: Fresnel::Exact,
+            ..RenderSettings::default()
+        };
+        let configured = camera.with_settings(&settings).unwrap();
+        assert_eq!(configured.get_tile_size(), 7);
+        assert_eq!(configured.get_settings().max_depth, Some(1));
+        assert_eq!(configured.get_settings().fresnel, Fresnel::Exact);
+        let output = camera.render_with(&settings, &world).unwrap();
+        assert_eq!(output.image.format(), PixelFormat::Half);
+        let plain = camera.set_samples(2).render(&world);
+        let half = plain.to_format(PixelFormat::Half);
+        let aovs = output.aovs.unwrap();
+        for (x, y) in [(0, 0), (20, 15), (17, 3), (39, 29)] {
+            let color = |image: &Canvas| <[Scalar; 3]>::from(image.pixel_at(x, y));
+            assert_eq!(color(&output.image), color(&half));
+            assert_eq!(color(&aovs.beauty), color(&plain));
+        }
+    }
+    #[test]
+    fn test_snapshots_of_a_render_in_progress() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(40, 30, PI / 3.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -1.5))
+            .unwrap()
+            .set_tile_size(8)
+            .unwrap()
+            .set_threads(1);
+        let mut snapshots = vec![];
+        let image = camera
+            .render_with_snapshots(&world, SnapshotInterval::Tiles(3), |canvas| {
+                snapshots.push(canvas.clone());
+                Ok(())
+            })
+            .unwrap();
+        // 20 tiles, the last of them finishing the image
+        assert_eq!(snapshots.len(), 6);
+        let color = |image: &Canvas, x, y| <[Scalar; 3]>::from(image.pixel_at(x, y));
+        assert_eq!(color(&image, 39, 29), color(&camera.render(&world), 39, 29));
+        assert!(image.pixel_at(20, 2).red > 0.0 && image.pixel_at(39, 29).red > 0.0);
+        assert_eq!(color(&snapshots[0], 20, 2), color(&image, 20, 2));
+        assert_eq!(color(&snapshots[0], 39, 29), [0.0; 3]);
+        assert_eq!(color(&snapshots[5], 39, 29), [0.0; 3]);
+        assert!(camera
+            .render_with_snapshots(&world, SnapshotInterval::Tiles(1), |_| Err(
+                RayTracerError::InvalidImage("disk full".to_string())
+            ))
+            .is_err());
+    }
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_rendering_into_a_mapped_canvas() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(40, 30, PI / 3.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -3.0))
+            .unwrap()
+            .set_alpha(true);
+        let path = std::env::temp_dir().join("ray-tracer-test-render-mapped");
+        let mapped = camera.render_mapped(&world, &path).unwrap();
+        let image = camera.render(&world);
+        for (x, y) in [(0, 0), (20, 15), (17, 3), (39, 29)] {
+            assert_approx_eq!(mapped.pixel_at(x, y), image.pixel_at(x, y));
+            assert_approx_eq!(mapped.alpha_at(x, y), image.alpha_at(x, y));
+        }
+        drop(mapped);
+        fs::remove_file(&path).unwrap();
+    }
+    #[cfg(feature = "spectral")]
+    #[test]
+    fn test_spectral_renders_split_light_by_dispersion() {
+        let glass = |dispersion| {
+            let mut world = World::new();
+            world.add_light(PointLight::new(Point::new(-10.0, 10.0, 10.0), WHITE));
+            world.add_shape(
+                Shape::new(Plane::new())
+                    .set_transform(
+                        translation(0.0, 0.0, -3.0) * &crate::transform::rotation_x(PI / 2.0),
+                    )
+                    .unwrap()
+                    .set_material(
+                        Material::new()
+                            .set_pattern(CheckersPattern::new(WHITE, BLACK), scaling(0.2, 0.2, 0.2))
+                            .unwrap(),
+                    ),
+            );
+            world.add_shape(
+                Shape::new(Sphere::new()).set_material(
+                    Material::new()
+                        .set_transparency(1.0)
+                        .unwrap()
+                        .set_refractive_index(1.5)
+                        .unwrap()
+                        .set_dispersion(dispersion)
+                        .unwrap(),
+                ),
+            );
+            world
+        };
+        let camera = Camera::new(21, 21, PI / 3.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap();
+        let plain = glass(0.0);
+        let image = camera.render(&plain);
+        let spectral = camera.render_spectral(&plain);
+        for (x, y) in [(10, 10), (4, 12), (0, 0)] {
+            assert_approx_eq!(spectral.pixel_at(x, y), image.pixel_at(x, y));
+        }
+        let split = camera.render_spectral(&glass(0.05));
+        let fringes = (0..21)
+            .flat_map(|y| (0..21).map(move |x| (x, y)))
+            .filter(|(x, y)| !split.pixel_at(*x, *y).approx_eq(&spectral.pixel_at(*x, *y)))
+            .count();
+        assert!(fringes > 0);
+    }
+    #[test]
+    fn test_exposure_scales_the_shaded_image() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(11, 11, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap();
+        let center = |camera: Camera| camera.render(&world).pixel_at(5, 5);
+        let c = center(camera);
+        assert_approx_eq!(center(camera.set_exposure(1.0)), c * 2.0);
+        assert_approx_eq!(center(camera.set_exposure(-2.0)), c * 0.25);
+        // debug views are left alone
+        let normals = camera.set_render_mode(RenderMode::Normals);
+        assert_approx_eq!(center(normals.set_exposure(3.0)), center(normals));
+    }
+
+    #[test]
+    fn test_exposure_from_camera_settings() {
+        let camera = Camera::new(10, 10, PI / 2.0).unwrap();
+        let sunny_16 = camera
+            .set_exposure_settings(100.0, 1.0 / 128.0, 16.0)
+            .unwrap();
+        assert_approx_eq!(sunny_16.get_exposure(), -15.0);
+        // a stop faster film makes up for a stop less light
+        let faster = camera
+            .set_exposure_settings(200.0, 1.0 / 128.0, 22.627417)
+            .unwrap();
+        assert_approx_eq!(faster.get_exposure(), -15.0);
+        assert!(camera.set_exposure_settings(100.0, 0.0, 16.0).is_err());
+    }
+
+    #[test]
+    fn test_auto_exposure_meters_the_scene() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(200, 100, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap()
+            .set_exposure(5.0);
+        let metered = camera.auto_expose(&world, 0.5).unwrap();
+        assert_eq!(metered.get_hsize(), 200);
+        let median = metered
+            .set_size(64, 32)
+            .unwrap()
+            .render(&world)
+            .luminance_histogram()
+            .percentile(0.5)
+            .unwrap();
+        assert!((median.log2() - (0.18 as Scalar).log2()).abs() <= 0.25);
+    }
+
+    #[test]
+    fn test_misses_are_transparent_when_rendering_alpha() {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(11, 11, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap();
+        assert!(!camera.render(&world).has_alpha());
+        let image = camera.set_alpha(true).render(&world);
+        assert!(image.has_alpha());
+        assert_approx_eq!(image.alpha_at(5, 5), 1.0);
+        assert_approx_eq!(image.alpha_at(0, 0), 0.0);
+    }
+
+    #[test]
+    fn test_debug_render_modes() {
+        let mut world = World::new();
+        world.add_shape(Shape::new(Sphere::new()));
+        world.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(translation(3.0, 0.0, 0.0))
+                .unwrap(),
+        );
+        let camera = Camera::new(11, 11, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap();
+        let center = camera.ray_for_pixel(5, 5).unwrap();
+        let comps = world.shade_ray(&center).unwrap();
+
+        let image = camera.set_render_mode(RenderMode::ObjectId).render(&world);
+        assert_approx_eq!(image.pixel_at(5, 5), object_color(0));
+        assert_approx_eq!(image.pixel_at(0, 0), BLACK);
+        assert!(!object_color(1).approx_eq(&object_color(0)));
+
+        let image = camera.set_render_mode(RenderMode::Normals).render(&world);
+        let n = comps.normalv;
+        let expected = Color::new((n.x + 1.0) / 2.0, (n.y + 1.0) / 2.0, (n.z + 1.0) / 2.0);
+        assert_approx_eq!(image.pixel_at(5, 5), expected);
+
+        let image = camera
+            .set_render_mode(RenderMode::Depth { max: 8.0 })
+            .render(&world);
+        assert_approx_eq!(image.pixel_at(5, 5), Color::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn test_picking_objects_by_pixel() {
+        let mut world = World::new();
+        world.add_shape(Shape::new(Sphere::new()));
+        let right = world
+            .add_shape(
+                Shape::new(Sphere::new())
+                    .set_transform(translation(-3.0, 0.0, 0.0))
+                    .unwrap(),
+            )
+            .id();
+        let camera = Camera::new(11, 11, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap();
+        assert_eq!(camera.pick(&world, 5, 5), Some(0));
+        assert_eq!(camera.pick(&world, 8, 5), Some(right));
+        assert_eq!(camera.pick(&world, 0, 0), None);
+    }
+
+    #[test]
+    fn test_wireframes_show_mesh_edges() {
+        let mut world = World::new();
+        let mesh = Mesh::new(
+            vec![
+                Point::new(0.0, -2.0, 0.0),
+                Point::new(0.0, 2.0, 0.0),
+                Point::new(2.0, 0.0, 0.0),
+            ],
+            vec![[0, 1, 2]],
+        )
+        .unwrap();
+        world.add_shape(Shape::new(mesh));
+        let camera = Camera::new(11, 11, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap()
+            .set_render_mode(RenderMode::Wireframe);
+        let image = camera.render(&world);
+        assert_approx_eq!(image.pixel_at(5, 5), WHITE);
+        let face = [image.pixel_at(3, 5), image.pixel_at(7, 5)]
+            .iter()
+            .any(|c| c.red > 0.0 && c.red < 0.5);
+        assert!(face);
+    }
+
+    #[test]
+    fn test_resizing_a_camera_keeps_its_settings() {
+        let c = Camera::new(200, 125, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, -2.0, 5.0))
+            .unwrap()
+            .set_samples(4)
+            .set_size(100, 250)
+            .unwrap();
+        assert_eq!((c.get_hsize(), c.get_vsize()), (100, 250));
+        assert_approx_eq!(c.pixel_size, 0.008);
+        assert_approx_eq!(c.transform, translation(0.0, -2.0, 5.0));
+        assert_eq!(c.samples, 4);
+        assert!(c.set_size(0, 10).is_err());
+    }
+
+    #[cfg(feature = "fs")]
+    fn checkpoint_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ray-tracer-{}-{}", std::process::id(), name))
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_resuming_a_render_from_a_checkpoint() {
+        use crate::color::MAGENTA;
+
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(40, 30, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -3.0))
+            .unwrap()
+            .set_samples(2);
+        let path = checkpoint_path("resume");
+        // pretend the first tile was finished before an interruption
+        Checkpoint {
+            width: 40,
+            height: 30,
+            tile_size: DEFAULT_TILE_SIZE,
+            samples: 2,
+            frame: 0,
+            fingerprint: camera.fingerprint(),
+            tiles: vec![(0, vec![(MAGENTA, 1.0); 256])],
+        }
+        .save(&path)
+        .unwrap();
+
+        let resumed = camera
+            .render_resumable(&world, &path, Duration::ZERO)
+            .unwrap();
+        let full = camera.render(&world);
+        assert_approx_eq!(resumed.pixel_at(0, 0), MAGENTA);
+        assert_approx_eq!(resumed.pixel_at(15, 15), MAGENTA);
+        for (x, y) in [(16, 0), (20, 15), (0, 16), (39, 29)] {
+            // exactly the same, not just approximately
+            let color = |image: &Canvas| <[Scalar; 3]>::from(image.pixel_at(x, y));
+            assert_eq!(color(&resumed), color(&full));
+        }
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_a_checkpoint_of_another_render_is_refused() {
+        let world = World::new();
+        let camera = Camera::new(40, 30, PI / 2.0).unwrap();
+        let path = checkpoint_path("mismatch");
+        let saved = |samples: usize, fingerprint: u64| {
+            Checkpoint {
+                width: 40,
+                height: 30,
+                tile_size: DEFAULT_TILE_SIZE,
+                samples,
+                frame: 0,
+                fingerprint,
+                tiles: vec![],
+            }
+            .save(&path)
+            .unwrap();
+        };
+        saved(8, camera.fingerprint());
+        let result = camera.render_resumable(&world, &path, Duration::ZERO);
+        assert!(matches!(result, Err(RayTracerError::InvalidCamera(_))));
+        // nor is one of a render that only differed in its exposure
+        saved(1, camera.set_exposure(1.0).fingerprint());
+        let result = camera.render_resumable(&world, &path, Duration::ZERO);
+        assert!(matches!(result, Err(RayTracerError::InvalidCamera(_))));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_timing_a_render() {
+        let mut world = World::new();
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(11, 11, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap()
+            .set_threads(2);
+        let (image, times) = camera.render_timed(&world);
+        assert_approx_eq!(image.pixel_at(5, 5), camera.render(&world).pixel_at(5, 5));
+        assert!(times.time_at(5, 5) > Duration::ZERO);
+        assert_eq!(times.heatmap().width(), 11);
+    }
+
+    #[test]
+    fn test_rendering_the_depth_buffer() {
+        let mut world = World::new();
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(11, 11, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap();
+        let depth = camera.render_depth(&world);
+        assert_approx_eq!(depth.depth_at(5, 5), 4.0);
+        assert_eq!(depth.depth_at(0, 0), Scalar::INFINITY);
+        // a scene measured in centimeters, modeled at half size
+        world.set_units(Units::Centimeters);
+        world.set_scale(2.0);
+        let depth = camera.render_depth(&world);
+        assert_approx_eq!(depth.depth_at(5, 5), 0.08);
+    }
+
+    #[test]
+    fn test_rendering_the_denoiser_guides() {
+        let mut world = World::new();
+        world.add_shape(
+            Shape::new(Sphere::new()).set_material(
+                Material::new()
+                    .set_color(Color::new(0.8, 0.2, 0.1))
+                    .unwrap(),
+            ),
+        );
+        let camera = Camera::new(11, 11, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap();
+        let guides = camera.render_guides(&world);
+        assert_approx_eq!(guides.albedo.pixel_at(5, 5), Color::new(0.8, 0.2, 0.1));
+        assert_approx_eq!(guides.normal.pixel_at(5, 5), Color::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(guides.depth.depth_at(5, 5), 4.0);
+        assert_approx_eq!(guides.albedo.pixel_at(0, 0), BLACK);
+        assert_eq!(guides.depth.depth_at(0, 0), Scalar::INFINITY);
+    }
+
+    #[test]
+    fn test_rendering_an_id_matte() {
+        let mut world = World::new();
+        world.add_shape(Shape::new(Sphere::new()).set_name("ball"));
+        world.add_shape(
+            Shape::new(Plane::new())
+                .set_transform(translation(0.0, -1.0, 0.0))
+                .unwrap(),
+        );
+        let camera = Camera::new(11, 11, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, 0.0, -5.0))
+            .unwrap()
+            .set_samples(4);
+        let matte = camera.render_id_matte(&world);
+        assert_eq!(matte.coverage_at(5, 5), [(name_hash("ball"), 1.0)]);
+        assert_eq!(matte.coverage_at(5, 10)[0].0, name_hash("shape 1"));
+        assert!(matte.coverage_at(5, 0).is_empty());
+        let mask = matte.mask(&["ball"]);
+        // the ball's outline is partly covered
+        assert!((0..11).any(|x| {
+            let c = mask.pixel_at(x, 5).red;
+            c > 0.0 && c < 1.0
+        }));
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_camera_settings_round_trip_through_json() {
+        let c = Camera::new(200, 125, PI / 2.0)
+            .unwrap()
+            .set_transform(translation(0.0, -2.0, 5.0))
+            .unwrap()
+            .set_samples(4);
+        let json = serde_json::to_string(&c).unwrap();
+        let c2: Camera = serde_json::from_str(&json).unwrap();
+        assert_approx_eq!(c2.pixel_size, 0.01);
+        assert_approx_eq!(c2.transform, c.transform);
+        assert_eq!(c2.samples, 4);
+    }
+
+    #[test]
+    fn test_invalid_camera_settings_are_errors() {
+        assert!(matches!(
+            Camera::new(0, 100, PI / 2.0),
+            Err(RayTracerError::InvalidCamera(_))
+        ));
+        assert!(matches!(
+            Camera::new(100, 100, PI),
+            Err(RayTracerError::InvalidCamera(_))
+        ));
+        assert!(matches!(
+            Camera::new(100, 100, PI / 2.0)
+                .unwrap()
+                .set_transform(scaling(0.0, 0.0, 0.0)),
+            Err(RayTracerError::NonInvertibleTransform)
+        ));
     }
 }
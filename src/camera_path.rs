@@ -0,0 +1,212 @@
+use crate::camera::Camera;
+use crate::error::{RayTracerError, Result};
+use crate::point::Point;
+use crate::scalar::Scalar;
+use crate::transform::view_transform;
+use crate::vector::Vector;
+
+// One pose of a camera move as animation tools export it: where the camera
+// is at `time` (in seconds), the point it looks at, and its field of view in
+// degrees. The camera is kept upright, with +y up.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraKeyframe {
+    pub time: Scalar,
+    pub position: Point,
+    pub look_at: Point,
+    pub fov: Scalar,
+}
+
+// A camera move through keyframes, linearly interpolated between them and
+// held at the first and last beyond its ends. Read from CSV with
+// `parse_csv`, or from JSON as a list of `CameraKeyframe`s handed to `new`.
+pub struct CameraPath {
+    keyframes: Vec<CameraKeyframe>,
+}
+
+const CSV_COLUMNS: usize = 8;
+
+impl CameraPath {
+    // The keyframes may come in any order.
+    pub fn new(mut keyframes: Vec<CameraKeyframe>) -> Result<Self> {
+        if keyframes.is_empty() {
+            return Err(RayTracerError::InvalidCamera(
+                "a camera path needs at least one keyframe".to_string(),
+            ));
+        }
+        if let Some(k) = keyframes.iter().find(|k| !k.time.is_finite()) {
+            return Err(RayTracerError::InvalidCamera(format!(
+                "keyframe time {} is not a number",
+                k.time
+            )));
+        }
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        Ok(Self { keyframes })
+    }
+    // One keyframe per line as
+    //
+    //     time,x,y,z,look_x,look_y,look_z,fov
+    //
+    // A first line that isn't numbers is taken for a header, and lines
+    // starting with '#' are comments.
+    pub fn parse_csv(text: &str) -> Result<Self> {
+        let mut keyframes = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| RayTracerError::Parse {
+                line: i + 1,
+                message,
+            };
+            let values = line
+                .split(',')
+                .map(|v| v.trim().parse::<Scalar>())
+                .collect::<std::result::Result<Vec<_>, _>>();
+            let values = match values {
+                Ok(values) => values,
+                Err(_) if keyframes.is_empty() && i == first_line(text) => continue,
+                Err(_) => return Err(error("expected numbers".to_string())),
+            };
+            if values.len() != CSV_COLUMNS {
+                return Err(error(format!(
+                    "expected {} columns, got {}",
+                    CSV_COLUMNS,
+                    values.len()
+                )));
+            }
+            keyframes.push(CameraKeyframe {
+                time: values[0],
+                position: Point::new(values[1], values[2], values[3]),
+                look_at: Point::new(values[4], values[5], values[6]),
+                fov: values[7],
+            });
+        }
+        Self::new(keyframes)
+    }
+    pub fn keyframes(&self) -> &[CameraKeyframe] {
+        &self.keyframes
+    }
+    pub fn start(&self) -> Scalar {
+        self.keyframes[0].time
+    }
+    pub fn end(&self) -> Scalar {
+        self.keyframes[self.keyframes.len() - 1].time
+    }
+    pub fn keyframe_at(&self, time: Scalar) -> CameraKeyframe {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return CameraKeyframe {
+                time,
+                ..self.keyframes[0]
+            };
+        }
+        let a = &self.keyframes[next - 1];
+        let Some(b) = self.keyframes.get(next) else {
+            return CameraKeyframe { time, ..*a };
+        };
+        let w = (time - a.time) / (b.time - a.time);
+        let lerp = |p: &Point, q: &Point| *p + &(&(*q - p) * w);
+        CameraKeyframe {
+            time,
+            position: lerp(&a.position, &b.position),
+            look_at: lerp(&a.look_at, &b.look_at),
+            fov: a.fov + (b.fov - a.fov) * w,
+        }
+    }
+    // `camera` posed as the path has it at `time`, with all its other
+    // settings kept.
+    pub fn camera_at(&self, camera: &Camera, time: Scalar) -> Result<Camera> {
+        let k = self.keyframe_at(time);
+        camera
+            .set_field_of_view(k.fov.to_radians())?
+            .set_transform(view_transform(
+                &k.position,
+                &k.look_at,
+                &Vector::new(0.0, 1.0, 0.0),
+            )?)
+    }
+}
+
+// The index of the first line with something other than a comment on it.
+fn first_line(text: &str) -> usize {
+    text.lines()
+        .position(|line| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::scalar::consts::PI;
+
+    const CSV: &str = "\
+# exported dolly move
+time,x,y,z,look_x,look_y,look_z,fov
+0, 0,1,-10, 0,1,0, 60
+2, 10,1,-10, 0,1,0, 40
+";
+
+    #[test]
+    fn test_reading_a_camera_path_from_csv() {
+        let path = CameraPath::parse_csv(CSV).unwrap();
+        assert_eq!(path.keyframes().len(), 2);
+        assert_eq!(path.start(), 0.0);
+        assert_eq!(path.end(), 2.0);
+        let k = path.keyframe_at(0.5);
+        assert_approx_eq!(k.position, Point::new(2.5, 1.0, -10.0));
+        assert_approx_eq!(k.fov, 55.0);
+        // held beyond the ends
+        assert_approx_eq!(path.keyframe_at(5.0).position, Point::new(10.0, 1.0, -10.0));
+        assert_approx_eq!(path.keyframe_at(-1.0).fov, 60.0);
+    }
+
+    #[test]
+    fn test_posing_a_camera_along_a_path() {
+        let path = CameraPath::parse_csv(CSV).unwrap();
+        let camera = Camera::new(100, 50, 1.0).unwrap().set_samples(4);
+        let posed = path.camera_at(&camera, 2.0).unwrap();
+        let expected = view_transform(
+            &Point::new(10.0, 1.0, -10.0),
+            &Point::new(0.0, 1.0, 0.0),
+            &Vector::new(0.0, 1.0, 0.0),
+        )
+        .unwrap();
+        assert_approx_eq!(posed.get_transform(), expected);
+        assert_approx_eq!(posed.get_field_of_view(), 2.0 * PI / 9.0);
+        assert_eq!(posed.get_hsize(), 100);
+    }
+
+    #[test]
+    fn test_camera_path_errors_report_their_line() {
+        assert!(matches!(
+            CameraPath::parse_csv("0,0,0,0,0,0,1,60\n1,0,0,0,0,0,1"),
+            Err(RayTracerError::Parse { line: 2, .. })
+        ));
+        assert!(matches!(
+            CameraPath::parse_csv("0,0,0,0,0,0,1,60\nnext,0,0,0,0,0,1,60"),
+            Err(RayTracerError::Parse { line: 2, .. })
+        ));
+        assert!(CameraPath::parse_csv("# nothing\n").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_reading_keyframes_from_json() {
+        let json = r#"[
+            {"time": 1, "position": {"x": 0, "y": 0, "z": -5},
+             "look_at": {"x": 0, "y": 0, "z": 0}, "fov": 90},
+            {"time": 0, "position": {"x": 0, "y": 5, "z": -5},
+             "look_at": {"x": 0, "y": 0, "z": 0}, "fov": 90}
+        ]"#;
+        let keyframes: Vec<CameraKeyframe> = serde_json::from_str(json).unwrap();
+        let path = CameraPath::new(keyframes).unwrap();
+        assert_approx_eq!(path.keyframe_at(0.0).position, Point::new(0.0, 5.0, -5.0));
+    }
+}
@@ -1,45 +1,342 @@
-use crate::color::Color;
+use crate::color::{Color, ColorSpace};
+use crate::scalar::Scalar;
+use crate::{exr, font, png};
 
+// How a canvas stores its colors, trading memory for fidelity. Colors are
+// converted as they are written: F32 keeps about seven significant digits
+// and Half about three, up to 65504, while U8 clamps to 0..1 in 256 steps
+// as an 8-bit PNG would. The default matches `Scalar`. Alpha is always kept
+// at full precision.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PixelFormat {
+    #[cfg_attr(not(feature = "f32"), default)]
+    F64,
+    #[cfg_attr(feature = "f32", default)]
+    F32,
+    Half,
+    U8,
+}
+
+#[derive(Clone)]
+enum Pixels {
+    F64(Vec<[f64; 3]>),
+    F32(Vec<[f32; 3]>),
+    Half(Vec<[u16; 3]>),
+    U8(Vec<[u8; 3]>),
+}
+
+impl Pixels {
+    fn new(size: usize, format: PixelFormat) -> Self {
+        match format {
+            PixelFormat::F64 => Pixels::F64(vec![[0.0; 3]; size]),
+            PixelFormat::F32 => Pixels::F32(vec![[0.0; 3]; size]),
+            PixelFormat::Half => Pixels::Half(vec![[0; 3]; size]),
+            PixelFormat::U8 => Pixels::U8(vec![[0; 3]; size]),
+        }
+    }
+    fn format(&self) -> PixelFormat {
+        match self {
+            Pixels::F64(_) => PixelFormat::F64,
+            Pixels::F32(_) => PixelFormat::F32,
+            Pixels::Half(_) => PixelFormat::Half,
+            Pixels::U8(_) => PixelFormat::U8,
+        }
+    }
+    #[allow(clippy::unnecessary_cast)]
+    fn get(&self, i: usize) -> Color {
+        let [red, green, blue] = match self {
+            Pixels::F64(p) => p[i].map(|v| v as Scalar),
+            Pixels::F32(p) => p[i].map(|v| v as Scalar),
+            Pixels::Half(p) => p[i].map(|v| from_half(v) as Scalar),
+            Pixels::U8(p) => p[i].map(|v| v as Scalar / MAX_COL as Scalar),
+        };
+        Color::new(red, green, blue)
+    }
+    #[allow(clippy::unnecessary_cast)]
+    fn set(&mut self, i: usize, c: Color) {
+        let rgb = [c.red, c.green, c.blue];
+        match self {
+            Pixels::F64(p) => p[i] = rgb.map(|v| v as f64),
+            Pixels::F32(p) => p[i] = rgb.map(|v| v as f32),
+            Pixels::Half(p) => p[i] = rgb.map(|v| to_half(v as f32)),
+            Pixels::U8(p) => p[i] = rgb.map(png::to_byte),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Canvas {
     width: usize,
     height: usize,
-    pixels: Vec<Color>,
+    pixels: Pixels,
+    // only allocated once some alpha has been written; opaque until then
+    alpha: Option<Vec<Scalar>>,
+    // how colors are written to PNG, PPM and RGBA8; EXRs are always linear
+    color_space: ColorSpace,
 }
 
 const MAX_COL: usize = 255;
 
+// The range of a luminance histogram, in stops (powers of two) either side
+// of 1, and its resolution.
+const HISTOGRAM_MIN_STOP: Scalar = -20.0;
+const HISTOGRAM_STOPS: usize = 40;
+const BINS_PER_STOP: usize = 4;
+// the luminance a typical scene averages to, which auto exposure aims for
+const MID_GRAY: Scalar = 0.18;
+
+// A canvas's pixels counted by luminance on a logarithmic scale, a quarter
+// stop per bin. Black pixels, usually rays that hit nothing, are counted
+// apart; luminances beyond the range fall into the first or last bin.
+pub struct LuminanceHistogram {
+    counts: Vec<usize>,
+    black: usize,
+}
+
+impl LuminanceHistogram {
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+    pub fn black(&self) -> usize {
+        self.black
+    }
+    // The luminance at the center of bin `i`.
+    pub fn bin_luminance(i: usize) -> Scalar {
+        (HISTOGRAM_MIN_STOP + (i as Scalar + 0.5) / BINS_PER_STOP as Scalar).exp2()
+    }
+    // The luminance that `fraction` of the pixels that aren't black are
+    // darker than, or None if all are black.
+    pub fn percentile(&self, fraction: Scalar) -> Option<Scalar> {
+        let total: usize = self.counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let rank = (fraction.clamp(0.0, 1.0) * total as Scalar).ceil().max(1.0) as usize;
+        let mut seen = 0;
+        let bin = self.counts.iter().position(|count| {
+            seen += count;
+            seen >= rank
+        })?;
+        Some(Self::bin_luminance(bin))
+    }
+    // The exposure, in stops for `Camera::set_exposure`, that brings the
+    // luminance at `fraction` to mid-gray; e.g. 0.5 exposes for the
+    // median. Zero for a black image.
+    pub fn auto_exposure(&self, fraction: Scalar) -> Scalar {
+        self.percentile(fraction)
+            .map_or(0.0, |luminance| (MID_GRAY / luminance).log2())
+    }
+}
+
 impl Canvas {
     pub fn new(width: usize, height: usize) -> Self {
+        Self::with_format(width, height, PixelFormat::default())
+    }
+    pub fn with_format(width: usize, height: usize, format: PixelFormat) -> Self {
         Canvas {
             width,
             height,
-            pixels: vec![Color::new(0.0, 0.0, 0.0); width * height],
+            pixels: Pixels::new(width * height, format),
+            alpha: None,
+            color_space: ColorSpace::Linear,
+        }
+    }
+    // The pixels stay linear; the space only applies to 8-bit output. The
+    // book's canvases are linear, writing 0.5 as 128.
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.color_space = color_space;
+    }
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+    pub fn format(&self) -> PixelFormat {
+        self.pixels.format()
+    }
+    // A copy with its colors stored as `format`.
+    pub fn to_format(&self, format: PixelFormat) -> Self {
+        let mut canvas = Self {
+            pixels: Pixels::new(self.width * self.height, format),
+            ..self.clone()
+        };
+        for i in 0..self.width * self.height {
+            canvas.pixels.set(i, self.pixels.get(i));
         }
+        canvas
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
     }
     pub fn pixel_at(&self, x: usize, y: usize) -> Color {
-        self.pixels[y * self.width + x]
+        self.pixels.get(y * self.width + x)
     }
     pub fn write_pixel(&mut self, x: usize, y: usize, c: Color) {
-        self.pixels[y * self.width + x] = c;
+        self.pixels.set(y * self.width + x, c);
+    }
+    pub fn has_alpha(&self) -> bool {
+        self.alpha.is_some()
+    }
+    pub fn alpha_at(&self, x: usize, y: usize) -> Scalar {
+        self.alpha
+            .as_ref()
+            .map_or(1.0, |alpha| alpha[y * self.width + x])
+    }
+    pub fn write_alpha(&mut self, x: usize, y: usize, a: Scalar) {
+        let size = self.width * self.height;
+        self.alpha.get_or_insert_with(|| vec![1.0; size])[y * self.width + x] = a;
+    }
+    // Stamps `text` in the built-in 8x8 font with its top left corner at
+    // (x, y), each font pixel `scale` canvas pixels wide. Newlines start a
+    // new line below, and whatever falls off the canvas is clipped. Text
+    // is opaque on a canvas with alpha.
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, color: Color, scale: usize) {
+        let size = font::GLYPH_SIZE * scale;
+        for (row, line) in text.lines().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let glyph = font::glyph(c);
+                for gy in 0..size {
+                    for gx in 0..size {
+                        if glyph[gy / scale] >> (gx / scale) & 1 == 1 {
+                            let (px, py) = (x + column * size + gx, y + row * size + gy);
+                            self.plot(px as isize, py as isize, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    // The drawing methods below take signed coordinates, so shapes may
+    // reach past any edge of the canvas, e.g. around a point projected
+    // just off screen, and are clipped to it. Like text, they are opaque
+    // on a canvas with alpha.
+    pub fn draw_line(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: Color) {
+        // Bresenham's algorithm
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+        let (mut x, mut y, mut error) = (x0, y0, dx + dy);
+        loop {
+            self.plot(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            if 2 * error >= dy {
+                error += dy;
+                x += sx;
+            }
+            if 2 * error <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+    }
+    // The outline of the rectangle with corners (x0, y0) and (x1, y1).
+    pub fn draw_rect(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: Color) {
+        self.draw_line(x0, y0, x1, y0, color);
+        self.draw_line(x1, y0, x1, y1, color);
+        self.draw_line(x1, y1, x0, y1, color);
+        self.draw_line(x0, y1, x0, y0, color);
+    }
+    pub fn fill_rect(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: Color) {
+        for y in y0.min(y1)..=y0.max(y1) {
+            self.draw_line(x0, y, x1, y, color);
+        }
+    }
+    pub fn draw_circle(&mut self, cx: isize, cy: isize, radius: isize, color: Color) {
+        // the midpoint algorithm, drawing an octant and its reflections
+        let (mut x, mut y, mut error) = (radius, 0, 1 - radius);
+        while x >= y {
+            for (dx, dy) in [(x, y), (y, x)] {
+                self.plot(cx + dx, cy + dy, color);
+                self.plot(cx - dx, cy + dy, color);
+                self.plot(cx + dx, cy - dy, color);
+                self.plot(cx - dx, cy - dy, color);
+            }
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+    }
+    pub fn fill_circle(&mut self, cx: isize, cy: isize, radius: isize, color: Color) {
+        for dy in -radius..=radius {
+            let half = ((radius * radius - dy * dy) as Scalar).sqrt().round() as isize;
+            self.draw_line(cx - half, cy + dy, cx + half, cy + dy, color);
+        }
+    }
+    fn plot(&mut self, x: isize, y: isize, color: Color) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+        let (x, y) = (x as usize, y as usize);
+        self.write_pixel(x, y, color);
+        if self.has_alpha() {
+            self.write_alpha(x, y, 1.0);
+        }
+    }
+    pub fn luminance_histogram(&self) -> LuminanceHistogram {
+        let bins = HISTOGRAM_STOPS * BINS_PER_STOP;
+        let mut histogram = LuminanceHistogram {
+            counts: vec![0; bins],
+            black: 0,
+        };
+        for i in 0..self.width * self.height {
+            let luminance = self.pixels.get(i).luminance();
+            if luminance <= 0.0 || luminance.is_nan() {
+                histogram.black += 1;
+                continue;
+            }
+            let bin = (luminance.log2() - HISTOGRAM_MIN_STOP) * BINS_PER_STOP as Scalar;
+            histogram.counts[(bin.max(0.0) as usize).min(bins - 1)] += 1;
+        }
+        histogram
+    }
+    // RGBA when the canvas has alpha, RGB otherwise.
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode(self)
+    }
+    // 8-bit RGBA rows with straight alpha, the layout of a browser ImageData.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(4 * self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let a = self.alpha_at(x, y);
+                let scale = if a > 0.0 { 1.0 / a } else { 1.0 };
+                let color = self.color_space.from_linear(self.pixel_at(x, y) * scale);
+                rgba.push(png::to_byte(color.red));
+                rgba.push(png::to_byte(color.green));
+                rgba.push(png::to_byte(color.blue));
+                rgba.push(png::to_byte(a));
+            }
+        }
+        rgba
+    }
+    pub fn to_exr(&self) -> Vec<u8> {
+        exr::encode(self)
     }
     pub fn to_ppm(&self) -> String {
         let mut result = format!("P3\n{} {}\n{}\n", self.width, self.height, MAX_COL);
         for y in 0..self.height {
-            let mut parts: Vec<f64> = Vec::with_capacity(3 * self.width);
+            let mut parts: Vec<Scalar> = Vec::with_capacity(3 * self.width);
             for x in 0..self.width {
-                let color = self.pixel_at(x, y);
+                let color = self.color_space.from_linear(self.pixel_at(x, y));
                 parts.push(color.red);
                 parts.push(color.green);
                 parts.push(color.blue);
             }
             let mut line = String::new();
             for p in parts {
-                let mut c = p * (MAX_COL as f64);
+                let mut c = p * (MAX_COL as Scalar);
                 if c < 0.0 {
                     c = 0.0;
                 }
-                if c > MAX_COL as f64 {
-                    c = MAX_COL as f64;
+                if c > MAX_COL as Scalar {
+                    c = MAX_COL as Scalar;
                 }
                 let s = (c.round() as usize).to_string();
                 if line.len() + 1 + s.len() > 70 {
@@ -61,6 +358,56 @@ impl Canvas {
     }
 }
 
+// The nearest half float (IEEE 754 binary16), rounding to even.
+fn to_half(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let e = exponent - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    // too small even for a subnormal half
+    if e < -10 {
+        return sign;
+    }
+    // subnormal halves have the implicit bit shifted into the mantissa
+    let (m, shift, base) = if e <= 0 {
+        (mantissa | 0x80_0000, (14 - e) as u32, 0)
+    } else {
+        (mantissa, 13, (e as u32) << 10)
+    };
+    let half = base | (m >> shift);
+    let rest = m & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    let round_up = rest > halfway || (rest == halfway && half & 1 == 1);
+    // a carry out of the mantissa correctly bumps the exponent
+    sign | (half + round_up as u32) as u16
+}
+
+fn from_half(h: u16) -> f32 {
+    let sign = ((h & 0x8000) as u32) << 16;
+    let exponent = ((h >> 10) & 0x1f) as u32;
+    let mantissa = (h & 0x3ff) as u32;
+    match exponent {
+        0 => {
+            let v = mantissa as f32 * (-24f32).exp2();
+            if sign != 0 {
+                -v
+            } else {
+                v
+            }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -88,6 +435,145 @@ mod tests {
         assert_approx_eq!(c.pixel_at(2, 3), red);
     }
 
+    #[test]
+    fn test_drawing_text() {
+        let mut c = Canvas::new(30, 40);
+        let (black, white) = (Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        c.draw_text(1, 2, "T\n-", white, 2);
+        // the bar of the T, two pixels high
+        assert_approx_eq!(c.pixel_at(1, 2), white);
+        assert_approx_eq!(c.pixel_at(12, 3), white);
+        assert_approx_eq!(c.pixel_at(3, 4), black);
+        // the dash on the line below
+        assert_approx_eq!(c.pixel_at(1, 23), black);
+        assert_approx_eq!(c.pixel_at(1, 24), white);
+        // clipped at the edges
+        c.draw_text(25, 35, "MM", white, 1);
+        assert_approx_eq!(c.pixel_at(26, 35), white);
+    }
+
+    #[test]
+    fn test_drawing_shapes() {
+        let mut c = Canvas::new(10, 10);
+        let (black, red) = (Color::new(0.0, 0.0, 0.0), Color::new(1.0, 0.0, 0.0));
+        c.draw_line(-5, -5, 20, 20, red);
+        for i in 0..10 {
+            assert_approx_eq!(c.pixel_at(i, i), red);
+        }
+        assert_approx_eq!(c.pixel_at(1, 0), black);
+        let mut c = Canvas::new(10, 10);
+        c.draw_rect(2, 2, 6, 5, red);
+        assert_approx_eq!(c.pixel_at(4, 2), red);
+        assert_approx_eq!(c.pixel_at(6, 4), red);
+        assert_approx_eq!(c.pixel_at(4, 4), black);
+        c.fill_rect(2, 2, 6, 5, red);
+        assert_approx_eq!(c.pixel_at(4, 4), red);
+        let mut c = Canvas::new(10, 10);
+        c.draw_circle(5, 5, 3, red);
+        for (x, y) in [(8, 5), (2, 5), (5, 8), (5, 2)] {
+            assert_approx_eq!(c.pixel_at(x, y), red);
+        }
+        assert_approx_eq!(c.pixel_at(5, 5), black);
+        c.fill_circle(5, 5, 3, red);
+        assert_approx_eq!(c.pixel_at(5, 5), red);
+        assert_approx_eq!(c.pixel_at(8, 8), black);
+    }
+
+    #[test]
+    fn test_a_luminance_histogram() {
+        let mut c = Canvas::new(4, 1);
+        c.write_pixel(1, 0, Color::new(0.25, 0.25, 0.25));
+        c.write_pixel(2, 0, Color::new(1.0, 1.0, 1.0));
+        c.write_pixel(3, 0, Color::new(1e9, 1e9, 1e9));
+        let histogram = c.luminance_histogram();
+        assert_eq!(histogram.black(), 1);
+        assert_eq!(histogram.counts().iter().sum::<usize>(), 3);
+        assert_eq!(histogram.counts()[72], 1);
+        assert_eq!(histogram.counts()[80], 1);
+        // too bright for the range
+        assert_eq!(histogram.counts()[159], 1);
+        assert_approx_eq!(histogram.percentile(0.5).unwrap(), (0.125 as Scalar).exp2());
+        assert!(Canvas::new(2, 2)
+            .luminance_histogram()
+            .percentile(0.5)
+            .is_none());
+    }
+
+    #[test]
+    fn test_auto_exposure_brings_the_median_to_mid_gray() {
+        let mut c = Canvas::new(3, 3);
+        for y in 0..3 {
+            for x in 0..3 {
+                c.write_pixel(x, y, Color::new(0.72, 0.72, 0.72));
+            }
+        }
+        // two stops too bright, to within a bin
+        let ev = c.luminance_histogram().auto_exposure(0.5);
+        assert!((ev + 2.0).abs() <= 0.125);
+        assert_eq!(
+            Canvas::new(3, 3).luminance_histogram().auto_exposure(0.5),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_half_floats() {
+        for (v, h) in [
+            (1.0, 0x3c00),
+            (-2.0, 0xc000),
+            (65504.0, 0x7bff),
+            (6e-8, 0x0001),
+        ] {
+            assert_eq!(to_half(v), h);
+            assert_eq!(from_half(h), if h == 1 { (-24f32).exp2() } else { v });
+        }
+        assert_eq!(to_half(1e6), 0x7c00);
+        assert_eq!(to_half(1e-9), 0);
+        // ties round to even
+        assert_eq!(to_half(1.0 + (-11f32).exp2()), 0x3c00);
+        assert_eq!(to_half(1.0 + 3.0 * (-11f32).exp2()), 0x3c02);
+    }
+
+    #[test]
+    fn test_canvas_pixel_formats() {
+        let color = Color::new(0.3, 2.5, -0.1);
+        for (format, expected, tolerance) in [
+            (PixelFormat::F32, color, 1e-6),
+            (PixelFormat::Half, color, 1e-3),
+            (PixelFormat::U8, Color::new(77.0 / 255.0, 1.0, 0.0), 1e-6),
+        ] {
+            let mut c = Canvas::with_format(2, 1, format);
+            assert_eq!(c.format(), format);
+            c.write_pixel(1, 0, color);
+            let stored = c.pixel_at(1, 0);
+            assert!((stored.red - expected.red).abs() <= tolerance);
+            assert!((stored.green - expected.green).abs() <= tolerance);
+            assert!((stored.blue - expected.blue).abs() <= tolerance);
+            let full = c.to_format(PixelFormat::F64);
+            assert_approx_eq!(full.pixel_at(1, 0), stored);
+        }
+    }
+
+    #[test]
+    fn test_a_canvas_is_opaque_until_alpha_is_written() {
+        let mut c = Canvas::new(10, 20);
+        assert!(!c.has_alpha());
+        assert_approx_eq!(c.alpha_at(2, 3), 1.0);
+        c.write_alpha(2, 3, 0.25);
+        assert!(c.has_alpha());
+        assert_approx_eq!(c.alpha_at(2, 3), 0.25);
+        assert_approx_eq!(c.alpha_at(3, 3), 1.0);
+    }
+
+    #[test]
+    fn test_rgba8_pixels_have_straight_alpha() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, Color::new(1.5, 0.5, 0.0));
+        c.write_pixel(1, 0, Color::new(0.25, 0.0, 0.5));
+        c.write_alpha(1, 0, 0.5);
+        assert_eq!(c.to_rgba8(), vec![255, 128, 0, 255, 128, 0, 255, 128]);
+    }
+
     #[test]
     fn test_constructing_the_ppm_header() {
         let c = Canvas::new(5, 3);
@@ -118,6 +604,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_writing_an_srgb_canvas() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, Color::from_srgb(0.5, 0.2, 1.0));
+        c.write_pixel(1, 0, Color::new(0.5, 0.5, 0.5));
+        c.write_alpha(1, 0, 0.5);
+        c.set_color_space(ColorSpace::Srgb);
+        assert_eq!(c.to_ppm().lines().nth(3), Some("128 51 255 188 188 188"));
+        assert_eq!(c.to_rgba8(), vec![128, 51, 255, 255, 255, 255, 255, 128]);
+    }
+
     #[test]
     fn test_splitting_long_lines_in_ppm_files() {
         let mut c = Canvas::new(10, 2);
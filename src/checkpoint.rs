@@ -0,0 +1,177 @@
+use crate::color::Color;
+use crate::error::{RayTracerError, Result};
+use crate::scalar::Scalar;
+#[cfg(feature = "fs")]
+use std::fs;
+use std::io;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u64 = 2;
+
+// The finished tiles of an interrupted render. Pixels only depend on their
+// coordinates and the frame number (see `Camera::render`), so a render
+// resumed from here produces exactly the image an uninterrupted one would.
+// The settings are stored to refuse resuming a different render; changes to
+// the scene itself can't be detected.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub width: usize,
+    pub height: usize,
+    pub tile_size: usize,
+    pub samples: usize,
+    pub frame: u64,
+    // a hash of the camera's other settings, see `Camera::fingerprint`
+    pub fingerprint: u64,
+    // tile index and its pixels' colors and alpha, in row-major order
+    pub tiles: Vec<(usize, Vec<(Color, Scalar)>)>,
+}
+
+impl Checkpoint {
+    // Colors are written as f64 so they read back unchanged.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        let header = [
+            VERSION,
+            self.width as u64,
+            self.height as u64,
+            self.tile_size as u64,
+            self.samples as u64,
+            self.frame,
+            self.fingerprint,
+            self.tiles.len() as u64,
+        ];
+        for value in header {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for (index, pixels) in &self.tiles {
+            bytes.extend_from_slice(&(*index as u64).to_le_bytes());
+            bytes.extend_from_slice(&(pixels.len() as u64).to_le_bytes());
+            for (color, alpha) in pixels {
+                for v in [color.red, color.green, color.blue, *alpha] {
+                    #[allow(clippy::unnecessary_cast)]
+                    let v = v as f64;
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(invalid("not a render checkpoint"));
+        }
+        let mut reader = Reader {
+            bytes: &bytes[MAGIC.len()..],
+        };
+        if reader.u64()? != VERSION {
+            return Err(invalid("unsupported checkpoint version"));
+        }
+        let mut checkpoint = Self {
+            width: reader.u64()? as usize,
+            height: reader.u64()? as usize,
+            tile_size: reader.u64()? as usize,
+            samples: reader.u64()? as usize,
+            frame: reader.u64()?,
+            fingerprint: reader.u64()?,
+            tiles: vec![],
+        };
+        for _ in 0..reader.u64()? {
+            let index = reader.u64()? as usize;
+            let count = reader.u64()? as usize;
+            let mut pixels = Vec::with_capacity(count.min(reader.bytes.len() / 32));
+            for _ in 0..count {
+                let color = Color::new(reader.scalar()?, reader.scalar()?, reader.scalar()?);
+                pixels.push((color, reader.scalar()?));
+            }
+            checkpoint.tiles.push((index, pixels));
+        }
+        Ok(checkpoint)
+    }
+    // None if there is no checkpoint at `path`.
+    #[cfg(feature = "fs")]
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(Self::decode(&bytes)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+    // Writes to a temporary file first, so an interruption while saving
+    // leaves the previous checkpoint intact.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, self.encode())?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+fn invalid(message: &str) -> RayTracerError {
+    RayTracerError::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self) -> Result<[u8; 8]> {
+        let (head, rest) = self
+            .bytes
+            .split_first_chunk::<8>()
+            .ok_or_else(|| invalid("truncated checkpoint"))?;
+        self.bytes = rest;
+        Ok(*head)
+    }
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+    #[allow(clippy::unnecessary_cast)]
+    fn scalar(&mut self) -> Result<Scalar> {
+        Ok(f64::from_le_bytes(self.take()?) as Scalar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            width: 20,
+            height: 10,
+            tile_size: 16,
+            samples: 4,
+            frame: 3,
+            fingerprint: 0x1234,
+            tiles: vec![(1, vec![(Color::new(0.1, 0.2, 0.3), 0.5); 40])],
+        }
+    }
+
+    #[test]
+    fn test_a_checkpoint_round_trips_through_bytes() {
+        let c = checkpoint();
+        assert_eq!(
+            Checkpoint::decode(&c.encode()).unwrap().encode(),
+            c.encode()
+        );
+    }
+
+    #[test]
+    fn test_truncated_checkpoints_are_errors() {
+        let bytes = checkpoint().encode();
+        assert!(Checkpoint::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Checkpoint::decode(b"P3\n").is_err());
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_loading_a_missing_checkpoint() {
+        let path = std::env::temp_dir().join("ray-tracer-no-such-checkpoint");
+        assert!(Checkpoint::load(&path).unwrap().is_none());
+    }
+}
@@ -1,11 +1,13 @@
 use crate::approx_eq::ApproxEq;
+use crate::scalar::Scalar;
 use std::ops::{Add, Mul, Sub};
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Color {
-    pub red: f64,
-    pub green: f64,
-    pub blue: f64,
+    pub red: Scalar,
+    pub green: Scalar,
+    pub blue: Scalar,
 }
 
 pub const WHITE: Color = Color {
@@ -18,11 +20,79 @@ pub const BLACK: Color = Color {
     green: 0.0,
     blue: 0.0,
 };
+pub const MAGENTA: Color = Color {
+    red: 1.0,
+    green: 0.0,
+    blue: 1.0,
+};
+
+// How color values are to be read. Shading is done on linear values,
+// proportional to light, while colors picked in an editor or color picker
+// are sRGB, with more of the range given to dark tones. Components beyond
+// 0..1 follow the same curve, mirrored for negative values.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum ColorSpace {
+    #[default]
+    Linear,
+    Srgb,
+}
+
+impl ColorSpace {
+    // A color given in this space, as the linear color to shade with.
+    pub fn to_linear(&self, c: Color) -> Color {
+        match self {
+            ColorSpace::Linear => c,
+            ColorSpace::Srgb => c.map(|v| {
+                let a = v.abs();
+                let linear = if a <= 0.04045 {
+                    a / 12.92
+                } else {
+                    ((a + 0.055) / 1.055).powf(2.4)
+                };
+                linear.copysign(v)
+            }),
+        }
+    }
+    // A linear color as it is written in this space.
+    pub fn from_linear(&self, c: Color) -> Color {
+        match self {
+            ColorSpace::Linear => c,
+            ColorSpace::Srgb => c.map(|v| {
+                let a = v.abs();
+                let encoded = if a <= 0.0031308 {
+                    a * 12.92
+                } else {
+                    1.055 * a.powf(1.0 / 2.4) - 0.055
+                };
+                encoded.copysign(v)
+            }),
+        }
+    }
+}
 
 impl Color {
-    pub fn new(red: f64, green: f64, blue: f64) -> Self {
+    pub const fn new(red: Scalar, green: Scalar, blue: Scalar) -> Self {
         Color { red, green, blue }
     }
+    // The linear color for sRGB components, such as a color picker shows.
+    pub fn from_srgb(red: Scalar, green: Scalar, blue: Scalar) -> Self {
+        ColorSpace::Srgb.to_linear(Color::new(red, green, blue))
+    }
+    fn map(&self, f: impl Fn(Scalar) -> Scalar) -> Self {
+        Color::new(f(self.red), f(self.green), f(self.blue))
+    }
+    pub fn is_finite(&self) -> bool {
+        self.red.is_finite() && self.green.is_finite() && self.blue.is_finite()
+    }
+    // Perceived brightness, by the Rec. 709 weights.
+    pub fn luminance(&self) -> Scalar {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
 }
 
 impl ApproxEq for Color {
@@ -33,6 +103,30 @@ impl ApproxEq for Color {
     }
 }
 
+impl From<[Scalar; 3]> for Color {
+    fn from([red, green, blue]: [Scalar; 3]) -> Self {
+        Self { red, green, blue }
+    }
+}
+
+impl From<(Scalar, Scalar, Scalar)> for Color {
+    fn from((red, green, blue): (Scalar, Scalar, Scalar)) -> Self {
+        Self { red, green, blue }
+    }
+}
+
+impl From<Color> for [Scalar; 3] {
+    fn from(v: Color) -> Self {
+        [v.red, v.green, v.blue]
+    }
+}
+
+impl From<Color> for (Scalar, Scalar, Scalar) {
+    fn from(v: Color) -> Self {
+        (v.red, v.green, v.blue)
+    }
+}
+
 impl Add for Color {
     type Output = Color;
 
@@ -57,27 +151,27 @@ impl Sub for Color {
     }
 }
 
-impl Mul<f64> for &Color {
+impl Mul<Scalar> for &Color {
     type Output = Color;
 
-    fn mul(self, other: f64) -> Self::Output {
-        Color {
-            red: self.red * other,
-            green: self.green * other,
-            blue: self.blue * other,
-        }
+    fn mul(self, other: Scalar) -> Self::Output {
+        *self * other
     }
 }
 
-impl Mul<f64> for Color {
+impl Mul<Scalar> for Color {
     type Output = Color;
 
-    fn mul(self, other: f64) -> Self::Output {
-        &self * other
+    fn mul(self, other: Scalar) -> Self::Output {
+        Color {
+            red: self.red * other,
+            green: self.green * other,
+            blue: self.blue * other,
+        }
     }
 }
 
-/* impl Mul<&Color> for f64 {
+/* impl Mul<&Color> for Scalar {
     type Output = Color;
 
     fn mul(self, other: &Color) -> Self::Output {
@@ -89,7 +183,7 @@ impl Mul<f64> for Color {
     }
 }
 
-impl Mul<Color> for f64 {
+impl Mul<Color> for Scalar {
     type Output = Color;
 
     fn mul(self, other: Color) -> Self::Output {
@@ -123,6 +217,27 @@ mod tests {
         assert_approx_eq!(c.blue, 1.7);
     }
 
+    #[test]
+    fn test_converting_colors_to_and_from_arrays_and_tuples() {
+        let c = Color::from([-0.5, 0.4, 1.7]);
+        assert_approx_eq!(c, Color::from((-0.5, 0.4, 1.7)));
+        assert_eq!(<[Scalar; 3]>::from(c), [-0.5, 0.4, 1.7]);
+        assert_eq!(<(Scalar, Scalar, Scalar)>::from(c), (-0.5, 0.4, 1.7));
+    }
+
+    #[test]
+    fn test_converting_between_color_spaces() {
+        let c = Color::from_srgb(0.5, 1.0, 0.02);
+        assert_approx_eq!(c, Color::new(0.21404, 1.0, 0.00155));
+        assert_approx_eq!(ColorSpace::Srgb.from_linear(c), Color::new(0.5, 1.0, 0.02));
+        let hdr = Color::new(4.0, -0.5, 0.0);
+        assert_approx_eq!(
+            ColorSpace::Srgb.to_linear(ColorSpace::Srgb.from_linear(hdr)),
+            hdr
+        );
+        assert_approx_eq!(ColorSpace::Linear.to_linear(hdr), hdr);
+    }
+
     #[test]
     fn test_adding_colors() {
         let c1 = Color::new(0.9, 0.6, 0.75);
@@ -1,8 +1,13 @@
+use crate::bounds::Bounds;
+use crate::error::{RayTracerError, Result};
 use crate::point::Point;
 use crate::ray::Ray;
+use crate::scalar::Scalar;
+use crate::sdf::SdfShape;
 use crate::shape::LocalShape;
 use crate::vector::Vector;
 
+#[derive(Default)]
 pub struct Cube {}
 
 impl Cube {
@@ -11,7 +16,7 @@ impl Cube {
     }
 }
 
-fn check_axis(origin: f64, direction: f64) -> (f64, f64) {
+fn check_axis(origin: Scalar, direction: Scalar) -> (Scalar, Scalar) {
     let tmin_numerator = -1.0 - origin;
     let tmax_numerator = 1.0 - origin;
 
@@ -28,7 +33,7 @@ fn check_axis(origin: f64, direction: f64) -> (f64, f64) {
 }
 
 impl LocalShape for Cube {
-    fn local_intersect(&self, ray: &Ray) -> Vec<f64> {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
         let (xtmin, xtmax) = check_axis(ray.origin.x, ray.direction.x);
         let (ytmin, ytmax) = check_axis(ray.origin.y, ray.direction.y);
         let (ztmin, ztmax) = check_axis(ray.origin.z, ray.direction.z);
@@ -53,6 +58,50 @@ impl LocalShape for Cube {
             Vector::new(0.0, 0.0, point.z)
         }
     }
+    fn local_bounds(&self) -> Bounds {
+        Bounds::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0))
+    }
+}
+
+// The cube from -1 to 1 with its edges and corners rounded off to
+// `radius`, so highlights run along them as on real, filleted objects. The
+// surface is found by ray marching its signed distance. It is a single
+// closed shape, so it can be glass, and the radius can be 0 (a cube) or 1
+// (a sphere).
+pub struct RoundedCube {
+    sdf: SdfShape<'static>,
+}
+
+impl RoundedCube {
+    pub fn new(radius: Scalar) -> Result<Self> {
+        if !(0.0..=1.0).contains(&radius) {
+            return Err(RayTracerError::InvalidShape(format!(
+                "corner radius must be between 0 and 1, got {}",
+                radius
+            )));
+        }
+        let distance = move |p: &Point| {
+            let q = [p.x, p.y, p.z].map(|c| c.abs() - (1.0 - radius));
+            let outside = q.iter().map(|c| c.max(0.0).powi(2)).sum::<Scalar>().sqrt();
+            let inside = q[0].max(q[1]).max(q[2]).min(0.0);
+            outside + inside - radius
+        };
+        Ok(Self {
+            sdf: SdfShape::new(distance, Cube::new().local_bounds())?,
+        })
+    }
+}
+
+impl LocalShape for RoundedCube {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
+        self.sdf.local_intersect(ray)
+    }
+    fn local_normal_at(&self, point: &Point) -> Vector {
+        self.sdf.local_normal_at(point)
+    }
+    fn local_bounds(&self) -> Bounds {
+        self.sdf.local_bounds()
+    }
 }
 
 #[cfg(test)]
@@ -198,4 +247,24 @@ mod tests {
             &Vector::new(-1.0, 0.0, 0.0)
         );
     }
+
+    #[test]
+    fn test_a_rounded_cube_is_a_cube_with_rounded_edges() {
+        let cube = RoundedCube::new(0.25).unwrap();
+        // the faces are where a cube's are
+        let r = Ray::new(Point::new(5.0, 0.5, 0.0), Vector::new(-1.0, 0.0, 0.0));
+        assert_approx_eq!(cube.local_intersect(&r), [4.0, 6.0]);
+        assert_approx_eq!(
+            cube.local_normal_at(&Point::new(1.0, 0.5, 0.0)),
+            Vector::new(1.0, 0.0, 0.0)
+        );
+        // the corner is cut off
+        let diagonal = Vector::new(-1.0, -1.0, -1.0).normalize();
+        let r = Ray::new(Point::new(2.0, 2.0, 2.0), diagonal);
+        let corner = 0.75 + 0.25 / Scalar::sqrt(3.0);
+        let t = cube.local_intersect(&r)[0];
+        assert_approx_eq!(r.position(t), Point::new(corner, corner, corner));
+        assert_approx_eq!(cube.local_normal_at(&r.position(t)), -diagonal);
+        assert!(RoundedCube::new(2.0).is_err());
+    }
 }
@@ -0,0 +1,307 @@
+use crate::approx_eq::EPSILON;
+use crate::bounds::Bounds;
+use crate::bvh::{Bvh, BvhBuilder};
+use crate::error::{RayTracerError, Result};
+use crate::point::Point;
+use crate::ray::Ray;
+use crate::scalar::Scalar;
+use crate::shape::LocalShape;
+use crate::vector::Vector;
+
+// Straight pieces each Bezier segment is swept along; enough for segments
+// that bend less than a right angle.
+const PIECES_PER_SEGMENT: usize = 8;
+
+// A cubic Bezier segment of a curve with its radius at either end.
+#[derive(Copy, Clone, Debug)]
+pub struct CurveSegment {
+    pub points: [Point; 4],
+    pub radii: [Scalar; 2],
+}
+
+impl CurveSegment {
+    pub fn new(points: [Point; 4], radii: [Scalar; 2]) -> Self {
+        Self { points, radii }
+    }
+    fn point_at(&self, u: Scalar) -> Point {
+        let v = 1.0 - u;
+        let weights = [v * v * v, 3.0 * v * v * u, 3.0 * v * u * u, u * u * u];
+        let mut p = Point::new(0.0, 0.0, 0.0);
+        for axis in 0..3 {
+            p[axis] = (0..4).map(|i| weights[i] * self.points[i][axis]).sum();
+        }
+        p
+    }
+}
+
+// A stretch of a curve swept by a circle: a capsule around the line from
+// `a` to `b`.
+struct Piece {
+    a: Point,
+    b: Point,
+    radius: Scalar,
+}
+
+impl Piece {
+    fn bounds(&self) -> Bounds {
+        let r = self.radius + EPSILON;
+        let mut bounds = Bounds::empty();
+        for p in [self.a, self.b] {
+            bounds.add_point(&Point::new(p.x - r, p.y - r, p.z - r));
+            bounds.add_point(&Point::new(p.x + r, p.y + r, p.z + r));
+        }
+        bounds
+    }
+    // Where the ray enters and leaves the capsule: the cylinder around the
+    // line between the ends and the spheres capping it.
+    fn intersect(&self, ray: &Ray, xs: &mut Vec<Scalar>) {
+        let axis = self.b - &self.a;
+        let length = axis.magnitude();
+        if length < EPSILON {
+            return self.intersect_cap(&self.a, ray, |_| true, xs);
+        }
+        let w = &axis / length;
+        let oa = ray.origin - &self.a;
+        let along = |t: Scalar| (&oa + &(&ray.direction * t)).dot(&w);
+        let d = &ray.direction - &(&w * ray.direction.dot(&w));
+        let o = &oa - &(&w * oa.dot(&w));
+        for t in solve_quadratic(d.dot(&d), 2.0 * o.dot(&d), o.dot(&o) - self.radius.powi(2)) {
+            if (0.0..=length).contains(&along(t)) {
+                xs.push(t);
+            }
+        }
+        self.intersect_cap(&self.a, ray, |t| along(t) < 0.0, xs);
+        self.intersect_cap(&self.b, ray, |t| along(t) > length, xs);
+    }
+    fn intersect_cap(
+        &self,
+        center: &Point,
+        ray: &Ray,
+        keep: impl Fn(Scalar) -> bool,
+        xs: &mut Vec<Scalar>,
+    ) {
+        let oc = ray.origin - center;
+        let (a, b) = (
+            ray.direction.dot(&ray.direction),
+            2.0 * oc.dot(&ray.direction),
+        );
+        let c = oc.dot(&oc) - self.radius.powi(2);
+        xs.extend(solve_quadratic(a, b, c).into_iter().filter(|t| keep(*t)));
+    }
+    fn closest_on_axis(&self, p: &Point) -> Point {
+        let axis = self.b - &self.a;
+        let length2 = axis.dot(&axis);
+        if length2 < EPSILON {
+            return self.a;
+        }
+        let u = ((*p - &self.a).dot(&axis) / length2).clamp(0.0, 1.0);
+        self.a + &(&axis * u)
+    }
+}
+
+fn solve_quadratic(a: Scalar, b: Scalar, c: Scalar) -> Vec<Scalar> {
+    let disc = b * b - 4.0 * a * c;
+    if a.abs() < EPSILON || disc < 0.0 {
+        return vec![];
+    }
+    let root = disc.sqrt();
+    vec![(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+}
+
+// Thin curves such as hair, grass or wires: cubic Bezier segments swept by
+// a circle whose radius varies along them. Like a mesh, many curves make a
+// single shape with its own BVH, which is far faster than a shape per
+// strand. Each segment is traced as a chain of capsules, which overlap at
+// the joints, so curves are for opaque materials only.
+pub struct Curves {
+    segments: Vec<CurveSegment>,
+    pieces: Vec<Piece>,
+    bvh: Bvh,
+}
+
+impl Curves {
+    pub fn new(segments: Vec<CurveSegment>) -> Result<Self> {
+        if let Some(segment) = segments
+            .iter()
+            .find(|s| s.radii.iter().any(|r| !r.is_finite() || *r <= 0.0))
+        {
+            return Err(RayTracerError::InvalidShape(format!(
+                "curve radii must be positive, got {:?}",
+                segment.radii
+            )));
+        }
+        let pieces: Vec<Piece> = segments
+            .iter()
+            .flat_map(|segment| {
+                let n = PIECES_PER_SEGMENT as Scalar;
+                (0..PIECES_PER_SEGMENT).map(move |i| {
+                    let [r0, r1] = segment.radii;
+                    let middle = (i as Scalar + 0.5) / n;
+                    Piece {
+                        a: segment.point_at(i as Scalar / n),
+                        b: segment.point_at((i + 1) as Scalar / n),
+                        radius: r0 + (r1 - r0) * middle,
+                    }
+                })
+            })
+            .collect();
+        let bvh = BvhBuilder::new().build(pieces.iter().enumerate().map(|(i, p)| (i, p.bounds())));
+        Ok(Self {
+            segments,
+            pieces,
+            bvh,
+        })
+    }
+    // Smooth curves through each strand's points (a Catmull-Rom spline),
+    // the radius going linearly from point to point.
+    pub fn from_strands(strands: &[Vec<(Point, Scalar)>]) -> Result<Self> {
+        let mut segments = vec![];
+        for strand in strands {
+            if strand.len() < 2 {
+                return Err(RayTracerError::InvalidShape(
+                    "a strand needs at least two points".to_string(),
+                ));
+            }
+            let point = |i: isize| strand[i.clamp(0, strand.len() as isize - 1) as usize].0;
+            for i in 0..strand.len() as isize - 1 {
+                let (p1, p2) = (point(i), point(i + 1));
+                let out = &(p2 - &point(i - 1)) / 6.0;
+                let back = &(point(i + 2) - &p1) / 6.0;
+                segments.push(CurveSegment::new(
+                    [p1, p1 + &out, p2 - &back, p2],
+                    [strand[i as usize].1, strand[i as usize + 1].1],
+                ));
+            }
+        }
+        Self::new(segments)
+    }
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+    // The piece `p` lies on the surface of, as the nearest among those
+    // whose bounds contain it.
+    fn locate(&self, p: &Point) -> Option<&Piece> {
+        let mut best: Option<(Scalar, usize)> = None;
+        self.bvh.for_each_containing(p, |i| {
+            let piece = &self.pieces[i];
+            let distance = ((*p - &piece.closest_on_axis(p)).magnitude() - piece.radius).abs();
+            if best.is_none_or(|(d, _)| distance < d) {
+                best = Some((distance, i));
+            }
+        });
+        best.map(|(_, i)| &self.pieces[i])
+    }
+}
+
+// Reads hair strands from a point list: one point per line as `x y z
+// radius`, with blank lines between strands and '#' starting a comment.
+pub fn parse_strands(text: &str) -> Result<Curves> {
+    // each strand with the line it starts on
+    let mut strands: Vec<(usize, Vec<(Point, Scalar)>)> = vec![];
+    let mut in_strand = false;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            in_strand = false;
+            continue;
+        }
+        let values = line
+            .split_whitespace()
+            .map(|t| t.parse::<Scalar>())
+            .collect::<std::result::Result<Vec<_>, _>>();
+        let Some(&[x, y, z, radius]) = values.as_deref().ok() else {
+            return Err(RayTracerError::Parse {
+                line: i + 1,
+                message: "expected x y z radius".to_string(),
+            });
+        };
+        if !in_strand {
+            strands.push((i + 1, vec![]));
+            in_strand = true;
+        }
+        strands
+            .last_mut()
+            .unwrap()
+            .1
+            .push((Point::new(x, y, z), radius));
+    }
+    if let Some((line, _)) = strands.iter().find(|(_, points)| points.len() < 2) {
+        return Err(RayTracerError::Parse {
+            line: *line,
+            message: "a strand needs at least two points".to_string(),
+        });
+    }
+    let strands: Vec<_> = strands.into_iter().map(|(_, points)| points).collect();
+    Curves::from_strands(&strands)
+}
+
+impl LocalShape for Curves {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
+        let mut xs = vec![];
+        self.bvh
+            .for_each(ray, |i| self.pieces[i].intersect(ray, &mut xs));
+        xs
+    }
+    fn local_normal_at(&self, p: &Point) -> Vector {
+        match self.locate(p) {
+            Some(piece) => (*p - &piece.closest_on_axis(p)).normalize(),
+            None => Vector::new(0.0, 1.0, 0.0),
+        }
+    }
+    fn local_bounds(&self) -> Bounds {
+        self.pieces.iter().fold(Bounds::empty(), |bounds, piece| {
+            bounds.merge(&piece.bounds())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    // A straight strand along the x axis from -1 to 1, of radius 0.1.
+    fn wire() -> Curves {
+        Curves::from_strands(&[vec![
+            (Point::new(-1.0, 0.0, 0.0), 0.1),
+            (Point::new(1.0, 0.0, 0.0), 0.1),
+        ]])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_a_ray_strikes_a_curve_like_a_thin_tube() {
+        let curves = wire();
+        let r = Ray::new(Point::new(0.3, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let mut xs = curves.local_intersect(&r);
+        xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_approx_eq!(xs[0], 4.9);
+        assert_approx_eq!(xs[xs.len() - 1], 5.1);
+        let n = curves.local_normal_at(&Point::new(0.3, 0.0, -0.1));
+        assert_approx_eq!(n, Vector::new(0.0, 0.0, -1.0));
+        let miss = Ray::new(Point::new(0.0, 0.2, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(curves.local_intersect(&miss).is_empty());
+        // rounded ends
+        let past = Ray::new(Point::new(1.05, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(!curves.local_intersect(&past).is_empty());
+        assert!(Curves::from_strands(&[vec![(Point::new(0.0, 0.0, 0.0), 0.1)]]).is_err());
+    }
+
+    #[test]
+    fn test_parsing_strands_from_a_point_list() {
+        let curves = parse_strands(
+            "# two strands\n0 0 0 0.1\n0 1 0 0.1\n0 2 1 0.05\n\n\n1 0 0 0.1\n1 1 0 0.1\n",
+        )
+        .unwrap();
+        assert_eq!(curves.segment_count(), 3);
+        // the spline passes through the points
+        assert_approx_eq!(curves.segments[1].points[3], Point::new(0.0, 2.0, 1.0));
+        for (text, line) in [("0 0 0\n", 1), ("0 0 0 1\n0 1 0 1\n\n5 5 5 1\n", 4)] {
+            match parse_strands(text) {
+                Err(RayTracerError::Parse { line: l, .. }) => assert_eq!(l, line),
+                _ => panic!("expected a parse error for {:?}", text),
+            }
+        }
+    }
+}
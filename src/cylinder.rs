@@ -1,23 +1,68 @@
 use crate::approx_eq::EPSILON;
+use crate::bounds::Bounds;
 use crate::point::Point;
 use crate::ray::Ray;
+use crate::scalar::Scalar;
 use crate::shape::LocalShape;
 use crate::vector::Vector;
 
-pub struct Cylinder {}
+// A unit radius cylinder around the y axis, infinitely long unless
+// truncated, and open-ended unless closed.
+pub struct Cylinder {
+    minimum: Scalar,
+    maximum: Scalar,
+    closed: bool,
+}
+
+impl Default for Cylinder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Cylinder {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            minimum: Scalar::NEG_INFINITY,
+            maximum: Scalar::INFINITY,
+            closed: false,
+        }
+    }
+    // Truncates the cylinder to minimum < y < maximum.
+    pub fn set_extent(self, minimum: Scalar, maximum: Scalar) -> Self {
+        Self {
+            minimum,
+            maximum,
+            ..self
+        }
+    }
+    pub fn set_closed(self, closed: bool) -> Self {
+        Self { closed, ..self }
+    }
+    fn intersect_caps(&self, ray: &Ray, xs: &mut Vec<Scalar>) {
+        if !self.closed || ray.direction.y.abs() < EPSILON {
+            return;
+        }
+        for y in [self.minimum, self.maximum] {
+            let t = (y - ray.origin.y) / ray.direction.y;
+            let x = ray.origin.x + t * ray.direction.x;
+            let z = ray.origin.z + t * ray.direction.z;
+            if x * x + z * z <= 1.0 + EPSILON {
+                xs.push(t);
+            }
+        }
     }
 }
 
 impl LocalShape for Cylinder {
-    fn local_intersect(&self, ray: &Ray) -> Vec<f64> {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
+        let mut xs = vec![];
+        self.intersect_caps(ray, &mut xs);
+
         let a = ray.direction.x * ray.direction.x + ray.direction.z * ray.direction.z;
 
         if a < EPSILON {
-            return vec![];
+            return xs;
         }
 
         let b = 2.0 * (ray.origin.x * ray.direction.x + ray.origin.z * ray.direction.z);
@@ -25,16 +70,35 @@ impl LocalShape for Cylinder {
         let disc = b * b - 4.0 * a * c;
 
         if disc < 0.0 {
-            return vec![];
+            return xs;
         }
 
-        vec![
+        for t in [
             (-b - disc.sqrt()) / (2.0 * a),
             (-b + disc.sqrt()) / (2.0 * a),
-        ]
+        ] {
+            let y = ray.origin.y + t * ray.direction.y;
+            if self.minimum < y && y < self.maximum {
+                xs.push(t);
+            }
+        }
+        xs
     }
     fn local_normal_at(&self, point: &Point) -> Vector {
-        Vector::new(point.x, 0.0, point.z)
+        let dist = point.x * point.x + point.z * point.z;
+        if dist < 1.0 && point.y >= self.maximum - EPSILON {
+            Vector::new(0.0, 1.0, 0.0)
+        } else if dist < 1.0 && point.y <= self.minimum + EPSILON {
+            Vector::new(0.0, -1.0, 0.0)
+        } else {
+            Vector::new(point.x, 0.0, point.z)
+        }
+    }
+    fn local_bounds(&self) -> Bounds {
+        Bounds::new(
+            Point::new(-1.0, self.minimum, -1.0),
+            Point::new(1.0, self.maximum, 1.0),
+        )
     }
 }
 
@@ -93,6 +157,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_intersecting_a_truncated_cylinder() {
+        let cyl = Cylinder::new().set_extent(1.0, 2.0);
+        let cases = [
+            (Point::new(0.0, 1.5, 0.0), Vector::new(0.1, 1.0, 0.0), 0),
+            (Point::new(0.0, 3.0, -5.0), Vector::new(0.0, 0.0, 1.0), 0),
+            (Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0), 0),
+            (Point::new(0.0, 2.0, -5.0), Vector::new(0.0, 0.0, 1.0), 0),
+            (Point::new(0.0, 1.0, -5.0), Vector::new(0.0, 0.0, 1.0), 0),
+            (Point::new(0.0, 1.5, -2.0), Vector::new(0.0, 0.0, 1.0), 2),
+        ];
+        for (origin, direction, count) in cases {
+            let r = Ray::new(origin, direction.normalize());
+            assert_eq!(cyl.local_intersect(&r).len(), count);
+        }
+    }
+
+    #[test]
+    fn test_intersecting_the_caps_of_a_closed_cylinder() {
+        let cyl = Cylinder::new().set_extent(1.0, 2.0).set_closed(true);
+        let cases = [
+            (Point::new(0.0, 3.0, 0.0), Vector::new(0.0, -1.0, 0.0), 2),
+            (Point::new(0.0, 3.0, -2.0), Vector::new(0.0, -1.0, 2.0), 2),
+            (Point::new(0.0, 4.0, -2.0), Vector::new(0.0, -1.0, 1.0), 2),
+            (Point::new(0.0, 0.0, -2.0), Vector::new(0.0, 1.0, 2.0), 2),
+            (Point::new(0.0, -1.0, -2.0), Vector::new(0.0, 1.0, 1.0), 2),
+        ];
+        for (origin, direction, count) in cases {
+            let r = Ray::new(origin, direction.normalize());
+            assert_eq!(cyl.local_intersect(&r).len(), count);
+        }
+    }
+
+    #[test]
+    fn test_the_normal_on_the_caps_of_a_closed_cylinder() {
+        let cyl = Cylinder::new().set_extent(1.0, 2.0).set_closed(true);
+        let down = Vector::new(0.0, -1.0, 0.0);
+        let up = Vector::new(0.0, 1.0, 0.0);
+        assert_approx_eq!(cyl.local_normal_at(&Point::new(0.0, 1.0, 0.0)), down);
+        assert_approx_eq!(cyl.local_normal_at(&Point::new(0.5, 1.0, 0.0)), down);
+        assert_approx_eq!(cyl.local_normal_at(&Point::new(0.0, 2.0, 0.5)), up);
+    }
+
+    #[test]
+    fn test_the_bounds_of_a_cylinder() {
+        assert!(!Cylinder::new().local_bounds().is_finite());
+        let b = Cylinder::new().set_extent(-2.0, 3.0).local_bounds();
+        assert_approx_eq!(b.min, Point::new(-1.0, -2.0, -1.0));
+        assert_approx_eq!(b.max, Point::new(1.0, 3.0, 1.0));
+    }
+
     #[test]
     fn test_normal_vector_on_a_cylinder() {
         let cyl = Cylinder::new();
@@ -0,0 +1,199 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::depth::DepthBuffer;
+use crate::scalar::Scalar;
+use crate::vector::Vector;
+
+// Noise-free buffers describing what each pixel sees, to guide a denoiser:
+// the unlit surface color, the shading normal (its x, y and z stored as
+// red, green and blue, black where nothing was hit) and the depth in
+// meters. See `Camera::render_guides`.
+pub struct GuideBuffers {
+    pub albedo: Canvas,
+    pub normal: Canvas,
+    pub depth: DepthBuffer,
+}
+
+impl GuideBuffers {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            albedo: Canvas::new(width, height),
+            normal: Canvas::new(width, height),
+            depth: DepthBuffer::new(width, height),
+        }
+    }
+    fn normal_at(&self, x: usize, y: usize) -> Vector {
+        let n = self.normal.pixel_at(x, y);
+        Vector::new(n.red, n.green, n.blue)
+    }
+}
+
+// Something that cleans up a noisy render given its guide buffers, so that
+// external denoisers can be plugged in alongside `BilateralDenoiser`.
+pub trait Denoiser {
+    fn denoise(&self, image: &Canvas, guides: &GuideBuffers) -> Canvas;
+}
+
+// A joint bilateral filter in the spirit of SVGF's edge-stopping functions:
+// each pixel is averaged with its neighbours within `radius`, weighted down
+// as their normals, depths and albedos differ from its own, so noise is
+// smoothed out within a surface but not across its edges.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BilateralDenoiser {
+    radius: usize,
+    normal_power: Scalar,
+    depth_tolerance: Scalar,
+    albedo_sigma: Scalar,
+}
+
+impl BilateralDenoiser {
+    pub fn new() -> Self {
+        Self {
+            radius: 4,
+            normal_power: 32.0,
+            depth_tolerance: 0.05,
+            albedo_sigma: 0.1,
+        }
+    }
+    pub fn set_radius(self, radius: usize) -> Self {
+        Self { radius, ..self }
+    }
+    // The weight falls with the cosine between the normals to this power.
+    pub fn set_normal_power(self, normal_power: Scalar) -> Self {
+        Self {
+            normal_power,
+            ..self
+        }
+    }
+    // The depth difference, as a fraction of the pixel's own depth, at which
+    // the weight has fallen to 1/e.
+    pub fn set_depth_tolerance(self, depth_tolerance: Scalar) -> Self {
+        Self {
+            depth_tolerance,
+            ..self
+        }
+    }
+    pub fn set_albedo_sigma(self, albedo_sigma: Scalar) -> Self {
+        Self {
+            albedo_sigma,
+            ..self
+        }
+    }
+    fn weight(&self, guides: &GuideBuffers, p: (usize, usize), q: (usize, usize)) -> Scalar {
+        let (dp, dq) = (
+            guides.depth.depth_at(p.0, p.1),
+            guides.depth.depth_at(q.0, q.1),
+        );
+        // the background only blends with itself
+        if dp.is_infinite() || dq.is_infinite() {
+            return if dp.is_infinite() && dq.is_infinite() {
+                1.0
+            } else {
+                0.0
+            };
+        }
+        let cos = guides.normal_at(p.0, p.1).dot(&guides.normal_at(q.0, q.1));
+        let normal = cos.max(0.0).powf(self.normal_power);
+        let depth = (-(dp - dq).abs() / (self.depth_tolerance * dp).max(Scalar::EPSILON)).exp();
+        let da = guides.albedo.pixel_at(p.0, p.1) - guides.albedo.pixel_at(q.0, q.1);
+        let da2 = da.red * da.red + da.green * da.green + da.blue * da.blue;
+        let albedo = (-da2 / (2.0 * self.albedo_sigma * self.albedo_sigma)).exp();
+        normal * depth * albedo
+    }
+}
+
+impl Default for BilateralDenoiser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Denoiser for BilateralDenoiser {
+    fn denoise(&self, image: &Canvas, guides: &GuideBuffers) -> Canvas {
+        let (width, height) = (image.width(), image.height());
+        let r = self.radius as isize;
+        // the spatial falloff reaches 1/e² at the edge of the window
+        let spatial_scale = 2.0 / (self.radius.max(1) * self.radius.max(1)) as Scalar;
+        let mut result = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut total = 0.0;
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        let (sx, sy) = (x as isize + dx, y as isize + dy);
+                        if sx < 0 || sy < 0 || sx >= width as isize || sy >= height as isize {
+                            continue;
+                        }
+                        let q = (sx as usize, sy as usize);
+                        let spatial = (-((dx * dx + dy * dy) as Scalar) * spatial_scale).exp();
+                        let w = spatial * self.weight(guides, (x, y), q);
+                        sum = sum + image.pixel_at(q.0, q.1) * w;
+                        total += w;
+                    }
+                }
+                // the pixel itself always has a weight of at least one
+                result.write_pixel(x, y, sum * (1.0 / total));
+                if image.has_alpha() {
+                    result.write_alpha(x, y, image.alpha_at(x, y));
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    // Two walls meeting down the middle of the image at a right angle, one
+    // facing the camera and one facing right, each rendered with noise.
+    fn walls() -> (Canvas, GuideBuffers) {
+        let mut image = Canvas::new(12, 6);
+        let mut guides = GuideBuffers::new(12, 6);
+        for y in 0..6 {
+            for x in 0..12 {
+                let noise = if (x + y) % 2 == 0 { 0.2 } else { -0.2 };
+                let (base, normal) = if x < 6 {
+                    (0.5, Color::new(0.0, 0.0, -1.0))
+                } else {
+                    (0.1, Color::new(1.0, 0.0, 0.0))
+                };
+                image.write_pixel(x, y, Color::new(1.0, 1.0, 1.0) * (base + noise));
+                guides.albedo.write_pixel(x, y, Color::new(0.8, 0.8, 0.8));
+                guides.normal.write_pixel(x, y, normal);
+                guides.depth.write_depth(x, y, 5.0);
+            }
+        }
+        (image, guides)
+    }
+
+    #[test]
+    fn test_noise_is_smoothed_within_a_surface() {
+        let (image, guides) = walls();
+        let denoised = BilateralDenoiser::new().denoise(&image, &guides);
+        for (x, y) in [(2, 2), (3, 2), (3, 3)] {
+            assert!((image.pixel_at(x, y).red - 0.5).abs() > 0.19);
+            assert!((denoised.pixel_at(x, y).red - 0.5).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_edges_in_the_guides_are_kept() {
+        let (image, guides) = walls();
+        let denoised = BilateralDenoiser::new().denoise(&image, &guides);
+        // the walls are at right angles, so neither bleeds into the other
+        for y in 0..6 {
+            assert!(denoised.pixel_at(5, y).red > 0.4);
+            assert!(denoised.pixel_at(6, y).red < 0.2);
+        }
+        // nor does the background into a surface
+        let mut guides = guides;
+        guides.depth.write_depth(0, 0, Scalar::INFINITY);
+        let denoised = BilateralDenoiser::new().denoise(&image, &guides);
+        assert_approx_eq!(denoised.pixel_at(0, 0), image.pixel_at(0, 0));
+    }
+}
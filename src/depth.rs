@@ -0,0 +1,159 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::scalar::Scalar;
+
+// Distance in meters from the camera to the first hit through each pixel's
+// center; infinite where the ray hits nothing.
+pub struct DepthBuffer {
+    width: usize,
+    height: usize,
+    depths: Vec<Scalar>,
+}
+
+impl DepthBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            depths: vec![Scalar::INFINITY; width * height],
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn depth_at(&self, x: usize, y: usize) -> Scalar {
+        self.depths[y * self.width + x]
+    }
+    pub fn write_depth(&mut self, x: usize, y: usize, depth: Scalar) {
+        self.depths[y * self.width + x] = depth;
+    }
+}
+
+// Blends each pixel towards `color` by 1 - e^(-density * depth), with the
+// density per meter, so pixels that hit nothing become the fog color.
+pub fn depth_fog(canvas: &Canvas, depth: &DepthBuffer, color: Color, density: Scalar) -> Canvas {
+    let mut image = Canvas::new(canvas.width(), canvas.height());
+    for y in 0..canvas.height() {
+        for x in 0..canvas.width() {
+            let fog = 1.0 - (-density * depth.depth_at(x, y)).exp();
+            let c = canvas.pixel_at(x, y) * (1.0 - fog) + color * fog;
+            image.write_pixel(x, y, c);
+        }
+    }
+    image
+}
+
+// A cheap depth of field: every pixel is averaged over a disc whose radius
+// in pixels grows with its distance from the focal plane, as
+// `aperture * |1 - focal_distance / depth|`, up to `max_radius`. The focal
+// distance is in meters, like the depths.
+pub fn depth_blur(
+    canvas: &Canvas,
+    depth: &DepthBuffer,
+    focal_distance: Scalar,
+    aperture: Scalar,
+    max_radius: usize,
+) -> Canvas {
+    let (width, height) = (canvas.width(), canvas.height());
+    let mut image = Canvas::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let coc = aperture * (1.0 - focal_distance / depth.depth_at(x, y)).abs();
+            let radius = (coc.round() as usize).min(max_radius);
+            let r = radius as isize;
+            let mut sum = Color::new(0.0, 0.0, 0.0);
+            let mut count = 0;
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    let (sx, sy) = (x as isize + dx, y as isize + dy);
+                    if dx * dx + dy * dy > r * r
+                        || sx < 0
+                        || sy < 0
+                        || sx >= width as isize
+                        || sy >= height as isize
+                    {
+                        continue;
+                    }
+                    sum = sum + canvas.pixel_at(sx as usize, sy as usize);
+                    count += 1;
+                }
+            }
+            image.write_pixel(x, y, sum * (1.0 / count as Scalar));
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::color::{BLACK, WHITE};
+
+    // A white vertical line down the middle of a black canvas.
+    fn line() -> Canvas {
+        let mut canvas = Canvas::new(9, 3);
+        for y in 0..3 {
+            canvas.write_pixel(4, y, WHITE);
+        }
+        canvas
+    }
+
+    fn flat_depth(width: usize, height: usize, d: Scalar) -> DepthBuffer {
+        let mut depth = DepthBuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                depth.write_depth(x, y, d);
+            }
+        }
+        depth
+    }
+
+    #[test]
+    fn test_a_new_depth_buffer_is_infinitely_deep() {
+        let depth = DepthBuffer::new(4, 2);
+        assert_eq!(depth.width(), 4);
+        assert_eq!(depth.height(), 2);
+        assert_eq!(depth.depth_at(3, 1), Scalar::INFINITY);
+    }
+
+    #[test]
+    fn test_fog_thickens_with_depth() {
+        let canvas = line();
+        let mut depth = flat_depth(9, 3, 0.0);
+        depth.write_depth(4, 1, 1.0);
+        depth.write_depth(4, 2, Scalar::INFINITY);
+        let fogged = depth_fog(&canvas, &depth, BLACK, 0.5);
+        assert_approx_eq!(fogged.pixel_at(4, 0), WHITE);
+        let e = (-0.5 as Scalar).exp();
+        assert_approx_eq!(fogged.pixel_at(4, 1), Color::new(e, e, e));
+        assert_approx_eq!(fogged.pixel_at(4, 2), BLACK);
+    }
+
+    #[test]
+    fn test_pixels_in_focus_stay_sharp() {
+        let canvas = line();
+        let blurred = depth_blur(&canvas, &flat_depth(9, 3, 5.0), 5.0, 4.0, 3);
+        for x in 0..9 {
+            assert_approx_eq!(blurred.pixel_at(x, 1), canvas.pixel_at(x, 1));
+        }
+    }
+
+    #[test]
+    fn test_pixels_out_of_focus_are_spread() {
+        let canvas = line();
+        // circle of confusion 4 * |1 - 5 / 10| = 2 pixels
+        let blurred = depth_blur(&canvas, &flat_depth(9, 3, 10.0), 5.0, 4.0, 3);
+        assert!(blurred.pixel_at(4, 1).red < 1.0);
+        assert!(blurred.pixel_at(6, 1).red > 0.0);
+        assert_approx_eq!(blurred.pixel_at(7, 1), BLACK);
+
+        // capped by the maximum radius
+        let blurred = depth_blur(&canvas, &flat_depth(9, 3, 10.0), 5.0, 4.0, 1);
+        assert_approx_eq!(blurred.pixel_at(6, 1), BLACK);
+    }
+}
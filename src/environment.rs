@@ -0,0 +1,265 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::error::{RayTracerError, Result};
+use crate::scalar::consts::PI;
+use crate::scalar::Scalar;
+use crate::vector::Vector;
+
+// The faces of a cube map, in the usual order. Each face is seen from the
+// inside with +y up, except the top and bottom, which have +z and -z up.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+const CUBE_FACES: [CubeFace; 6] = [
+    CubeFace::PositiveX,
+    CubeFace::NegativeX,
+    CubeFace::PositiveY,
+    CubeFace::NegativeY,
+    CubeFace::PositiveZ,
+    CubeFace::NegativeZ,
+];
+
+impl CubeFace {
+    // The face a direction points at, and where on it, from (0, 0) at the
+    // top left to (1, 1) at the bottom right.
+    fn locate(d: &Vector) -> (Self, Scalar, Scalar) {
+        let (ax, ay, az) = (d.x.abs(), d.y.abs(), d.z.abs());
+        let (face, major, s, t) = if ax >= ay && ax >= az {
+            if d.x > 0.0 {
+                (CubeFace::PositiveX, ax, -d.z, -d.y)
+            } else {
+                (CubeFace::NegativeX, ax, d.z, -d.y)
+            }
+        } else if ay >= az {
+            if d.y > 0.0 {
+                (CubeFace::PositiveY, ay, d.x, d.z)
+            } else {
+                (CubeFace::NegativeY, ay, d.x, -d.z)
+            }
+        } else if d.z > 0.0 {
+            (CubeFace::PositiveZ, az, d.x, -d.y)
+        } else {
+            (CubeFace::NegativeZ, az, -d.x, -d.y)
+        };
+        (face, (s / major + 1.0) / 2.0, (t / major + 1.0) / 2.0)
+    }
+}
+
+// The light arriving from every direction, stored as an equirectangular
+// image laid out as `CameraProjection::Equirectangular` renders one:
+// longitude across with -z in the middle, latitude down from +y.
+pub struct EnvironmentMap {
+    image: Canvas,
+}
+
+impl EnvironmentMap {
+    pub fn from_equirectangular(image: Canvas) -> Result<Self> {
+        if image.width() == 0 || image.height() == 0 {
+            return Err(RayTracerError::InvalidImage(
+                "an environment map can't be empty".to_string(),
+            ));
+        }
+        Ok(Self { image })
+    }
+    // Six square face images of one size, in `CubeFace` order.
+    pub fn from_faces(faces: [Canvas; 6]) -> Result<Self> {
+        let size = faces[0].width();
+        if size == 0
+            || faces
+                .iter()
+                .any(|f| f.width() != size || f.height() != size)
+        {
+            return Err(RayTracerError::InvalidImage(
+                "cube map faces must be square and all of one size".to_string(),
+            ));
+        }
+        let mut image = Canvas::new(4 * size, 2 * size);
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let (face, s, t) = CubeFace::locate(&Self::direction(&image, x, y));
+                let face = &faces[CUBE_FACES.iter().position(|f| *f == face).unwrap()];
+                image.write_pixel(x, y, pixel_near(face, s, t));
+            }
+        }
+        Ok(Self { image })
+    }
+    // A single image with the faces unfolded in a cross, either four faces
+    // wide (-x, +z, +x, -z across the middle, +y above and -y below +z) or
+    // four faces tall (+y, +z, -y, and -z upside down, down the middle, -x
+    // and +x beside +z).
+    pub fn from_cross(cross: &Canvas) -> Result<Self> {
+        let (width, height) = (cross.width(), cross.height());
+        let (size, layout) = if width * 3 == height * 4 {
+            (width / 4, HORIZONTAL_CROSS)
+        } else if width * 4 == height * 3 {
+            (width / 3, VERTICAL_CROSS)
+        } else {
+            return Err(RayTracerError::InvalidImage(format!(
+                "a cross layout must be 4:3 or 3:4, got {}x{}",
+                width, height
+            )));
+        };
+        let faces = layout.map(|(col, row, flipped)| {
+            let mut face = Canvas::new(size, size);
+            for y in 0..size {
+                for x in 0..size {
+                    let (fx, fy) = if flipped {
+                        (size - 1 - x, size - 1 - y)
+                    } else {
+                        (x, y)
+                    };
+                    face.write_pixel(x, y, cross.pixel_at(col * size + fx, row * size + fy));
+                }
+            }
+            face
+        });
+        Self::from_faces(faces)
+    }
+    pub fn image(&self) -> &Canvas {
+        &self.image
+    }
+    // The light arriving from `direction`, which needn't be normalized.
+    pub fn sample(&self, direction: &Vector) -> Color {
+        let d = direction.normalize();
+        let longitude = (-d.x).atan2(-d.z);
+        let latitude = d.y.clamp(-1.0, 1.0).asin();
+        pixel_near(
+            &self.image,
+            longitude / (2.0 * PI) + 0.5,
+            0.5 - latitude / PI,
+        )
+    }
+    // The direction through the center of a pixel of an equirectangular
+    // image.
+    fn direction(image: &Canvas, x: usize, y: usize) -> Vector {
+        let longitude = ((x as Scalar + 0.5) / image.width() as Scalar - 0.5) * 2.0 * PI;
+        let latitude = (0.5 - (y as Scalar + 0.5) / image.height() as Scalar) * PI;
+        Vector::new(
+            -longitude.sin() * latitude.cos(),
+            latitude.sin(),
+            -longitude.cos() * latitude.cos(),
+        )
+    }
+}
+
+// Where each face sits in a cross, in faces from the top left, and whether
+// it is upside down there, in `CubeFace` order.
+const HORIZONTAL_CROSS: [(usize, usize, bool); 6] = [
+    (2, 1, false),
+    (0, 1, false),
+    (1, 0, false),
+    (1, 2, false),
+    (1, 1, false),
+    (3, 1, false),
+];
+const VERTICAL_CROSS: [(usize, usize, bool); 6] = [
+    (2, 1, false),
+    (0, 1, false),
+    (1, 0, false),
+    (1, 2, false),
+    (1, 1, false),
+    (1, 3, true),
+];
+
+// The pixel of `image` covering (s, t), each from 0 to 1 across it.
+fn pixel_near(image: &Canvas, s: Scalar, t: Scalar) -> Color {
+    let x = ((s * image.width() as Scalar) as usize).min(image.width() - 1);
+    let y = ((t * image.height() as Scalar) as usize).min(image.height() - 1);
+    image.pixel_at(x, y)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    fn face_colors() -> [Color; 6] {
+        [
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 1.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(1.0, 0.0, 1.0),
+            Color::new(0.0, 0.0, 1.0),
+            Color::new(1.0, 1.0, 0.0),
+        ]
+    }
+
+    fn axes() -> [Vector; 6] {
+        [
+            Vector::new(1.0, 0.0, 0.0),
+            Vector::new(-1.0, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+            Vector::new(0.0, -1.0, 0.0),
+            Vector::new(0.0, 0.0, 1.0),
+            Vector::new(0.0, 0.0, -1.0),
+        ]
+    }
+
+    fn filled(size: usize, color: Color) -> Canvas {
+        let mut canvas = Canvas::new(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                canvas.write_pixel(x, y, color);
+            }
+        }
+        canvas
+    }
+
+    #[test]
+    fn test_an_environment_from_six_faces() {
+        let env = EnvironmentMap::from_faces(face_colors().map(|c| filled(8, c))).unwrap();
+        assert_eq!(env.image().width(), 32);
+        assert_eq!(env.image().height(), 16);
+        for (axis, color) in axes().iter().zip(face_colors()) {
+            assert_approx_eq!(env.sample(axis), color);
+        }
+        // the middle of the image looks down -z, as an equirectangular
+        // render does
+        assert_approx_eq!(env.image().pixel_at(16, 8), face_colors()[5]);
+        assert!(matches!(
+            EnvironmentMap::from_faces(
+                [8, 8, 8, 8, 8, 4].map(|size| filled(size, Color::new(0.0, 0.0, 0.0)))
+            ),
+            Err(RayTracerError::InvalidImage(_))
+        ));
+    }
+
+    #[test]
+    fn test_an_environment_from_a_cross() {
+        let size = 4;
+        let colors = face_colors();
+        for (width, height, layout) in [(4, 3, HORIZONTAL_CROSS), (3, 4, VERTICAL_CROSS)] {
+            let mut cross = Canvas::new(width * size, height * size);
+            for ((col, row, flipped), color) in layout.iter().zip(colors) {
+                for y in 0..size {
+                    for x in 0..size {
+                        // mark the top left of each face as it is seen
+                        let corner = if *flipped { size - 1 } else { 0 };
+                        let c = if x == corner && y == corner {
+                            Color::new(0.5, 0.5, 0.5)
+                        } else {
+                            color
+                        };
+                        cross.write_pixel(col * size + x, row * size + y, c);
+                    }
+                }
+            }
+            let env = EnvironmentMap::from_cross(&cross).unwrap();
+            for (axis, color) in axes().iter().zip(colors) {
+                assert_approx_eq!(env.sample(axis), color);
+            }
+            // seen from inside, the top left of -z is towards +x
+            let corner = Vector::new(0.9, 0.9, -1.0);
+            assert_approx_eq!(env.sample(&corner), Color::new(0.5, 0.5, 0.5));
+        }
+        assert!(EnvironmentMap::from_cross(&Canvas::new(8, 8)).is_err());
+    }
+}
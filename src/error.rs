@@ -0,0 +1,49 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum RayTracerError {
+    NonInvertibleTransform,
+    InvalidMaterial(String),
+    InvalidCamera(String),
+    InvalidMesh(String),
+    InvalidShape(String),
+    InvalidLight(String),
+    InvalidImage(String),
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, RayTracerError>;
+
+impl fmt::Display for RayTracerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NonInvertibleTransform => write!(f, "transform is not invertible"),
+            Self::InvalidMaterial(message) => write!(f, "invalid material: {}", message),
+            Self::InvalidCamera(message) => write!(f, "invalid camera: {}", message),
+            Self::InvalidMesh(message) => write!(f, "invalid mesh: {}", message),
+            Self::InvalidShape(message) => write!(f, "invalid shape: {}", message),
+            Self::InvalidLight(message) => write!(f, "invalid light: {}", message),
+            Self::InvalidImage(message) => write!(f, "invalid image: {}", message),
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for RayTracerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RayTracerError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
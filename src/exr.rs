@@ -0,0 +1,155 @@
+use crate::canvas::Canvas;
+use crate::scalar::Scalar;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+// One channel of an image, named as in a layer ("CryptoObject00.R") or
+// plainly ("R"), with its value at each pixel.
+pub struct Channel<'c> {
+    pub name: String,
+    pub value: Box<dyn Fn(usize, usize) -> Scalar + 'c>,
+}
+
+// Encodes the canvas as an uncompressed scanline OpenEXR image with 32-bit
+// float channels. Unlike the 8-bit formats the values are not clamped, and
+// the colors stay premultiplied by alpha as EXR expects.
+pub fn encode(canvas: &Canvas) -> Vec<u8> {
+    let mut channels = vec![
+        Channel {
+            name: "R".to_string(),
+            value: Box::new(|x, y| canvas.pixel_at(x, y).red),
+        },
+        Channel {
+            name: "G".to_string(),
+            value: Box::new(|x, y| canvas.pixel_at(x, y).green),
+        },
+        Channel {
+            name: "B".to_string(),
+            value: Box::new(|x, y| canvas.pixel_at(x, y).blue),
+        },
+    ];
+    if canvas.has_alpha() {
+        channels.push(Channel {
+            name: "A".to_string(),
+            value: Box::new(|x, y| canvas.alpha_at(x, y)),
+        });
+    }
+    encode_channels(canvas.width(), canvas.height(), channels, &[])
+}
+
+// Like `encode` for any set of channels, with extra string attributes in
+// the header, e.g. metadata other tools look for.
+pub fn encode_channels(
+    width: usize,
+    height: usize,
+    mut channels: Vec<Channel>,
+    strings: &[(String, String)],
+) -> Vec<u8> {
+    // channels must be listed in alphabetical order
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut channel_list = vec![];
+    for channel in &channels {
+        channel_list.extend_from_slice(channel.name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        // pLinear and reserved bytes, then x and y sampling
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+
+    let mut window = vec![];
+    for v in [0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+
+    let mut exr = MAGIC.to_vec();
+    exr.extend_from_slice(&VERSION);
+    write_attribute(&mut exr, "channels", "chlist", &channel_list);
+    write_attribute(&mut exr, "compression", "compression", &[0]);
+    write_attribute(&mut exr, "dataWindow", "box2i", &window);
+    write_attribute(&mut exr, "displayWindow", "box2i", &window);
+    write_attribute(&mut exr, "lineOrder", "lineOrder", &[0]);
+    write_attribute(&mut exr, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    write_attribute(&mut exr, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(&mut exr, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    for (name, value) in strings {
+        write_attribute(&mut exr, name, "string", value.as_bytes());
+    }
+    exr.push(0);
+
+    // one scanline per block, preceded by the table of block offsets
+    let block_size = 8 + 4 * channels.len() * width;
+    let first_block = exr.len() + 8 * height;
+    for y in 0..height {
+        exr.extend_from_slice(&((first_block + y * block_size) as u64).to_le_bytes());
+    }
+    for y in 0..height {
+        exr.extend_from_slice(&(y as i32).to_le_bytes());
+        exr.extend_from_slice(&((block_size - 8) as i32).to_le_bytes());
+        for channel in &channels {
+            for x in 0..width {
+                // a no-op with the f32 feature
+                #[allow(clippy::unnecessary_cast)]
+                let v = (channel.value)(x, y) as f32;
+                exr.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+    }
+    exr
+}
+
+fn write_attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(kind.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::color::Color;
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|w| w == needle)
+    }
+
+    #[test]
+    fn test_an_exr_starts_with_the_magic_number_and_header() {
+        let exr = encode(&Canvas::new(3, 2));
+        assert_eq!(exr[..4], MAGIC);
+        assert_eq!(exr[4..8], VERSION);
+        assert_eq!(find(&exr, b"channels\0chlist\0"), Some(8));
+        assert!(find(&exr, b"A\0").is_none());
+        assert!(find(&exr, b"dataWindow\0box2i\0").is_some());
+    }
+
+    #[test]
+    fn test_the_pixel_data_follows_the_offset_table() {
+        let mut c = Canvas::new(1, 2);
+        c.write_pixel(0, 1, Color::new(1.5, 0.5, 0.25));
+        c.write_alpha(0, 1, 0.75);
+        let exr = encode(&c);
+        let block_size = 8 + 4 * 4;
+        let last = &exr[exr.len() - block_size..];
+        assert_eq!(last[..4], 1i32.to_le_bytes());
+        assert_eq!(last[4..8], 16i32.to_le_bytes());
+        let values: Vec<f32> = last[8..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, [0.75, 0.25, 0.5, 1.5]);
+        let offset_table = exr.len() - 2 * block_size - 16;
+        let offset =
+            u64::from_le_bytes(exr[offset_table + 8..offset_table + 16].try_into().unwrap());
+        assert_eq!(offset as usize, exr.len() - block_size);
+    }
+}
@@ -0,0 +1,355 @@
+// A C interface for driving the renderer from other languages; the matching
+// declarations are in include/ray_tracer.h. Worlds are opaque heap objects,
+// shapes are referred to by the ids the `rt_world_add_*` functions return,
+// and failures are reported as negative return values.
+use crate::camera::Camera;
+use crate::color::{Color, WHITE};
+use crate::cube::Cube;
+use crate::error::{RayTracerError, Result};
+use crate::light::PointLight;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::plane::Plane;
+use crate::point::Point;
+use crate::scalar::Scalar;
+use crate::shape::{LocalShape, Shape};
+use crate::sphere::Sphere;
+use crate::transform::{view_transform, Affine, IDENTITY_AFFINE};
+use crate::vector::Vector;
+use crate::world::World;
+use std::os::raw::c_int;
+use std::slice;
+
+pub const RT_OK: c_int = 0;
+// a null pointer, unknown shape id or too small buffer
+pub const RT_INVALID_ARGUMENT: c_int = -1;
+// the renderer rejected the input, e.g. a non-invertible transform
+pub const RT_RENDER_ERROR: c_int = -2;
+
+pub type RtWorld = World<'static>;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RtMaterial {
+    pub color: [f64; 3],
+    pub ambient: f64,
+    pub diffuse: f64,
+    pub specular: f64,
+    pub shininess: f64,
+    pub reflective: f64,
+    pub transparency: f64,
+    pub refractive_index: f64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RtCamera {
+    pub width: u32,
+    pub height: u32,
+    pub field_of_view: f64,
+    pub from: [f64; 3],
+    pub to: [f64; 3],
+    pub up: [f64; 3],
+}
+
+impl TryFrom<&RtMaterial> for Material<'static> {
+    type Error = RayTracerError;
+
+    #[allow(clippy::unnecessary_cast)]
+    fn try_from(m: &RtMaterial) -> Result<Self> {
+        Material::new()
+            .set_color(color(&m.color))?
+            .set_ambient(m.ambient as Scalar)?
+            .set_diffuse(m.diffuse as Scalar)?
+            .set_specular(m.specular as Scalar)?
+            .set_shininess(m.shininess as Scalar)?
+            .set_reflective(m.reflective as Scalar)?
+            .set_transparency(m.transparency as Scalar)?
+            .set_refractive_index(m.refractive_index as Scalar)
+    }
+}
+
+impl From<&Material<'_>> for RtMaterial {
+    #[allow(clippy::unnecessary_cast)]
+    fn from(m: &Material) -> Self {
+        let c = m.get_solid_color().unwrap_or(WHITE);
+        Self {
+            color: [c.red as f64, c.green as f64, c.blue as f64],
+            ambient: m.get_ambient() as f64,
+            diffuse: m.get_diffuse() as f64,
+            specular: m.get_specular() as f64,
+            shininess: m.get_shininess() as f64,
+            reflective: m.get_reflective() as f64,
+            transparency: m.get_transparency() as f64,
+            refractive_index: m.get_refractive_index() as f64,
+        }
+    }
+}
+
+impl RtCamera {
+    #[allow(clippy::unnecessary_cast)]
+    fn build(&self) -> crate::error::Result<Camera> {
+        let [x, y, z] = self.up.map(|v| v as Scalar);
+        Camera::new(
+            self.width as usize,
+            self.height as usize,
+            self.field_of_view as Scalar,
+        )?
+        .set_transform(view_transform(
+            &point(&self.from),
+            &point(&self.to),
+            &Vector::new(x, y, z),
+        )?)
+    }
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn color(c: &[f64; 3]) -> Color {
+    Color::new(c[0] as Scalar, c[1] as Scalar, c[2] as Scalar)
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn point(p: &[f64; 3]) -> Point {
+    Point::new(p[0] as Scalar, p[1] as Scalar, p[2] as Scalar)
+}
+
+// The top three rows of a row-major 4x4 matrix; null means the identity.
+#[allow(clippy::unnecessary_cast)]
+unsafe fn affine(transform: *const f64) -> Affine {
+    if transform.is_null() {
+        return IDENTITY_AFFINE;
+    }
+    let t = slice::from_raw_parts(transform, 12);
+    let row = |i: usize| {
+        [
+            t[4 * i] as Scalar,
+            t[4 * i + 1] as Scalar,
+            t[4 * i + 2] as Scalar,
+        ]
+    };
+    Affine::new(
+        Matrix::new([row(0), row(1), row(2)]),
+        Vector::new(t[3] as Scalar, t[7] as Scalar, t[11] as Scalar),
+    )
+}
+
+unsafe fn add_shape(
+    world: *mut RtWorld,
+    shape: impl LocalShape + 'static,
+    transform: *const f64,
+) -> i64 {
+    let Some(world) = world.as_mut() else {
+        return RT_INVALID_ARGUMENT.into();
+    };
+    match Shape::new(shape).set_transform(affine(transform)) {
+        Ok(shape) => world.add_shape(shape).id() as i64,
+        Err(_) => RT_RENDER_ERROR.into(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn rt_world_new() -> *mut RtWorld {
+    Box::into_raw(Box::new(World::new()))
+}
+
+/// # Safety
+/// `world` must be null or come from `rt_world_new`, and is not used again.
+#[no_mangle]
+pub unsafe extern "C" fn rt_world_free(world: *mut RtWorld) {
+    if !world.is_null() {
+        drop(Box::from_raw(world));
+    }
+}
+
+/// # Safety
+/// `world` must come from `rt_world_new`; `position` and `intensity` must
+/// point to three doubles each.
+#[no_mangle]
+pub unsafe extern "C" fn rt_world_add_light(
+    world: *mut RtWorld,
+    position: *const f64,
+    intensity: *const f64,
+) -> c_int {
+    let (Some(world), false, false) = (world.as_mut(), position.is_null(), intensity.is_null())
+    else {
+        return RT_INVALID_ARGUMENT;
+    };
+    let position = &*(position as *const [f64; 3]);
+    let intensity = &*(intensity as *const [f64; 3]);
+    world.add_light(PointLight::new(point(position), color(intensity)));
+    RT_OK
+}
+
+/// # Safety
+/// `world` must come from `rt_world_new`; `transform` must be null or point
+/// to twelve doubles.
+#[no_mangle]
+pub unsafe extern "C" fn rt_world_add_sphere(world: *mut RtWorld, transform: *const f64) -> i64 {
+    add_shape(world, Sphere::new(), transform)
+}
+
+/// # Safety
+/// As for `rt_world_add_sphere`.
+#[no_mangle]
+pub unsafe extern "C" fn rt_world_add_plane(world: *mut RtWorld, transform: *const f64) -> i64 {
+    add_shape(world, Plane::new(), transform)
+}
+
+/// # Safety
+/// As for `rt_world_add_sphere`.
+#[no_mangle]
+pub unsafe extern "C" fn rt_world_add_cube(world: *mut RtWorld, transform: *const f64) -> i64 {
+    add_shape(world, Cube::new(), transform)
+}
+
+// The defaults of `Material::new`, for callers to adjust.
+#[no_mangle]
+pub extern "C" fn rt_material_default() -> RtMaterial {
+    RtMaterial::from(&Material::new())
+}
+
+/// Materials with parameters out of range are rejected.
+///
+/// # Safety
+/// `world` must come from `rt_world_new` and `material` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn rt_shape_set_material(
+    world: *mut RtWorld,
+    shape: i64,
+    material: *const RtMaterial,
+) -> c_int {
+    let (Some(world), Some(material)) = (world.as_mut(), material.as_ref()) else {
+        return RT_INVALID_ARGUMENT;
+    };
+    if shape < 0 || shape as usize >= world.shape_count() {
+        return RT_INVALID_ARGUMENT;
+    }
+    match Material::try_from(material) {
+        Ok(material) => {
+            world.shape(shape as usize).set_material(material);
+            RT_OK
+        }
+        Err(_) => RT_INVALID_ARGUMENT,
+    }
+}
+
+/// # Safety
+/// `world` must come from `rt_world_new`, `camera` must be valid and
+/// `pixels` must point to `len` writable bytes. The image is written as
+/// `4 * width * height` bytes of RGBA, row by row.
+#[no_mangle]
+pub unsafe extern "C" fn rt_render(
+    world: *const RtWorld,
+    camera: *const RtCamera,
+    pixels: *mut u8,
+    len: usize,
+) -> c_int {
+    let (Some(world), Some(camera), false) = (world.as_ref(), camera.as_ref(), pixels.is_null())
+    else {
+        return RT_INVALID_ARGUMENT;
+    };
+    if len < 4 * camera.width as usize * camera.height as usize {
+        return RT_INVALID_ARGUMENT;
+    }
+    match camera.build() {
+        Ok(camera) => {
+            let rgba = camera.render(world).to_rgba8();
+            slice::from_raw_parts_mut(pixels, rgba.len()).copy_from_slice(&rgba);
+            RT_OK
+        }
+        Err(RayTracerError::InvalidCamera(_)) => RT_INVALID_ARGUMENT,
+        Err(_) => RT_RENDER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    const CAMERA: RtCamera = RtCamera {
+        width: 11,
+        height: 11,
+        field_of_view: 0.5,
+        from: [0.0, 0.0, -5.0],
+        to: [0.0, 0.0, 0.0],
+        up: [0.0, 1.0, 0.0],
+    };
+
+    #[test]
+    fn test_rendering_through_the_c_interface() {
+        unsafe {
+            let world = rt_world_new();
+            assert_eq!(
+                rt_world_add_light(world, [-10.0, 10.0, -10.0].as_ptr(), [1.0; 3].as_ptr()),
+                RT_OK
+            );
+            let floor = [
+                1.0, 0.0, 0.0, 0.0, //
+                0.0, 1.0, 0.0, -1.0, //
+                0.0, 0.0, 1.0, 0.0,
+            ];
+            assert_eq!(rt_world_add_plane(world, floor.as_ptr()), 0);
+            let sphere = rt_world_add_sphere(world, ptr::null());
+            assert_eq!(sphere, 1);
+            let material = RtMaterial {
+                color: [1.0, 0.0, 0.0],
+                ..rt_material_default()
+            };
+            assert_eq!(rt_shape_set_material(world, sphere, &material), RT_OK);
+
+            let mut pixels = vec![0; 4 * 11 * 11];
+            assert_eq!(
+                rt_render(world, &CAMERA, pixels.as_mut_ptr(), pixels.len()),
+                RT_OK
+            );
+            let center = &pixels[4 * (5 * 11 + 5)..][..4];
+            assert!(center[0] > 100);
+            assert_eq!(center[1], 0);
+            assert_eq!(center[3], 255);
+            rt_world_free(world);
+        }
+    }
+
+    #[test]
+    fn test_the_default_material_matches_the_renderer() {
+        let material = Material::try_from(&rt_material_default()).unwrap();
+        assert_eq!(material.solid_key(), Material::new().solid_key());
+    }
+
+    #[test]
+    fn test_invalid_arguments_are_rejected() {
+        unsafe {
+            let world = rt_world_new();
+            let material = rt_material_default();
+            assert_eq!(
+                rt_shape_set_material(world, 0, &material),
+                RT_INVALID_ARGUMENT
+            );
+            let sphere = rt_world_add_sphere(world, ptr::null());
+            let negative = RtMaterial {
+                diffuse: -1.0,
+                ..material
+            };
+            assert_eq!(
+                rt_shape_set_material(world, sphere, &negative),
+                RT_INVALID_ARGUMENT
+            );
+            let singular = [0.0; 12];
+            assert_eq!(
+                rt_world_add_cube(world, singular.as_ptr()),
+                RT_RENDER_ERROR as i64
+            );
+            let mut pixels = vec![0; 10];
+            assert_eq!(
+                rt_render(world, &CAMERA, pixels.as_mut_ptr(), pixels.len()),
+                RT_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                rt_world_add_light(ptr::null_mut(), ptr::null(), ptr::null()),
+                RT_INVALID_ARGUMENT
+            );
+            rt_world_free(world);
+        }
+    }
+}
@@ -1,11 +1,13 @@
+use crate::scalar::Scalar;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Intersection {
-    pub t: f64,
+    pub t: Scalar,
     pub object_id: usize,
 }
 
 impl Intersection {
-    pub fn new(t: f64, object_id: usize) -> Self {
+    pub fn new(t: Scalar, object_id: usize) -> Self {
         Self { t, object_id }
     }
 }
@@ -23,8 +25,15 @@ impl Intersections {
             intersections
         }
     }
+    pub fn iter(&self) -> impl Iterator<Item = &Intersection> {
+        self.intersections.iter()
+    }
     pub fn hit_index(&self) -> Option<usize> {
-        self.intersections.iter().position(|i| i.t >= 0.0)
+        self.hit_index_after(0.0)
+    }
+    // The first intersection with t >= min_t.
+    pub fn hit_index_after(&self, min_t: Scalar) -> Option<usize> {
+        self.intersections.iter().position(|i| i.t >= min_t)
     }
 }
 
@@ -35,6 +44,7 @@ impl From<Intersections> for Vec<Intersection> {
 }
 
 #[cfg(test)]
+#[allow(clippy::clone_on_copy)]
 mod tests {
 
     use super::*;
@@ -85,6 +95,15 @@ mod tests {
         assert_eq!(i, None);
     }
 
+    #[test]
+    fn test_the_hit_can_skip_intersections_close_to_the_origin() {
+        let i1 = Intersection::new(0.00001, 1);
+        let i2 = Intersection::new(1.0, 2);
+        let xs = Intersections::new([i1, i2]);
+        assert_eq!(xs.hit_index(), Some(0));
+        assert_eq!(xs.hit_index_after(0.001), Some(1));
+    }
+
     #[test]
     fn test_the_hit_is_always_the_lowest_nonnegative_intersection() {
         let i1 = Intersection::new(5.0, 1);
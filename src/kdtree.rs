@@ -0,0 +1,296 @@
+use crate::accelerator::TraversalStats;
+use crate::approx_eq::EPSILON;
+use crate::bounds::Bounds;
+use crate::point::Point;
+use crate::ray::Ray;
+use crate::scalar::Scalar;
+
+const MAX_LEAF_SIZE: usize = 4;
+
+enum Node {
+    Leaf {
+        start: usize,
+        end: usize,
+    },
+    Interior {
+        axis: usize,
+        split: Scalar,
+        left: usize,
+        right: usize,
+    },
+}
+
+// A kd-tree over opaque item ids: space is cut by axis-aligned planes and an
+// item is listed in every leaf its bounds overlap, so leaves are visited
+// front to back but may repeat an item. As with the BVH, items with
+// infinite bounds are always reported as candidates.
+pub struct KdTree {
+    bounds: Bounds,
+    nodes: Vec<Node>,
+    // indices into `items`, per leaf
+    leaf_items: Vec<usize>,
+    items: Vec<(usize, Bounds)>,
+    unbounded: Vec<usize>,
+}
+
+impl KdTree {
+    pub fn new(items: impl IntoIterator<Item = (usize, Bounds)>) -> Self {
+        let (items, unbounded): (Vec<_>, Vec<_>) = items
+            .into_iter()
+            .partition(|(_, bounds)| bounds.is_finite());
+        let bounds = items
+            .iter()
+            .fold(Bounds::empty(), |acc, (_, b)| acc.merge(b));
+        let mut tree = Self {
+            bounds,
+            nodes: vec![],
+            leaf_items: vec![],
+            items,
+            unbounded: unbounded.into_iter().map(|(id, _)| id).collect(),
+        };
+        if !tree.items.is_empty() {
+            // the depth limit suggested by Physically Based Rendering
+            let depth = 8 + (1.3 * (tree.items.len() as Scalar).log2()) as usize;
+            tree.build((0..tree.items.len()).collect(), bounds, depth);
+        }
+        tree
+    }
+    fn build(&mut self, items: Vec<usize>, bounds: Bounds, depth: usize) -> usize {
+        let node_index = self.nodes.len();
+        let leaf = |tree: &mut Self, items: &[usize]| {
+            let start = tree.leaf_items.len();
+            tree.leaf_items.extend_from_slice(items);
+            tree.nodes.push(Node::Leaf {
+                start,
+                end: tree.leaf_items.len(),
+            });
+            node_index
+        };
+        if items.len() <= MAX_LEAF_SIZE || depth == 0 {
+            return leaf(self, &items);
+        }
+
+        // split the widest axis at the median centroid
+        let axis = (0..3)
+            .max_by(|&a, &b| {
+                let da = bounds.max[a] - bounds.min[a];
+                let db = bounds.max[b] - bounds.min[b];
+                da.partial_cmp(&db).unwrap()
+            })
+            .unwrap();
+        let mut centroids: Vec<Scalar> = items
+            .iter()
+            .map(|i| self.items[*i].1.centroid()[axis])
+            .collect();
+        let mid = centroids.len() / 2;
+        let (_, split, _) = centroids.select_nth_unstable_by(mid, |a, b| a.partial_cmp(b).unwrap());
+        let split = *split;
+        let (left_items, right_items): (Vec<usize>, Vec<usize>) = (
+            items
+                .iter()
+                .copied()
+                .filter(|i| self.items[*i].1.min[axis] <= split)
+                .collect(),
+            items
+                .iter()
+                .copied()
+                .filter(|i| self.items[*i].1.max[axis] >= split)
+                .collect(),
+        );
+        // nothing is gained when every item straddles the plane
+        if left_items.len() == items.len() && right_items.len() == items.len() {
+            return leaf(self, &items);
+        }
+
+        // reserve the slot so the children can refer to their parent's index
+        self.nodes.push(Node::Leaf { start: 0, end: 0 });
+        let mut left_bounds = bounds;
+        left_bounds.max[axis] = split;
+        let mut right_bounds = bounds;
+        right_bounds.min[axis] = split;
+        let left = self.build(left_items, left_bounds, depth - 1);
+        let right = self.build(right_items, right_bounds, depth - 1);
+        self.nodes[node_index] = Node::Interior {
+            axis,
+            split,
+            left,
+            right,
+        };
+        node_index
+    }
+    // Calls `f` with every item whose bounds the ray may hit, roughly in
+    // order along the ray, stopping early when `f` returns true. Returns
+    // whether it stopped early.
+    pub fn traverse(
+        &self,
+        ray: &Ray,
+        stats: &mut TraversalStats,
+        mut f: impl FnMut(usize) -> bool,
+    ) -> bool {
+        stats.candidates += self.unbounded.len();
+        if self.unbounded.iter().any(|id| f(*id)) {
+            return true;
+        }
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let (tmin, tmax) = self.bounds.ray_interval(ray);
+        if tmin > tmax || tmax < 0.0 {
+            return false;
+        }
+        let mut seen = vec![];
+        let mut stack = vec![(0, tmin.max(0.0), tmax)];
+        while let Some((index, tmin, tmax)) = stack.pop() {
+            stats.nodes += 1;
+            match self.nodes[index] {
+                Node::Leaf { start, end } => {
+                    for item in &self.leaf_items[start..end] {
+                        if seen.contains(item) {
+                            continue;
+                        }
+                        seen.push(*item);
+                        let (id, bounds) = &self.items[*item];
+                        if !bounds.intersects(ray) {
+                            continue;
+                        }
+                        stats.candidates += 1;
+                        if f(*id) {
+                            return true;
+                        }
+                    }
+                }
+                Node::Interior {
+                    axis,
+                    split,
+                    left,
+                    right,
+                } => {
+                    let origin = ray.origin[axis];
+                    let direction = ray.direction[axis];
+                    let (near, far) = if origin < split || (origin == split && direction <= 0.0) {
+                        (left, right)
+                    } else {
+                        (right, left)
+                    };
+                    // where the ray crosses the plane, with some slack so
+                    // rounding never skips a child the ray grazes
+                    let t = (split - origin) / direction;
+                    if t.is_nan() {
+                        stack.push((far, tmin, tmax));
+                        stack.push((near, tmin, tmax));
+                    } else if t > tmax + EPSILON || t < 0.0 {
+                        stack.push((near, tmin, tmax));
+                    } else if t < tmin - EPSILON {
+                        stack.push((far, tmin, tmax));
+                    } else {
+                        stack.push((far, t.max(tmin), tmax));
+                        stack.push((near, tmin, t.min(tmax)));
+                    }
+                }
+            }
+        }
+        false
+    }
+    pub fn for_each_containing(&self, p: &Point, mut f: impl FnMut(usize)) {
+        self.unbounded.iter().for_each(|id| f(*id));
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut seen = vec![];
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            match self.nodes[index] {
+                Node::Leaf { start, end } => {
+                    for item in &self.leaf_items[start..end] {
+                        let (id, bounds) = &self.items[*item];
+                        if !seen.contains(item) && bounds.contains(p) {
+                            seen.push(*item);
+                            f(*id);
+                        }
+                    }
+                }
+                Node::Interior {
+                    axis,
+                    split,
+                    left,
+                    right,
+                } => {
+                    if p[axis] <= split + EPSILON {
+                        stack.push(left);
+                    }
+                    if p[axis] >= split - EPSILON {
+                        stack.push(right);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::vector::Vector;
+
+    fn unit_box_at(x: Scalar) -> Bounds {
+        Bounds::new(
+            Point::new(x - 0.5, -0.5, -0.5),
+            Point::new(x + 0.5, 0.5, 0.5),
+        )
+    }
+
+    fn candidates(tree: &KdTree, ray: &Ray) -> Vec<usize> {
+        let mut ids = vec![];
+        tree.traverse(ray, &mut TraversalStats::default(), |id| {
+            ids.push(id);
+            false
+        });
+        ids
+    }
+
+    #[test]
+    fn test_a_ray_only_visits_the_boxes_it_passes_through() {
+        let tree = KdTree::new((0..20).map(|i| (i, unit_box_at(2.0 * i as Scalar))));
+        let r = Ray::new(Point::new(10.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(candidates(&tree, &r), vec![5]);
+    }
+
+    #[test]
+    fn test_boxes_are_visited_front_to_back_and_once() {
+        // overlapping boxes straddle the splitting planes
+        let tree = KdTree::new((0..20).map(|i| (i, unit_box_at(0.75 * i as Scalar))));
+        let r = Ray::new(Point::new(20.0, 0.0, 0.0), Vector::new(-1.0, 0.0, 0.0));
+        let mut ids = candidates(&tree, &r);
+        assert_eq!(ids.len(), 20);
+        // leaves come in order, though not the items within them
+        assert!(ids[..3].iter().all(|id| *id >= 15));
+        assert!(ids[17..].iter().all(|id| *id <= 4));
+        ids.sort();
+        assert_eq!(ids, (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_boxes_behind_the_ray_are_skipped() {
+        let tree = KdTree::new((0..20).map(|i| (i, unit_box_at(2.0 * i as Scalar))));
+        let r = Ray::new(Point::new(9.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let mut ids = candidates(&tree, &r);
+        ids.sort();
+        assert_eq!(ids, (5..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_unbounded_items_are_always_candidates() {
+        let tree = KdTree::new([(0, unit_box_at(0.0)), (1, Bounds::infinite())]);
+        let r = Ray::new(Point::new(10.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(candidates(&tree, &r), vec![1]);
+    }
+
+    #[test]
+    fn test_finding_the_boxes_containing_a_point() {
+        let tree = KdTree::new((0..20).map(|i| (i, unit_box_at(2.0 * i as Scalar))));
+        let mut ids = vec![];
+        tree.for_each_containing(&Point::new(10.25, 0.0, 0.0), |id| ids.push(id));
+        assert_eq!(ids, vec![5]);
+    }
+}
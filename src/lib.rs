@@ -0,0 +1,66 @@
+pub mod accelerator;
+pub mod aov;
+pub mod approx_eq;
+pub mod bloom;
+pub mod bounds;
+pub mod bvh;
+pub mod camera;
+pub mod camera_path;
+pub mod canvas;
+pub mod checkpoint;
+pub mod color;
+pub mod cube;
+pub mod curve;
+pub mod cylinder;
+pub mod denoise;
+pub mod depth;
+pub mod environment;
+pub mod error;
+pub mod exr;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod font;
+pub mod intersection;
+pub mod kdtree;
+pub mod light;
+pub mod manifest;
+#[cfg(feature = "mmap")]
+pub mod mapped;
+pub mod material;
+pub mod matte;
+pub mod matrix;
+pub mod mesh;
+pub mod metaball;
+pub mod mtl;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod packet;
+pub mod pattern;
+pub mod plane;
+pub mod png;
+pub mod point;
+pub mod prefab;
+pub mod procgen;
+#[cfg(feature = "preview")]
+pub mod preview;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod quadric;
+pub mod ray;
+pub mod sampler;
+pub mod scalar;
+#[cfg(feature = "serde")]
+pub mod scene;
+pub mod sdf;
+pub mod shape;
+#[cfg(feature = "spectral")]
+pub mod spectrum;
+pub mod sphere;
+pub mod tile;
+pub mod timing;
+pub mod transform;
+pub mod units;
+pub mod vector;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod world;
@@ -1,10 +1,44 @@
 use crate::color::Color;
+use crate::error::{RayTracerError, Result};
 use crate::point::Point;
+use crate::sampler::unit_ball;
+use crate::scalar::Scalar;
 use crate::vector::Vector;
 
+const DEFAULT_SHADOW_SAMPLES: usize = 16;
+
+// Remembers the shape that last blocked a shadow ray towards each light,
+// by the light's index in the world. Shadow rays of neighbouring pixels
+// tend to be blocked by the same shape, so it is worth testing that one
+// before intersecting the whole world. An id that is stale only costs a
+// miss.
+#[derive(Debug, Clone, Default)]
+pub struct OccluderCache {
+    // object ids by light index
+    entries: Vec<Option<usize>>,
+}
+
+impl OccluderCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn get(&self, light: usize) -> Option<usize> {
+        self.entries.get(light).copied().flatten()
+    }
+    pub fn set(&mut self, light: usize, object_id: usize) {
+        if light >= self.entries.len() {
+            self.entries.resize(light + 1, None);
+        }
+        self.entries[light] = Some(object_id);
+    }
+}
+
 pub struct PointLight {
     position: Point,
     intensity: Color,
+    radius: Scalar,
+    shadow_samples: usize,
+    visible: bool,
 }
 
 impl PointLight {
@@ -12,17 +46,76 @@ impl PointLight {
         PointLight {
             position,
             intensity,
+            radius: 0.0,
+            shadow_samples: DEFAULT_SHADOW_SAMPLES,
+            visible: false,
+        }
+    }
+    // A nonzero radius turns the light into a small sphere and gives soft
+    // shadows, estimated from `shadow_samples` rays to points inside it.
+    pub fn set_radius(self, radius: Scalar) -> Result<Self> {
+        if !(radius >= 0.0 && radius.is_finite()) {
+            return Err(RayTracerError::InvalidLight(format!(
+                "radius must be non-negative, got {}",
+                radius
+            )));
         }
+        Ok(Self { radius, ..self })
+    }
+    pub fn set_shadow_samples(self, shadow_samples: usize) -> Self {
+        Self {
+            shadow_samples: shadow_samples.max(1),
+            ..self
+        }
+    }
+    // A visible light shows as a glowing sphere of its radius, in its own
+    // color, to camera rays and in reflections and refractions, so mirrors
+    // show where the light comes from. A light without a radius stays
+    // invisible.
+    pub fn set_visible(self, visible: bool) -> Self {
+        Self { visible, ..self }
+    }
+    pub(crate) fn update_position(&mut self, position: Point) {
+        self.position = position;
+    }
+    pub fn get_position(&self) -> Point {
+        self.position
+    }
+    pub fn get_intensity(&self) -> Color {
+        self.intensity
+    }
+    pub fn get_radius(&self) -> Scalar {
+        self.radius
+    }
+    pub fn get_shadow_samples(&self) -> usize {
+        self.shadow_samples
+    }
+    pub fn is_visible(&self) -> bool {
+        self.visible
     }
     pub fn combine(&self, color: &Color) -> Color {
         self.intensity * color
     }
-    pub fn scale_intensity(&self, factor: f64) -> Color {
+    pub fn scale_intensity(&self, factor: Scalar) -> Color {
         self.intensity * factor
     }
     pub fn vector_from(&self, point: &Point) -> Vector {
         self.position - point
     }
+    // Vector from `point` to a point within the light's radius, picked by
+    // `sample` from the unit cube.
+    pub fn sample_vector_from(&self, point: &Point, sample: [Scalar; 3]) -> Vector {
+        self.sample_vector_within(point, self.radius, sample)
+    }
+    // The same within any `radius` of the light's position.
+    pub fn sample_vector_within(
+        &self,
+        point: &Point,
+        radius: Scalar,
+        sample: [Scalar; 3],
+    ) -> Vector {
+        self.vector_from(point) + &(&unit_ball(sample) * radius)
+    }
 }
 
 #[cfg(test)]
@@ -30,6 +123,8 @@ mod tests {
 
     use super::*;
     use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::color::WHITE;
+    use crate::sampler::Sampler;
 
     #[test]
     fn test_a_point_light_has_a_position_and_intensity() {
@@ -38,5 +133,32 @@ mod tests {
         let light = PointLight::new(position, intensity);
         assert_approx_eq!(light.position, position);
         assert_approx_eq!(light.intensity, intensity);
+        assert_eq!(light.get_radius(), 0.0);
+    }
+
+    #[test]
+    fn test_sampling_points_on_a_light_with_a_radius() {
+        let light = PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE)
+            .set_radius(2.0)
+            .unwrap();
+        let mut sampler = Sampler::new(0);
+        for _ in 0..50 {
+            let sample = [sampler.next_f64(), sampler.next_f64(), sampler.next_f64()];
+            let v = light.sample_vector_from(&Point::new(0.0, 0.0, 0.0), sample);
+            let offset = v - &Vector::new(0.0, 0.0, -10.0);
+            assert!(offset.magnitude() <= 2.0);
+        }
+    }
+
+    #[test]
+    fn test_a_light_needs_a_valid_radius() {
+        let light = || PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE);
+        assert!(light().set_radius(0.0).is_ok());
+        for radius in [-1.0, Scalar::NAN, Scalar::INFINITY] {
+            assert!(matches!(
+                light().set_radius(radius),
+                Err(RayTracerError::InvalidLight(_))
+            ));
+        }
     }
 }
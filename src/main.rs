@@ -1,39 +1,54 @@
-mod approx_eq;
-mod camera;
-mod canvas;
-mod color;
-mod cube;
-mod cylinder;
-mod intersection;
-mod light;
-mod material;
-mod matrix;
-mod pattern;
-mod plane;
-mod point;
-mod ray;
-mod shape;
-mod sphere;
-mod transform;
-mod vector;
-mod world;
-
-use camera::Camera;
-use color::{Color, WHITE};
-use cube::Cube;
-use light::PointLight;
-use material::Material;
-use pattern::CheckersPattern;
-use plane::Plane;
-use point::Point;
-use shape::Shape;
-use std::f64::consts::PI;
+use ray_tracer::camera::Camera;
+use ray_tracer::canvas::Canvas;
+#[cfg(feature = "preview")]
+use ray_tracer::canvas::PixelFormat;
+use ray_tracer::color::{Color, WHITE};
+use ray_tracer::cube::Cube;
+use ray_tracer::error::RayTracerError;
+use ray_tracer::light::PointLight;
+use ray_tracer::manifest::{parse_manifest, RenderJob};
+use ray_tracer::material::Material;
+use ray_tracer::mesh::Mesh;
+use ray_tracer::net::{render_distributed, serve};
+use ray_tracer::pattern::{CheckersPattern, RingPattern, StripedPattern, TriplanarPattern};
+use ray_tracer::plane::Plane;
+use ray_tracer::point::Point;
+#[cfg(feature = "preview")]
+use ray_tracer::preview::{fly, preview};
+use ray_tracer::scalar::consts::PI;
+use ray_tracer::scalar::Scalar;
+use ray_tracer::shape::Shape;
+use ray_tracer::sphere::Sphere;
+#[cfg(feature = "preview")]
+use ray_tracer::tile::TileOrder;
+use ray_tracer::transform::{
+    rotation_x, rotation_y, rotation_z, scaling, translation, view_transform, IDENTITY_AFFINE,
+};
+use ray_tracer::vector::Vector;
+use ray_tracer::world::World;
+use std::env;
 use std::fs;
-use transform::{translation, view_transform, IDENTITY_AFFINE};
-use vector::Vector;
-use world::World;
+use std::net::TcpListener;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
 
-fn main() {
+const USAGE: &str = "usage: ray-tracer [--manifest FILE [--jobs N] | \
+                     [--distribute ADDRESS,... | --preview | --fly] --job SCENE [KEY=VALUE...] | \
+                     demo [NAME [KEY=VALUE...]] | --worker ADDRESS]";
+
+type Scene = (World<'static>, Camera);
+type SceneFn = fn() -> Result<Scene, RayTracerError>;
+
+// The built-in scenes, by name, with a line describing each.
+const DEMOS: &[(&str, &str, SceneFn)] = &[
+    ("demo", "a glass cube on a checkered floor", demo),
+    ("glass", "reflection and refraction", glass_demo),
+    ("patterns", "every kind of pattern", patterns_demo),
+    ("mesh", "triangle meshes, smooth and faceted", mesh_demo),
+];
+
+fn demo() -> Result<Scene, RayTracerError> {
     let mut world = World::new();
     world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
 
@@ -44,33 +59,441 @@ fn main() {
                 .set_pattern(
                     CheckersPattern::new(Color::new(1.0, 0.9, 0.9), Color::new(0.5, 0.45, 0.45)),
                     IDENTITY_AFFINE,
-                )
-                .set_specular(0.0),
+                )?
+                .set_specular(0.0)?,
         ),
     );
 
     // cube
     world.add_shape(
         Shape::new(Cube::new())
-            .set_transform(translation(0.0, 1.0, 0.5))
+            .set_transform(translation(0.0, 1.0, 0.5))?
             .set_material(
                 Material::new()
-                    .set_color(Color::new(0.1, 1.0, 0.5))
-                    .set_diffuse(0.7)
-                    .set_specular(0.0)
-                    .set_transparency(1.0)
-                    .set_refractive_index(1.5)
-                    .set_reflective(0.9),
+                    .set_color(Color::new(0.1, 1.0, 0.5))?
+                    .set_diffuse(0.7)?
+                    .set_specular(0.0)?
+                    .set_transparency(1.0)?
+                    .set_refractive_index(1.5)?
+                    .set_reflective(0.9)?,
             ),
     );
 
-    let camera = Camera::new(800, 400, PI / 3.0).set_transform(view_transform(
+    let camera = Camera::new(800, 400, PI / 3.0)?.set_transform(view_transform(
         &Point::new(2.0, 4.0, -6.0),
         &Point::new(0.0, 1.0, -1.0),
         &Vector::new(0.0, 1.0, 0.0),
-    ));
+    )?)?;
+
+    Ok((world, camera))
+}
+
+fn looking_at(from: Point, to: Point) -> Result<Camera, RayTracerError> {
+    Camera::new(800, 400, PI / 3.0)?.set_transform(view_transform(
+        &from,
+        &to,
+        &Vector::new(0.0, 1.0, 0.0),
+    )?)
+}
+
+fn checkered_floor() -> Result<Shape<'static>, RayTracerError> {
+    Ok(Shape::new(Plane::new()).set_material(
+        Material::new()
+            .set_pattern(
+                CheckersPattern::new(Color::new(0.9, 0.9, 0.9), Color::new(0.3, 0.3, 0.3)),
+                IDENTITY_AFFINE,
+            )?
+            .set_specular(0.0)?
+            .set_reflective(0.1)?,
+    ))
+}
+
+// A hollow glass ball in front of a mirror ball and a matte one, so both
+// the refracted and the reflected views are visible.
+fn glass_demo() -> Result<Scene, RayTracerError> {
+    let mut world = World::new();
+    world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+    world.add_shape(checkered_floor()?);
+
+    let glass = || {
+        Material::new()
+            .set_color(Color::new(0.05, 0.05, 0.05))?
+            .set_diffuse(0.1)?
+            .set_specular(1.0)?
+            .set_shininess(300.0)?
+            .set_reflective(0.9)?
+            .set_transparency(0.9)?
+            .set_refractive_index(1.5)
+    };
+    world.add_shape(
+        Shape::new(Sphere::new())
+            .set_transform(translation(0.0, 1.0, 0.0))?
+            .set_material(glass()?),
+    );
+    // the air bubble inside makes it a thick shell
+    world.add_shape(
+        Shape::new(Sphere::new())
+            .set_transform(translation(0.0, 1.0, 0.0) * &scaling(0.7, 0.7, 0.7))?
+            .set_material(glass()?.set_refractive_index(1.0)?),
+    );
+    world.add_shape(
+        Shape::new(Sphere::new())
+            .set_transform(translation(-2.0, 1.0, 3.0))?
+            .set_material(
+                Material::new()
+                    .set_color(Color::new(0.1, 0.1, 0.1))?
+                    .set_diffuse(0.2)?
+                    .set_reflective(0.9)?,
+            ),
+    );
+    world.add_shape(
+        Shape::new(Sphere::new())
+            .set_transform(translation(2.0, 1.0, 3.0))?
+            .set_material(
+                Material::new()
+                    .set_color(Color::new(0.9, 0.2, 0.1))?
+                    .set_specular(0.3)?,
+            ),
+    );
+
+    let camera = looking_at(Point::new(0.0, 2.5, -6.0), Point::new(0.0, 1.0, 1.0))?;
+    Ok((world, camera))
+}
+
+// A row of shapes, one per kind of pattern.
+fn patterns_demo() -> Result<Scene, RayTracerError> {
+    let mut world = World::new();
+    world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+    world.add_shape(checkered_floor()?);
+
+    let red = Color::new(0.9, 0.2, 0.1);
+    let cream = Color::new(1.0, 0.95, 0.8);
+    let blue = Color::new(0.1, 0.3, 0.9);
+    let small = scaling(0.25, 0.25, 0.25);
+    let materials = [
+        Material::new().set_pattern(
+            StripedPattern::new(red, cream),
+            rotation_z(PI / 4.0) * &small,
+        )?,
+        Material::new().set_pattern(
+            RingPattern::new(blue, cream),
+            rotation_x(PI / 2.0) * &scaling(0.15, 0.15, 0.15),
+        )?,
+        Material::new().set_pattern(CheckersPattern::new(red, blue), small)?,
+        // a gradient by height, written inline
+        Material::new().set_pattern(
+            move |p: &Point| red + (blue - red) * ((p.y + 1.0) / 2.0),
+            IDENTITY_AFFINE,
+        )?,
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        world.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(translation(2.5 * i as Scalar - 3.75, 1.0, 0.0))?
+                .set_material(material),
+        );
+    }
+    // projected onto every face without stretching
+    world.add_shape(
+        Shape::new(Cube::new())
+            .set_transform(
+                translation(0.0, 0.75, -2.5) * &rotation_y(PI / 5.0) * &scaling(0.75, 0.75, 0.75),
+            )?
+            .set_material(Material::new().set_pattern(
+                TriplanarPattern::new(CheckersPattern::new(cream, blue)),
+                scaling(0.5, 0.5, 0.5),
+            )?),
+    );
+
+    let camera = looking_at(Point::new(0.0, 4.0, -9.0), Point::new(0.0, 1.0, 0.0))?;
+    Ok((world, camera))
+}
+
+// A torus around the y axis as a mesh of `rings` by `sides` quads, colored
+// around the ring.
+fn torus(rings: usize, sides: usize) -> Result<Mesh, RayTracerError> {
+    let mut vertices = vec![];
+    let mut colors = vec![];
+    for i in 0..rings {
+        let u = 2.0 * PI * i as Scalar / rings as Scalar;
+        for j in 0..sides {
+            let v = 2.0 * PI * j as Scalar / sides as Scalar;
+            let r = 1.0 + 0.4 * v.cos();
+            vertices.push(Point::new(r * u.cos(), 0.4 * v.sin(), r * u.sin()));
+            colors.push(Color::new(
+                0.5 + 0.5 * u.cos(),
+                0.5 + 0.5 * (u + 2.0 * PI / 3.0).cos(),
+                0.5 + 0.5 * (u + 4.0 * PI / 3.0).cos(),
+            ));
+        }
+    }
+    let index = |i: usize, j: usize| ((i % rings) * sides + j % sides) as u32;
+    let mut indices = vec![];
+    for i in 0..rings {
+        for j in 0..sides {
+            let (a, b) = (index(i, j), index(i + 1, j));
+            let (c, d) = (index(i + 1, j + 1), index(i, j + 1));
+            indices.push([a, c, b]);
+            indices.push([a, d, c]);
+        }
+    }
+    Mesh::new(vertices, indices)?.set_colors(colors)
+}
+
+// The same torus twice: with normals smoothed across its faces, and at a
+// lower resolution with every edge left sharp.
+fn mesh_demo() -> Result<Scene, RayTracerError> {
+    let mut world = World::new();
+    world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+    world.add_shape(checkered_floor()?);
+
+    // keeps the inside of the ring, where the faces meet at an angle, from
+    // shadowing itself
+    world.set_surface_offset(0.001);
+    let material = || Material::new().set_specular(0.4);
+    world.add_shape(
+        Shape::new(torus(96, 48)?.generate_normals(PI / 3.0))
+            .set_transform(translation(-1.6, 1.0, 0.0) * &rotation_x(-PI / 4.0))?
+            .set_material(material()?),
+    );
+    world.add_shape(
+        Shape::new(torus(16, 8)?.generate_normals(0.0))
+            .set_transform(translation(1.6, 1.0, 0.0) * &rotation_x(-PI / 4.0))?
+            .set_material(material()?),
+    );
+
+    let camera = looking_at(Point::new(0.0, 3.0, -6.0), Point::new(0.0, 0.8, 0.0))?;
+    Ok((world, camera))
+}
+
+fn scene(job: &RenderJob) -> Result<Scene, RayTracerError> {
+    match DEMOS.iter().find(|(name, _, _)| *name == job.scene) {
+        Some((_, _, build)) => build(),
+        None => Err(RayTracerError::Parse {
+            line: job.line,
+            message: format!("unknown scene '{}'", job.scene),
+        }),
+    }
+}
+
+fn list_demos() {
+    for (name, description, _) in DEMOS {
+        println!("{:<10}{}", name, description);
+    }
+}
+
+fn write_canvas(canvas: &Canvas, path: &str) -> Result<(), RayTracerError> {
+    if path.ends_with(".png") {
+        fs::write(path, canvas.to_png())?;
+    } else if path.ends_with(".exr") {
+        fs::write(path, canvas.to_exr())?;
+    } else {
+        fs::write(path, canvas.to_ppm())?;
+    }
+    Ok(())
+}
+
+// The job's scene with its overrides applied to the camera.
+fn setup(job: &RenderJob) -> Result<Scene, RayTracerError> {
+    let (world, camera) = scene(job)?;
+    let mut camera = camera.set_size(
+        job.width.unwrap_or(camera.get_hsize()),
+        job.height.unwrap_or(camera.get_vsize()),
+    )?;
+    if let Some(samples) = job.samples {
+        camera = camera.set_samples(samples);
+    }
+    if let Some(exposure) = job.exposure {
+        camera = camera.set_exposure(exposure);
+    }
+    Ok((world, camera))
+}
+
+// With a snapshot interval the output is written as the render goes, so
+// long jobs can be checked on before they finish.
+fn render(job: &RenderJob) -> Result<(), RayTracerError> {
+    let (world, camera) = setup(job)?;
+    let canvas = match job.snapshot {
+        Some(interval) => camera
+            .render_with_snapshots(&world, interval, |canvas| write_canvas(canvas, &job.output))?,
+        None => camera.render(&world),
+    };
+    write_canvas(&canvas, &job.output)
+}
+
+// Workers are sent the job itself and set up the scene on their side.
+fn render_on_workers(job: &RenderJob, workers: &str) -> Result<(), RayTracerError> {
+    let (_, camera) = setup(job)?;
+    let workers: Vec<&str> = workers.split(',').collect();
+    let canvas = render_distributed(&camera, &job.to_tokens().join(" "), &workers)?;
+    write_canvas(&canvas, &job.output)
+}
+
+// S in the preview window saves to the job's output.
+#[cfg(feature = "preview")]
+fn render_in_window(job: &RenderJob, flying: bool) -> Result<(), RayTracerError> {
+    let (world, camera) = setup(job)?;
+    // fill in from the middle, where the subject usually is, into an image
+    // half the size that still keeps highlights for saving as EXR
+    let camera = camera
+        .set_tile_order(TileOrder::Spiral)
+        .set_pixel_format(PixelFormat::Half);
+    let title = format!("{} -> {}", job.scene, job.output);
+    if flying {
+        return fly(&camera, &world, &title);
+    }
+    preview(&camera, &world, &title, |canvas| {
+        write_canvas(canvas, &job.output)
+    })?;
+    Ok(())
+}
+
+#[cfg(not(feature = "preview"))]
+fn render_in_window(_job: &RenderJob, _flying: bool) -> Result<(), RayTracerError> {
+    Err(RayTracerError::Io(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "built without the preview feature",
+    )))
+}
+
+fn run_worker(address: &str) -> Result<(), RayTracerError> {
+    let listener = TcpListener::bind(address)?;
+    println!("rendering tiles for {}", listener.local_addr()?);
+    serve(listener, |key| setup(&RenderJob::parse(0, key)?))
+}
+
+// Renders every job in turn, going on past failures; returns how many
+// failed.
+fn run_sequentially(jobs: &[RenderJob]) -> usize {
+    let mut failed = 0;
+    for job in jobs {
+        let start = Instant::now();
+        let result = render(job);
+        report(job, start.elapsed(), result.is_ok());
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            failed += 1;
+        }
+    }
+    failed
+}
+
+// Renders every job in its own process, at most `parallel` at a time;
+// returns how many failed.
+fn run_in_processes(jobs: &[RenderJob], parallel: usize) -> Result<usize, RayTracerError> {
+    let exe = env::current_exe()?;
+    let mut pending = jobs.iter();
+    let mut running: Vec<(&RenderJob, Child, Instant)> = vec![];
+    let mut failed = 0;
+    loop {
+        while running.len() < parallel {
+            let Some(job) = pending.next() else {
+                break;
+            };
+            let child = Command::new(&exe)
+                .arg("--job")
+                .args(job.to_tokens())
+                .spawn()?;
+            running.push((job, child, Instant::now()));
+        }
+        if running.is_empty() {
+            break;
+        }
+        let mut i = 0;
+        while i < running.len() {
+            if let Some(status) = running[i].1.try_wait()? {
+                let (job, _, start) = running.remove(i);
+                report(job, start.elapsed(), status.success());
+                if !status.success() {
+                    failed += 1;
+                }
+            } else {
+                i += 1;
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(failed)
+}
+
+fn report(job: &RenderJob, elapsed: Duration, success: bool) {
+    println!(
+        "{:>8.2}s  {} -> {}{}",
+        elapsed.as_secs_f64(),
+        job.scene,
+        job.output,
+        if success { "" } else { "  FAILED" }
+    );
+}
 
-    let canvas = camera.render(&world);
+fn run_manifest(path: &str, parallel: usize) -> Result<(), RayTracerError> {
+    let jobs = parse_manifest(&fs::read_to_string(path)?)?;
+    // catch unknown scenes before spending time on the others
+    for job in &jobs {
+        scene(job)?;
+    }
+    let start = Instant::now();
+    let failed = if parallel > 1 {
+        run_in_processes(&jobs, parallel)?
+    } else {
+        run_sequentially(&jobs)
+    };
+    println!(
+        "{} jobs in {:.2}s",
+        jobs.len(),
+        start.elapsed().as_secs_f64()
+    );
+    if failed > 0 {
+        return Err(RayTracerError::Io(std::io::Error::other(format!(
+            "{} of {} jobs failed",
+            failed,
+            jobs.len()
+        ))));
+    }
+    Ok(())
+}
+
+fn usage_error() -> RayTracerError {
+    RayTracerError::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, USAGE))
+}
 
-    fs::write("canvas.ppm", canvas.to_ppm()).expect("Unable to write file");
+fn main() -> Result<(), RayTracerError> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => render(&RenderJob::parse(0, "demo output=canvas.ppm")?),
+        Some("--job") => render(&RenderJob::parse(0, &args[1..].join(" "))?),
+        Some("--distribute") => {
+            let workers = args.get(1).ok_or_else(usage_error)?;
+            if args.get(2).map(String::as_str) != Some("--job") {
+                return Err(usage_error());
+            }
+            render_on_workers(&RenderJob::parse(0, &args[3..].join(" "))?, workers)
+        }
+        Some(mode @ ("--preview" | "--fly")) => {
+            if args.get(1).map(String::as_str) != Some("--job") {
+                return Err(usage_error());
+            }
+            render_in_window(&RenderJob::parse(0, &args[2..].join(" "))?, mode == "--fly")
+        }
+        Some("demo") if args.len() == 1 => {
+            list_demos();
+            Ok(())
+        }
+        // written to NAME.ppm unless an output is given
+        Some("demo") => render(&RenderJob::parse(0, &args[1..].join(" "))?),
+        Some("--worker") => run_worker(args.get(1).ok_or_else(usage_error)?),
+        Some("--manifest") => {
+            let path = args.get(1).ok_or_else(usage_error)?;
+            let parallel = match args.get(2).map(String::as_str) {
+                None => 1,
+                Some("--jobs") => args
+                    .get(3)
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(usage_error)?,
+                Some(_) => return Err(usage_error()),
+            };
+            run_manifest(path, parallel)
+        }
+        Some(_) => Err(usage_error()),
+    }
 }
@@ -0,0 +1,181 @@
+use crate::camera::SnapshotInterval;
+use crate::error::{RayTracerError, Result};
+use crate::scalar::Scalar;
+use std::time::Duration;
+
+// One render in a batch manifest. Each non-blank line of a manifest names
+// a scene followed by optional `key=value` overrides, e.g.
+//
+//     # nightly suite
+//     demo output=demo.png width=400 height=200 samples=4 exposure=-1
+//     demo output=big.png width=4000 height=2000 snapshot=30s
+//
+// Lines starting with '#' are comments. A snapshot interval, in seconds
+// (`30s`) or tiles (`16tiles`), writes the unfinished image to the output
+// that often.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderJob {
+    pub line: usize,
+    pub scene: String,
+    pub output: String,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples: Option<usize>,
+    // in stops, see `Camera::set_exposure`
+    pub exposure: Option<Scalar>,
+    pub snapshot: Option<SnapshotInterval>,
+}
+
+impl RenderJob {
+    pub fn parse(line: usize, text: &str) -> Result<Self> {
+        let error = |message: String| RayTracerError::Parse { line, message };
+        let mut tokens = text.split_whitespace();
+        let scene = tokens
+            .next()
+            .ok_or_else(|| error("missing scene name".to_string()))?;
+        let mut job = Self {
+            line,
+            scene: scene.to_string(),
+            output: format!("{}.ppm", scene),
+            width: None,
+            height: None,
+            samples: None,
+            exposure: None,
+            snapshot: None,
+        };
+        for token in tokens {
+            let (key, value) = token
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected key=value, got '{}'", token)))?;
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| error(format!("{} must be a positive integer", key)))
+            };
+            match key {
+                "output" => job.output = value.to_string(),
+                "width" => job.width = Some(number()?),
+                "height" => job.height = Some(number()?),
+                "samples" => job.samples = Some(number()?),
+                "exposure" => {
+                    let exposure = value
+                        .parse::<Scalar>()
+                        .ok()
+                        .filter(|e| e.is_finite())
+                        .ok_or_else(|| error("exposure must be a number".to_string()))?;
+                    job.exposure = Some(exposure);
+                }
+                "snapshot" => {
+                    let interval = parse_interval(value).ok_or_else(|| {
+                        error("snapshot must be seconds like 30s or tiles like 16tiles".to_string())
+                    })?;
+                    job.snapshot = Some(interval);
+                }
+                _ => return Err(error(format!("unknown setting '{}'", key))),
+            }
+        }
+        Ok(job)
+    }
+    // The job as it would be written in a manifest, one token per element.
+    pub fn to_tokens(&self) -> Vec<String> {
+        let mut tokens = vec![self.scene.clone(), format!("output={}", self.output)];
+        let settings = [
+            ("width", self.width),
+            ("height", self.height),
+            ("samples", self.samples),
+        ];
+        for (key, value) in settings {
+            if let Some(value) = value {
+                tokens.push(format!("{}={}", key, value));
+            }
+        }
+        if let Some(exposure) = self.exposure {
+            tokens.push(format!("exposure={}", exposure));
+        }
+        match self.snapshot {
+            Some(SnapshotInterval::Time(time)) => {
+                tokens.push(format!("snapshot={}s", time.as_secs_f64()))
+            }
+            Some(SnapshotInterval::Tiles(tiles)) => tokens.push(format!("snapshot={}tiles", tiles)),
+            None => {}
+        }
+        tokens
+    }
+}
+
+fn parse_interval(value: &str) -> Option<SnapshotInterval> {
+    if let Some(tiles) = value.strip_suffix("tiles") {
+        let tiles = tiles.parse::<usize>().ok().filter(|n| *n > 0)?;
+        return Some(SnapshotInterval::Tiles(tiles));
+    }
+    let seconds = value.strip_suffix('s')?.parse::<f64>().ok();
+    let seconds = seconds.filter(|s| *s > 0.0)?;
+    let interval = Duration::try_from_secs_f64(seconds).ok()?;
+    Some(SnapshotInterval::Time(interval))
+}
+
+pub fn parse_manifest(text: &str) -> Result<Vec<RenderJob>> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, text)| RenderJob::parse(line, text))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parsing_a_manifest() {
+        let jobs = parse_manifest(
+            "# nightly\n\ndemo\n  demo output=small.png width=200 height=100 samples=4\n",
+        )
+        .unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].line, 3);
+        assert_eq!(jobs[0].output, "demo.ppm");
+        assert_eq!(jobs[0].width, None);
+        assert_eq!(jobs[1].line, 4);
+        assert_eq!(jobs[1].scene, "demo");
+        assert_eq!(jobs[1].output, "small.png");
+        assert_eq!(jobs[1].width, Some(200));
+        assert_eq!(jobs[1].height, Some(100));
+        assert_eq!(jobs[1].samples, Some(4));
+    }
+
+    #[test]
+    fn test_manifest_errors_report_their_line() {
+        for (text, line) in [
+            ("demo\ndemo width=0", 2),
+            ("demo samples", 1),
+            ("\n\ndemo colour=red", 3),
+            ("demo exposure=bright", 1),
+            ("demo snapshot=30", 1),
+            ("demo snapshot=0tiles", 1),
+            ("demo snapshot=1e300s", 1),
+        ] {
+            match parse_manifest(text) {
+                Err(RayTracerError::Parse { line: l, .. }) => assert_eq!(l, line),
+                _ => panic!("expected a parse error for {:?}", text),
+            }
+        }
+    }
+
+    #[test]
+    fn test_a_job_round_trips_through_its_tokens() {
+        for text in [
+            "demo output=a.png samples=2 exposure=-1.5",
+            "demo snapshot=2.5s",
+            "demo snapshot=16tiles",
+        ] {
+            let job = RenderJob::parse(7, text).unwrap();
+            let again = RenderJob::parse(7, &job.to_tokens().join(" ")).unwrap();
+            assert_eq!(again, job);
+        }
+    }
+}
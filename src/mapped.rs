@@ -0,0 +1,175 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::error::{RayTracerError, Result};
+use crate::scalar::Scalar;
+use crate::tile::Rect;
+use memmap2::MmapMut;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"RTMC";
+const VERSION: u32 = 1;
+// magic, version, width and height
+const HEADER_SIZE: usize = 24;
+// red, green, blue and transparency as f32
+const PIXEL_SIZE: usize = 16;
+
+// A canvas whose pixels live in a memory-mapped file instead of RAM, for
+// renders too large to hold in memory: the operating system only keeps the
+// rows being worked on resident. Pixels are stored as f32, half the size of
+// an in-memory f64 canvas with alpha. Alpha is stored as its
+// complement, so the zero-filled new file starts out opaque black.
+pub struct MappedCanvas {
+    width: usize,
+    height: usize,
+    map: MmapMut,
+}
+
+impl MappedCanvas {
+    // Creates (or truncates) the file at `path` for a black canvas.
+    pub fn create(path: &Path, width: usize, height: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((HEADER_SIZE + width * height * PIXEL_SIZE) as u64)?;
+        let mut map = Self::map(&file)?;
+        map[0..4].copy_from_slice(MAGIC);
+        map[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        map[8..16].copy_from_slice(&(width as u64).to_le_bytes());
+        map[16..24].copy_from_slice(&(height as u64).to_le_bytes());
+        Ok(Self { width, height, map })
+    }
+    // Opens a canvas made by `create`, to read it back or keep writing.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let map = Self::map(&file)?;
+        if map.len() < HEADER_SIZE || &map[0..4] != MAGIC {
+            return Err(invalid("not a mapped canvas"));
+        }
+        if u32::from_le_bytes(map[4..8].try_into().unwrap()) != VERSION {
+            return Err(invalid("unsupported mapped canvas version"));
+        }
+        let width = u64::from_le_bytes(map[8..16].try_into().unwrap()) as usize;
+        let height = u64::from_le_bytes(map[16..24].try_into().unwrap()) as usize;
+        if map.len() != HEADER_SIZE + width * height * PIXEL_SIZE {
+            return Err(invalid("truncated mapped canvas"));
+        }
+        Ok(Self { width, height, map })
+    }
+    fn map(file: &File) -> Result<MmapMut> {
+        // the file is ours for as long as the canvas is; changes to it from
+        // elsewhere would only garble pixels
+        Ok(unsafe { MmapMut::map_mut(file)? })
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn pixel_at(&self, x: usize, y: usize) -> Color {
+        let [red, green, blue, _] = self.read(x, y);
+        Color::new(red, green, blue)
+    }
+    pub fn alpha_at(&self, x: usize, y: usize) -> Scalar {
+        1.0 - self.read(x, y)[3]
+    }
+    pub fn write_pixel(&mut self, x: usize, y: usize, c: Color) {
+        let alpha = self.alpha_at(x, y);
+        self.write(x, y, c, alpha);
+    }
+    pub fn write_alpha(&mut self, x: usize, y: usize, a: Scalar) {
+        let color = self.pixel_at(x, y);
+        self.write(x, y, color, a);
+    }
+    // Writes a rectangle of colors and alphas in row-major order, as the
+    // tile renderer produces them.
+    pub fn write_rect(&mut self, rect: &Rect, pixels: &[(Color, Scalar)]) {
+        for (i, (color, alpha)) in pixels.iter().enumerate() {
+            self.write(
+                rect.x + i % rect.width,
+                rect.y + i / rect.width,
+                *color,
+                *alpha,
+            );
+        }
+    }
+    // `count` rows from `y` on as an in-memory canvas, for processing or
+    // writing out a large image a strip at a time.
+    pub fn rows(&self, y: usize, count: usize) -> Canvas {
+        let mut canvas = Canvas::new(self.width, count);
+        for row in 0..count {
+            for x in 0..self.width {
+                canvas.write_pixel(x, row, self.pixel_at(x, y + row));
+                let alpha = self.alpha_at(x, y + row);
+                if alpha != 1.0 {
+                    canvas.write_alpha(x, row, alpha);
+                }
+            }
+        }
+        canvas
+    }
+    // Writes the changed pixels out to the file.
+    pub fn flush(&self) -> Result<()> {
+        Ok(self.map.flush()?)
+    }
+    fn offset(&self, x: usize, y: usize) -> usize {
+        assert!(x < self.width && y < self.height);
+        HEADER_SIZE + (y * self.width + x) * PIXEL_SIZE
+    }
+    #[allow(clippy::unnecessary_cast)]
+    fn read(&self, x: usize, y: usize) -> [Scalar; 4] {
+        let offset = self.offset(x, y);
+        let bytes = &self.map[offset..offset + PIXEL_SIZE];
+        [0, 1, 2, 3]
+            .map(|i| f32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap()) as Scalar)
+    }
+    #[allow(clippy::unnecessary_cast)]
+    fn write(&mut self, x: usize, y: usize, c: Color, a: Scalar) {
+        let offset = self.offset(x, y);
+        let bytes = &mut self.map[offset..offset + PIXEL_SIZE];
+        for (i, v) in [c.red, c.green, c.blue, 1.0 - a].into_iter().enumerate() {
+            bytes[4 * i..4 * i + 4].copy_from_slice(&(v as f32).to_le_bytes());
+        }
+    }
+}
+
+fn invalid(message: &str) -> RayTracerError {
+    RayTracerError::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    #[test]
+    fn test_a_mapped_canvas_keeps_its_pixels_in_a_file() {
+        let path = std::env::temp_dir().join("ray-tracer-test-mapped-canvas");
+        let mut canvas = MappedCanvas::create(&path, 4, 3).unwrap();
+        assert_approx_eq!(canvas.pixel_at(3, 2), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(canvas.alpha_at(3, 2), 1.0);
+        canvas.write_pixel(1, 2, Color::new(0.25, 0.5, 1.0));
+        canvas.write_rect(
+            &Rect::new(2, 0, 2, 1),
+            &[(Color::new(1.0, 0.0, 0.0), 0.5); 2],
+        );
+        canvas.flush().unwrap();
+        drop(canvas);
+        let canvas = MappedCanvas::open(&path).unwrap();
+        assert_eq!((canvas.width(), canvas.height()), (4, 3));
+        assert_approx_eq!(canvas.pixel_at(1, 2), Color::new(0.25, 0.5, 1.0));
+        let strip = canvas.rows(0, 1);
+        assert_approx_eq!(strip.pixel_at(3, 0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(strip.alpha_at(3, 0), 0.5);
+        assert_eq!(strip.alpha_at(0, 0), 1.0);
+        std::fs::write(&path, b"RTMC").unwrap();
+        assert!(MappedCanvas::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
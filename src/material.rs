@@ -1,26 +1,287 @@
-use crate::color::{Color, BLACK, WHITE};
+use crate::color::{Color, ColorSpace, BLACK, WHITE};
+use crate::error::{RayTracerError, Result};
 use crate::light::PointLight;
-use crate::pattern::Pattern;
+use crate::pattern::{Pattern, PatternContext};
 use crate::point::Point;
+use crate::scalar::consts::PI;
+use crate::scalar::Scalar;
 use crate::transform::Affine;
 use crate::vector::{reflect, Vector};
 
 enum PatternWrap<'a> {
     Solid(Color),
     Custom(Box<dyn Pattern + 'a>, Affine),
+    Blend(Box<BlendedMaterial<'a>>),
+    // the colors of a blend resolved at a point, mixed by weight
+    Mix(&'a PatternWrap<'a>, &'a PatternWrap<'a>, Scalar),
+    Borrowed(&'a PatternWrap<'a>),
+}
+
+impl PatternWrap<'_> {
+    fn color_at(
+        &self,
+        shape_inv_transform: &Affine,
+        point: &Point,
+        eyev: &Vector,
+        normalv: &Vector,
+    ) -> Color {
+        match self {
+            PatternWrap::Solid(c) => *c,
+            PatternWrap::Custom(getter, pattern_inv_trans) => {
+                let to_pattern = pattern_inv_trans * shape_inv_transform;
+                let p = to_pattern * point;
+                // normals go by the transpose of the inverse, as in
+                // `Shape::normal_at`, to stay normal under non-uniform scaling
+                let n = to_pattern
+                    .get_transform()
+                    .inverse()
+                    .map_or(*normalv, |m| (m.transpose() * normalv).normalize());
+                getter.get_color(&PatternContext {
+                    point: p,
+                    normal: n,
+                    world_point: *point,
+                    world_normal: *normalv,
+                    direction: -*eyev,
+                })
+            }
+            PatternWrap::Blend(blend) => {
+                let weight = blend.weight(shape_inv_transform, point, eyev, normalv);
+                PatternWrap::Mix(&blend.first.color, &blend.second.color, weight).color_at(
+                    shape_inv_transform,
+                    point,
+                    eyev,
+                    normalv,
+                )
+            }
+            PatternWrap::Mix(first, second, weight) => {
+                let a = first.color_at(shape_inv_transform, point, eyev, normalv);
+                let b = second.color_at(shape_inv_transform, point, eyev, normalv);
+                a + (b - a) * *weight
+            }
+            PatternWrap::Borrowed(wrap) => wrap.color_at(shape_inv_transform, point, eyev, normalv),
+        }
+    }
+}
+
+pub struct LightContribution {
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+    pub rim: Color,
+}
+
+impl LightContribution {
+    pub fn total(&self) -> Color {
+        // Add the contributions together to get the final shading
+        self.ambient + self.diffuse + self.specular + self.rim
+    }
+}
+
+// What `Material::validate` does with a parameter outside its valid range.
+// Scene files and the language bindings validate every material they are
+// given with the default policy.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ValidationPolicy {
+    Clamp,
+    Warn,
+    #[default]
+    Error,
+}
+
+// The ranges parameters are checked against.
+const NON_NEGATIVE: (Scalar, Scalar) = (0.0, Scalar::INFINITY);
+const UNIT: (Scalar, Scalar) = (0.0, 1.0);
+const INDEX: (Scalar, Scalar) = (1.0, Scalar::INFINITY);
+
+fn validate_param(
+    name: &str,
+    value: Scalar,
+    (min, max): (Scalar, Scalar),
+    default: Scalar,
+    policy: ValidationPolicy,
+) -> Result<Scalar> {
+    if value.is_finite() && value >= min && value <= max {
+        return Ok(value);
+    }
+    let fixed = if value.is_finite() {
+        value.clamp(min, max)
+    } else {
+        default
+    };
+    match policy {
+        ValidationPolicy::Clamp => Ok(fixed),
+        ValidationPolicy::Warn => {
+            eprintln!("warning: material {} is {}, using {}", name, value, fixed);
+            Ok(fixed)
+        }
+        ValidationPolicy::Error => Err(RayTracerError::InvalidMaterial(format!(
+            "{} must be between {} and {}, got {}",
+            name, min, max, value
+        ))),
+    }
+}
+
+// Validates each channel of a color, which must be finite and non-negative.
+fn validate_color(
+    name: &str,
+    color: Color,
+    default: Color,
+    policy: ValidationPolicy,
+) -> Result<Color> {
+    Ok(Color::new(
+        validate_param(name, color.red, NON_NEGATIVE, default.red, policy)?,
+        validate_param(name, color.green, NON_NEGATIVE, default.green, policy)?,
+        validate_param(name, color.blue, NON_NEGATIVE, default.blue, policy)?,
+    ))
+}
+
+// A metal's complex index of refraction n + ik for red, green and blue.
+// Metals reflect by how their Fresnel reflectance changes with the angle,
+// which tints the reflection at normal incidence and whitens it towards
+// grazing angles, something a constant reflection tint can't do.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Conductor {
+    pub n: Color,
+    pub k: Color,
+}
+
+impl Conductor {
+    // measured values at about 650, 550 and 450 nm
+    pub const GOLD: Conductor = Conductor::new(
+        Color::new(0.143, 0.374, 1.442),
+        Color::new(3.983, 2.385, 1.603),
+    );
+    pub const SILVER: Conductor = Conductor::new(
+        Color::new(0.155, 0.117, 0.138),
+        Color::new(4.828, 3.122, 2.147),
+    );
+    pub const COPPER: Conductor = Conductor::new(
+        Color::new(0.200, 0.924, 1.102),
+        Color::new(3.912, 2.452, 2.142),
+    );
+    pub const ALUMINUM: Conductor = Conductor::new(
+        Color::new(1.657, 0.880, 0.521),
+        Color::new(9.224, 6.270, 4.837),
+    );
+
+    pub const fn new(n: Color, k: Color) -> Self {
+        Self { n, k }
+    }
+    // The Fresnel reflectance for light arriving from air at an angle
+    // whose cosine is `cos`, averaged over both polarizations.
+    pub fn reflectance(&self, cos: Scalar) -> Color {
+        let cos = cos.clamp(0.0, 1.0);
+        let (cos2, sin2) = (cos * cos, 1.0 - cos * cos);
+        let channel = |n: Scalar, k: Scalar| {
+            let t = n * n - k * k - sin2;
+            let a2b2 = (t * t + 4.0 * n * n * k * k).sqrt();
+            let a = ((a2b2 + t) / 2.0).max(0.0).sqrt();
+            let rs = (a2b2 + cos2 - 2.0 * a * cos) / (a2b2 + cos2 + 2.0 * a * cos);
+            let rp = rs * (a2b2 * cos2 + sin2 * sin2 - 2.0 * a * cos * sin2)
+                / (a2b2 * cos2 + sin2 * sin2 + 2.0 * a * cos * sin2);
+            (rs + rp) / 2.0
+        };
+        Color::new(
+            channel(self.n.red, self.k.red),
+            channel(self.n.green, self.k.green),
+            channel(self.n.blue, self.k.blue),
+        )
+    }
+}
+
+// The wavelengths in nanometers red, green and blue stand for where light
+// is treated as waves.
+const RGB_WAVELENGTHS: [Scalar; 3] = [650.0, 550.0, 450.0];
+
+// A transparent film a few hundred nanometers thick on the surface, such
+// as soap or oil. Light reflected off its top and off the surface below
+// interferes, so that the reflection turns iridescent with the film's
+// thickness and the viewing angle.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThinFilm {
+    // in nanometers
+    pub thickness: Scalar,
+    pub refractive_index: Scalar,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: Scalar, refractive_index: Scalar) -> Self {
+        Self {
+            thickness,
+            refractive_index,
+        }
+    }
+    // The reflectance of the film between media of index `n1` (where the
+    // light comes from) and `n3`, for light at an angle whose cosine is
+    // `cos`, averaged over both polarizations. Rays with a wavelength get
+    // their wavelength's reflectance in all three channels.
+    pub fn reflectance(
+        &self,
+        n1: Scalar,
+        n3: Scalar,
+        cos: Scalar,
+        wavelength: Option<Scalar>,
+    ) -> Color {
+        let n2 = self.refractive_index;
+        let sin2 = 1.0 - cos * cos;
+        let cos_in = |n: Scalar| {
+            let sin2_t = sin2 * (n1 / n) * (n1 / n);
+            (sin2_t <= 1.0).then(|| (1.0 - sin2_t).sqrt())
+        };
+        let (Some(cos2), Some(cos3)) = (cos_in(n2), cos_in(n3)) else {
+            // total internal reflection
+            return WHITE;
+        };
+        let airy = |r12: Scalar, r23: Scalar, phase: Scalar| {
+            let interference = 2.0 * r12 * r23 * phase.cos();
+            (r12 * r12 + r23 * r23 + interference) / (1.0 + r12 * r12 * r23 * r23 + interference)
+        };
+        let channel = |nm: Scalar| {
+            let phase = 4.0 * PI * n2 * self.thickness * cos2 / nm;
+            let s = airy(
+                (n1 * cos - n2 * cos2) / (n1 * cos + n2 * cos2),
+                (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3),
+                phase,
+            );
+            let p = airy(
+                (n2 * cos - n1 * cos2) / (n2 * cos + n1 * cos2),
+                (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3),
+                phase,
+            );
+            (s + p) / 2.0
+        };
+        let [red, green, blue] = match wavelength {
+            Some(nm) => [channel(nm); 3],
+            None => RGB_WAVELENGTHS.map(channel),
+        };
+        Color::new(red, green, blue)
+    }
 }
 
 pub struct Material<'a> {
     color: PatternWrap<'a>,
-    ambient: f64,
-    diffuse: f64,
-    specular: f64,
-    shininess: f64,
-    reflective: f64,
-    transparency: f64,
-    refractive_index: f64,
+    ambient: Scalar,
+    diffuse: Scalar,
+    specular: Scalar,
+    shininess: Scalar,
+    reflective: Scalar,
+    transparency: Scalar,
+    refractive_index: Scalar,
+    dispersion: Scalar,
+    priority: u32,
+    rim_color: Color,
+    rim_exponent: Scalar,
+    reflection_tint: Color,
+    transmission_tint: Color,
+    conductor: Option<Conductor>,
+    thin_film: Option<ThinFilm>,
 }
 
+// The wavelength a refractive index is usually given at, in nanometers.
+const SODIUM_D_LINE: Scalar = 587.6;
+
 pub const DEFAULT_MATERIAL: Material = Material {
     color: PatternWrap::Solid(WHITE),
     ambient: 0.1,
@@ -30,61 +291,450 @@ pub const DEFAULT_MATERIAL: Material = Material {
     reflective: 0.0,
     transparency: 0.0,
     refractive_index: 1.0,
+    dispersion: 0.0,
+    priority: 0,
+    rim_color: BLACK,
+    rim_exponent: 4.0,
+    reflection_tint: WHITE,
+    transmission_tint: WHITE,
+    conductor: None,
+    thin_film: None,
 };
 
+impl Default for Material<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a> Material<'a> {
     pub fn new() -> Self {
         Self { ..DEFAULT_MATERIAL }
     }
-    pub fn set_color(&self, color: Color) -> Self {
-        Self {
+    // The setters check their values as `validate` does, failing on any out
+    // of range.
+    pub fn set_color(&self, color: Color) -> Result<Self> {
+        let color = validate_color("color", color, WHITE, ValidationPolicy::Error)?;
+        Ok(Self {
             color: PatternWrap::Solid(color),
             ..*self
-        }
+        })
     }
-    pub fn set_pattern(&self, pattern: impl Pattern + 'a, transform: Affine) -> Self {
-        let inverse_transform = transform.inverse().unwrap();
-        Self {
+    pub fn set_pattern(&self, pattern: impl Pattern + 'a, transform: Affine) -> Result<Self> {
+        let inverse_transform = transform
+            .inverse()
+            .ok_or(RayTracerError::NonInvertibleTransform)?;
+        Ok(Self {
             color: PatternWrap::Custom(Box::new(pattern), inverse_transform),
             ..*self
-        }
+        })
+    }
+    pub fn set_ambient(self, ambient: Scalar) -> Result<Self> {
+        self.set_field(MaterialField::Ambient(ambient))
+    }
+    pub fn set_diffuse(self, diffuse: Scalar) -> Result<Self> {
+        self.set_field(MaterialField::Diffuse(diffuse))
+    }
+    pub fn set_specular(self, specular: Scalar) -> Result<Self> {
+        self.set_field(MaterialField::Specular(specular))
+    }
+    pub fn set_shininess(self, shininess: Scalar) -> Result<Self> {
+        self.set_field(MaterialField::Shininess(shininess))
+    }
+    pub fn set_reflective(self, reflective: Scalar) -> Result<Self> {
+        self.set_field(MaterialField::Reflective(reflective))
+    }
+    pub fn set_transparency(self, transparency: Scalar) -> Result<Self> {
+        self.set_field(MaterialField::Transparency(transparency))
+    }
+    pub fn set_refractive_index(self, refractive_index: Scalar) -> Result<Self> {
+        self.set_field(MaterialField::RefractiveIndex(refractive_index))
     }
-    pub fn set_ambient(self, ambient: f64) -> Self {
-        Self { ambient, ..self }
+    // How much the refractive index grows towards short wavelengths, as the
+    // B of Cauchy's equation n(λ) = A + B/λ² with λ in micrometers, the
+    // refractive index being n at 587.6 nm. Crown glass is about 0.004 and
+    // flint glass 0.01. Only rays with a wavelength see it, see
+    // `Ray::set_wavelength`.
+    pub fn set_dispersion(self, dispersion: Scalar) -> Result<Self> {
+        let dispersion = validate_param(
+            "dispersion",
+            dispersion,
+            NON_NEGATIVE,
+            0.0,
+            ValidationPolicy::Error,
+        )?;
+        Ok(Self { dispersion, ..self })
     }
-    pub fn set_diffuse(self, diffuse: f64) -> Self {
-        Self { diffuse, ..self }
+    // Decides which volume a ray is in where volumes overlap: the one with
+    // the highest priority, e.g. an ice cube over the water it floats in.
+    // Surfaces inside a volume of higher priority are skipped.
+    pub fn set_priority(self, priority: u32) -> Self {
+        Self { priority, ..self }
     }
-    pub fn set_specular(self, specular: f64) -> Self {
-        Self { specular, ..self }
+    // A sheen that grows towards grazing angles, as (1 - |N·V|)^exponent.
+    // Black (the default) turns it off.
+    pub fn set_rim(self, rim_color: Color, rim_exponent: Scalar) -> Result<Self> {
+        let policy = ValidationPolicy::Error;
+        Ok(Self {
+            rim_color: validate_color("rim color", rim_color, BLACK, policy)?,
+            rim_exponent: validate_param("rim exponent", rim_exponent, NON_NEGATIVE, 0.0, policy)?,
+            ..self
+        })
     }
-    pub fn set_shininess(self, shininess: f64) -> Self {
-        Self { shininess, ..self }
+    // Colors multiplying the reflected and the refracted light, channel by
+    // channel, e.g. warm tones for a gold mirror or green for bottle glass.
+    // White (the default) leaves them as they are.
+    pub fn set_reflection_tint(self, reflection_tint: Color) -> Result<Self> {
+        let policy = ValidationPolicy::Error;
+        Ok(Self {
+            reflection_tint: validate_color("reflection tint", reflection_tint, WHITE, policy)?,
+            ..self
+        })
     }
-    pub fn set_reflective(self, reflective: f64) -> Self {
-        Self { reflective, ..self }
+    pub fn set_transmission_tint(self, transmission_tint: Color) -> Result<Self> {
+        let policy = ValidationPolicy::Error;
+        Ok(Self {
+            transmission_tint: validate_color(
+                "transmission tint",
+                transmission_tint,
+                WHITE,
+                policy,
+            )?,
+            ..self
+        })
     }
-    pub fn set_transparency(self, transparency: f64) -> Self {
-        Self { transparency, ..self }
+    // Makes the reflection that of a metal, weighted by the conductor's
+    // Fresnel reflectance at each hit rather than a constant. `reflective`
+    // still scales it, so metals want it at 1.
+    pub fn set_conductor(self, conductor: Option<Conductor>) -> Self {
+        Self { conductor, ..self }
     }
-    pub fn set_refractive_index(self, refractive_index: f64) -> Self {
-        Self { refractive_index, ..self }
+    // Coats the surface with a thin film, whose reflectance then weighs
+    // reflection against refraction instead of the world's Fresnel
+    // setting. A soap bubble is a fully transparent and reflective sphere
+    // of refractive index 1 with a film of index 1.33.
+    pub fn set_thin_film(self, thin_film: Option<ThinFilm>) -> Self {
+        Self { thin_film, ..self }
+    }
+    // Takes the material's colors to have been given in `space`, converting
+    // them to the linear colors shading needs. Patterns convert their own.
+    pub fn convert_colors(self, space: ColorSpace) -> Self {
+        let color = match self.color {
+            PatternWrap::Solid(c) => PatternWrap::Solid(space.to_linear(c)),
+            color => color,
+        };
+        Self {
+            color,
+            rim_color: space.to_linear(self.rim_color),
+            reflection_tint: space.to_linear(self.reflection_tint),
+            transmission_tint: space.to_linear(self.transmission_tint),
+            ..self
+        }
+    }
+    // Checks that all parameters are finite and in range: reflective and
+    // transparency within [0, 1], refractive indices at least 1 and the
+    // rest, colors included, non-negative. Out of range values are clamped
+    // (or, if not finite, reset to their default) unless the policy says to
+    // fail. Pattern colors are left alone.
+    pub fn validate(self, policy: ValidationPolicy) -> Result<Self> {
+        let d = &DEFAULT_MATERIAL;
+        let color = match self.color {
+            PatternWrap::Solid(c) => PatternWrap::Solid(validate_color("color", c, WHITE, policy)?),
+            color => color,
+        };
+        let conductor = match self.conductor {
+            Some(c) => Some(Conductor::new(
+                validate_color("conductor n", c.n, WHITE, policy)?,
+                validate_color("conductor k", c.k, BLACK, policy)?,
+            )),
+            None => None,
+        };
+        let thin_film = match self.thin_film {
+            Some(film) => Some(ThinFilm::new(
+                validate_param("film thickness", film.thickness, NON_NEGATIVE, 0.0, policy)?,
+                validate_param(
+                    "film refractive index",
+                    film.refractive_index,
+                    INDEX,
+                    1.0,
+                    policy,
+                )?,
+            )),
+            None => None,
+        };
+        Ok(Self {
+            color,
+            ambient: validate_param("ambient", self.ambient, NON_NEGATIVE, d.ambient, policy)?,
+            diffuse: validate_param("diffuse", self.diffuse, NON_NEGATIVE, d.diffuse, policy)?,
+            specular: validate_param("specular", self.specular, NON_NEGATIVE, d.specular, policy)?,
+            shininess: validate_param(
+                "shininess",
+                self.shininess,
+                NON_NEGATIVE,
+                d.shininess,
+                policy,
+            )?,
+            reflective: validate_param("reflective", self.reflective, UNIT, d.reflective, policy)?,
+            transparency: validate_param(
+                "transparency",
+                self.transparency,
+                UNIT,
+                d.transparency,
+                policy,
+            )?,
+            refractive_index: validate_param(
+                "refractive index",
+                self.refractive_index,
+                INDEX,
+                d.refractive_index,
+                policy,
+            )?,
+            dispersion: validate_param(
+                "dispersion",
+                self.dispersion,
+                NON_NEGATIVE,
+                d.dispersion,
+                policy,
+            )?,
+            rim_color: validate_color("rim color", self.rim_color, d.rim_color, policy)?,
+            rim_exponent: validate_param(
+                "rim exponent",
+                self.rim_exponent,
+                NON_NEGATIVE,
+                d.rim_exponent,
+                policy,
+            )?,
+            reflection_tint: validate_color(
+                "reflection tint",
+                self.reflection_tint,
+                d.reflection_tint,
+                policy,
+            )?,
+            transmission_tint: validate_color(
+                "transmission tint",
+                self.transmission_tint,
+                d.transmission_tint,
+                policy,
+            )?,
+            conductor,
+            thin_film,
+            ..self
+        })
     }
     pub fn is_reflective(&self) -> bool {
         self.reflective > 0.0
     }
     pub fn reflected_color(&self, color: &Color) -> Color {
-        color * self.reflective
+        *color * &self.reflection_tint * self.reflective
+    }
+    // `reflected_color` for light reflected towards the eye at an angle
+    // whose cosine is `cos`, which only matters for conductors.
+    pub fn reflected_color_at(&self, color: &Color, cos: Scalar) -> Color {
+        match &self.conductor {
+            Some(conductor) => self.reflected_color(&(*color * &conductor.reflectance(cos))),
+            None => self.reflected_color(color),
+        }
     }
     pub fn is_transparent(&self) -> bool {
         self.transparency > 0.0
     }
     pub fn scale_transparency(&self, color: &Color) -> Color {
-        color * self.transparency
+        *color * &self.transmission_tint * self.transparency
+    }
+    // One parameter changed, e.g. by a `SceneDelta` between frames.
+    pub fn set_field(self, field: MaterialField) -> Result<Self> {
+        Ok(self.apply_field(field.validate(ValidationPolicy::Error)?))
+    }
+    // `set_field` for a field already validated.
+    pub(crate) fn apply_field(self, field: MaterialField) -> Self {
+        match field {
+            MaterialField::Color(color) => Self {
+                color: PatternWrap::Solid(color),
+                ..self
+            },
+            MaterialField::Ambient(ambient) => Self { ambient, ..self },
+            MaterialField::Diffuse(diffuse) => Self { diffuse, ..self },
+            MaterialField::Specular(specular) => Self { specular, ..self },
+            MaterialField::Shininess(shininess) => Self { shininess, ..self },
+            MaterialField::Reflective(reflective) => Self { reflective, ..self },
+            MaterialField::Transparency(transparency) => Self {
+                transparency,
+                ..self
+            },
+            MaterialField::RefractiveIndex(refractive_index) => Self {
+                refractive_index,
+                ..self
+            },
+        }
     }
-    pub fn get_refractive_index(&self) -> f64 {
+    // A copy of a solid material; patterns can't be copied.
+    pub(crate) fn clone_solid(&self) -> Option<Material<'a>> {
+        let PatternWrap::Solid(color) = self.color else {
+            return None;
+        };
+        Some(Self {
+            color: PatternWrap::Solid(color),
+            ..*self
+        })
+    }
+    // The parameters of a solid material as bits, so materials that shade
+    // alike have equal keys. Patterns are code and can't be compared, so
+    // patterned materials have none. Every field is named, so a new one
+    // can't be left out of the key.
+    #[allow(clippy::unnecessary_cast)]
+    pub(crate) fn solid_key(&self) -> Option<Vec<u64>> {
+        let Material {
+            color: PatternWrap::Solid(color),
+            ambient,
+            diffuse,
+            specular,
+            shininess,
+            reflective,
+            transparency,
+            refractive_index,
+            dispersion,
+            priority,
+            rim_color,
+            rim_exponent,
+            reflection_tint,
+            transmission_tint,
+            conductor,
+            thin_film,
+        } = self
+        else {
+            return None;
+        };
+        let colors = [color, rim_color, reflection_tint, transmission_tint];
+        let scalars = [
+            ambient,
+            diffuse,
+            specular,
+            shininess,
+            reflective,
+            transparency,
+            refractive_index,
+            dispersion,
+            rim_exponent,
+        ];
+        let conductor = conductor.map(|c| [c.n, c.k]);
+        let thin_film = thin_film.map(|f| [f.thickness, f.refractive_index]);
+        let mut key = vec![*priority as u64];
+        let rgb = |c: &Color| [c.red, c.green, c.blue];
+        key.extend(colors.into_iter().flat_map(rgb).map(|v| v.to_bits() as u64));
+        key.extend(scalars.into_iter().map(|v| v.to_bits() as u64));
+        key.push(conductor.is_some() as u64);
+        key.extend(
+            conductor
+                .iter()
+                .flatten()
+                .flat_map(rgb)
+                .map(|v| v.to_bits() as u64),
+        );
+        key.push(thin_film.is_some() as u64);
+        key.extend(thin_film.iter().flatten().map(|v| v.to_bits() as u64));
+        Some(key)
+    }
+    // The surface's own color at a point, before any lighting.
+    pub fn color_at(
+        &self,
+        shape_inv_transform: &Affine,
+        point: &Point,
+        eyev: &Vector,
+        normalv: &Vector,
+    ) -> Color {
+        self.color
+            .color_at(shape_inv_transform, point, eyev, normalv)
+    }
+    // The material as it is at a point of the surface: a blended material's
+    // parameters and colors mixed by its mask there, after resolving any
+    // blends it is made of. Any other material is returned as it is.
+    pub fn at(
+        &self,
+        shape_inv_transform: &Affine,
+        point: &Point,
+        eyev: &Vector,
+        normalv: &Vector,
+    ) -> Material<'_> {
+        let PatternWrap::Blend(blend) = &self.color else {
+            return Material {
+                color: PatternWrap::Borrowed(&self.color),
+                ..*self
+            };
+        };
+        let w = blend.weight(shape_inv_transform, point, eyev, normalv);
+        let a = blend.first.at(shape_inv_transform, point, eyev, normalv);
+        let b = blend.second.at(shape_inv_transform, point, eyev, normalv);
+        let mix = |a: Scalar, b: Scalar| a + (b - a) * w;
+        Material {
+            // colors of nested blends are resolved as they are looked up
+            color: PatternWrap::Mix(&blend.first.color, &blend.second.color, w),
+            ambient: mix(a.ambient, b.ambient),
+            diffuse: mix(a.diffuse, b.diffuse),
+            specular: mix(a.specular, b.specular),
+            shininess: mix(a.shininess, b.shininess),
+            reflective: mix(a.reflective, b.reflective),
+            transparency: mix(a.transparency, b.transparency),
+            refractive_index: mix(a.refractive_index, b.refractive_index),
+            dispersion: mix(a.dispersion, b.dispersion),
+            priority: if w < 0.5 { a.priority } else { b.priority },
+            rim_color: a.rim_color + (b.rim_color - a.rim_color) * w,
+            rim_exponent: mix(a.rim_exponent, b.rim_exponent),
+            reflection_tint: a.reflection_tint + (b.reflection_tint - a.reflection_tint) * w,
+            transmission_tint: a.transmission_tint
+                + (b.transmission_tint - a.transmission_tint) * w,
+            conductor: if w < 0.5 { a.conductor } else { b.conductor },
+            thin_film: if w < 0.5 { a.thin_film } else { b.thin_film },
+        }
+    }
+    // The color of a solid material; patterned ones have none.
+    pub fn get_solid_color(&self) -> Option<Color> {
+        match self.color {
+            PatternWrap::Solid(c) => Some(c),
+            _ => None,
+        }
+    }
+    pub fn get_ambient(&self) -> Scalar {
+        self.ambient
+    }
+    pub fn get_diffuse(&self) -> Scalar {
+        self.diffuse
+    }
+    pub fn get_specular(&self) -> Scalar {
+        self.specular
+    }
+    pub fn get_shininess(&self) -> Scalar {
+        self.shininess
+    }
+    pub fn get_reflective(&self) -> Scalar {
+        self.reflective
+    }
+    pub fn get_transparency(&self) -> Scalar {
+        self.transparency
+    }
+    pub fn get_refractive_index(&self) -> Scalar {
         self.refractive_index
     }
+    pub fn get_dispersion(&self) -> Scalar {
+        self.dispersion
+    }
+    // The refractive index for light of `wavelength` nanometers, or the
+    // plain one for rays without a wavelength.
+    pub fn refractive_index_at(&self, wavelength: Option<Scalar>) -> Scalar {
+        match wavelength {
+            Some(nm) if self.dispersion != 0.0 => {
+                let inverse_square = |nm: Scalar| 1e6 / (nm * nm);
+                self.refractive_index
+                    + self.dispersion * (inverse_square(nm) - inverse_square(SODIUM_D_LINE))
+            }
+            _ => self.refractive_index,
+        }
+    }
+    pub fn get_thin_film(&self) -> Option<ThinFilm> {
+        self.thin_film
+    }
+    pub fn get_priority(&self) -> u32 {
+        self.priority
+    }
     pub fn lighting(
         &self,
         light: &PointLight,
@@ -94,19 +744,38 @@ impl<'a> Material<'a> {
         normalv: &Vector,
         in_shadow: bool,
     ) -> Color {
-        let color = match &self.color {
-            PatternWrap::Solid(c) => *c,
-            PatternWrap::Custom(getter, pattern_inv_trans) => {
-                let p = pattern_inv_trans * &(shape_inv_transform * point);
-                getter.get_color(&p)
-            }
-        };
+        self.light_contribution(light, shape_inv_transform, point, eyev, normalv, in_shadow)
+            .total()
+    }
+    // Same as `lighting`, but keeping the three terms apart.
+    pub fn light_contribution(
+        &self,
+        light: &PointLight,
+        shape_inv_transform: &Affine,
+        point: &Point,
+        eyev: &Vector,
+        normalv: &Vector,
+        in_shadow: bool,
+    ) -> LightContribution {
+        if let PatternWrap::Blend(_) = self.color {
+            return self
+                .at(shape_inv_transform, point, eyev, normalv)
+                .light_contribution(light, shape_inv_transform, point, eyev, normalv, in_shadow);
+        }
+        let color = self
+            .color
+            .color_at(shape_inv_transform, point, eyev, normalv);
         // combine the surface color with the light's color/intensity
         let effective_color = light.combine(&color);
         // compute the ambient contribution
         let ambient = effective_color * self.ambient;
         if in_shadow {
-            return ambient;
+            return LightContribution {
+                ambient,
+                diffuse: BLACK,
+                specular: BLACK,
+                rim: BLACK,
+            };
         }
         // find the direction to the light source
         let lightv = light.vector_from(point).normalize();
@@ -134,8 +803,244 @@ impl<'a> Material<'a> {
                 specular = light.scale_intensity(self.specular * factor);
             }
         }
-        // Add the three contributions together to get the final shading
-        ambient + diffuse + specular
+        // the rim term ignores the light's direction so backlit edges glow
+        let edge = 1.0 - eyev.dot(normalv).abs();
+        let rim = light.combine(&self.rim_color) * edge.powf(self.rim_exponent);
+        LightContribution {
+            ambient,
+            diffuse,
+            specular,
+            rim,
+        }
+    }
+}
+
+// A material parameter with its new value, see `Material::set_field`.
+#[derive(Copy, Clone, Debug)]
+pub enum MaterialField {
+    Color(Color),
+    Ambient(Scalar),
+    Diffuse(Scalar),
+    Specular(Scalar),
+    Shininess(Scalar),
+    Reflective(Scalar),
+    Transparency(Scalar),
+    RefractiveIndex(Scalar),
+}
+
+impl MaterialField {
+    // Checks the new value as `Material::validate` does.
+    pub fn validate(self, policy: ValidationPolicy) -> Result<Self> {
+        let d = &DEFAULT_MATERIAL;
+        Ok(match self {
+            MaterialField::Color(c) => {
+                MaterialField::Color(validate_color("color", c, WHITE, policy)?)
+            }
+            MaterialField::Ambient(v) => MaterialField::Ambient(validate_param(
+                "ambient",
+                v,
+                NON_NEGATIVE,
+                d.ambient,
+                policy,
+            )?),
+            MaterialField::Diffuse(v) => MaterialField::Diffuse(validate_param(
+                "diffuse",
+                v,
+                NON_NEGATIVE,
+                d.diffuse,
+                policy,
+            )?),
+            MaterialField::Specular(v) => MaterialField::Specular(validate_param(
+                "specular",
+                v,
+                NON_NEGATIVE,
+                d.specular,
+                policy,
+            )?),
+            MaterialField::Shininess(v) => MaterialField::Shininess(validate_param(
+                "shininess",
+                v,
+                NON_NEGATIVE,
+                d.shininess,
+                policy,
+            )?),
+            MaterialField::Reflective(v) => MaterialField::Reflective(validate_param(
+                "reflective",
+                v,
+                UNIT,
+                d.reflective,
+                policy,
+            )?),
+            MaterialField::Transparency(v) => MaterialField::Transparency(validate_param(
+                "transparency",
+                v,
+                UNIT,
+                d.transparency,
+                policy,
+            )?),
+            MaterialField::RefractiveIndex(v) => MaterialField::RefractiveIndex(validate_param(
+                "refractive index",
+                v,
+                INDEX,
+                d.refractive_index,
+                policy,
+            )?),
+        })
+    }
+}
+
+// Two materials mixed by a mask pattern, for worn or dirty patches and
+// gradual transitions between surfaces. Where the mask is black the surface
+// is `first`, where it is white `second`, and in between every parameter
+// and color is interpolated by the mask's brightness (the average of its
+// channels). Turned into a `Material` to be put on a shape; until it is
+// resolved at a point (see `Material::at`) it reports the first material's
+// parameters, so a blended volume has the first's priority and refractive
+// index.
+pub struct BlendedMaterial<'a> {
+    first: Material<'a>,
+    second: Material<'a>,
+    mask: PatternWrap<'a>,
+}
+
+impl<'a> BlendedMaterial<'a> {
+    pub fn new(
+        first: Material<'a>,
+        second: Material<'a>,
+        mask: impl Pattern + 'a,
+        transform: Affine,
+    ) -> Result<Self> {
+        let inverse_transform = transform
+            .inverse()
+            .ok_or(RayTracerError::NonInvertibleTransform)?;
+        Ok(Self {
+            first,
+            second,
+            mask: PatternWrap::Custom(Box::new(mask), inverse_transform),
+        })
+    }
+    fn weight(
+        &self,
+        shape_inv_transform: &Affine,
+        point: &Point,
+        eyev: &Vector,
+        normalv: &Vector,
+    ) -> Scalar {
+        let c = self
+            .mask
+            .color_at(shape_inv_transform, point, eyev, normalv);
+        ((c.red + c.green + c.blue) / 3.0).clamp(0.0, 1.0)
+    }
+}
+
+impl<'a> From<BlendedMaterial<'a>> for Material<'a> {
+    fn from(blend: BlendedMaterial<'a>) -> Self {
+        let params = Material {
+            color: PatternWrap::Solid(WHITE),
+            ..blend.first
+        };
+        Material {
+            color: PatternWrap::Blend(Box::new(blend)),
+            ..params
+        }
+    }
+}
+
+// Only a material's parameters are (de)serialized. A custom pattern is code
+// and cannot be written out, so a patterned material reads back as solid.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct MaterialParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<Color>,
+    ambient: Scalar,
+    diffuse: Scalar,
+    specular: Scalar,
+    shininess: Scalar,
+    reflective: Scalar,
+    transparency: Scalar,
+    refractive_index: Scalar,
+    dispersion: Scalar,
+    priority: u32,
+    rim_color: Color,
+    rim_exponent: Scalar,
+    reflection_tint: Color,
+    transmission_tint: Color,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conductor: Option<Conductor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thin_film: Option<ThinFilm>,
+}
+
+#[cfg(feature = "serde")]
+impl Default for MaterialParams {
+    fn default() -> Self {
+        Self::from(&DEFAULT_MATERIAL)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<&Material<'_>> for MaterialParams {
+    fn from(m: &Material) -> Self {
+        Self {
+            color: match m.color {
+                PatternWrap::Solid(c) => Some(c),
+                _ => None,
+            },
+            ambient: m.ambient,
+            diffuse: m.diffuse,
+            specular: m.specular,
+            shininess: m.shininess,
+            reflective: m.reflective,
+            transparency: m.transparency,
+            refractive_index: m.refractive_index,
+            dispersion: m.dispersion,
+            priority: m.priority,
+            rim_color: m.rim_color,
+            rim_exponent: m.rim_exponent,
+            reflection_tint: m.reflection_tint,
+            transmission_tint: m.transmission_tint,
+            conductor: m.conductor,
+            thin_film: m.thin_film,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Material<'_> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        MaterialParams::from(self).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Material<'_> {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let params = MaterialParams::deserialize(deserializer)?;
+        Ok(Self {
+            color: PatternWrap::Solid(params.color.unwrap_or(WHITE)),
+            ambient: params.ambient,
+            diffuse: params.diffuse,
+            specular: params.specular,
+            shininess: params.shininess,
+            reflective: params.reflective,
+            transparency: params.transparency,
+            refractive_index: params.refractive_index,
+            dispersion: params.dispersion,
+            priority: params.priority,
+            rim_color: params.rim_color,
+            rim_exponent: params.rim_exponent,
+            reflection_tint: params.reflection_tint,
+            transmission_tint: params.transmission_tint,
+            conductor: params.conductor,
+            thin_film: params.thin_film,
+        })
     }
 }
 
@@ -146,9 +1051,45 @@ mod tests {
     use crate::approx_eq::{assert_approx_eq, ApproxEq};
     use crate::material::Material;
     use crate::point::ORIGIN;
-    use crate::transform::IDENTITY_AFFINE;
+    use crate::transform::{scaling, IDENTITY_AFFINE};
     use crate::vector::Vector;
 
+    #[test]
+    fn test_metals_reflect_their_color_head_on_and_white_at_grazing_angles() {
+        let gold = Conductor::GOLD.reflectance(1.0);
+        assert!(gold.red > 0.9 && gold.red > gold.green && gold.green > gold.blue);
+        assert_approx_eq!(Conductor::GOLD.reflectance(0.0), WHITE);
+        let m = Material::new()
+            .set_reflective(1.0)
+            .unwrap()
+            .set_conductor(Some(Conductor::SILVER));
+        let c = m.reflected_color_at(&Color::new(0.5, 0.5, 0.5), 1.0);
+        assert_approx_eq!(c, Conductor::SILVER.reflectance(1.0) * 0.5);
+        assert_approx_eq!(
+            Material::new()
+                .set_reflective(1.0)
+                .unwrap()
+                .reflected_color_at(&WHITE, 1.0),
+            WHITE
+        );
+    }
+
+    #[test]
+    fn test_thin_films_reflect_colors_that_shift_with_thickness() {
+        let soap = |thickness| ThinFilm::new(thickness, 1.33).reflectance(1.0, 1.0, 1.0, None);
+        // a quarter wave thick film reflects that wavelength the most
+        let green = soap(550.0 / (4.0 * 1.33));
+        assert!(green.green > green.red && green.green > green.blue);
+        let thicker = soap(400.0);
+        assert!(!thicker.approx_eq(&green));
+        // a film too thin to interfere reflects nothing between equal media
+        assert_approx_eq!(soap(0.0), BLACK);
+        assert_approx_eq!(
+            ThinFilm::new(300.0, 1.33).reflectance(1.5, 1.0, 0.1, None),
+            WHITE
+        );
+    }
+
     #[test]
     fn test_lighting_with_the_eye_between_the_light_and_the_surface() {
         let m = Material::new();
@@ -164,7 +1105,7 @@ mod tests {
     fn test_lighting_with_the_eye_between_light_and_surface_eye_offset_45() {
         let m = Material::new();
         let position = ORIGIN;
-        let eyev = Vector::new(0.0, 2f64.sqrt() / 2.0, -2f64.sqrt() / 2.0);
+        let eyev = Vector::new(0.0, Scalar::sqrt(2.0) / 2.0, -Scalar::sqrt(2.0) / 2.0);
         let normalv = Vector::new(0.0, 0.0, -1.0);
         let light = PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE);
         let result = m.lighting(&light, &IDENTITY_AFFINE, &position, &eyev, &normalv, false);
@@ -186,7 +1127,7 @@ mod tests {
     fn test_lighting_with_eye_in_the_path_of_the_reflection_vector() {
         let m = Material::new();
         let position = ORIGIN;
-        let eyev = Vector::new(0.0, -2f64.sqrt() / 2.0, -2f64.sqrt() / 2.0);
+        let eyev = Vector::new(0.0, -Scalar::sqrt(2.0) / 2.0, -Scalar::sqrt(2.0) / 2.0);
         let normalv = Vector::new(0.0, 0.0, -1.0);
         let light = PointLight::new(Point::new(0.0, 10.0, -10.0), WHITE);
         let result = m.lighting(&light, &IDENTITY_AFFINE, &position, &eyev, &normalv, false);
@@ -214,4 +1155,333 @@ mod tests {
         let result = m.lighting(&light, &IDENTITY_AFFINE, &position, &eyev, &normalv, true);
         assert_approx_eq!(result, Color::new(0.1, 0.1, 0.1));
     }
+
+    #[test]
+    fn test_lighting_with_a_closure_pattern() {
+        let m = Material::new()
+            .set_pattern(
+                |p: &Point| if p.x < 0.5 { WHITE } else { BLACK },
+                IDENTITY_AFFINE,
+            )
+            .unwrap()
+            .set_ambient(1.0)
+            .unwrap()
+            .set_diffuse(0.0)
+            .unwrap()
+            .set_specular(0.0)
+            .unwrap();
+        let eyev = Vector::new(0.0, 0.0, -1.0);
+        let normalv = Vector::new(0.0, 0.0, -1.0);
+        let light = PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE);
+        let c1 = m.lighting(
+            &light,
+            &IDENTITY_AFFINE,
+            &Point::new(0.0, 0.0, 0.0),
+            &eyev,
+            &normalv,
+            false,
+        );
+        let c2 = m.lighting(
+            &light,
+            &IDENTITY_AFFINE,
+            &Point::new(1.0, 0.0, 0.0),
+            &eyev,
+            &normalv,
+            false,
+        );
+        assert_approx_eq!(c1, WHITE);
+        assert_approx_eq!(c2, BLACK);
+    }
+
+    #[test]
+    fn test_lighting_with_a_rim_term() {
+        let m = Material::new()
+            .set_ambient(0.0)
+            .unwrap()
+            .set_diffuse(0.0)
+            .unwrap()
+            .set_specular(0.0)
+            .unwrap()
+            .set_rim(Color::new(1.0, 0.5, 0.0), 2.0)
+            .unwrap();
+        let light = PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE);
+        let normalv = Vector::new(0.0, 0.0, -1.0);
+        let head_on = Vector::new(0.0, 0.0, -1.0);
+        let result = m.lighting(&light, &IDENTITY_AFFINE, &ORIGIN, &head_on, &normalv, false);
+        assert_approx_eq!(result, BLACK);
+        let grazing = Vector::new(0.0, Scalar::sqrt(3.0) / 2.0, -0.5);
+        let result = m.lighting(&light, &IDENTITY_AFFINE, &ORIGIN, &grazing, &normalv, false);
+        assert_approx_eq!(result, Color::new(0.25, 0.125, 0.0));
+        let result = m.lighting(&light, &IDENTITY_AFFINE, &ORIGIN, &grazing, &normalv, true);
+        assert_approx_eq!(result, BLACK);
+    }
+
+    #[test]
+    fn test_tints_scale_reflected_and_refracted_light_per_channel() {
+        let gold = Color::new(1.0, 0.8, 0.3);
+        let m = Material::new()
+            .set_reflective(0.5)
+            .unwrap()
+            .set_reflection_tint(gold)
+            .unwrap()
+            .set_transparency(0.5)
+            .unwrap()
+            .set_transmission_tint(Color::new(0.2, 1.0, 0.4))
+            .unwrap();
+        assert_approx_eq!(m.reflected_color(&WHITE), Color::new(0.5, 0.4, 0.15));
+        assert_approx_eq!(
+            m.scale_transparency(&Color::new(1.0, 0.5, 1.0)),
+            Color::new(0.1, 0.25, 0.2)
+        );
+        // untinted, every channel is scaled alike
+        let plain = Material::new().set_reflective(0.5).unwrap();
+        assert_approx_eq!(plain.reflected_color(&gold), gold * 0.5);
+    }
+
+    #[test]
+    fn test_lighting_with_a_blended_material() {
+        let flat = |color: Color| {
+            Material::new()
+                .set_color(color)
+                .unwrap()
+                .set_ambient(1.0)
+                .unwrap()
+                .set_diffuse(0.0)
+                .unwrap()
+                .set_specular(0.0)
+                .unwrap()
+        };
+        let m: Material = BlendedMaterial::new(
+            flat(Color::new(1.0, 0.0, 0.0)),
+            flat(Color::new(0.0, 0.0, 1.0)),
+            |p: &Point| if p.x < 0.0 { BLACK } else { WHITE * 0.5 },
+            IDENTITY_AFFINE,
+        )
+        .unwrap()
+        .into();
+        let light = PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE);
+        let eyev = Vector::new(0.0, 0.0, -1.0);
+        let lighting = |x: Scalar| {
+            let p = Point::new(x, 0.0, 0.0);
+            m.lighting(&light, &IDENTITY_AFFINE, &p, &eyev, &eyev, false)
+        };
+        assert_approx_eq!(lighting(-1.0), Color::new(1.0, 0.0, 0.0));
+        assert_approx_eq!(lighting(1.0), Color::new(0.5, 0.0, 0.5));
+    }
+
+    #[test]
+    fn test_a_blended_material_mixes_its_parameters_at_a_point() {
+        let m: Material = BlendedMaterial::new(
+            Material::new()
+                .set_refractive_index(1.0)
+                .unwrap()
+                .set_priority(1),
+            Material::new()
+                .set_reflective(1.0)
+                .unwrap()
+                .set_refractive_index(2.0)
+                .unwrap()
+                .set_priority(2),
+            |_: &Point| WHITE * 0.25,
+            IDENTITY_AFFINE,
+        )
+        .unwrap()
+        .into();
+        let normalv = Vector::new(0.0, 0.0, -1.0);
+        let resolved = m.at(&IDENTITY_AFFINE, &ORIGIN, &normalv, &normalv);
+        assert_approx_eq!(resolved.get_reflective(), 0.25);
+        assert_approx_eq!(resolved.get_refractive_index(), 1.25);
+        assert_eq!(resolved.get_priority(), 1);
+        // unresolved, it is the first material
+        assert_eq!(m.get_reflective(), 0.0);
+        assert!(m.solid_key().is_none());
+    }
+
+    #[test]
+    fn test_nested_blends_are_resolved_before_mixing() {
+        let reflective = |r: Scalar| Material::new().set_reflective(r).unwrap();
+        let half = |_: &Point| WHITE * 0.5;
+        let inner: Material =
+            BlendedMaterial::new(reflective(0.0), reflective(1.0), half, IDENTITY_AFFINE)
+                .unwrap()
+                .into();
+        let m: Material = BlendedMaterial::new(inner, reflective(1.0), half, IDENTITY_AFFINE)
+            .unwrap()
+            .into();
+        let normalv = Vector::new(0.0, 0.0, -1.0);
+        let resolved = m.at(&IDENTITY_AFFINE, &ORIGIN, &normalv, &normalv);
+        assert_approx_eq!(resolved.get_reflective(), 0.75);
+    }
+
+    #[test]
+    fn test_dispersion_bends_blue_light_more() {
+        let m = Material::new()
+            .set_refractive_index(1.5)
+            .unwrap()
+            .set_dispersion(0.004)
+            .unwrap();
+        assert_approx_eq!(m.refractive_index_at(None), 1.5);
+        assert_approx_eq!(m.refractive_index_at(Some(587.6)), 1.5);
+        assert!(m.refractive_index_at(Some(450.0)) > 1.5);
+        assert!(m.refractive_index_at(Some(650.0)) < 1.5);
+        let plain = Material::new().set_refractive_index(1.5).unwrap();
+        assert_approx_eq!(plain.refractive_index_at(Some(450.0)), 1.5);
+    }
+
+    #[test]
+    fn test_validating_a_valid_material_leaves_it_unchanged() {
+        let m = Material::new()
+            .set_transparency(1.0)
+            .unwrap()
+            .set_refractive_index(1.5)
+            .unwrap()
+            .validate(ValidationPolicy::Error)
+            .unwrap();
+        assert_approx_eq!(m.transparency, 1.0);
+        assert_approx_eq!(m.refractive_index, 1.5);
+    }
+
+    #[test]
+    fn test_clamping_out_of_range_material_parameters() {
+        let m = Material {
+            shininess: -10.0,
+            transparency: 1.5,
+            refractive_index: 0.5,
+            ..Material::new()
+        }
+        .validate(ValidationPolicy::Clamp)
+        .unwrap();
+        assert_approx_eq!(m.shininess, 0.0);
+        assert_approx_eq!(m.transparency, 1.0);
+        assert_approx_eq!(m.refractive_index, 1.0);
+    }
+
+    #[test]
+    fn test_non_finite_material_parameters_are_reset_to_defaults() {
+        let m = Material {
+            diffuse: Scalar::NAN,
+            ..Material::new()
+        }
+        .validate(ValidationPolicy::Warn)
+        .unwrap();
+        assert_approx_eq!(m.diffuse, DEFAULT_MATERIAL.diffuse);
+    }
+
+    #[test]
+    fn test_patterns_get_normals_in_pattern_space() {
+        struct NormalPattern;
+        impl Pattern for NormalPattern {
+            fn get_color(&self, context: &PatternContext) -> Color {
+                let n = context.normal;
+                Color::new(n.x, n.y, n.z)
+            }
+        }
+        let m = Material::new()
+            .set_pattern(NormalPattern, IDENTITY_AFFINE)
+            .unwrap();
+        // a sphere squashed to half its height, at 45 degrees on its surface
+        let shape_inv_transform = scaling(1.0, 2.0, 1.0);
+        let s = (0.5 as Scalar).sqrt();
+        let world_normal = Vector::new(1.0, 2.0, 0.0).normalize();
+        let c = m.color_at(
+            &shape_inv_transform,
+            &Point::new(s, s / 2.0, 0.0),
+            &world_normal,
+            &world_normal,
+        );
+        assert_approx_eq!(c, Color::new(s, s, 0.0));
+    }
+
+    #[test]
+    fn test_validating_colors_films_and_conductors() {
+        let m = Material {
+            color: PatternWrap::Solid(Color::new(-1.0, 0.5, Scalar::NAN)),
+            reflection_tint: Color::new(Scalar::INFINITY, 1.0, 1.0),
+            ..Material::new()
+        }
+        .set_conductor(Some(Conductor::new(Color::new(-1.0, 1.0, 1.0), BLACK)))
+        .set_thin_film(Some(ThinFilm::new(-100.0, 0.5)))
+        .validate(ValidationPolicy::Clamp)
+        .unwrap();
+        assert_approx_eq!(
+            m.color_at(
+                &IDENTITY_AFFINE,
+                &ORIGIN,
+                &Vector::new(0.0, 0.0, -1.0),
+                &Vector::new(0.0, 0.0, -1.0)
+            ),
+            Color::new(0.0, 0.5, 1.0)
+        );
+        assert_approx_eq!(m.reflection_tint, WHITE);
+        assert_approx_eq!(m.conductor.unwrap().n, Color::new(0.0, 1.0, 1.0));
+        let film = m.get_thin_film().unwrap();
+        assert_approx_eq!(film.thickness, 0.0);
+        assert_approx_eq!(film.refractive_index, 1.0);
+        let result = Material {
+            rim_color: Color::new(0.0, -0.1, 0.0),
+            ..Material::new()
+        }
+        .validate(ValidationPolicy::Error);
+        assert!(matches!(result, Err(RayTracerError::InvalidMaterial(_))));
+    }
+
+    #[test]
+    fn test_invalid_material_parameters_can_be_errors() {
+        let result = Material {
+            transparency: 1.5,
+            ..Material::new()
+        }
+        .validate(ValidationPolicy::Error);
+        assert!(matches!(result, Err(RayTracerError::InvalidMaterial(_))));
+    }
+
+    #[test]
+    fn test_setters_reject_invalid_parameters() {
+        let invalid =
+            |result: Result<Material>| matches!(result, Err(RayTracerError::InvalidMaterial(_)));
+        assert!(invalid(Material::new().set_ambient(-0.1)));
+        assert!(invalid(Material::new().set_shininess(Scalar::NAN)));
+        assert!(invalid(Material::new().set_reflective(1.5)));
+        assert!(invalid(Material::new().set_refractive_index(0.5)));
+        assert!(invalid(Material::new().set_dispersion(-0.01)));
+        assert!(invalid(Material::new().set_color(Color::new(
+            Scalar::INFINITY,
+            0.0,
+            0.0
+        ))));
+        assert!(invalid(Material::new().set_rim(WHITE, -1.0)));
+        assert!(invalid(
+            Material::new().set_transmission_tint(Color::new(0.0, -1.0, 0.0))
+        ));
+        assert!(invalid(
+            Material::new().set_field(MaterialField::Diffuse(Scalar::NAN))
+        ));
+        let clamped = MaterialField::Transparency(1.5).validate(ValidationPolicy::Clamp);
+        assert!(matches!(clamped, Ok(MaterialField::Transparency(t)) if t == 1.0));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_a_material_round_trips_through_json() {
+        let m = Material::new()
+            .set_color(Color::new(0.1, 0.2, 0.3))
+            .unwrap()
+            .set_reflective(0.5)
+            .unwrap();
+        let json = serde_json::to_string(&m).unwrap();
+        let m2: Material = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&m2).unwrap(), json);
+        assert!(m2.is_reflective());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_missing_material_parameters_take_their_defaults() {
+        let m: Material = serde_json::from_str(r#"{"ambient": 0.5}"#).unwrap();
+        let expected = Material::new().set_ambient(0.5).unwrap();
+        assert_eq!(
+            serde_json::to_value(&m).unwrap(),
+            serde_json::to_value(&expected).unwrap()
+        );
+    }
 }
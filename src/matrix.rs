@@ -1,6 +1,7 @@
 use crate::approx_eq::ApproxEq;
-use crate::point::Point;
-use crate::vector::Vector;
+use crate::point::Point3;
+use crate::scalar::{Float, Scalar};
+use crate::vector::Vector3;
 use std::ops::Mul;
 
 pub const IDENTITY_MATRIX: Matrix = Matrix {
@@ -8,29 +9,28 @@ pub const IDENTITY_MATRIX: Matrix = Matrix {
 };
 
 #[derive(Copy, Clone, Debug)]
-pub struct Matrix {
-    elems: [[f64; 3]; 3],
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Matrix3<T> {
+    elems: [[T; 3]; 3],
 }
 
-impl Matrix {
-    pub fn new(elems: [[f64; 3]; 3]) -> Self {
+pub type Matrix = Matrix3<Scalar>;
+
+impl<T: Float> Matrix3<T> {
+    pub fn new(elems: [[T; 3]; 3]) -> Self {
         Self { elems }
     }
-    pub fn at(&self, i: usize, j: usize) -> f64 {
+    pub fn at(&self, i: usize, j: usize) -> T {
         self.elems[i][j]
     }
     pub fn transpose(&self) -> Self {
-        let mut elems = [[0f64; 3]; 3];
-        for i in 0..3 {
-            for j in 0..3 {
-                elems[i][j] = self.elems[j][i];
-            }
+        Self {
+            elems: std::array::from_fn(|i| std::array::from_fn(|j| self.elems[j][i])),
         }
-        Self { elems }
     }
 }
 
-impl ApproxEq for Matrix {
+impl<T: Float> ApproxEq for Matrix3<T> {
     fn approx_eq(&self, other: &Self) -> bool {
         self.elems[0].approx_eq(&other.elems[0])
             && self.elems[1].approx_eq(&other.elems[1])
@@ -38,27 +38,35 @@ impl ApproxEq for Matrix {
     }
 }
 
-impl Mul<&Matrix> for Matrix {
+impl<T: Float> Mul<&Matrix3<T>> for Matrix3<T> {
     type Output = Self;
 
     fn mul(self, rhs: &Self) -> Self::Output {
-        let mut elems = [[0f64; 3]; 3];
-        for i in 0..3 {
-            for j in 0..3 {
-                elems[i][j] = self.elems[i][0] * rhs.elems[0][j]
-                    + self.elems[i][1] * rhs.elems[1][j]
-                    + self.elems[i][2] * rhs.elems[2][j];
-            }
+        Self {
+            elems: std::array::from_fn(|i| {
+                std::array::from_fn(|j| {
+                    self.elems[i][0] * rhs.elems[0][j]
+                        + self.elems[i][1] * rhs.elems[1][j]
+                        + self.elems[i][2] * rhs.elems[2][j]
+                })
+            }),
         }
-        Self { elems }
     }
 }
 
-impl Mul<&Vector> for &Matrix {
-    type Output = Vector;
+impl<T: Float> Mul<&Vector3<T>> for &Matrix3<T> {
+    type Output = Vector3<T>;
 
-    fn mul(self, rhs: &Vector) -> Self::Output {
-        Vector::new(
+    fn mul(self, rhs: &Vector3<T>) -> Self::Output {
+        *self * rhs
+    }
+}
+
+impl<T: Float> Mul<&Vector3<T>> for Matrix3<T> {
+    type Output = Vector3<T>;
+
+    fn mul(self, rhs: &Vector3<T>) -> Self::Output {
+        Vector3::new(
             self.elems[0][0] * rhs.x + self.elems[0][1] * rhs.y + self.elems[0][2] * rhs.z,
             self.elems[1][0] * rhs.x + self.elems[1][1] * rhs.y + self.elems[1][2] * rhs.z,
             self.elems[2][0] * rhs.x + self.elems[2][1] * rhs.y + self.elems[2][2] * rhs.z,
@@ -66,19 +74,19 @@ impl Mul<&Vector> for &Matrix {
     }
 }
 
-impl Mul<&Vector> for Matrix {
-    type Output = Vector;
+impl<T: Float> Mul<&Point3<T>> for &Matrix3<T> {
+    type Output = Point3<T>;
 
-    fn mul(self, rhs: &Vector) -> Self::Output {
-        &self * rhs
+    fn mul(self, rhs: &Point3<T>) -> Self::Output {
+        *self * rhs
     }
 }
 
-impl Mul<&Point> for &Matrix {
-    type Output = Point;
+impl<T: Float> Mul<&Point3<T>> for Matrix3<T> {
+    type Output = Point3<T>;
 
-    fn mul(self, rhs: &Point) -> Self::Output {
-        Point::new(
+    fn mul(self, rhs: &Point3<T>) -> Self::Output {
+        Point3::new(
             self.elems[0][0] * rhs.x + self.elems[0][1] * rhs.y + self.elems[0][2] * rhs.z,
             self.elems[1][0] * rhs.x + self.elems[1][1] * rhs.y + self.elems[1][2] * rhs.z,
             self.elems[2][0] * rhs.x + self.elems[2][1] * rhs.y + self.elems[2][2] * rhs.z,
@@ -86,34 +94,26 @@ impl Mul<&Point> for &Matrix {
     }
 }
 
-impl Mul<&Point> for Matrix {
-    type Output = Point;
-
-    fn mul(self, rhs: &Point) -> Self::Output {
-        &self * rhs
-    }
-}
-
 #[derive(Debug)]
-struct Matrix2 {
-    elems: [[f64; 2]; 2],
+struct Matrix2<T> {
+    elems: [[T; 2]; 2],
 }
 
-impl Matrix2 {
-    fn determinant(&self) -> f64 {
+impl<T: Float> Matrix2<T> {
+    fn determinant(&self) -> T {
         self.elems[0][0] * self.elems[1][1] - self.elems[1][0] * self.elems[0][1]
     }
 }
 
-impl ApproxEq for Matrix2 {
+impl<T: Float> ApproxEq for Matrix2<T> {
     fn approx_eq(&self, other: &Self) -> bool {
         self.elems[0].approx_eq(&other.elems[0]) && self.elems[1].approx_eq(&other.elems[1])
     }
 }
 
-impl Matrix {
-    fn submatrix(&self, i: usize, j: usize) -> Matrix2 {
-        let mut elems = [[0f64; 2]; 2];
+impl<T: Float> Matrix3<T> {
+    fn submatrix(&self, i: usize, j: usize) -> Matrix2<T> {
+        let mut elems = [[T::zero(); 2]; 2];
         let mut j2 = 0;
         for j1 in 0..3 {
             if j1 != j {
@@ -129,35 +129,30 @@ impl Matrix {
         }
         Matrix2 { elems }
     }
-    fn minor(&self, i: usize, j: usize) -> f64 {
+    fn minor(&self, i: usize, j: usize) -> T {
         self.submatrix(i, j).determinant()
     }
-    fn cofactor(&self, i: usize, j: usize) -> f64 {
+    fn cofactor(&self, i: usize, j: usize) -> T {
         let m = self.minor(i, j);
-        if (i + j) % 2 == 0 {
+        if (i + j).is_multiple_of(2) {
             m
         } else {
             -m
         }
     }
-    fn determinant(&self) -> f64 {
+    fn determinant(&self) -> T {
         self.elems[0][0] * self.cofactor(0, 0)
             + self.elems[0][1] * self.cofactor(0, 1)
             + self.elems[0][2] * self.cofactor(0, 2)
     }
     pub fn inverse(&self) -> Option<Self> {
         let det = self.determinant();
-        if det.approx_eq(&0.0) {
+        if det.approx_eq(&T::zero()) {
             return None;
         }
-        let mut elems = [[0f64; 3]; 3];
-        for j in 0..3 {
-            for i in 0..3 {
-                let c = self.cofactor(i, j);
-                elems[j][i] = c / det;
-            }
-        }
-        Some(Self { elems })
+        Some(Self {
+            elems: std::array::from_fn(|j| std::array::from_fn(|i| self.cofactor(i, j) / det)),
+        })
     }
 }
 
@@ -166,6 +161,7 @@ mod tests {
 
     use super::*;
     use crate::approx_eq::assert_approx_eq;
+    use crate::vector::Vector;
 
     #[test]
     fn test_matrix_equality_with_identical_matrices() {
@@ -288,6 +284,16 @@ mod tests {
         assert_approx_eq!(a * &b, IDENTITY_MATRIX);
     }
 
+    #[test]
+    fn test_inverting_a_matrix_of_another_scalar_type() {
+        let a = Matrix3::<f32>::new([[-5.0, 2.0, 6.0], [1.0, -5.0, 1.0], [7.0, 7.0, -6.0]]);
+        let b = a.inverse().unwrap();
+        assert_approx_eq!(
+            a * &b,
+            Matrix3::new([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+        );
+    }
+
     #[test]
     fn test_multiplying_a_product_by_its_inverse() {
         let a = Matrix::new([[3.0, -9.0, 7.0], [3.0, -8.0, 2.0], [-4.0, 4.0, 4.0]]);
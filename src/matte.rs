@@ -0,0 +1,212 @@
+use crate::canvas::Canvas;
+use crate::color::WHITE;
+use crate::exr::{self, Channel};
+use crate::scalar::Scalar;
+use std::collections::BTreeMap;
+
+// The layer the mattes are written to, as compositors expect it.
+const LAYER: &str = "CryptoObject";
+// How many objects per pixel are kept in an EXR, two to a channel group.
+pub const MATTE_RANKS: usize = 6;
+
+// Per pixel, how much of it each object covers, for pulling out selection
+// masks in compositing. Objects are identified by their names' hashes as in
+// Cryptomatte, so the ids stay the same between renders even when shapes
+// are added or reordered. Shapes without a name are called "shape <id>".
+pub struct IdMatte {
+    width: usize,
+    height: usize,
+    coverage: Vec<Vec<(u32, Scalar)>>,
+    names: BTreeMap<String, u32>,
+}
+
+impl IdMatte {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            coverage: vec![vec![]; width * height],
+            names: BTreeMap::new(),
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub(crate) fn add(&mut self, x: usize, y: usize, name: &str, coverage: Scalar) {
+        let hash = *self
+            .names
+            .entry(name.to_string())
+            .or_insert_with(|| name_hash(name));
+        let pixel = &mut self.coverage[y * self.width + x];
+        match pixel.iter_mut().find(|(h, _)| *h == hash) {
+            Some((_, c)) => *c += coverage,
+            None => pixel.push((hash, coverage)),
+        }
+        pixel.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    }
+    // The name hashes in the pixel with their coverage, largest first.
+    pub fn coverage_at(&self, x: usize, y: usize) -> &[(u32, Scalar)] {
+        &self.coverage[y * self.width + x]
+    }
+    // The coverage of the named objects together, as a gray mask.
+    pub fn mask(&self, names: &[&str]) -> Canvas {
+        let hashes: Vec<u32> = names.iter().map(|name| name_hash(name)).collect();
+        let mut mask = Canvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let c: Scalar = self
+                    .coverage_at(x, y)
+                    .iter()
+                    .filter(|(h, _)| hashes.contains(h))
+                    .map(|(_, c)| c)
+                    .sum();
+                mask.write_pixel(x, y, WHITE * c);
+            }
+        }
+        mask
+    }
+    // The names seen and their hashes in hex, as a JSON object.
+    pub fn manifest(&self) -> String {
+        let entries: Vec<String> = self
+            .names
+            .iter()
+            .map(|(name, hash)| format!("\"{}\":\"{:08x}\"", escape(name), hash))
+            .collect();
+        format!("{{{}}}", entries.join(","))
+    }
+    // An OpenEXR image in the Cryptomatte layout: the `MATTE_RANKS` objects
+    // covering most of each pixel, as (id, coverage) pairs in RGBA channel
+    // groups, with the manifest in the header.
+    pub fn to_exr(&self) -> Vec<u8> {
+        let mut channels = vec![];
+        for rank in 0..MATTE_RANKS {
+            let group = format!("{}{:02}", LAYER, rank / 2);
+            let (id, coverage) = if rank % 2 == 0 {
+                ("R", "G")
+            } else {
+                ("B", "A")
+            };
+            channels.push(Channel {
+                name: format!("{}.{}", group, id),
+                value: Box::new(move |x, y| {
+                    self.coverage_at(x, y)
+                        .get(rank)
+                        .map_or(0.0, |(hash, _)| hash_to_float(*hash) as Scalar)
+                }),
+            });
+            channels.push(Channel {
+                name: format!("{}.{}", group, coverage),
+                value: Box::new(move |x, y| {
+                    self.coverage_at(x, y).get(rank).map_or(0.0, |(_, c)| *c)
+                }),
+            });
+        }
+        let key = format!("cryptomatte/{:07x}", name_hash(LAYER) >> 4);
+        let strings = [
+            ("name", LAYER.to_string()),
+            ("hash", "MurmurHash3_32".to_string()),
+            ("conversion", "uint32_to_float32".to_string()),
+            ("manifest", self.manifest()),
+        ]
+        .map(|(field, value)| (format!("{}/{}", key, field), value));
+        exr::encode_channels(self.width, self.height, channels, &strings)
+    }
+}
+
+// MurmurHash3 (x86, 32-bit, seed 0) of the name, as Cryptomatte hashes
+// names.
+pub fn name_hash(name: &str) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+    let mix = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    let data = name.as_bytes();
+    let mut h = 0u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        h ^= mix(u32::from_le_bytes(chunk.try_into().unwrap()));
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let k = tail
+            .iter()
+            .enumerate()
+            .fold(0u32, |k, (i, b)| k | (*b as u32) << (8 * i));
+        h ^= mix(k);
+    }
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85ebca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2ae35);
+    h ^ (h >> 16)
+}
+
+// The hash stored as the bits of a float, nudged off the exponents that
+// would make it a denormal, infinite or NaN.
+pub fn hash_to_float(hash: u32) -> f32 {
+    let exponent = (hash >> 23) & 0xff;
+    if exponent == 0 || exponent == 0xff {
+        f32::from_bits(hash ^ (1 << 23))
+    } else {
+        f32::from_bits(hash)
+    }
+}
+
+fn escape(name: &str) -> String {
+    let mut escaped = String::new();
+    for c in name.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    #[test]
+    fn test_names_are_hashed_as_cryptomatte_does() {
+        assert_eq!(name_hash(""), 0);
+        assert_eq!(name_hash("hello"), 0x248bfa47);
+        assert!(hash_to_float(name_hash("bunny")).is_normal());
+        assert!(hash_to_float(0).is_normal());
+        assert!(hash_to_float(u32::MAX).is_finite());
+    }
+
+    #[test]
+    fn test_masks_and_manifest_of_an_id_matte() {
+        let mut matte = IdMatte::new(2, 1);
+        matte.add(0, 0, "floor", 0.25);
+        matte.add(0, 0, "ball \"red\"", 0.75);
+        matte.add(1, 0, "floor", 1.0);
+        let coverage = matte.coverage_at(0, 0);
+        assert_eq!(coverage[0], (name_hash("ball \"red\""), 0.75));
+        assert_approx_eq!(matte.mask(&["floor"]).pixel_at(0, 0), WHITE * 0.25);
+        assert_approx_eq!(matte.mask(&["floor"]).pixel_at(1, 0), WHITE);
+        assert_eq!(
+            matte.manifest(),
+            format!(
+                "{{\"ball \\\"red\\\"\":\"{:08x}\",\"floor\":\"{:08x}\"}}",
+                name_hash("ball \"red\""),
+                name_hash("floor")
+            )
+        );
+        let exr = matte.to_exr();
+        let contains = |needle: &[u8]| exr.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"CryptoObject00.R\0"));
+        assert!(contains(b"CryptoObject02.A\0"));
+        assert!(contains(b"/manifest\0string\0"));
+        assert!(contains(b"MurmurHash3_32"));
+    }
+}
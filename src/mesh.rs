@@ -0,0 +1,582 @@
+use crate::approx_eq::EPSILON;
+use crate::bounds::Bounds;
+use crate::bvh::{Bvh, BvhBuilder};
+use crate::color::Color;
+use crate::error::{RayTracerError, Result};
+use crate::point::Point;
+use crate::ray::Ray;
+use crate::scalar::Scalar;
+use crate::shape::LocalShape;
+use crate::vector::Vector;
+
+// Triangles stored as shared vertex (and optional normal and color) buffers
+// indexed by u32 triples, so a large model is a single shape rather than one
+// boxed shape per triangle.
+pub struct Mesh {
+    vertices: Vec<Point>,
+    normals: Vec<Vector>,
+    colors: Vec<Color>,
+    indices: Vec<[u32; 3]>,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Point>, indices: Vec<[u32; 3]>) -> Result<Self> {
+        if let Some(index) = indices
+            .iter()
+            .flatten()
+            .find(|i| **i as usize >= vertices.len())
+        {
+            return Err(RayTracerError::InvalidMesh(format!(
+                "vertex index {} out of range",
+                index
+            )));
+        }
+        let bvh = build_bvh(&BvhBuilder::new(), &vertices, &indices);
+        Ok(Self {
+            vertices,
+            normals: vec![],
+            colors: vec![],
+            indices,
+            bvh,
+        })
+    }
+    // Rebuilds the BVH over the triangles, e.g. with a different leaf size.
+    pub fn set_bvh_builder(self, builder: BvhBuilder) -> Self {
+        let bvh = build_bvh(&builder, &self.vertices, &self.indices);
+        Self { bvh, ..self }
+    }
+    // Per-vertex normals, interpolated across each triangle.
+    pub fn set_normals(self, normals: Vec<Vector>) -> Result<Self> {
+        if normals.len() != self.vertices.len() {
+            return Err(RayTracerError::InvalidMesh(format!(
+                "{} normals for {} vertices",
+                normals.len(),
+                self.vertices.len()
+            )));
+        }
+        let normals = normals.iter().map(|n| n.normalize()).collect();
+        Ok(Self { normals, ..self })
+    }
+    // Per-vertex colors, interpolated across each triangle and multiplied
+    // into the material's color, as scanned models tend to come with.
+    pub fn set_colors(self, colors: Vec<Color>) -> Result<Self> {
+        if colors.len() != self.vertices.len() {
+            return Err(RayTracerError::InvalidMesh(format!(
+                "{} colors for {} vertices",
+                colors.len(),
+                self.vertices.len()
+            )));
+        }
+        Ok(Self { colors, ..self })
+    }
+    // Vertex normals for a mesh that came without them. Each corner gets
+    // the area-weighted average of the faces around its vertex that are
+    // within `crease_angle` (in radians) of its own face, so smooth areas
+    // are shaded smoothly while sharper edges stay crisp. Vertices on such
+    // edges are split, one copy per distinct normal.
+    pub fn generate_normals(self, crease_angle: Scalar) -> Self {
+        let min_cos = crease_angle.cos();
+        // the cross product's length is twice the face's area
+        let weighted: Vec<Vector> = (0..self.indices.len())
+            .map(|triangle| {
+                let [p1, p2, p3] = self.corners(triangle);
+                (p3 - &p1).cross(&(p2 - &p1))
+            })
+            .collect();
+        let unit: Vec<Option<Vector>> = weighted
+            .iter()
+            .map(|n| (n.magnitude() > EPSILON).then(|| n.normalize()))
+            .collect();
+        let mut faces_at = vec![vec![]; self.vertices.len()];
+        for (triangle, tri) in self.indices.iter().enumerate() {
+            for v in tri {
+                faces_at[*v as usize].push(triangle);
+            }
+        }
+        let mut vertices = vec![];
+        let mut normals: Vec<Vector> = vec![];
+        let mut colors = vec![];
+        // the copies made of each original vertex so far
+        let mut copies: Vec<Vec<u32>> = vec![vec![]; self.vertices.len()];
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for (triangle, tri) in self.indices.iter().enumerate() {
+            indices.push(tri.map(|v| {
+                let own = unit[triangle];
+                let mut n = Vector::new(0.0, 0.0, 0.0);
+                for other in &faces_at[v as usize] {
+                    let smooth = match (own, unit[*other]) {
+                        (Some(a), Some(b)) => a.dot(&b) >= min_cos,
+                        _ => *other == triangle,
+                    };
+                    if smooth {
+                        n = &n + &weighted[*other];
+                    }
+                }
+                let n = own.map_or(Vector::new(0.0, 1.0, 0.0), |own| {
+                    if n.magnitude() > EPSILON {
+                        n.normalize()
+                    } else {
+                        own
+                    }
+                });
+                let existing = copies[v as usize]
+                    .iter()
+                    .find(|i| normals[**i as usize].dot(&n) > 1.0 - EPSILON);
+                if let Some(i) = existing {
+                    return *i;
+                }
+                let i = vertices.len() as u32;
+                vertices.push(self.vertices[v as usize]);
+                normals.push(n);
+                if !self.colors.is_empty() {
+                    colors.push(self.colors[v as usize]);
+                }
+                copies[v as usize].push(i);
+                i
+            }));
+        }
+        Self {
+            vertices,
+            normals,
+            colors,
+            indices,
+            bvh: self.bvh,
+        }
+    }
+    // New positions for the vertices, and optionally new normals, for
+    // skinned or morphing meshes between frames. The triangles stay as they
+    // are, so the BVH is refitted rather than rebuilt. Normals that aren't
+    // given are kept.
+    pub fn set_vertices(
+        mut self,
+        vertices: Vec<Point>,
+        normals: Option<Vec<Vector>>,
+    ) -> Result<Self> {
+        self.deform(vertices, normals)?;
+        Ok(self)
+    }
+    fn deform(&mut self, vertices: Vec<Point>, normals: Option<Vec<Vector>>) -> Result<()> {
+        if vertices.len() != self.vertices.len() {
+            return Err(RayTracerError::InvalidMesh(format!(
+                "{} vertices given for a mesh of {}",
+                vertices.len(),
+                self.vertices.len()
+            )));
+        }
+        if let Some(v) = vertices
+            .iter()
+            .find(|v| !(v.x.is_finite() && v.y.is_finite() && v.z.is_finite()))
+        {
+            return Err(RayTracerError::InvalidMesh(format!(
+                "vertex {:?} is not finite",
+                v
+            )));
+        }
+        if let Some(normals) = &normals {
+            if normals.len() != self.vertices.len() {
+                return Err(RayTracerError::InvalidMesh(format!(
+                    "{} normals for {} vertices",
+                    normals.len(),
+                    self.vertices.len()
+                )));
+            }
+        }
+        self.vertices = vertices;
+        if let Some(normals) = normals {
+            self.normals = normals.iter().map(|n| n.normalize()).collect();
+        }
+        let refitted = self
+            .bvh
+            .refit(|i| triangle_bounds(&self.vertices, &self.indices[i]));
+        debug_assert!(refitted, "finite vertices give finite bounds");
+        Ok(())
+    }
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+    fn corners(&self, triangle: usize) -> [Point; 3] {
+        self.indices[triangle].map(|i| self.vertices[i as usize])
+    }
+    fn face_normal(&self, triangle: usize) -> Vector {
+        let [p1, p2, p3] = self.corners(triangle);
+        (p3 - &p1).cross(&(p2 - &p1)).normalize()
+    }
+    fn intersect_triangle(&self, triangle: usize, ray: &Ray) -> Option<Scalar> {
+        let [p1, p2, p3] = self.corners(triangle);
+        let e1 = p2 - &p1;
+        let e2 = p3 - &p1;
+        let dir_cross_e2 = ray.direction.cross(&e2);
+        let det = e1.dot(&dir_cross_e2);
+        if det.abs() < EPSILON {
+            return None;
+        }
+        let f = 1.0 / det;
+        let p1_to_origin = ray.origin - &p1;
+        let u = f * p1_to_origin.dot(&dir_cross_e2);
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let origin_cross_e1 = p1_to_origin.cross(&e1);
+        let v = f * ray.direction.dot(&origin_cross_e1);
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        Some(f * e2.dot(&origin_cross_e1))
+    }
+    // Barycentric weights of `p` projected onto the triangle's plane.
+    fn barycentric(&self, triangle: usize, p: &Point) -> [Scalar; 3] {
+        let [p1, p2, p3] = self.corners(triangle);
+        let e1 = p2 - &p1;
+        let e2 = p3 - &p1;
+        let ep = *p - &p1;
+        let d11 = e1.dot(&e1);
+        let d12 = e1.dot(&e2);
+        let d22 = e2.dot(&e2);
+        let dp1 = ep.dot(&e1);
+        let dp2 = ep.dot(&e2);
+        let denom = d11 * d22 - d12 * d12;
+        let u = (d22 * dp1 - d12 * dp2) / denom;
+        let v = (d11 * dp2 - d12 * dp1) / denom;
+        [1.0 - u - v, u, v]
+    }
+    // The shapes interface only hands back the hit point, so find the
+    // triangle it lies on among those whose bounds contain it.
+    fn locate(&self, p: &Point) -> Option<(usize, [Scalar; 3])> {
+        let mut best: Option<(Scalar, usize, [Scalar; 3])> = None;
+        self.bvh.for_each_containing(p, |triangle| {
+            let weights = self.barycentric(triangle, p);
+            if weights.iter().any(|w| *w < -EPSILON) {
+                return;
+            }
+            let [p1, _, _] = self.corners(triangle);
+            let distance = (*p - &p1).dot(&self.face_normal(triangle)).abs();
+            if best.is_none_or(|(d, _, _)| distance < d) {
+                best = Some((distance, triangle, weights));
+            }
+        });
+        best.map(|(_, triangle, weights)| (triangle, weights))
+    }
+}
+
+fn build_bvh(builder: &BvhBuilder, vertices: &[Point], indices: &[[u32; 3]]) -> Bvh {
+    builder.build(
+        indices
+            .iter()
+            .enumerate()
+            .map(|(i, tri)| (i, triangle_bounds(vertices, tri))),
+    )
+}
+
+fn triangle_bounds(vertices: &[Point], triangle: &[u32; 3]) -> Bounds {
+    let mut bounds = Bounds::empty();
+    for v in triangle {
+        bounds.add_point(&vertices[*v as usize]);
+    }
+    bounds
+}
+
+impl LocalShape for Mesh {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
+        let mut xs = vec![];
+        self.bvh.for_each(ray, |triangle| {
+            if let Some(t) = self.intersect_triangle(triangle, ray) {
+                xs.push(t);
+            }
+        });
+        xs
+    }
+    fn local_normal_at(&self, p: &Point) -> Vector {
+        let Some((triangle, weights)) = self.locate(p) else {
+            return Vector::new(0.0, 1.0, 0.0);
+        };
+        if self.normals.is_empty() {
+            return self.face_normal(triangle);
+        }
+        let [n1, n2, n3] = self.indices[triangle].map(|i| self.normals[i as usize]);
+        &(&(weights[0] * &n1) + &(weights[1] * &n2)) + &(weights[2] * &n3)
+    }
+    fn local_geometric_normal_at(&self, p: &Point) -> Vector {
+        match self.locate(p) {
+            Some((triangle, _)) => self.face_normal(triangle),
+            None => Vector::new(0.0, 1.0, 0.0),
+        }
+    }
+    fn local_color_at(&self, p: &Point) -> Option<Color> {
+        if self.colors.is_empty() {
+            return None;
+        }
+        let (triangle, weights) = self.locate(p)?;
+        let [c1, c2, c3] = self.indices[triangle].map(|i| self.colors[i as usize]);
+        Some(c1 * weights[0] + c2 * weights[1] + c3 * weights[2])
+    }
+    fn local_bounds(&self) -> Bounds {
+        self.vertices.iter().fold(Bounds::empty(), |mut bounds, v| {
+            bounds.add_point(v);
+            bounds
+        })
+    }
+    // Hanika's shadow terminator fix: project the point onto the tangent
+    // planes of the three vertices and interpolate, which lifts it off the
+    // flat triangle towards the smooth surface the normals describe.
+    fn local_shadow_origin(&self, p: &Point) -> Point {
+        if self.normals.is_empty() {
+            return *p;
+        }
+        let Some((triangle, weights)) = self.locate(p) else {
+            return *p;
+        };
+        let corners = self.corners(triangle);
+        let mut offset = Vector::new(0.0, 0.0, 0.0);
+        for (k, i) in self.indices[triangle].iter().enumerate() {
+            let n = self.normals[*i as usize];
+            let below = (*p - &corners[k]).dot(&n).min(0.0);
+            offset = offset - &(&n * (weights[k] * below));
+        }
+        *p + &offset
+    }
+    fn local_edge_distance(&self, p: &Point) -> Option<Scalar> {
+        let (_, weights) = self.locate(p)?;
+        Some(weights.into_iter().fold(Scalar::INFINITY, Scalar::min))
+    }
+    fn local_deform(&mut self, vertices: Vec<Point>, normals: Option<Vec<Vector>>) -> Result<()> {
+        self.deform(vertices, normals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    fn triangle() -> Mesh {
+        Mesh::new(
+            vec![
+                Point::new(0.0, 1.0, 0.0),
+                Point::new(-1.0, 0.0, 0.0),
+                Point::new(1.0, 0.0, 0.0),
+            ],
+            vec![[0, 1, 2]],
+        )
+        .unwrap()
+    }
+
+    // A flat n x n grid of squares in the xz plane, two triangles each.
+    fn grid(n: u32) -> Mesh {
+        let vertices = (0..=n)
+            .flat_map(|z| (0..=n).map(move |x| Point::new(x as Scalar, 0.0, z as Scalar)))
+            .collect();
+        let indices = (0..n)
+            .flat_map(|z| {
+                (0..n).flat_map(move |x| {
+                    let i = z * (n + 1) + x;
+                    [[i, i + 1, i + n + 1], [i + 1, i + n + 2, i + n + 1]]
+                })
+            })
+            .collect();
+        Mesh::new(vertices, indices).unwrap()
+    }
+
+    #[test]
+    fn test_intersecting_a_ray_parallel_to_the_triangle() {
+        let r = Ray::new(Point::new(0.0, -1.0, -2.0), Vector::new(0.0, 1.0, 0.0));
+        assert!(triangle().local_intersect(&r).is_empty());
+    }
+
+    #[test]
+    fn test_a_ray_misses_the_triangle_edges() {
+        let t = triangle();
+        for origin in [
+            Point::new(1.0, 1.0, -2.0),
+            Point::new(-1.0, 1.0, -2.0),
+            Point::new(0.0, -1.0, -2.0),
+        ] {
+            let r = Ray::new(origin, Vector::new(0.0, 0.0, 1.0));
+            assert!(t.local_intersect(&r).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_a_ray_strikes_a_triangle() {
+        let r = Ray::new(Point::new(0.0, 0.5, -2.0), Vector::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(triangle().local_intersect(&r), [2.0]);
+    }
+
+    #[test]
+    fn test_the_normal_of_a_flat_triangle() {
+        let t = triangle();
+        let n = Vector::new(0.0, 0.0, -1.0);
+        assert_approx_eq!(t.local_normal_at(&Point::new(0.0, 0.5, 0.0)), n);
+        assert_approx_eq!(t.local_normal_at(&Point::new(-0.5, 0.5, 0.0)), n);
+        assert_approx_eq!(t.local_normal_at(&Point::new(0.5, 0.25, 0.0)), n);
+    }
+
+    #[test]
+    fn test_interpolating_vertex_normals() {
+        let t = triangle()
+            .set_normals(vec![
+                Vector::new(0.0, 1.0, 0.0),
+                Vector::new(-1.0, 0.0, 0.0),
+                Vector::new(1.0, 0.0, 0.0),
+            ])
+            .unwrap();
+        let n = t.local_normal_at(&Point::new(0.0, 1.0, 0.0));
+        assert_approx_eq!(n, Vector::new(0.0, 1.0, 0.0));
+        let n = t.local_normal_at(&Point::new(0.5, 0.5, 0.0));
+        assert_approx_eq!(n, Vector::new(0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn test_the_shadow_origin_of_a_flat_triangle_is_the_point() {
+        let p = Point::new(0.0, 0.5, 0.0);
+        assert_approx_eq!(triangle().local_shadow_origin(&p), p);
+    }
+
+    #[test]
+    fn test_smooth_normals_lift_the_shadow_origin() {
+        let t = triangle()
+            .set_normals(vec![
+                Vector::new(0.0, 1.0, -1.0),
+                Vector::new(-1.0, 0.0, -1.0),
+                Vector::new(1.0, 0.0, -1.0),
+            ])
+            .unwrap();
+        let p = t.local_shadow_origin(&Point::new(0.0, 0.5, 0.0));
+        assert!(p.z < -EPSILON);
+
+        // the corners themselves stay put
+        let corner = Point::new(-1.0, 0.0, 0.0);
+        assert_approx_eq!(t.local_shadow_origin(&corner), corner);
+    }
+
+    #[test]
+    fn test_the_distance_to_a_triangle_edge() {
+        let t = triangle();
+        assert_approx_eq!(
+            t.local_edge_distance(&Point::new(0.0, 0.5, 0.0)).unwrap(),
+            0.25
+        );
+        assert_approx_eq!(
+            t.local_edge_distance(&Point::new(0.0, 0.0, 0.0)).unwrap(),
+            0.0
+        );
+        assert!(t.local_edge_distance(&Point::new(0.0, 2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_out_of_range_indices_are_rejected() {
+        let result = Mesh::new(vec![Point::new(0.0, 0.0, 0.0)], vec![[0, 0, 1]]);
+        assert!(matches!(result, Err(RayTracerError::InvalidMesh(_))));
+    }
+
+    #[test]
+    fn test_interpolating_vertex_colors() {
+        let t = triangle()
+            .set_colors(vec![
+                Color::new(1.0, 0.0, 0.0),
+                Color::new(0.0, 1.0, 0.0),
+                Color::new(0.0, 0.0, 1.0),
+            ])
+            .unwrap();
+        let c = t.local_color_at(&Point::new(0.0, 1.0, 0.0)).unwrap();
+        assert_approx_eq!(c, Color::new(1.0, 0.0, 0.0));
+        let c = t.local_color_at(&Point::new(0.0, 0.0, 0.0)).unwrap();
+        assert_approx_eq!(c, Color::new(0.0, 0.5, 0.5));
+        assert!(triangle()
+            .local_color_at(&Point::new(0.0, 0.5, 0.0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_the_color_count_must_match_the_vertices() {
+        let result = triangle().set_colors(vec![Color::new(1.0, 1.0, 1.0)]);
+        assert!(matches!(result, Err(RayTracerError::InvalidMesh(_))));
+    }
+
+    // The four corners of a unit square in the xz plane, plus the top of
+    // a wall standing on its far edge.
+    fn folded() -> Mesh {
+        Mesh::new(
+            vec![
+                Point::new(0.0, 0.0, 0.0),
+                Point::new(1.0, 0.0, 0.0),
+                Point::new(0.0, 0.0, 1.0),
+                Point::new(1.0, 0.0, 1.0),
+                Point::new(0.0, 1.0, 1.0),
+                Point::new(1.0, 1.0, 1.0),
+            ],
+            vec![[0, 1, 2], [1, 3, 2], [2, 3, 4], [3, 5, 4]],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_generated_normals_of_a_flat_mesh() {
+        let g = grid(2).generate_normals(0.5);
+        assert_eq!(g.vertices.len(), 9);
+        for n in &g.normals {
+            assert_approx_eq!(*n, Vector::new(0.0, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn test_generated_normals_keep_sharp_edges() {
+        let m = folded().generate_normals(0.5);
+        // the two vertices on the fold are split in two
+        assert_eq!(m.vertices.len(), 8);
+        let n = m.local_normal_at(&Point::new(0.5, 0.0, 0.9));
+        assert_approx_eq!(n, Vector::new(0.0, 1.0, 0.0));
+        let n = m.local_normal_at(&Point::new(0.5, 0.1, 1.0));
+        assert_approx_eq!(n, Vector::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_generated_normals_smooth_across_wide_creases() {
+        let m = folded().generate_normals(2.0);
+        assert_eq!(m.vertices.len(), 6);
+        let n = m.local_normal_at(&Point::new(0.5, 0.0, 1.0)).normalize();
+        let half = Scalar::sqrt(0.5);
+        assert_approx_eq!(n, Vector::new(0.0, half, -half));
+    }
+
+    #[test]
+    fn test_the_normal_count_must_match_the_vertices() {
+        let result = triangle().set_normals(vec![Vector::new(0.0, 1.0, 0.0)]);
+        assert!(matches!(result, Err(RayTracerError::InvalidMesh(_))));
+    }
+
+    #[test]
+    fn test_intersecting_a_large_mesh() {
+        let g = grid(100);
+        assert_eq!(g.triangle_count(), 20000);
+        let r = Ray::new(Point::new(42.3, 5.0, 17.6), Vector::new(0.0, -1.0, 0.0));
+        assert_approx_eq!(g.local_intersect(&r), [5.0]);
+        let n = g.local_normal_at(&Point::new(42.3, 0.0, 17.6));
+        assert_approx_eq!(n, Vector::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_deforming_a_mesh_refits_it() {
+        let g = grid(100);
+        // tilt the grid so its height is its x coordinate
+        let tilted: Vec<Point> = g
+            .vertices
+            .iter()
+            .map(|v| Point::new(v.x, v.x, v.z))
+            .collect();
+        let g = g.set_vertices(tilted, None).unwrap();
+        let r = Ray::new(Point::new(42.3, 100.0, 17.6), Vector::new(0.0, -1.0, 0.0));
+        assert_approx_eq!(g.local_intersect(&r), [57.7]);
+        assert_approx_eq!(g.local_bounds().max, Point::new(100.0, 100.0, 100.0));
+        let result = g.set_vertices(vec![Point::new(0.0, 0.0, 0.0); 3], None);
+        assert!(matches!(result, Err(RayTracerError::InvalidMesh(_))));
+    }
+
+    #[test]
+    fn test_the_bounds_of_a_mesh() {
+        let b = grid(3).local_bounds();
+        assert_approx_eq!(b.min, Point::new(0.0, 0.0, 0.0));
+        assert_approx_eq!(b.max, Point::new(3.0, 0.0, 3.0));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::approx_eq::EPSILON;
+use crate::bounds::Bounds;
+use crate::error::{RayTracerError, Result};
+use crate::point::Point;
+use crate::ray::Ray;
+use crate::scalar::Scalar;
+use crate::shape::LocalShape;
+use crate::vector::Vector;
+
+// Field samples taken along a ray per radius of the smallest ball. Features
+// thinner than a step may be missed.
+const STEPS_PER_RADIUS: Scalar = 8.0;
+
+// One control point of a blobby surface, adding a Gaussian of the given
+// strength and radius to the field.
+#[derive(Copy, Clone, Debug)]
+pub struct Metaball {
+    pub center: Point,
+    pub radius: Scalar,
+    pub strength: Scalar,
+}
+
+impl Metaball {
+    pub fn new(center: Point, radius: Scalar, strength: Scalar) -> Self {
+        Self {
+            center,
+            radius,
+            strength,
+        }
+    }
+    fn field(&self, p: &Point) -> Scalar {
+        let d = *p - &self.center;
+        self.strength * (-d.dot(&d) / (self.radius * self.radius)).exp()
+    }
+}
+
+// The surface where the summed fields of the balls reach `threshold`:
+// balls on their own are spheres, and they melt into each other as they
+// come close. A ball of strength 1 alone with a threshold of 1/e has
+// exactly its radius.
+pub struct Metaballs {
+    balls: Vec<Metaball>,
+    threshold: Scalar,
+    bounds: Bounds,
+    step: Scalar,
+}
+
+impl Metaballs {
+    pub fn new(balls: Vec<Metaball>, threshold: Scalar) -> Result<Self> {
+        if !(threshold > 0.0 && threshold.is_finite()) {
+            return Err(RayTracerError::InvalidShape(format!(
+                "metaball threshold must be positive, got {}",
+                threshold
+            )));
+        }
+        if let Some(ball) = balls
+            .iter()
+            .find(|b| !(b.radius > 0.0 && b.radius.is_finite() && b.strength.is_finite()))
+        {
+            return Err(RayTracerError::InvalidShape(format!(
+                "invalid metaball {:?}",
+                ball
+            )));
+        }
+        // Beyond this distance from every center each ball adds less than
+        // its share of the threshold, so the field stays below it.
+        let mut bounds = Bounds::empty();
+        let share = threshold / balls.len() as Scalar;
+        for ball in &balls {
+            if ball.strength <= share {
+                continue;
+            }
+            let reach = ball.radius * (ball.strength / share).ln().sqrt();
+            let c = ball.center;
+            bounds.add_point(&Point::new(c.x - reach, c.y - reach, c.z - reach));
+            bounds.add_point(&Point::new(c.x + reach, c.y + reach, c.z + reach));
+        }
+        let smallest = balls
+            .iter()
+            .map(|b| b.radius)
+            .fold(Scalar::INFINITY, Scalar::min);
+        Ok(Self {
+            balls,
+            threshold,
+            bounds,
+            step: smallest / STEPS_PER_RADIUS,
+        })
+    }
+    // The summed field less the threshold: positive inside the surface.
+    fn excess(&self, p: &Point) -> Scalar {
+        self.balls.iter().map(|b| b.field(p)).sum::<Scalar>() - self.threshold
+    }
+}
+
+impl LocalShape for Metaballs {
+    // Samples the field in small steps through the bounds and bisects each
+    // step where it crosses the threshold.
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
+        let (start, end) = self.bounds.ray_interval(ray);
+        if start > end {
+            return vec![];
+        }
+        let dt = self.step / ray.direction.magnitude();
+        let steps = ((end - start) / dt).ceil() as usize;
+        let excess = |t: Scalar| self.excess(&ray.position(t));
+        let mut xs = vec![];
+        let (mut t0, mut e0) = (start, excess(start));
+        for i in 1..=steps {
+            let t1 = (start + i as Scalar * dt).min(end);
+            let e1 = excess(t1);
+            if (e0 > 0.0) != (e1 > 0.0) {
+                let (mut lo, mut hi) = (t0, t1);
+                while hi - lo > EPSILON / 10.0 {
+                    let mid = (lo + hi) / 2.0;
+                    if (excess(mid) > 0.0) == (e0 > 0.0) {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                xs.push((lo + hi) / 2.0);
+            }
+            (t0, e0) = (t1, e1);
+        }
+        xs
+    }
+    // The field falls off outwards, so the normal is against its gradient.
+    fn local_normal_at(&self, p: &Point) -> Vector {
+        self.balls
+            .iter()
+            .fold(Vector::new(0.0, 0.0, 0.0), |n, b| {
+                n + &(&(*p - &b.center) * (b.field(p) / (b.radius * b.radius)))
+            })
+            .normalize()
+    }
+    fn local_bounds(&self) -> Bounds {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::point::ORIGIN;
+    use crate::scalar::consts::E;
+
+    const E_INV: Scalar = 1.0 / E;
+
+    #[test]
+    fn test_a_lone_metaball_is_a_sphere() {
+        let balls = Metaballs::new(vec![Metaball::new(ORIGIN, 1.0, 1.0)], E_INV).unwrap();
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(balls.local_intersect(&r), [4.0, 6.0]);
+        let n = balls.local_normal_at(&Point::new(0.0, 0.6, 0.8));
+        assert_approx_eq!(n, Vector::new(0.0, 0.6, 0.8));
+        assert!(Metaballs::new(vec![Metaball::new(ORIGIN, 0.0, 1.0)], E_INV).is_err());
+    }
+
+    #[test]
+    fn test_nearby_metaballs_melt_together() {
+        let pair = |distance: Scalar| {
+            Metaballs::new(
+                vec![
+                    Metaball::new(Point::new(-distance / 2.0, 0.0, 0.0), 1.0, 1.0),
+                    Metaball::new(Point::new(distance / 2.0, 0.0, 0.0), 1.0, 1.0),
+                ],
+                E_INV,
+            )
+            .unwrap()
+        };
+        // passes between the two spheres the balls would be alone
+        let r = Ray::new(Point::new(0.0, 0.5, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(pair(2.2).local_intersect(&r).len(), 2);
+        assert!(pair(6.0).local_intersect(&r).is_empty());
+    }
+}
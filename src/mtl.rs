@@ -0,0 +1,131 @@
+use crate::color::Color;
+use crate::error::{RayTracerError, Result};
+use crate::material::{Material, MaterialField, ValidationPolicy};
+use crate::scalar::Scalar;
+use std::collections::HashMap;
+
+// Reads the materials of a Wavefront .mtl library by name. Kd becomes the
+// color, the average of Ks the specular strength, Ns the shininess, d (or
+// Tr) the transparency and Ni the refractive index. Everything else,
+// texture maps included, is skipped. Values out of range are clamped, as
+// exporters commonly write things like Ni 0.
+pub fn parse_mtl(text: &str) -> Result<HashMap<String, Material<'static>>> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Material<'static>)> = None;
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| RayTracerError::Parse {
+            line: i + 1,
+            message,
+        };
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next().filter(|k| !k.starts_with('#')) else {
+            continue;
+        };
+        let values = tokens
+            .map(|t| t.parse::<Scalar>())
+            .collect::<std::result::Result<Vec<_>, _>>();
+        if keyword == "newmtl" {
+            let name = line.trim()["newmtl".len()..].trim();
+            if name.is_empty() {
+                return Err(error("newmtl needs a name".to_string()));
+            }
+            materials.extend(current.take());
+            current = Some((name.to_string(), Material::new()));
+            continue;
+        }
+        let expect = |count: usize| match &values {
+            Ok(values) if values.len() == count => Ok(values.clone()),
+            _ => Err(error(format!("{} expects {} numbers", keyword, count))),
+        };
+        let statement = match keyword {
+            "Kd" | "Ks" => Some((keyword, expect(3)?)),
+            "Ns" | "d" | "Tr" | "Ni" => Some((keyword, expect(1)?)),
+            _ => None,
+        };
+        let Some((keyword, v)) = statement else {
+            continue;
+        };
+        let (_, material) = current
+            .as_mut()
+            .ok_or_else(|| error(format!("{} before any newmtl", keyword)))?;
+        let field = match keyword {
+            "Kd" => MaterialField::Color(Color::new(v[0], v[1], v[2])),
+            "Ks" => MaterialField::Specular((v[0] + v[1] + v[2]) / 3.0),
+            "Ns" => MaterialField::Shininess(v[0]),
+            "d" => MaterialField::Transparency(1.0 - v[0]),
+            "Tr" => MaterialField::Transparency(v[0]),
+            _ => MaterialField::RefractiveIndex(v[0]),
+        };
+        let m = std::mem::take(material);
+        *material = m.set_field(field.validate(ValidationPolicy::Clamp)?)?;
+    }
+    materials.extend(current);
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::color::WHITE;
+    use crate::light::PointLight;
+    use crate::point::ORIGIN;
+    use crate::transform::IDENTITY_AFFINE;
+    use crate::vector::Vector;
+
+    #[test]
+    fn test_parsing_a_material_library() {
+        let text = "\
+# two materials
+newmtl red plastic
+Ka 0 0 0
+Kd 0.8 0.1 0.1
+Ks 0.3 0.6 0.9
+Ns 50
+illum 2
+
+newmtl glass
+d 0.25
+Ni 1.5
+map_Kd glass.png
+";
+        let materials = parse_mtl(text).unwrap();
+        assert_eq!(materials.len(), 2);
+        let plastic = &materials["red plastic"];
+        // in shadow only the ambient term, a tenth of the color, is left
+        let ambient = plastic.lighting(
+            &PointLight::new(ORIGIN, WHITE),
+            &IDENTITY_AFFINE,
+            &ORIGIN,
+            &Vector::new(0.0, 0.0, -1.0),
+            &Vector::new(0.0, 0.0, -1.0),
+            true,
+        );
+        assert_approx_eq!(ambient, Color::new(0.08, 0.01, 0.01));
+        assert_approx_eq!(plastic.get_specular(), 0.6);
+        assert_eq!(plastic.get_shininess(), 50.0);
+        let glass = &materials["glass"];
+        assert_eq!(glass.get_transparency(), 0.75);
+        assert_eq!(glass.get_refractive_index(), 1.5);
+    }
+
+    #[test]
+    fn test_material_library_values_are_clamped() {
+        let materials = parse_mtl("newmtl air\nNi 0\nd 1.5\n").unwrap();
+        let air = &materials["air"];
+        assert_eq!(air.get_refractive_index(), 1.0);
+        assert_eq!(air.get_transparency(), 0.0);
+    }
+
+    #[test]
+    fn test_material_library_errors_report_their_line() {
+        assert!(matches!(
+            parse_mtl("Kd 1 1 1"),
+            Err(RayTracerError::Parse { line: 1, .. })
+        ));
+        assert!(matches!(
+            parse_mtl("newmtl a\n\nNs shiny"),
+            Err(RayTracerError::Parse { line: 3, .. })
+        ));
+    }
+}
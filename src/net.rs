@@ -0,0 +1,368 @@
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::error::{RayTracerError, Result};
+use crate::scalar::Scalar;
+use crate::tile::{tile_rects, Rect};
+use crate::world::World;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+// Splitting a render across machines. Workers render single tiles of a
+// scene named by a key, which they turn into a world and camera themselves
+// (and keep for later tiles), so only the key and the tile travel over the
+// network. Every message is a u64 length followed by that many bytes; all
+// numbers are little endian u64 and colors f64.
+//
+//     request:  key length, key (UTF-8), x, y, width, height
+//     response: 0, pixel count, then red, green, blue, alpha per pixel
+//           or: 1, error message (UTF-8)
+
+// Largest message accepted, so a bad peer can't make us allocate anything.
+const MAX_MESSAGE: u64 = 1 << 30;
+
+type Scene = Arc<(World<'static>, Camera)>;
+
+// Answers tile requests on `listener` until it fails, one thread per
+// connection.
+pub fn serve(
+    listener: TcpListener,
+    resolve: impl Fn(&str) -> Result<(World<'static>, Camera)> + Sync,
+) -> Result<()> {
+    let scenes: Mutex<HashMap<String, Scene>> = Mutex::new(HashMap::new());
+    let (resolve, scenes) = (&resolve, &scenes);
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            scope.spawn(move || {
+                if let Err(err) = handle_connection(stream, resolve, scenes) {
+                    eprintln!("worker connection failed: {}", err);
+                }
+            });
+        }
+        Ok(())
+    })
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    resolve: &(impl Fn(&str) -> Result<(World<'static>, Camera)> + Sync),
+    scenes: &Mutex<HashMap<String, Scene>>,
+) -> Result<()> {
+    while let Some(request) = read_message(&mut stream)? {
+        let mut request = request.as_slice();
+        let key = read_string(&mut request)?;
+        let tile = Rect::new(
+            read_u64(&mut request)? as usize,
+            read_u64(&mut request)? as usize,
+            read_u64(&mut request)? as usize,
+            read_u64(&mut request)? as usize,
+        );
+        let mut response = vec![];
+        match render_tile(&key, &tile, resolve, scenes) {
+            Ok(colors) => {
+                response.extend_from_slice(&0u64.to_le_bytes());
+                response.extend_from_slice(&(colors.len() as u64).to_le_bytes());
+                for (color, alpha) in colors {
+                    for v in [color.red, color.green, color.blue, alpha] {
+                        #[allow(clippy::unnecessary_cast)]
+                        let v = v as f64;
+                        response.extend_from_slice(&v.to_le_bytes());
+                    }
+                }
+            }
+            Err(err) => {
+                response.extend_from_slice(&1u64.to_le_bytes());
+                response.extend_from_slice(err.to_string().as_bytes());
+            }
+        }
+        write_message(&mut stream, &response)?;
+    }
+    Ok(())
+}
+
+fn render_tile(
+    key: &str,
+    tile: &Rect,
+    resolve: &impl Fn(&str) -> Result<(World<'static>, Camera)>,
+    scenes: &Mutex<HashMap<String, Scene>>,
+) -> Result<Vec<(Color, Scalar)>> {
+    let cached = scenes.lock().unwrap().get(key).cloned();
+    let scene = match cached {
+        Some(scene) => scene,
+        None => {
+            let scene = Arc::new(resolve(key)?);
+            scenes
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), scene.clone());
+            scene
+        }
+    };
+    let (world, camera) = &*scene;
+    let inside = |start: usize, length: usize, size: usize| {
+        start.checked_add(length).is_some_and(|end| end <= size)
+    };
+    if !inside(tile.x, tile.width, camera.canvas_width())
+        || !inside(tile.y, tile.height, camera.get_vsize())
+    {
+        return Err(invalid_data(format!(
+            "tile {:?} is outside the image",
+            tile
+        )));
+    }
+    Ok(camera.tile_colors(world, tile))
+}
+
+// The tiles still to render, and how many are out with workers that may
+// yet give them back.
+struct TileQueue {
+    tiles: Vec<Rect>,
+    in_flight: usize,
+}
+
+impl TileQueue {
+    // Waits while the queue is empty but tiles are out, as a failing worker
+    // hands its tile back; None once every tile is done.
+    fn take(queue: &Mutex<Self>, returned: &Condvar) -> Option<Rect> {
+        let mut queue = queue.lock().unwrap();
+        loop {
+            if let Some(tile) = queue.tiles.pop() {
+                queue.in_flight += 1;
+                return Some(tile);
+            }
+            if queue.in_flight == 0 {
+                return None;
+            }
+            queue = returned.wait(queue).unwrap();
+        }
+    }
+    // Marks a tile as done, or puts it back when `unfinished`.
+    fn finish(queue: &Mutex<Self>, returned: &Condvar, unfinished: Option<Rect>) {
+        let mut queue = queue.lock().unwrap();
+        queue.in_flight -= 1;
+        queue.tiles.extend(unfinished);
+        returned.notify_all();
+    }
+}
+
+// Renders `camera`'s image by handing its tiles out to the workers, which
+// must resolve `key` to the same scene and camera. A worker that fails
+// gives its tile back to the others; the render only fails if no worker
+// is left.
+pub fn render_distributed(
+    camera: &Camera,
+    key: &str,
+    workers: &[impl ToSocketAddrs + Sync],
+) -> Result<Canvas> {
+    let width = camera.canvas_width();
+    let height = camera.get_vsize();
+    let queue = Mutex::new(TileQueue {
+        tiles: tile_rects(width, height, camera.get_tile_size()),
+        in_flight: 0,
+    });
+    let returned = Condvar::new();
+    let image = Mutex::new(camera.tiled_canvas());
+    let errors = Mutex::new(vec![]);
+    thread::scope(|scope| {
+        for worker in workers {
+            let (queue, returned, image, errors) = (&queue, &returned, &image, &errors);
+            scope.spawn(move || {
+                let result = TcpStream::connect(worker).map_err(RayTracerError::from);
+                let mut stream = match result {
+                    Ok(stream) => stream,
+                    Err(err) => return errors.lock().unwrap().push(err),
+                };
+                while let Some(tile) = TileQueue::take(queue, returned) {
+                    match request_tile(&mut stream, key, &tile) {
+                        Ok(colors) => {
+                            camera.write_tile(&mut image.lock().unwrap(), &tile, &colors);
+                            TileQueue::finish(queue, returned, None);
+                        }
+                        Err(err) => {
+                            TileQueue::finish(queue, returned, Some(tile));
+                            return errors.lock().unwrap().push(err);
+                        }
+                    }
+                }
+            });
+        }
+    });
+    if !queue.into_inner().unwrap().tiles.is_empty() {
+        return Err(errors
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| invalid_data("no workers to render on".to_string())));
+    }
+    Ok(image.into_inner().unwrap().into_canvas())
+}
+
+fn request_tile(stream: &mut TcpStream, key: &str, tile: &Rect) -> Result<Vec<(Color, Scalar)>> {
+    let mut request = vec![];
+    request.extend_from_slice(&(key.len() as u64).to_le_bytes());
+    request.extend_from_slice(key.as_bytes());
+    for v in [tile.x, tile.y, tile.width, tile.height] {
+        request.extend_from_slice(&(v as u64).to_le_bytes());
+    }
+    write_message(stream, &request)?;
+
+    let response = read_message(stream)?
+        .ok_or_else(|| invalid_data("worker closed the connection".to_string()))?;
+    let mut response = response.as_slice();
+    if read_u64(&mut response)? != 0 {
+        let message = String::from_utf8_lossy(response).to_string();
+        return Err(RayTracerError::Io(io::Error::other(message)));
+    }
+    let count = read_u64(&mut response)? as usize;
+    if count != tile.width * tile.height {
+        return Err(invalid_data(format!(
+            "worker sent {} pixels for a {}x{} tile",
+            count, tile.width, tile.height
+        )));
+    }
+    (0..count)
+        .map(|_| {
+            let color = Color::new(
+                read_scalar(&mut response)?,
+                read_scalar(&mut response)?,
+                read_scalar(&mut response)?,
+            );
+            Ok((color, read_scalar(&mut response)?))
+        })
+        .collect()
+}
+
+fn invalid_data(message: String) -> RayTracerError {
+    RayTracerError::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+// A single write, so Nagle's algorithm doesn't hold back the payload.
+fn write_message(stream: &mut impl Write, payload: &[u8]) -> Result<()> {
+    let mut message = (payload.len() as u64).to_le_bytes().to_vec();
+    message.extend_from_slice(payload);
+    stream.write_all(&message)?;
+    Ok(())
+}
+
+// None when the peer closed the connection between messages.
+fn read_message(stream: &mut impl Read) -> Result<Option<Vec<u8>>> {
+    let mut length = [0; 8];
+    match stream.read_exact(&mut length) {
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let length = u64::from_le_bytes(length);
+    if length > MAX_MESSAGE {
+        return Err(invalid_data(format!(
+            "message of {} bytes is too long",
+            length
+        )));
+    }
+    let mut payload = vec![0; length as usize];
+    stream.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+fn read_u64(bytes: &mut &[u8]) -> Result<u64> {
+    let mut buffer = [0; 8];
+    bytes.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn read_scalar(bytes: &mut &[u8]) -> Result<Scalar> {
+    Ok(f64::from_bits(read_u64(bytes)?) as Scalar)
+}
+
+fn read_string(bytes: &mut &[u8]) -> Result<String> {
+    let length = read_u64(bytes)? as usize;
+    if length > bytes.len() {
+        return Err(invalid_data("truncated message".to_string()));
+    }
+    let (string, rest) = bytes.split_at(length);
+    *bytes = rest;
+    String::from_utf8(string.to_vec()).map_err(|_| invalid_data("key is not UTF-8".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::color::WHITE;
+    use crate::light::PointLight;
+    use crate::point::Point;
+    use crate::scalar::consts::PI;
+    use crate::shape::Shape;
+    use crate::sphere::Sphere;
+    use crate::transform::translation;
+
+    fn scene(key: &str) -> Result<(World<'static>, Camera)> {
+        if key != "sphere" {
+            return Err(RayTracerError::InvalidCamera(format!("no scene {}", key)));
+        }
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        world.add_shape(Shape::new(Sphere::new()));
+        let camera = Camera::new(40, 30, PI / 2.0)?
+            .set_transform(translation(0.0, 0.0, -3.0))?
+            .set_samples(2)
+            .set_tile_size(7)?;
+        Ok((world, camera))
+    }
+
+    fn start_worker() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, scene));
+        address
+    }
+
+    #[test]
+    fn test_a_distributed_render_matches_a_local_one() {
+        let workers = [start_worker(), start_worker()];
+        let (world, camera) = scene("sphere").unwrap();
+        let image = render_distributed(&camera, "sphere", &workers).unwrap();
+        let local = camera.render(&world);
+        for y in 0..30 {
+            for x in 0..40 {
+                assert_eq!(
+                    <[Scalar; 3]>::from(image.pixel_at(x, y)),
+                    <[Scalar; 3]>::from(local.pixel_at(x, y))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_worker_errors_reach_the_coordinator() {
+        let workers = [start_worker()];
+        let (_, camera) = scene("sphere").unwrap();
+        match render_distributed(&camera, "cube", &workers) {
+            Err(err) => assert!(err.to_string().contains("no scene cube")),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_tiles_outside_the_image_are_rejected() {
+        let scenes = Mutex::new(HashMap::new());
+        let tile = Rect::new(usize::MAX, 0, 2, 2);
+        assert!(render_tile("sphere", &tile, &scene, &scenes).is_err());
+        let tile = Rect::new(38, 28, 2, 2);
+        assert_eq!(
+            render_tile("sphere", &tile, &scene, &scenes).unwrap().len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_rendering_without_workers_fails() {
+        let (_, camera) = scene("sphere").unwrap();
+        assert!(render_distributed(&camera, "sphere", &[] as &[&str]).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::bounds::Bounds;
+use crate::ray::Ray;
+use crate::scalar::Scalar;
+
+// Camera rays are traced this many at a time.
+pub const PACKET_SIZE: usize = 8;
+
+// A bit per ray of a packet, the lowest for its first ray.
+pub type LaneMask = u32;
+
+// Rays that start close together and point in similar directions, such as
+// those through neighbouring pixels, traced together so each node of an
+// acceleration structure is fetched once for all of them. The coordinates
+// are stored per axis so the box tests run over the lanes in lockstep.
+pub struct RayPacket<'a> {
+    rays: &'a [Ray],
+    origin: [[Scalar; PACKET_SIZE]; 3],
+    direction: [[Scalar; PACKET_SIZE]; 3],
+}
+
+impl<'a> RayPacket<'a> {
+    // At most PACKET_SIZE rays.
+    pub fn new(rays: &'a [Ray]) -> Self {
+        assert!(rays.len() <= PACKET_SIZE);
+        let mut origin = [[0.0; PACKET_SIZE]; 3];
+        // unused lanes point nowhere in particular and are masked out anyway
+        let mut direction = [[1.0; PACKET_SIZE]; 3];
+        for (lane, ray) in rays.iter().enumerate() {
+            for axis in 0..3 {
+                origin[axis][lane] = ray.origin[axis];
+                direction[axis][lane] = ray.direction[axis];
+            }
+        }
+        Self {
+            rays,
+            origin,
+            direction,
+        }
+    }
+    pub fn rays(&self) -> &'a [Ray] {
+        self.rays
+    }
+    pub fn mask(&self) -> LaneMask {
+        ((1u64 << self.rays.len()) - 1) as LaneMask
+    }
+    // The lanes among `active` whose rays pass through the box, by the
+    // same test as `Bounds::intersects`.
+    pub fn hits(&self, bounds: &Bounds, active: LaneMask) -> LaneMask {
+        let mut tmin = [Scalar::NEG_INFINITY; PACKET_SIZE];
+        let mut tmax = [Scalar::INFINITY; PACKET_SIZE];
+        for axis in 0..3 {
+            for lane in 0..PACKET_SIZE {
+                let o = self.origin[axis][lane];
+                let d = self.direction[axis][lane];
+                let t0 = (bounds.min[axis] - o) / d;
+                let t1 = (bounds.max[axis] - o) / d;
+                let (t0, t1) = if t0 > t1 { (t1, t0) } else { (t0, t1) };
+                tmin[lane] = tmin[lane].max(t0);
+                tmax[lane] = tmax[lane].min(t1);
+            }
+        }
+        let mut hits = 0;
+        for lane in 0..PACKET_SIZE {
+            if tmin[lane] <= tmax[lane] && tmax[lane] >= 0.0 {
+                hits |= 1 << lane;
+            }
+        }
+        hits & active
+    }
+}
+
+// The lanes set in `mask`, in order.
+pub fn lanes(mask: LaneMask) -> impl Iterator<Item = usize> {
+    (0..PACKET_SIZE).filter(move |lane| mask & (1 << lane) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::point::Point;
+    use crate::vector::Vector;
+
+    #[test]
+    fn test_a_packet_box_test_agrees_with_single_rays() {
+        let bounds = Bounds::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0));
+        let rays: Vec<Ray> = (0..7)
+            .map(|i| {
+                let x = i as Scalar * 0.5 - 1.5;
+                Ray::new(Point::new(x, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0))
+            })
+            .collect();
+        let packet = RayPacket::new(&rays);
+        let expected = rays
+            .iter()
+            .enumerate()
+            .filter(|(_, ray)| bounds.intersects(ray))
+            .fold(0, |mask, (lane, _)| mask | 1 << lane);
+        assert_eq!(packet.hits(&bounds, packet.mask()), expected);
+        assert_eq!(expected, 0b0111110);
+        // inactive lanes never hit
+        assert_eq!(packet.hits(&bounds, 0b100), 0b100);
+        assert_eq!(packet.hits(&bounds, 0b1), 0);
+    }
+
+    #[test]
+    fn test_the_lanes_of_a_mask() {
+        assert_eq!(lanes(0b10110).collect::<Vec<_>>(), vec![1, 2, 4]);
+    }
+}
@@ -1,7 +1,50 @@
-use crate::{color::Color, point::Point};
+use crate::canvas::Canvas;
+use crate::scalar::consts::PI;
+use crate::scalar::Scalar;
+use crate::{color::Color, point::Point, vector::Vector};
 
-pub trait Pattern {
-    fn get_color(&self, point: &Point) -> Color;
+// What a pattern knows about the point being shaded. `point` and `normal`
+// are in pattern space; the rest is in world space.
+#[derive(Copy, Clone, Debug)]
+pub struct PatternContext {
+    pub point: Point,
+    pub normal: Vector,
+    pub world_point: Point,
+    pub world_normal: Vector,
+    // direction of the ray arriving at the point
+    pub direction: Vector,
+}
+
+impl PatternContext {
+    // A point seen head-on, with pattern and world space coinciding.
+    pub fn new(point: Point, normal: Vector) -> Self {
+        Self {
+            point,
+            normal,
+            world_point: point,
+            world_normal: normal,
+            direction: -normal,
+        }
+    }
+    // 1 where the surface faces the viewer, falling to 0 at grazing angles.
+    pub fn facing_ratio(&self) -> Scalar {
+        self.world_normal.dot(&self.direction).abs()
+    }
+}
+
+pub trait Pattern: Send + Sync {
+    fn get_color(&self, context: &PatternContext) -> Color;
+}
+
+// Lets quick patterns that only depend on the point be written inline, e.g.
+// `material.set_pattern(|p: &Point| Color::new(p.x, p.y, p.z), transform)`.
+impl<F> Pattern for F
+where
+    F: Fn(&Point) -> Color + Send + Sync,
+{
+    fn get_color(&self, context: &PatternContext) -> Color {
+        self(&context.point)
+    }
 }
 
 // StripedPattern
@@ -18,7 +61,8 @@ impl StripedPattern {
 }
 
 impl Pattern for StripedPattern {
-    fn get_color(&self, point: &Point) -> Color {
+    fn get_color(&self, context: &PatternContext) -> Color {
+        let point = &context.point;
         if point.x.floor() as i32 % 2 == 0 {
             self.c1
         } else {
@@ -41,7 +85,8 @@ impl RingPattern {
 }
 
 impl Pattern for RingPattern {
-    fn get_color(&self, point: &Point) -> Color {
+    fn get_color(&self, context: &PatternContext) -> Color {
+        let point = &context.point;
         if (point.x * point.x + point.z * point.z).floor() as i32 % 2 == 0 {
             self.c1
         } else {
@@ -64,7 +109,8 @@ impl CheckersPattern {
 }
 
 impl Pattern for CheckersPattern {
-    fn get_color(&self, point: &Point) -> Color {
+    fn get_color(&self, context: &PatternContext) -> Color {
+        let point = &context.point;
         if (point.x.floor() + point.y.floor() + point.z.floor()) as i32 % 2 == 0 {
             self.c1
         } else {
@@ -72,3 +118,403 @@ impl Pattern for CheckersPattern {
         }
     }
 }
+
+// GridPattern
+
+// Lines of one color over another, as on graph paper: one line at each
+// whole x and z, `line_width` wide. Wrap it in a `TriplanarPattern` to rule
+// every face of a shape.
+pub struct GridPattern {
+    line: Color,
+    background: Color,
+    line_width: Scalar,
+}
+
+impl GridPattern {
+    pub fn new(line: Color, background: Color, line_width: Scalar) -> Self {
+        Self {
+            line,
+            background,
+            line_width,
+        }
+    }
+}
+
+impl Pattern for GridPattern {
+    fn get_color(&self, context: &PatternContext) -> Color {
+        let point = &context.point;
+        let on_line = |v: Scalar| (v - v.round()).abs() < self.line_width / 2.0;
+        if on_line(point.x) || on_line(point.z) {
+            self.line
+        } else {
+            self.background
+        }
+    }
+}
+
+// FractalPattern
+
+// The fractal drawn by a `FractalPattern`, with each point's x and z taken
+// as a complex number x + zi.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Fractal {
+    Mandelbrot,
+    // the Julia set of z² + c for c = re + im·i
+    Julia { re: Scalar, im: Scalar },
+}
+
+// A Mandelbrot or Julia set in the xz plane. Points that escape are colored
+// by how quickly they do, along a ramp running evenly through `palette`;
+// points still bounded after `max_iterations` are inside the set.
+pub struct FractalPattern {
+    fractal: Fractal,
+    max_iterations: usize,
+    palette: Vec<Color>,
+    inside: Color,
+}
+
+impl FractalPattern {
+    pub fn new(fractal: Fractal, max_iterations: usize, palette: Vec<Color>) -> Self {
+        Self {
+            fractal,
+            max_iterations,
+            palette,
+            inside: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+    pub fn set_inside(self, inside: Color) -> Self {
+        Self { inside, ..self }
+    }
+    // The color a fraction `t` of the way along the palette.
+    fn ramp(&self, t: Scalar) -> Color {
+        let Some(last) = self.palette.len().checked_sub(1) else {
+            return self.inside;
+        };
+        let position = t.clamp(0.0, 1.0) * last as Scalar;
+        let i = (position.floor() as usize).min(last);
+        let next = self.palette[(i + 1).min(last)];
+        self.palette[i] + (next - self.palette[i]) * (position - i as Scalar)
+    }
+}
+
+impl Pattern for FractalPattern {
+    fn get_color(&self, context: &PatternContext) -> Color {
+        let point = &context.point;
+        let (mut re, mut im, c_re, c_im) = match self.fractal {
+            Fractal::Mandelbrot => (0.0, 0.0, point.x, point.z),
+            Fractal::Julia { re, im } => (point.x, point.z, re, im),
+        };
+        for n in 0..self.max_iterations {
+            let r2 = re * re + im * im;
+            // escaping far beyond radius 2 lets the count be smoothed, so
+            // the ramp has no bands
+            if r2 > 256.0 {
+                let smooth = n as Scalar + 1.0 - (0.5 * r2.ln()).log2();
+                return self.ramp(smooth / self.max_iterations as Scalar);
+            }
+            (re, im) = (re * re - im * im + c_re, 2.0 * re * im + c_im);
+        }
+        self.inside
+    }
+}
+
+// TriplanarPattern
+
+// Projects a pattern onto the three axis planes and blends the projections
+// by how much the normal faces each axis, so it doesn't stretch on faces
+// that are not aligned with a single plane. Each projection samples the
+// wrapped pattern in its xz plane.
+pub struct TriplanarPattern<P: Pattern> {
+    pattern: P,
+    sharpness: Scalar,
+}
+
+impl<P: Pattern> TriplanarPattern<P> {
+    pub fn new(pattern: P) -> Self {
+        Self {
+            pattern,
+            sharpness: 4.0,
+        }
+    }
+    // Higher values narrow the blend between projections.
+    pub fn set_sharpness(self, sharpness: Scalar) -> Self {
+        Self { sharpness, ..self }
+    }
+}
+
+impl<P: Pattern> Pattern for TriplanarPattern<P> {
+    fn get_color(&self, context: &PatternContext) -> Color {
+        let (p, n) = (&context.point, &context.normal);
+        let weights = [n.x, n.y, n.z].map(|c| c.abs().powf(self.sharpness));
+        let total: Scalar = weights.iter().sum();
+        if total == 0.0 {
+            return self.pattern.get_color(context);
+        }
+        let up = Vector::new(0.0, 1.0, 0.0);
+        let projections = [
+            Point::new(p.z, 0.0, p.y),
+            Point::new(p.x, 0.0, p.z),
+            Point::new(p.x, 0.0, p.y),
+        ];
+        projections
+            .iter()
+            .zip(weights)
+            .filter(|(_, w)| *w > 0.0)
+            .fold(Color::new(0.0, 0.0, 0.0), |acc, (point, w)| {
+                let projected = PatternContext {
+                    point: *point,
+                    normal: up,
+                    ..*context
+                };
+                acc + self.pattern.get_color(&projected) * (w / total)
+            })
+    }
+}
+
+// ImagePattern
+
+// How a point in pattern space is mapped to texture coordinates (u, v),
+// each running from 0 to 1 across the image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UvMapping {
+    // x and z, repeating every unit
+    Planar,
+    // longitude and latitude around the origin, for the unit sphere
+    Spherical,
+    // the angle about the y axis, and y repeating every unit
+    Cylindrical,
+}
+
+impl UvMapping {
+    pub fn uv(&self, p: &Point) -> (Scalar, Scalar) {
+        let around = || 1.0 - (p.x.atan2(p.z) / (2.0 * PI) + 0.5);
+        match self {
+            UvMapping::Planar => (p.x.rem_euclid(1.0), p.z.rem_euclid(1.0)),
+            UvMapping::Spherical => {
+                let radius = Vector::new(p.x, p.y, p.z).magnitude();
+                let phi = if radius > 0.0 {
+                    (p.y / radius).clamp(-1.0, 1.0).acos()
+                } else {
+                    0.0
+                };
+                (around(), 1.0 - phi / PI)
+            }
+            UvMapping::Cylindrical => (around(), p.y.rem_euclid(1.0)),
+        }
+    }
+}
+
+// A canvas wrapped onto a surface, e.g. one generated procedurally or an
+// earlier render. v = 0 is the bottom row of the image; pixels are picked
+// nearest to the texture coordinates.
+pub struct ImagePattern {
+    canvas: Canvas,
+    mapping: UvMapping,
+    seam: Scalar,
+    poles: Poles,
+}
+
+// What a spherical mapping does towards the poles.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Poles {
+    // the top and bottom rows of the image are squeezed into the poles
+    #[default]
+    Pinch,
+    // each hemisphere shows the whole image, its bottom row along the
+    // equator and its top row at the pole
+    Mirrored,
+}
+
+impl ImagePattern {
+    pub fn from_canvas(canvas: Canvas, mapping: UvMapping) -> Self {
+        Self {
+            canvas,
+            mapping,
+            seam: 0.0,
+            poles: Poles::default(),
+        }
+    }
+    // Turns where the left and right edges of the image meet, which is on
+    // -z by default, by `angle` radians about the y axis towards +x. Only
+    // spherical and cylindrical mappings have a seam.
+    pub fn set_seam(self, angle: Scalar) -> Self {
+        Self {
+            seam: angle,
+            ..self
+        }
+    }
+    // Only spherical mappings have poles.
+    pub fn set_poles(self, poles: Poles) -> Self {
+        Self { poles, ..self }
+    }
+    pub fn uv(&self, p: &Point) -> (Scalar, Scalar) {
+        let (u, v) = self.mapping.uv(p);
+        if self.mapping == UvMapping::Planar {
+            return (u, v);
+        }
+        let u = (u - self.seam / (2.0 * PI)).rem_euclid(1.0);
+        if self.mapping == UvMapping::Spherical && self.poles == Poles::Mirrored {
+            return (u, (2.0 * v - 1.0).abs());
+        }
+        (u, v)
+    }
+    pub fn color_at_uv(&self, u: Scalar, v: Scalar) -> Color {
+        let (width, height) = (self.canvas.width(), self.canvas.height());
+        if width == 0 || height == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let x = (u.clamp(0.0, 1.0) * (width - 1) as Scalar).round() as usize;
+        let y = ((1.0 - v.clamp(0.0, 1.0)) * (height - 1) as Scalar).round() as usize;
+        self.canvas.pixel_at(x, y)
+    }
+}
+
+impl Pattern for ImagePattern {
+    fn get_color(&self, context: &PatternContext) -> Color {
+        let (u, v) = self.uv(&context.point);
+        self.color_at_uv(u, v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::color::{BLACK, MAGENTA, WHITE};
+    use crate::point::ORIGIN;
+
+    #[test]
+    fn test_a_grid_pattern_draws_lines_of_its_width() {
+        let pattern = GridPattern::new(WHITE, BLACK, 0.2);
+        let color_at = |x, z| {
+            pattern.get_color(&PatternContext::new(
+                Point::new(x, 0.0, z),
+                Vector::new(0.0, 1.0, 0.0),
+            ))
+        };
+        assert_approx_eq!(color_at(0.5, 0.5), BLACK);
+        assert_approx_eq!(color_at(2.05, 0.5), WHITE);
+        assert_approx_eq!(color_at(0.5, -2.95), WHITE);
+        assert_approx_eq!(color_at(0.15, 0.5), BLACK);
+    }
+
+    #[test]
+    fn test_fractal_patterns_color_points_by_how_fast_they_escape() {
+        let color_at = |pattern: &FractalPattern, x, z| {
+            pattern.get_color(&PatternContext::new(
+                Point::new(x, 0.0, z),
+                Vector::new(0.0, 1.0, 0.0),
+            ))
+        };
+        let mandelbrot =
+            FractalPattern::new(Fractal::Mandelbrot, 10, vec![BLACK, WHITE]).set_inside(MAGENTA);
+        assert_approx_eq!(color_at(&mandelbrot, -0.5, 0.0), MAGENTA);
+        // 2 + 2i leaves radius 16 on the third step
+        let smooth: Scalar = 4.0 - (0.5 * (10600.0 as Scalar).ln()).log2();
+        let grey = smooth / 10.0;
+        assert_approx_eq!(
+            color_at(&mandelbrot, 2.0, 2.0),
+            Color::new(grey, grey, grey)
+        );
+        // with c = 0 the Julia set is the unit disk
+        let julia = FractalPattern::new(Fractal::Julia { re: 0.0, im: 0.0 }, 10, vec![WHITE]);
+        assert_approx_eq!(color_at(&julia, 0.6, 0.6), BLACK);
+        assert_approx_eq!(color_at(&julia, 0.8, 0.8), WHITE);
+    }
+
+    #[test]
+    fn test_a_triplanar_pattern_uses_the_plane_facing_the_normal() {
+        let pattern = TriplanarPattern::new(StripedPattern::new(WHITE, BLACK));
+        let p = Point::new(0.5, 1.5, 1.5);
+        let color_facing = |n| pattern.get_color(&PatternContext::new(p, n));
+        // facing x, the pattern's x comes from the point's z
+        assert_approx_eq!(color_facing(Vector::new(1.0, 0.0, 0.0)), BLACK);
+        // facing y or z, it comes from the point's x
+        assert_approx_eq!(color_facing(Vector::new(0.0, 1.0, 0.0)), WHITE);
+        assert_approx_eq!(color_facing(Vector::new(0.0, 0.0, -1.0)), WHITE);
+    }
+
+    #[test]
+    fn test_a_triplanar_pattern_blends_between_planes() {
+        let pattern = TriplanarPattern::new(StripedPattern::new(WHITE, BLACK)).set_sharpness(1.0);
+        let p = Point::new(0.5, 0.0, 1.5);
+        let n = Vector::new(1.0, 1.0, 0.0).normalize();
+        let color = pattern.get_color(&PatternContext::new(p, n));
+        assert_approx_eq!(color, Color::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn test_the_facing_ratio_of_a_pattern_context() {
+        let head_on = PatternContext::new(ORIGIN, Vector::new(0.0, 0.0, -1.0));
+        assert_approx_eq!(head_on.facing_ratio(), 1.0);
+        let grazing = PatternContext {
+            direction: Vector::new(1.0, 0.0, 0.0),
+            ..head_on
+        };
+        assert_approx_eq!(grazing.facing_ratio(), 0.0);
+    }
+
+    fn corners() -> ImagePattern {
+        let mut canvas = Canvas::new(2, 2);
+        canvas.write_pixel(0, 0, Color::new(1.0, 0.0, 0.0));
+        canvas.write_pixel(1, 0, Color::new(0.0, 1.0, 0.0));
+        canvas.write_pixel(0, 1, Color::new(0.0, 0.0, 1.0));
+        canvas.write_pixel(1, 1, WHITE);
+        ImagePattern::from_canvas(canvas, UvMapping::Planar)
+    }
+
+    #[test]
+    fn test_an_image_pattern_samples_its_canvas() {
+        let pattern = corners();
+        let color = |x, z| {
+            pattern.get_color(&PatternContext::new(
+                Point::new(x, 0.0, z),
+                Vector::new(0.0, 1.0, 0.0),
+            ))
+        };
+        // v runs up the image, from its last row to its first
+        assert_approx_eq!(color(0.1, 0.1), Color::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(color(0.9, 0.1), WHITE);
+        assert_approx_eq!(color(0.1, 0.9), Color::new(1.0, 0.0, 0.0));
+        assert_approx_eq!(color(1.9, -0.1), Color::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_spherical_and_cylindrical_uv_mappings() {
+        let cases = [
+            (Point::new(0.0, 0.0, -1.0), 0.0, 0.5),
+            (Point::new(1.0, 0.0, 0.0), 0.25, 0.5),
+            (Point::new(0.0, 0.0, 1.0), 0.5, 0.5),
+            (Point::new(-1.0, 0.0, 0.0), 0.75, 0.5),
+            (Point::new(0.0, 1.0, 0.0), 0.5, 1.0),
+            (Point::new(0.0, -1.0, 0.0), 0.5, 0.0),
+        ];
+        for (p, u, v) in cases {
+            let (su, sv) = UvMapping::Spherical.uv(&p);
+            assert_approx_eq!(su, u);
+            assert_approx_eq!(sv, v);
+        }
+        let (u, v) = UvMapping::Cylindrical.uv(&Point::new(1.0, 1.25, 0.0));
+        assert_approx_eq!(u, 0.25);
+        assert_approx_eq!(v, 0.25);
+    }
+
+    #[test]
+    fn test_moving_the_seam_and_mirroring_the_poles() {
+        let pattern = ImagePattern::from_canvas(Canvas::new(4, 4), UvMapping::Spherical)
+            .set_seam(PI / 2.0)
+            .set_poles(Poles::Mirrored);
+        let (u, v) = pattern.uv(&Point::new(1.0, 0.0, 0.0));
+        assert_approx_eq!(u, 0.0);
+        assert_approx_eq!(v, 0.0);
+        let (u, _) = pattern.uv(&Point::new(0.0, 0.0, -1.0));
+        assert_approx_eq!(u, 0.75);
+        let north = pattern.uv(&Point::new(0.0, 1.0, 0.0)).1;
+        let south = pattern.uv(&Point::new(0.0, -1.0, 0.0)).1;
+        assert_approx_eq!(north, 1.0);
+        assert_approx_eq!(south, 1.0);
+        let (_, v) = pattern.uv(&Point::new(0.0, -0.5, -0.5));
+        assert_approx_eq!(v, 0.5);
+    }
+}
@@ -1,9 +1,12 @@
 use crate::approx_eq::EPSILON;
+use crate::color::Color;
 use crate::shape::LocalShape;
 use crate::point::Point;
 use crate::ray::Ray;
+use crate::scalar::Scalar;
 use crate::vector::Vector;
 
+#[derive(Default)]
 pub struct Plane {}
 
 impl Plane {
@@ -13,7 +16,7 @@ impl Plane {
 }
 
 impl LocalShape for Plane {
-    fn local_intersect(&self, ray: &Ray) -> Vec<f64> {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
         if ray.direction.y.abs() < EPSILON {
             return vec![];
         }
@@ -25,6 +28,104 @@ impl LocalShape for Plane {
     }
 }
 
+// How a `GroundPlane` colors itself: squares of the two colors, or lines
+// of the first color `line_width` wide over the second.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GroundStyle {
+    Checkers,
+    Grid { line_width: Scalar },
+}
+
+// The floor nearly every scene stands on: the xz plane with a checker or
+// grid pattern of its own, returned as the shape's color (so the material
+// should be white). The pattern is read straight off the hit point,
+// without the transform multiplies and pattern lookups of a plane with a
+// `CheckersPattern`, and without the checker noise such a pattern has
+// exactly at y = 0.
+pub struct GroundPlane {
+    style: GroundStyle,
+    colors: [Color; 2],
+    size: Scalar,
+    // color, and the distances from the origin the fade starts and ends at
+    horizon: Option<(Color, Scalar, Scalar)>,
+}
+
+impl GroundPlane {
+    // Squares `size` wide.
+    pub fn checkers(c1: Color, c2: Color, size: Scalar) -> Self {
+        Self {
+            style: GroundStyle::Checkers,
+            colors: [c1, c2],
+            size,
+            horizon: None,
+        }
+    }
+    // Lines every `size` units.
+    pub fn grid(line: Color, background: Color, size: Scalar, line_width: Scalar) -> Self {
+        Self {
+            style: GroundStyle::Grid { line_width },
+            colors: [line, background],
+            size,
+            horizon: None,
+        }
+    }
+    // Blends the pattern into `color` from `start` to `end` units away from
+    // the origin, hiding the moiré of squares far smaller than a pixel. With
+    // `end` no further than `start` the pattern gives way to `color` at once.
+    pub fn set_horizon_fade(self, color: Color, start: Scalar, end: Scalar) -> Self {
+        Self {
+            horizon: Some((color, start, end)),
+            ..self
+        }
+    }
+    fn pattern_at(&self, p: &Point) -> Color {
+        let (x, z) = (p.x / self.size, p.z / self.size);
+        let [c1, c2] = self.colors;
+        match self.style {
+            GroundStyle::Checkers => {
+                if (x.floor() + z.floor()) as i64 % 2 == 0 {
+                    c1
+                } else {
+                    c2
+                }
+            }
+            GroundStyle::Grid { line_width } => {
+                let half = line_width / (2.0 * self.size);
+                let near_line = |v: Scalar| (v - v.round()).abs() < half;
+                if near_line(x) || near_line(z) {
+                    c1
+                } else {
+                    c2
+                }
+            }
+        }
+    }
+}
+
+impl LocalShape for GroundPlane {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
+        Plane::new().local_intersect(ray)
+    }
+    fn local_normal_at(&self, _object_point: &Point) -> Vector {
+        Vector::new(0.0, 1.0, 0.0)
+    }
+    fn local_color_at(&self, p: &Point) -> Option<Color> {
+        let color = self.pattern_at(p);
+        let Some((horizon, start, end)) = self.horizon else {
+            return Some(color);
+        };
+        let distance = (p.x * p.x + p.z * p.z).sqrt();
+        let fade = if end > start {
+            ((distance - start) / (end - start)).clamp(0.0, 1.0)
+        } else if distance < start {
+            0.0
+        } else {
+            1.0
+        };
+        Some(color + (horizon - color) * fade)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -72,4 +173,49 @@ mod tests {
         let xs = p.local_intersect(&r);
         assert_approx_eq!(xs, [1.0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_a_checkered_ground_plane() {
+        let (black, white) = (Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        let checkers = GroundPlane::checkers(white, black, 2.0);
+        let color_at = |x, y, z| checkers.local_color_at(&Point::new(x, y, z)).unwrap();
+        assert_approx_eq!(color_at(0.5, 0.0, 0.5), white);
+        assert_approx_eq!(color_at(2.5, 0.0, 0.5), black);
+        assert_approx_eq!(color_at(-0.5, 0.0, 0.5), black);
+        // no noise from points a hair below the plane
+        assert_approx_eq!(color_at(0.5, -EPSILON, 0.5), white);
+    }
+
+    #[test]
+    fn test_a_grid_ground_plane() {
+        let (black, white) = (Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        let grid = GroundPlane::grid(white, black, 1.0, 0.1);
+        let color_at = |x, z| grid.local_color_at(&Point::new(x, 0.0, z)).unwrap();
+        assert_approx_eq!(color_at(3.02, 0.5), white);
+        assert_approx_eq!(color_at(3.5, 0.5), black);
+    }
+
+    #[test]
+    fn test_a_ground_plane_fades_into_the_horizon() {
+        let (black, white) = (Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        let gray = Color::new(0.5, 0.5, 0.5);
+        let faded = GroundPlane::checkers(white, black, 2.0).set_horizon_fade(gray, 10.0, 20.0);
+        let color_at = |x, z| faded.local_color_at(&Point::new(x, 0.0, z)).unwrap();
+        assert_approx_eq!(color_at(0.5, 0.5), white);
+        assert_approx_eq!(color_at(0.0, 15.0), Color::new(0.25, 0.25, 0.25));
+        assert_approx_eq!(color_at(0.0, 100.0), gray);
+    }
+
+    #[test]
+    fn test_a_horizon_fade_without_width_is_a_hard_cutoff() {
+        let (black, white) = (Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        let gray = Color::new(0.5, 0.5, 0.5);
+        for end in [10.0, 5.0] {
+            let faded = GroundPlane::checkers(white, black, 2.0).set_horizon_fade(gray, 10.0, end);
+            let color_at = |x, z| faded.local_color_at(&Point::new(x, 0.0, z)).unwrap();
+            assert_approx_eq!(color_at(0.5, 9.5), white);
+            assert_approx_eq!(color_at(0.0, 10.0), gray);
+            assert_approx_eq!(color_at(0.0, 100.0), gray);
+        }
+    }
+}
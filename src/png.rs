@@ -0,0 +1,140 @@
+use crate::canvas::Canvas;
+use crate::scalar::Scalar;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// largest payload of a stored (uncompressed) deflate block
+const MAX_STORED_BLOCK: usize = 0xffff;
+
+// Encodes the canvas as an 8-bit PNG, RGBA when the canvas has alpha and RGB
+// otherwise. The image data is wrapped in stored deflate blocks, trading file
+// size for not needing a compressor.
+pub fn encode(canvas: &Canvas) -> Vec<u8> {
+    let alpha = canvas.has_alpha();
+    let channels = if alpha { 4 } else { 3 };
+
+    let mut ihdr = vec![];
+    ihdr.extend_from_slice(&(canvas.width() as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(canvas.height() as u32).to_be_bytes());
+    // bit depth 8, color type RGBA or RGB, deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, if alpha { 6 } else { 2 }, 0, 0, 0]);
+
+    let mut raw = Vec::with_capacity(canvas.height() * (1 + channels * canvas.width()));
+    for y in 0..canvas.height() {
+        // filter type none
+        raw.push(0);
+        for x in 0..canvas.width() {
+            let a = canvas.alpha_at(x, y);
+            // canvas colors are premultiplied by coverage, PNG expects straight alpha
+            let scale = if alpha && a > 0.0 { 1.0 / a } else { 1.0 };
+            let color = canvas
+                .color_space()
+                .from_linear(canvas.pixel_at(x, y) * scale);
+            raw.push(to_byte(color.red));
+            raw.push(to_byte(color.green));
+            raw.push(to_byte(color.blue));
+            if alpha {
+                raw.push(to_byte(a));
+            }
+        }
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub(crate) fn to_byte(v: Scalar) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate, 32K window, no preset dictionary, check bits making it a multiple of 31
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn test_checksums_of_known_strings() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_an_opaque_canvas_is_written_as_rgb() {
+        let png = encode(&Canvas::new(5, 3));
+        assert_eq!(png[..8], SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..24], [0, 0, 0, 5, 0, 0, 0, 3]);
+        assert_eq!(png[24..26], [8, 2]);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+
+    #[test]
+    fn test_a_canvas_with_alpha_is_written_as_rgba() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, Color::new(0.5, 0.0, 0.25));
+        c.write_alpha(0, 0, 0.5);
+        c.write_alpha(1, 0, 0.0);
+        let png = encode(&c);
+        assert_eq!(png[24..26], [8, 6]);
+        // IDAT payload: zlib header, one stored block header, then the scanline
+        let data = &png[33 + 8..];
+        assert_eq!(&data[..2], [0x78, 0x01]);
+        assert_eq!(data[2..7], [1, 9, 0, !9, 0xff]);
+        assert_eq!(data[7..16], [0, 255, 0, 128, 128, 0, 0, 0, 0]);
+    }
+}
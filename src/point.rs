@@ -1,36 +1,96 @@
-use std::ops::{Add, Sub};
+use std::ops::{Add, Index, IndexMut, Sub};
 use crate::approx_eq::ApproxEq;
-use crate::vector::Vector;
+use crate::scalar::{Float, Scalar};
+use crate::vector::Vector3;
 
 #[derive(Debug, Copy, Clone)]
-pub struct Point {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Point3<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
 }
 
+pub type Point = Point3<Scalar>;
+
 pub const ORIGIN: Point = Point {
     x: 0.0,
     y: 0.0,
     z: 0.0,
 };
 
-impl Point {
-    pub fn new(x: f64, y: f64, z: f64) -> Self {
+impl<T> Point3<T> {
+    pub fn new(x: T, y: T, z: T) -> Self {
         Self { x, y, z }
     }
 }
 
-impl ApproxEq for Point {
+impl<T: Float> ApproxEq for Point3<T> {
     fn approx_eq(&self, other: &Self) -> bool {
         self.x.approx_eq(&other.x) && self.y.approx_eq(&other.y) && self.z.approx_eq(&other.z)
     }
 }
 
-impl Add<&Vector> for &Point {
-    type Output = Point;
+impl<T> From<[T; 3]> for Point3<T> {
+    fn from([x, y, z]: [T; 3]) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl<T> From<(T, T, T)> for Point3<T> {
+    fn from((x, y, z): (T, T, T)) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl<T> From<Point3<T>> for [T; 3] {
+    fn from(v: Point3<T>) -> Self {
+        [v.x, v.y, v.z]
+    }
+}
+
+impl<T> From<Point3<T>> for (T, T, T) {
+    fn from(v: Point3<T>) -> Self {
+        (v.x, v.y, v.z)
+    }
+}
+
+impl<T> Index<usize> for Point3<T> {
+    type Output = T;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("axis index out of range: {}", axis),
+        }
+    }
+}
+
+impl<T> IndexMut<usize> for Point3<T> {
+    fn index_mut(&mut self, axis: usize) -> &mut Self::Output {
+        match axis {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("axis index out of range: {}", axis),
+        }
+    }
+}
+
+impl<T: Float> Add<&Vector3<T>> for &Point3<T> {
+    type Output = Point3<T>;
 
-    fn add(self, rhs: &Vector) -> Self::Output {
+    fn add(self, rhs: &Vector3<T>) -> Self::Output {
+        *self + rhs
+    }
+}
+
+impl<T: Float> Add<&Vector3<T>> for Point3<T> {
+    type Output = Point3<T>;
+
+    fn add(self, rhs: &Vector3<T>) -> Self::Output {
         Self::Output {
             x: self.x + rhs.x,
             y: self.y + rhs.y,
@@ -39,18 +99,18 @@ impl Add<&Vector> for &Point {
     }
 }
 
-impl Add<&Vector> for Point {
-    type Output = Point;
+impl<T: Float> Sub<&Vector3<T>> for &Point3<T> {
+    type Output = Point3<T>;
 
-    fn add(self, rhs: &Vector) -> Self::Output {
-        &self + rhs
+    fn sub(self, rhs: &Vector3<T>) -> Self::Output {
+        *self - rhs
     }
 }
 
-impl Sub<&Vector> for &Point {
-    type Output = Point;
+impl<T: Float> Sub<&Vector3<T>> for Point3<T> {
+    type Output = Point3<T>;
 
-    fn sub(self, rhs: &Vector) -> Self::Output {
+    fn sub(self, rhs: &Vector3<T>) -> Self::Output {
         Self::Output {
             x: self.x - rhs.x,
             y: self.y - rhs.y,
@@ -59,18 +119,18 @@ impl Sub<&Vector> for &Point {
     }
 }
 
-impl Sub<&Vector> for Point {
-    type Output = Point;
+impl<T: Float> Sub<&Point3<T>> for &Point3<T> {
+    type Output = Vector3<T>;
 
-    fn sub(self, rhs: &Vector) -> Self::Output {
-        &self - rhs
+    fn sub(self, rhs: &Point3<T>) -> Self::Output {
+        *self - rhs
     }
 }
 
-impl Sub<&Point> for &Point {
-    type Output = Vector;
+impl<T: Float> Sub<&Point3<T>> for Point3<T> {
+    type Output = Vector3<T>;
 
-    fn sub(self, rhs: &Point) -> Self::Output {
+    fn sub(self, rhs: &Point3<T>) -> Self::Output {
         Self::Output {
             x: self.x - rhs.x,
             y: self.y - rhs.y,
@@ -79,14 +139,6 @@ impl Sub<&Point> for &Point {
     }
 }
 
-impl Sub<&Point> for Point {
-    type Output = Vector;
-
-    fn sub(self, rhs: &Point) -> Self::Output {
-        &self - rhs
-    }
-}
-
 #[cfg(test)]
 mod tests {
 
@@ -100,4 +152,20 @@ mod tests {
         assert_approx_eq!(p.y, -4.0);
         assert_approx_eq!(p.z, 3.0);
     }
+
+    #[test]
+    fn test_converting_points_to_and_from_arrays_and_tuples() {
+        let p = Point::from([4.0, -4.0, 3.0]);
+        assert_approx_eq!(p, Point::from((4.0, -4.0, 3.0)));
+        assert_eq!(<[Scalar; 3]>::from(p), [4.0, -4.0, 3.0]);
+        assert_eq!(<(Scalar, Scalar, Scalar)>::from(p), (4.0, -4.0, 3.0));
+    }
+
+    #[test]
+    fn test_indexing_a_point_by_axis() {
+        let mut p = Point::new(4.0, -4.0, 3.0);
+        assert_eq!([p[0], p[1], p[2]], [4.0, -4.0, 3.0]);
+        p[1] = 2.0;
+        assert_approx_eq!(p, Point::new(4.0, 2.0, 3.0));
+    }
 }
@@ -0,0 +1,291 @@
+use crate::color::Color;
+use crate::cube::{Cube, RoundedCube};
+use crate::cylinder::Cylinder;
+use crate::error::Result;
+use crate::material::Material;
+use crate::pattern::CheckersPattern;
+use crate::plane::Plane;
+use crate::scalar::consts::{FRAC_PI_2, PI};
+use crate::scalar::Scalar;
+use crate::shape::Shape;
+use crate::sphere::Sphere;
+use crate::transform::{
+    rotation_x, rotation_y, rotation_z, scaling, translation, Affine, IDENTITY_AFFINE,
+};
+use crate::world::World;
+
+// Ready-made assemblies of primitives. Each prefab returns its shapes
+// already placed by `transform`; `add_all` puts them in a world. Materials
+// can't be cloned, so prefabs with several parts take a function making a
+// fresh material for each.
+
+pub fn add_all<'a>(world: &mut World<'a>, shapes: Vec<Shape<'a>>) -> Vec<usize> {
+    shapes
+        .into_iter()
+        .map(|shape| world.add_shape(shape).id())
+        .collect()
+}
+
+fn place<'a>(
+    transform: &Affine,
+    local: Affine,
+    shape: Shape<'a>,
+    material: Material<'a>,
+) -> Result<Shape<'a>> {
+    Ok(shape
+        .set_transform(*transform * &local)?
+        .set_material(material))
+}
+
+// A box centered on the origin.
+pub fn cuboid<'a>(
+    transform: &Affine,
+    width: Scalar,
+    height: Scalar,
+    depth: Scalar,
+    material: Material<'a>,
+) -> Result<Shape<'a>> {
+    place(
+        transform,
+        scaling(width / 2.0, height / 2.0, depth / 2.0),
+        Shape::new(Cube::new()),
+        material,
+    )
+}
+
+// The cube from -1 to 1 with its edges and corners rounded off by `radius`,
+// as a single `RoundedCube`.
+pub fn rounded_cube<'a>(
+    transform: &Affine,
+    radius: Scalar,
+    material: Material<'a>,
+) -> Result<Shape<'a>> {
+    place(
+        transform,
+        IDENTITY_AFFINE,
+        Shape::new(RoundedCube::new(radius)?),
+        material,
+    )
+}
+
+// Six spheres joined by six cylinders in a ring of radius 1 in the xz
+// plane; `thickness` is the radius of the cylinders.
+pub fn hexagon<'a>(
+    transform: &Affine,
+    thickness: Scalar,
+    material: impl Fn() -> Material<'a>,
+) -> Result<Vec<Shape<'a>>> {
+    let mut shapes = vec![];
+    for n in 0..6 {
+        let side = *transform * &rotation_y(n as Scalar * PI / 3.0);
+        let corner = thickness * 1.5;
+        shapes.push(place(
+            &side,
+            translation(0.0, 0.0, -1.0) * &scaling(corner, corner, corner),
+            Shape::new(Sphere::new()),
+            material(),
+        )?);
+        shapes.push(place(
+            &side,
+            translation(0.0, 0.0, -1.0)
+                * &rotation_y(-PI / 6.0)
+                * &rotation_z(-FRAC_PI_2)
+                * &scaling(thickness, 1.0, thickness),
+            Shape::new(Cylinder::new().set_extent(0.0, 1.0)),
+            material(),
+        )?);
+    }
+    Ok(shapes)
+}
+
+// A 2 x 1 table top at height 1, standing on four legs on the xz plane.
+pub fn table<'a>(
+    transform: &Affine,
+    material: impl Fn() -> Material<'a>,
+) -> Result<Vec<Shape<'a>>> {
+    let mut shapes = vec![cuboid(
+        &(*transform * &translation(0.0, 0.95, 0.0)),
+        2.0,
+        0.1,
+        1.0,
+        material(),
+    )?];
+    for (x, z) in [(-0.9, -0.4), (0.9, -0.4), (-0.9, 0.4), (0.9, 0.4)] {
+        shapes.push(leg(transform, x, z, 0.9, material())?);
+    }
+    Ok(shapes)
+}
+
+// A 0.5 x 0.5 seat at height 0.5 with its back towards +z.
+pub fn chair<'a>(
+    transform: &Affine,
+    material: impl Fn() -> Material<'a>,
+) -> Result<Vec<Shape<'a>>> {
+    let mut shapes = vec![
+        cuboid(
+            &(*transform * &translation(0.0, 0.475, 0.0)),
+            0.5,
+            0.05,
+            0.5,
+            material(),
+        )?,
+        cuboid(
+            &(*transform * &translation(0.0, 0.75, 0.225)),
+            0.5,
+            0.5,
+            0.05,
+            material(),
+        )?,
+    ];
+    for (x, z) in [(-0.2, -0.2), (0.2, -0.2), (-0.2, 0.2), (0.2, 0.2)] {
+        shapes.push(leg(transform, x, z, 0.45, material())?);
+    }
+    Ok(shapes)
+}
+
+fn leg<'a>(
+    transform: &Affine,
+    x: Scalar,
+    z: Scalar,
+    height: Scalar,
+    material: Material<'a>,
+) -> Result<Shape<'a>> {
+    place(
+        transform,
+        translation(x, 0.0, z) * &scaling(0.04, 1.0, 0.04),
+        Shape::new(Cylinder::new().set_extent(0.0, height).set_closed(true)),
+        material,
+    )
+}
+
+// The inside of a cube `size` wide with its floor on the xz plane, every
+// wall checkered in unit squares.
+pub fn checkerboard_room<'a>(
+    transform: &Affine,
+    size: Scalar,
+    c1: Color,
+    c2: Color,
+) -> Result<Vec<Shape<'a>>> {
+    let half = size / 2.0;
+    let walls = [
+        translation(0.0, 0.0, 0.0),
+        translation(0.0, size, 0.0),
+        translation(0.0, half, half) * &rotation_x(FRAC_PI_2),
+        translation(0.0, half, -half) * &rotation_x(FRAC_PI_2),
+        translation(half, half, 0.0) * &rotation_z(FRAC_PI_2),
+        translation(-half, half, 0.0) * &rotation_z(FRAC_PI_2),
+    ];
+    walls
+        .into_iter()
+        .map(|wall| {
+            let material = Material::new()
+                .set_pattern(CheckersPattern::new(c1, c2), IDENTITY_AFFINE)?
+                .set_specular(0.0)?;
+            place(transform, wall, Shape::new(Plane::new()), material)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::error::RayTracerError;
+    use crate::point::Point;
+    use crate::ray::Ray;
+    use crate::vector::Vector;
+
+    fn world_of(shapes: Vec<Shape<'_>>) -> World<'_> {
+        let mut world = World::new();
+        add_all(&mut world, shapes);
+        world
+    }
+
+    #[test]
+    fn test_the_bounds_of_a_cuboid() {
+        let world = world_of(vec![cuboid(
+            &translation(1.0, 0.0, 0.0),
+            4.0,
+            2.0,
+            1.0,
+            Material::new(),
+        )
+        .unwrap()]);
+        let b = world.bounds();
+        assert_approx_eq!(b.min, Point::new(-1.0, -1.0, -0.5));
+        assert_approx_eq!(b.max, Point::new(3.0, 1.0, 0.5));
+    }
+
+    #[test]
+    fn test_a_rounded_cube_is_missed_at_its_corners() {
+        let world = world_of(vec![
+            rounded_cube(&IDENTITY_AFFINE, 0.5, Material::new()).unwrap()
+        ]);
+        let b = world.bounds();
+        assert_approx_eq!(b.min, Point::new(-1.0, -1.0, -1.0));
+        assert_approx_eq!(b.max, Point::new(1.0, 1.0, 1.0));
+
+        // straight on, the face is flat
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(world.shade_ray(&r).unwrap().t, 4.0);
+        // diagonally, the corner is cut off
+        let d = Vector::new(1.0, 1.0, 1.0).normalize();
+        let r = Ray::new(Point::new(-5.0, -5.0, -5.0), d);
+        let t = world.shade_ray(&r).unwrap().t;
+        let corner = 0.5 * Scalar::sqrt(3.0) + 0.5;
+        assert_approx_eq!(t, Scalar::sqrt(75.0) - corner);
+    }
+
+    #[test]
+    fn test_rounded_cubes_need_a_valid_radius() {
+        assert!(matches!(
+            rounded_cube(&IDENTITY_AFFINE, 1.5, Material::new()),
+            Err(RayTracerError::InvalidShape(_))
+        ));
+    }
+
+    #[test]
+    fn test_a_hexagon_is_a_ring_of_spheres_and_cylinders() {
+        let world = world_of(hexagon(&IDENTITY_AFFINE, 0.25, Material::new).unwrap());
+        let b = world.bounds();
+        assert!(b.max.x > 0.866 && b.max.x < 1.5);
+        assert!(b.max.z > 1.0 && b.max.z < 1.5);
+        // the center of the ring is empty
+        let r = Ray::new(Point::new(0.0, 5.0, 0.0), Vector::new(0.0, -1.0, 0.0));
+        assert!(world.shade_ray(&r).is_none());
+    }
+
+    #[test]
+    fn test_furniture_stands_on_the_floor() {
+        for shapes in [
+            table(&IDENTITY_AFFINE, Material::new).unwrap(),
+            chair(&IDENTITY_AFFINE, Material::new).unwrap(),
+        ] {
+            let b = world_of(shapes).bounds();
+            assert_approx_eq!(b.min.y, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_a_room_encloses_the_origin() {
+        let room = checkerboard_room(
+            &IDENTITY_AFFINE,
+            10.0,
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.0, 0.0, 0.0),
+        )
+        .unwrap();
+        let world = world_of(room);
+        let from = Point::new(0.0, 5.0, 0.0);
+        for direction in [
+            Vector::new(1.0, 0.0, 0.0),
+            Vector::new(0.0, 0.0, -1.0),
+            Vector::new(0.0, 1.0, 0.0),
+            Vector::new(0.0, -1.0, 0.0),
+        ] {
+            let r = Ray::new(from, direction);
+            assert_approx_eq!(world.shade_ray(&r).unwrap().t, 5.0);
+        }
+    }
+}
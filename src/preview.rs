@@ -0,0 +1,310 @@
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::error::{RayTracerError, Result};
+use crate::png;
+use crate::point::Point;
+use crate::scalar::Scalar;
+use crate::tile::{Rect, TiledCanvas};
+use crate::transform::{view_transform, Affine};
+use crate::vector::Vector;
+use crate::world::World;
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+// While flying, frames are rendered at this fraction of the full size.
+const DRAFT_SCALE: usize = 4;
+// world units per second, four times that with shift held
+const FLY_SPEED: Scalar = 2.0;
+// radians per pixel of mouse movement
+const LOOK_SPEED: Scalar = 0.005;
+const MAX_PITCH: Scalar = 1.5;
+const UP: Vector = Vector {
+    x: 0.0,
+    y: 1.0,
+    z: 0.0,
+};
+
+// Renders in the background while a window shows each tile as it finishes.
+// Escape cancels the render and S hands the image so far to `save`; once
+// the render is done the window stays open until it is closed. Returns the
+// finished canvas, or None if the render was cancelled.
+pub fn preview(
+    camera: &Camera,
+    world: &World,
+    title: &str,
+    save: impl Fn(&Canvas) -> Result<()>,
+) -> Result<Option<Canvas>> {
+    let (width, height) = (camera.canvas_width(), camera.get_vsize());
+    let image = Mutex::new(camera.tiled_canvas());
+    let cancel = AtomicBool::new(false);
+    let mut window =
+        Window::new(title, width, height, WindowOptions::default()).map_err(window_error)?;
+    window.set_target_fps(30);
+    let mut buffer = vec![0; width * height];
+
+    let finished = thread::scope(|scope| {
+        let render = scope.spawn(|| camera.render_into(world, &image, &cancel));
+        let mut result = Ok(());
+        while window.is_open() && !window.is_key_pressed(Key::Escape, KeyRepeat::No) {
+            {
+                let mut image = image.lock().unwrap();
+                for rect in image.take_dirty_rects() {
+                    copy_rect(&mut buffer, image.canvas(), &rect);
+                }
+                if window.is_key_pressed(Key::S, KeyRepeat::No) {
+                    let status = match save(image.canvas()) {
+                        Ok(()) => "saved".to_string(),
+                        Err(err) => format!("save failed: {}", err),
+                    };
+                    window.set_title(&format!("{} ({})", title, status));
+                }
+            }
+            if let Err(err) = window.update_with_buffer(&buffer, width, height) {
+                result = Err(window_error(err));
+                break;
+            }
+        }
+        cancel.store(true, Ordering::Relaxed);
+        result.map(|()| render.join().unwrap())
+    })?;
+
+    Ok(finished.then(|| image.into_inner().unwrap().into_canvas()))
+}
+
+// Flies `camera` through the world: WASD moves, Q and E go down and up and
+// dragging with the left mouse button looks around. While moving, frames are
+// drafts at a reduced size with one sample per pixel; once the camera is
+// still, the full render fills in tile by tile. Escape or closing the
+// window ends the flight.
+pub fn fly(camera: &Camera, world: &World, title: &str) -> Result<()> {
+    let mut flying = FlyCamera::from_transform(&camera.get_transform())
+        .ok_or(RayTracerError::NonInvertibleTransform)?;
+    let (width, height) = (camera.canvas_width(), camera.get_vsize());
+    let draft = camera
+        .set_size(
+            (camera.get_hsize() / DRAFT_SCALE).max(1),
+            (height / DRAFT_SCALE).max(1),
+        )?
+        .set_samples(1);
+    let mut window =
+        Window::new(title, width, height, WindowOptions::default()).map_err(window_error)?;
+    window.set_target_fps(30);
+    let mut buffer = vec![0; width * height];
+
+    thread::scope(|scope| {
+        let mut refining: Option<Refinement> = None;
+        let mut moved = true;
+        let mut last_mouse = None;
+        let mut last_frame = Instant::now();
+        while window.is_open() && !window.is_key_pressed(Key::Escape, KeyRepeat::No) {
+            #[allow(clippy::unnecessary_cast)]
+            let seconds = last_frame.elapsed().as_secs_f64() as Scalar;
+            last_frame = Instant::now();
+            let fast = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+            let step = seconds * FLY_SPEED * if fast { 4.0 } else { 1.0 };
+            let axis = |ahead: Key, back: Key| {
+                (window.is_key_down(ahead) as i8 - window.is_key_down(back) as i8) as Scalar
+            };
+            let (forward, right, up) = (
+                axis(Key::W, Key::S),
+                axis(Key::D, Key::A),
+                axis(Key::E, Key::Q),
+            );
+            if (forward, right, up) != (0.0, 0.0, 0.0) {
+                flying.fly(forward * step, right * step, up * step);
+                moved = true;
+            }
+            let mouse = window
+                .get_mouse_pos(MouseMode::Pass)
+                .filter(|_| window.get_mouse_down(MouseButton::Left));
+            if let (Some((x, y)), Some((last_x, last_y))) = (mouse, last_mouse) {
+                if (x, y) != (last_x, last_y) {
+                    #[allow(clippy::unnecessary_cast)]
+                    let (dx, dy) = ((x - last_x) as Scalar, (y - last_y) as Scalar);
+                    flying.turn(dx * LOOK_SPEED, -dy * LOOK_SPEED);
+                    moved = true;
+                }
+            }
+            last_mouse = mouse;
+
+            if moved {
+                if let Some(refinement) = refining.take() {
+                    refinement.stop();
+                }
+                let canvas = draft.set_transform(flying.view()?)?.render(world);
+                upscale(&mut buffer, width, &canvas);
+                moved = false;
+                let full = camera.set_transform(flying.view()?)?;
+                let image = Arc::new(Mutex::new(camera.tiled_canvas()));
+                let cancel = Arc::new(AtomicBool::new(false));
+                let render = scope.spawn({
+                    let (image, cancel) = (image.clone(), cancel.clone());
+                    move || full.render_into(world, &image, &cancel)
+                });
+                refining = Some(Refinement {
+                    render,
+                    image,
+                    cancel,
+                });
+            }
+            if let Some(refinement) = &refining {
+                let mut image = refinement.image.lock().unwrap();
+                for rect in image.take_dirty_rects() {
+                    copy_rect(&mut buffer, image.canvas(), &rect);
+                }
+            }
+            window
+                .update_with_buffer(&buffer, width, height)
+                .map_err(window_error)?;
+        }
+        if let Some(refinement) = refining {
+            refinement.stop();
+        }
+        Ok(())
+    })
+}
+
+// A full render of the current view, running in the background.
+struct Refinement<'scope> {
+    render: thread::ScopedJoinHandle<'scope, bool>,
+    image: Arc<Mutex<TiledCanvas>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Refinement<'_> {
+    fn stop(self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.render.join().unwrap();
+    }
+}
+
+// A camera's position and heading: yaw turns right about the y axis, with
+// zero looking down the negative z axis, and pitch is the angle above the
+// horizon.
+#[derive(Clone, Copy, Debug)]
+struct FlyCamera {
+    position: Point,
+    yaw: Scalar,
+    pitch: Scalar,
+}
+
+impl FlyCamera {
+    fn from_transform(transform: &Affine) -> Option<Self> {
+        let inverse = transform.inverse()?;
+        let forward = (inverse * &Vector::new(0.0, 0.0, -1.0)).normalize();
+        Some(Self {
+            position: inverse * &Point::new(0.0, 0.0, 0.0),
+            yaw: (-forward.x).atan2(-forward.z),
+            pitch: forward
+                .y
+                .clamp(-1.0, 1.0)
+                .asin()
+                .clamp(-MAX_PITCH, MAX_PITCH),
+        })
+    }
+    fn view(&self) -> Result<Affine> {
+        let forward = Vector::new(
+            -self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            -self.yaw.cos() * self.pitch.cos(),
+        );
+        view_transform(&self.position, &(self.position + &forward), &UP)
+    }
+    // Moves level with the ground regardless of pitch, like walking.
+    fn fly(&mut self, forward: Scalar, right: Scalar, up: Scalar) {
+        let ahead = Vector::new(-self.yaw.sin(), 0.0, -self.yaw.cos());
+        let side = Vector::new(-self.yaw.cos(), 0.0, self.yaw.sin());
+        self.position = self.position + &(&ahead * forward) + &(&side * right) + &(&UP * up);
+    }
+    fn turn(&mut self, yaw: Scalar, pitch: Scalar) {
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+}
+
+// Window pixels are 0RGB words.
+fn to_rgb(color: Color) -> u32 {
+    let [r, g, b] = <[_; 3]>::from(color).map(|v| png::to_byte(v) as u32);
+    r << 16 | g << 8 | b
+}
+
+fn copy_rect(buffer: &mut [u32], canvas: &Canvas, rect: &Rect) {
+    for y in rect.y..rect.y + rect.height {
+        for x in rect.x..rect.x + rect.width {
+            buffer[y * canvas.width() + x] =
+                to_rgb(canvas.color_space().from_linear(canvas.pixel_at(x, y)));
+        }
+    }
+}
+
+// Fills the window buffer with a smaller canvas, one block per pixel.
+fn upscale(buffer: &mut [u32], width: usize, canvas: &Canvas) {
+    let height = buffer.len() / width;
+    for y in 0..height {
+        for x in 0..width {
+            let (cx, cy) = (
+                (x * canvas.width() / width).min(canvas.width() - 1),
+                (y * canvas.height() / height).min(canvas.height() - 1),
+            );
+            buffer[y * width + x] =
+                to_rgb(canvas.color_space().from_linear(canvas.pixel_at(cx, cy)));
+        }
+    }
+}
+
+fn window_error(err: minifb::Error) -> RayTracerError {
+    RayTracerError::Io(io::Error::other(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    #[test]
+    fn test_copying_a_tile_into_the_window_buffer() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.write_pixel(1, 1, Color::new(1.0, 0.5, 0.0));
+        canvas.write_pixel(2, 1, Color::new(0.0, 0.0, 2.0));
+        let mut buffer = vec![7; 6];
+        copy_rect(&mut buffer, &canvas, &Rect::new(1, 1, 2, 1));
+        assert_eq!(buffer, vec![7, 7, 7, 7, 0xff8000, 0x0000ff]);
+    }
+
+    #[test]
+    fn test_a_fly_camera_keeps_the_view_it_starts_from() {
+        let from = Point::new(1.0, 2.0, -5.0);
+        let view = view_transform(&from, &Point::new(0.0, 1.0, 0.0), &UP).unwrap();
+        let flying = FlyCamera::from_transform(&view).unwrap();
+        assert_approx_eq!(flying.position, from);
+        assert_approx_eq!(flying.view().unwrap(), view);
+    }
+
+    #[test]
+    fn test_flying_moves_along_the_heading_and_keeps_level() {
+        let view =
+            view_transform(&Point::new(0.0, 0.0, 0.0), &Point::new(1.0, 1.0, 0.0), &UP).unwrap();
+        let mut flying = FlyCamera::from_transform(&view).unwrap();
+        flying.fly(2.0, 1.0, 0.5);
+        assert_approx_eq!(flying.position, Point::new(2.0, 0.5, -1.0));
+        flying.turn(0.0, 10.0);
+        assert_approx_eq!(flying.pitch, MAX_PITCH);
+    }
+
+    #[test]
+    fn test_drafts_are_scaled_up_to_the_window() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(1, 0, Color::new(1.0, 1.0, 1.0));
+        let mut buffer = vec![0; 8];
+        upscale(&mut buffer, 4, &canvas);
+        assert_eq!(
+            buffer,
+            vec![0, 0, 0xffffff, 0xffffff, 0, 0, 0xffffff, 0xffffff]
+        );
+    }
+}
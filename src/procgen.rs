@@ -0,0 +1,225 @@
+use crate::bounds::Bounds;
+use crate::color::{Color, WHITE};
+use crate::error::{RayTracerError, Result};
+use crate::material::Material;
+use crate::point::Point;
+use crate::sampler::Sampler;
+use crate::scalar::consts::PI;
+use crate::scalar::Scalar;
+use crate::shape::Shape;
+use crate::transform::{rotation_y, scaling, translation, Affine};
+
+// Random candidates tried per instance before a crowded area is given up on.
+const ATTEMPTS_PER_INSTANCE: usize = 30;
+
+// Where one scattered instance goes: its position, a uniform scale, a turn
+// about the y axis and a jittered color for its material.
+#[derive(Copy, Clone, Debug)]
+pub struct Placement {
+    pub position: Point,
+    pub scale: Scalar,
+    pub rotation: Scalar,
+    pub color: Color,
+}
+
+impl Placement {
+    // Scales and turns about the instance's own origin before moving it.
+    pub fn transform(&self) -> Affine {
+        translation(self.position.x, self.position.y, self.position.z)
+            * &rotation_y(self.rotation)
+            * &scaling(self.scale, self.scale, self.scale)
+    }
+}
+
+// Seeded random placement of instances, so the same seed always gives the
+// same scene. Instances are kept `spacing` apart (center to center) by
+// rejecting candidates that come too close, which means fewer than asked
+// for may fit.
+#[derive(Copy, Clone, Debug)]
+pub struct Scatter {
+    seed: u64,
+    spacing: Scalar,
+    scale: (Scalar, Scalar),
+    rotation: (Scalar, Scalar),
+    color: Color,
+    color_jitter: Scalar,
+}
+
+impl Scatter {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            spacing: 0.0,
+            scale: (1.0, 1.0),
+            rotation: (0.0, 2.0 * PI),
+            color: WHITE,
+            color_jitter: 0.0,
+        }
+    }
+    pub fn set_spacing(self, spacing: Scalar) -> Self {
+        Self { spacing, ..self }
+    }
+    pub fn set_scale(self, min: Scalar, max: Scalar) -> Self {
+        Self {
+            scale: (min, max),
+            ..self
+        }
+    }
+    // In radians about the y axis.
+    pub fn set_rotation(self, min: Scalar, max: Scalar) -> Self {
+        Self {
+            rotation: (min, max),
+            ..self
+        }
+    }
+    // Each channel of `color` is moved by up to `jitter` either way.
+    pub fn set_color(self, color: Color, jitter: Scalar) -> Self {
+        Self {
+            color,
+            color_jitter: jitter,
+            ..self
+        }
+    }
+    // Up to `count` placements on the xz plane within a `width` (along x) by
+    // `depth` (along z) rectangle centered on the origin.
+    pub fn on_plane(&self, count: usize, width: Scalar, depth: Scalar) -> Result<Vec<Placement>> {
+        let half = Point::new(width / 2.0, 0.0, depth / 2.0);
+        self.within(count, &Bounds::new(Point::new(-half.x, 0.0, -half.z), half))
+    }
+    // Up to `count` placements with their origins inside `bounds`.
+    pub fn within(&self, count: usize, bounds: &Bounds) -> Result<Vec<Placement>> {
+        self.validate(bounds)?;
+        let mut sampler = Sampler::new(self.seed);
+        let mut placements: Vec<Placement> = vec![];
+        let spacing2 = self.spacing * self.spacing;
+        for _ in 0..count * ATTEMPTS_PER_INSTANCE {
+            if placements.len() == count {
+                break;
+            }
+            let mut axis =
+                |a: usize| bounds.min[a] + sampler.next_f64() * (bounds.max[a] - bounds.min[a]);
+            let position = Point::new(axis(0), axis(1), axis(2));
+            if placements.iter().any(|p| {
+                let d = position - &p.position;
+                d.dot(&d) < spacing2
+            }) {
+                continue;
+            }
+            placements.push(self.place(position, &mut sampler));
+        }
+        Ok(placements)
+    }
+    fn place(&self, position: Point, sampler: &mut Sampler) -> Placement {
+        let mut between = |(min, max): (Scalar, Scalar)| min + sampler.next_f64() * (max - min);
+        let scale = between(self.scale);
+        let rotation = between(self.rotation);
+        let j = self.color_jitter;
+        let color = Color::new(
+            (self.color.red + between((-j, j))).max(0.0),
+            (self.color.green + between((-j, j))).max(0.0),
+            (self.color.blue + between((-j, j))).max(0.0),
+        );
+        Placement {
+            position,
+            scale,
+            rotation,
+            color,
+        }
+    }
+    fn validate(&self, bounds: &Bounds) -> Result<()> {
+        if !bounds.is_finite() {
+            return Err(RayTracerError::InvalidShape(
+                "instances can only be scattered within finite bounds".to_string(),
+            ));
+        }
+        if !(self.scale.0 > 0.0 && self.scale.0 <= self.scale.1) {
+            return Err(RayTracerError::InvalidShape(format!(
+                "scale range must be positive and in order, got {} to {}",
+                self.scale.0, self.scale.1
+            )));
+        }
+        Ok(())
+    }
+}
+
+// A shape from `shape` for each placement, moved there by `transform` after
+// the placement's own transform after the shape's. `material` makes each
+// instance's material, typically from the placement's color.
+pub fn instantiate<'a>(
+    placements: &[Placement],
+    transform: &Affine,
+    shape: impl Fn() -> Shape<'a>,
+    material: impl Fn(&Placement) -> Material<'a>,
+) -> Result<Vec<Shape<'a>>> {
+    placements
+        .iter()
+        .map(|placement| {
+            let shape = shape();
+            let local = *shape.get_transform();
+            Ok(shape
+                .set_transform(*transform * &placement.transform() * &local)?
+                .set_material(material(placement)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::prefab::add_all;
+    use crate::sphere::Sphere;
+    use crate::world::World;
+
+    #[test]
+    fn test_scattering_is_seeded_and_keeps_its_spacing() {
+        let scatter = Scatter::new(7)
+            .set_spacing(1.0)
+            .set_scale(0.2, 0.4)
+            .set_color(Color::new(0.5, 0.5, 0.5), 0.1);
+        let placements = scatter.on_plane(50, 10.0, 10.0).unwrap();
+        assert_eq!(placements.len(), 50);
+        for (i, p) in placements.iter().enumerate() {
+            assert_eq!(p.position.y, 0.0);
+            assert!(p.position.x.abs() <= 5.0 && p.position.z.abs() <= 5.0);
+            assert!(p.scale >= 0.2 && p.scale <= 0.4);
+            assert!((p.color.red - 0.5).abs() <= 0.1);
+            for q in &placements[..i] {
+                assert!((p.position - &q.position).magnitude() >= 1.0);
+            }
+        }
+        let again = scatter.on_plane(50, 10.0, 10.0).unwrap();
+        assert_approx_eq!(again[17].position, placements[17].position);
+        let other = Scatter::new(8).on_plane(50, 10.0, 10.0).unwrap();
+        assert!((other[0].position - &placements[0].position).magnitude() > 1e-6);
+        // only so many fit
+        let crowded = scatter.set_spacing(3.0).on_plane(100, 4.0, 4.0).unwrap();
+        assert!(!crowded.is_empty() && crowded.len() < 9);
+    }
+
+    #[test]
+    fn test_instantiating_placed_shapes() {
+        let bounds = Bounds::new(Point::new(-5.0, -5.0, -5.0), Point::new(5.0, 5.0, 5.0));
+        let placements = Scatter::new(1)
+            .set_scale(0.5, 0.5)
+            .within(20, &bounds)
+            .unwrap();
+        let shapes = instantiate(
+            &placements,
+            &translation(0.0, 10.0, 0.0),
+            || Shape::new(Sphere::new()),
+            |p| Material::new().set_color(p.color).unwrap(),
+        )
+        .unwrap();
+        let mut world = World::new();
+        add_all(&mut world, shapes);
+        let b = world.bounds();
+        assert!(b.min.y >= 4.5 && b.max.y <= 15.5);
+        assert!(matches!(
+            Scatter::new(1).set_scale(0.0, 1.0).within(1, &bounds),
+            Err(RayTracerError::InvalidShape(_))
+        ));
+        assert!(Scatter::new(1).within(1, &Bounds::infinite()).is_err());
+    }
+}
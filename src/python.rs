@@ -0,0 +1,413 @@
+// Python bindings, importable as `ray_tracer` once built with maturin.
+// Transforms are 4x4 nested lists (or numpy arrays) in row-major order, and
+// shapes and materials are plain values that are copied into a world when
+// added to it.
+use crate::camera::Camera;
+use crate::color::{Color, WHITE};
+use crate::cube::Cube;
+use crate::cylinder::Cylinder;
+use crate::error::RayTracerError;
+use crate::light::PointLight;
+use crate::material::Material;
+use crate::matrix::Matrix;
+use crate::plane::Plane;
+use crate::point::Point;
+use crate::scalar::Scalar;
+use crate::shape::Shape;
+use crate::sphere::Sphere;
+use crate::transform::{self, Affine, IDENTITY_AFFINE};
+use crate::vector::Vector;
+use crate::world::World;
+use numpy::ndarray::Array3;
+use numpy::{IntoPyArray, PyArray3};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+type Rows = Vec<Vec<f64>>;
+type Triple = (f64, f64, f64);
+
+impl From<RayTracerError> for PyErr {
+    fn from(err: RayTracerError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn scalar(v: f64) -> Scalar {
+    v as Scalar
+}
+
+fn point((x, y, z): Triple) -> Point {
+    Point::new(scalar(x), scalar(y), scalar(z))
+}
+
+fn color((r, g, b): Triple) -> Color {
+    Color::new(scalar(r), scalar(g), scalar(b))
+}
+
+// The top three rows are used; a fourth must be 0, 0, 0, 1.
+fn affine(rows: &Rows) -> PyResult<Affine> {
+    let valid = (rows.len() == 3 || rows.len() == 4 && rows[3] == [0.0, 0.0, 0.0, 1.0])
+        && rows.iter().all(|row| row.len() == 4);
+    if !valid {
+        return Err(PyValueError::new_err(
+            "transforms must be affine 4x4 matrices",
+        ));
+    }
+    let row = |i: usize| [scalar(rows[i][0]), scalar(rows[i][1]), scalar(rows[i][2])];
+    Ok(Affine::new(
+        Matrix::new([row(0), row(1), row(2)]),
+        Vector::new(scalar(rows[0][3]), scalar(rows[1][3]), scalar(rows[2][3])),
+    ))
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn rows(a: &Affine) -> Rows {
+    let m = a.get_transform();
+    let t = a.get_translate();
+    let t = [t.x, t.y, t.z];
+    let mut rows: Rows = (0..3)
+        .map(|i| {
+            vec![
+                m.at(i, 0) as f64,
+                m.at(i, 1) as f64,
+                m.at(i, 2) as f64,
+                t[i] as f64,
+            ]
+        })
+        .collect();
+    rows.push(vec![0.0, 0.0, 0.0, 1.0]);
+    rows
+}
+
+#[pyfunction]
+fn identity() -> Rows {
+    rows(&IDENTITY_AFFINE)
+}
+
+#[pyfunction]
+fn translation(x: f64, y: f64, z: f64) -> Rows {
+    rows(&transform::translation(scalar(x), scalar(y), scalar(z)))
+}
+
+#[pyfunction]
+fn scaling(x: f64, y: f64, z: f64) -> Rows {
+    rows(&transform::scaling(scalar(x), scalar(y), scalar(z)))
+}
+
+#[pyfunction]
+fn rotation_x(r: f64) -> Rows {
+    rows(&transform::rotation_x(scalar(r)))
+}
+
+#[pyfunction]
+fn rotation_y(r: f64) -> Rows {
+    rows(&transform::rotation_y(scalar(r)))
+}
+
+#[pyfunction]
+fn rotation_z(r: f64) -> Rows {
+    rows(&transform::rotation_z(scalar(r)))
+}
+
+#[pyfunction]
+fn view_transform(from: Triple, to: Triple, up: Triple) -> PyResult<Rows> {
+    Ok(rows(&transform::view_transform(
+        &point(from),
+        &point(to),
+        &Vector::new(scalar(up.0), scalar(up.1), scalar(up.2)),
+    )?))
+}
+
+#[pyclass(name = "Material", get_all, set_all)]
+#[derive(Clone)]
+struct PyMaterial {
+    color: Triple,
+    ambient: f64,
+    diffuse: f64,
+    specular: f64,
+    shininess: f64,
+    reflective: f64,
+    transparency: f64,
+    refractive_index: f64,
+}
+
+#[pymethods]
+impl PyMaterial {
+    #[new]
+    #[pyo3(signature = (
+        color = PyMaterial::default().color,
+        ambient = PyMaterial::default().ambient,
+        diffuse = PyMaterial::default().diffuse,
+        specular = PyMaterial::default().specular,
+        shininess = PyMaterial::default().shininess,
+        reflective = PyMaterial::default().reflective,
+        transparency = PyMaterial::default().transparency,
+        refractive_index = PyMaterial::default().refractive_index,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        color: Triple,
+        ambient: f64,
+        diffuse: f64,
+        specular: f64,
+        shininess: f64,
+        reflective: f64,
+        transparency: f64,
+        refractive_index: f64,
+    ) -> Self {
+        Self {
+            color,
+            ambient,
+            diffuse,
+            specular,
+            shininess,
+            reflective,
+            transparency,
+            refractive_index,
+        }
+    }
+}
+
+// The defaults of `Material::new`.
+impl Default for PyMaterial {
+    #[allow(clippy::unnecessary_cast)]
+    fn default() -> Self {
+        let m = Material::new();
+        let c = m.get_solid_color().unwrap_or(WHITE);
+        Self {
+            color: (c.red as f64, c.green as f64, c.blue as f64),
+            ambient: m.get_ambient() as f64,
+            diffuse: m.get_diffuse() as f64,
+            specular: m.get_specular() as f64,
+            shininess: m.get_shininess() as f64,
+            reflective: m.get_reflective() as f64,
+            transparency: m.get_transparency() as f64,
+            refractive_index: m.get_refractive_index() as f64,
+        }
+    }
+}
+
+impl PyMaterial {
+    fn build(&self) -> PyResult<Material<'static>> {
+        let material = Material::new()
+            .set_color(color(self.color))?
+            .set_ambient(scalar(self.ambient))?
+            .set_diffuse(scalar(self.diffuse))?
+            .set_specular(scalar(self.specular))?
+            .set_shininess(scalar(self.shininess))?
+            .set_reflective(scalar(self.reflective))?
+            .set_transparency(scalar(self.transparency))?
+            .set_refractive_index(scalar(self.refractive_index))?;
+        Ok(material)
+    }
+}
+
+#[derive(Clone)]
+enum Primitive {
+    Sphere,
+    Plane,
+    Cube,
+    Cylinder {
+        minimum: f64,
+        maximum: f64,
+        closed: bool,
+    },
+}
+
+#[pyclass(name = "Shape")]
+#[derive(Clone)]
+struct PyShape {
+    primitive: Primitive,
+    #[pyo3(get, set)]
+    transform: Rows,
+    #[pyo3(get, set)]
+    material: PyMaterial,
+    #[pyo3(get, set)]
+    name: Option<String>,
+}
+
+impl PyShape {
+    fn new(primitive: Primitive) -> Self {
+        Self {
+            primitive,
+            transform: identity(),
+            material: PyMaterial::default(),
+            name: None,
+        }
+    }
+    fn build(&self) -> PyResult<Shape<'static>> {
+        let shape = match self.primitive {
+            Primitive::Sphere => Shape::new(Sphere::new()),
+            Primitive::Plane => Shape::new(Plane::new()),
+            Primitive::Cube => Shape::new(Cube::new()),
+            Primitive::Cylinder {
+                minimum,
+                maximum,
+                closed,
+            } => Shape::new(
+                Cylinder::new()
+                    .set_extent(scalar(minimum), scalar(maximum))
+                    .set_closed(closed),
+            ),
+        };
+        let shape = shape
+            .set_transform(affine(&self.transform)?)?
+            .set_material(self.material.build()?);
+        Ok(match &self.name {
+            Some(name) => shape.set_name(name),
+            None => shape,
+        })
+    }
+}
+
+#[pymethods]
+impl PyShape {
+    #[staticmethod]
+    fn sphere() -> Self {
+        Self::new(Primitive::Sphere)
+    }
+    #[staticmethod]
+    fn plane() -> Self {
+        Self::new(Primitive::Plane)
+    }
+    #[staticmethod]
+    fn cube() -> Self {
+        Self::new(Primitive::Cube)
+    }
+    #[staticmethod]
+    #[pyo3(signature = (minimum = f64::NEG_INFINITY, maximum = f64::INFINITY, closed = false))]
+    fn cylinder(minimum: f64, maximum: f64, closed: bool) -> Self {
+        Self::new(Primitive::Cylinder {
+            minimum,
+            maximum,
+            closed,
+        })
+    }
+}
+
+#[pyclass(name = "World")]
+struct PyWorld {
+    world: World<'static>,
+}
+
+#[pymethods]
+impl PyWorld {
+    #[new]
+    fn new() -> Self {
+        Self {
+            world: World::new(),
+        }
+    }
+    #[pyo3(signature = (position, intensity = (1.0, 1.0, 1.0), radius = 0.0))]
+    fn add_light(&mut self, position: Triple, intensity: Triple, radius: f64) -> PyResult<()> {
+        self.world.add_light(
+            PointLight::new(point(position), color(intensity)).set_radius(scalar(radius))?,
+        );
+        Ok(())
+    }
+    // Returns the shape's object id, as reported by `Camera.pick`.
+    fn add_shape(&mut self, shape: &PyShape) -> PyResult<usize> {
+        Ok(self.world.add_shape(shape.build()?).id())
+    }
+    fn __len__(&self) -> usize {
+        self.world.shape_count()
+    }
+}
+
+#[pyclass(name = "Camera")]
+struct PyCamera {
+    camera: Camera,
+}
+
+#[pymethods]
+impl PyCamera {
+    #[new]
+    #[pyo3(signature = (width, height, field_of_view, transform = None))]
+    fn new(
+        width: usize,
+        height: usize,
+        field_of_view: f64,
+        transform: Option<Rows>,
+    ) -> PyResult<Self> {
+        let camera = Camera::new(width, height, scalar(field_of_view))?;
+        let camera = match transform {
+            Some(rows) => camera.set_transform(affine(&rows)?)?,
+            None => camera,
+        };
+        Ok(Self { camera })
+    }
+    #[getter]
+    fn get_width(&self) -> usize {
+        self.camera.get_hsize()
+    }
+    #[getter]
+    fn get_height(&self) -> usize {
+        self.camera.get_vsize()
+    }
+    #[setter]
+    fn set_transform(&mut self, rows: Rows) -> PyResult<()> {
+        self.camera = self.camera.set_transform(affine(&rows)?)?;
+        Ok(())
+    }
+    #[setter]
+    fn set_samples(&mut self, samples: usize) {
+        self.camera = self.camera.set_samples(samples);
+    }
+    fn pick(&self, world: &PyWorld, x: usize, y: usize) -> Option<usize> {
+        self.camera.pick(&world.world, x, y)
+    }
+    // The image as a (height, width, 3) float array of linear RGB.
+    #[allow(clippy::unnecessary_cast)]
+    fn render<'py>(&self, py: Python<'py>, world: &PyWorld) -> Bound<'py, PyArray3<f64>> {
+        let canvas = py.detach(|| self.camera.render(&world.world));
+        let (width, height) = (canvas.width(), canvas.height());
+        let mut pixels = Vec::with_capacity(3 * width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let c = canvas.pixel_at(x, y);
+                pixels.extend([c.red as f64, c.green as f64, c.blue as f64]);
+            }
+        }
+        Array3::from_shape_vec((height, width, 3), pixels)
+            .unwrap()
+            .into_pyarray(py)
+    }
+}
+
+#[pymodule]
+fn ray_tracer(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyWorld>()?;
+    m.add_class::<PyCamera>()?;
+    m.add_class::<PyShape>()?;
+    m.add_class::<PyMaterial>()?;
+    m.add_function(wrap_pyfunction!(identity, m)?)?;
+    m.add_function(wrap_pyfunction!(translation, m)?)?;
+    m.add_function(wrap_pyfunction!(scaling, m)?)?;
+    m.add_function(wrap_pyfunction!(rotation_x, m)?)?;
+    m.add_function(wrap_pyfunction!(rotation_y, m)?)?;
+    m.add_function(wrap_pyfunction!(rotation_z, m)?)?;
+    m.add_function(wrap_pyfunction!(view_transform, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    #[test]
+    fn test_transforms_round_trip_through_rows() {
+        let t = transform::translation(1.0, 2.0, 3.0) * &transform::rotation_y(0.5);
+        assert_approx_eq!(affine(&rows(&t)).unwrap(), t);
+        assert_eq!(rows(&t)[3], [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_transforms_must_be_affine() {
+        assert!(affine(&vec![vec![1.0, 0.0, 0.0]; 3]).is_err());
+        let mut projective = identity();
+        projective[3][2] = 1.0;
+        assert!(affine(&projective).is_err());
+    }
+}
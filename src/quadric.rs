@@ -0,0 +1,126 @@
+use crate::approx_eq::EPSILON;
+use crate::bounds::Bounds;
+use crate::point::Point;
+use crate::ray::Ray;
+use crate::scalar::Scalar;
+use crate::shape::LocalShape;
+use crate::vector::Vector;
+
+// The surface Ax² + By² + Cz² + Dxy + Exz + Fyz + Gx + Hy + Iz + J = 0 for
+// coefficients [A, B, ..., J]: ellipsoids, paraboloids, hyperboloids, cones
+// and cylinders all in one. Most quadrics are unbounded, and can be clipped
+// to a box.
+pub struct Quadric {
+    coefficients: [Scalar; 10],
+    clip: Bounds,
+}
+
+impl Quadric {
+    pub fn new(coefficients: [Scalar; 10]) -> Self {
+        Self {
+            coefficients,
+            clip: Bounds::infinite(),
+        }
+    }
+    // Keeps only the part of the surface inside `clip`.
+    pub fn set_clip(self, clip: Bounds) -> Self {
+        Self { clip, ..self }
+    }
+}
+
+impl LocalShape for Quadric {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
+        let [a, b, c, d, e, f, g, h, i, j] = self.coefficients;
+        let (o, v) = (ray.origin, ray.direction);
+        let qa = a * v.x * v.x
+            + b * v.y * v.y
+            + c * v.z * v.z
+            + d * v.x * v.y
+            + e * v.x * v.z
+            + f * v.y * v.z;
+        let qb = 2.0 * (a * o.x * v.x + b * o.y * v.y + c * o.z * v.z)
+            + d * (o.x * v.y + o.y * v.x)
+            + e * (o.x * v.z + o.z * v.x)
+            + f * (o.y * v.z + o.z * v.y)
+            + g * v.x
+            + h * v.y
+            + i * v.z;
+        let qc = a * o.x * o.x
+            + b * o.y * o.y
+            + c * o.z * o.z
+            + d * o.x * o.y
+            + e * o.x * o.z
+            + f * o.y * o.z
+            + g * o.x
+            + h * o.y
+            + i * o.z
+            + j;
+        let xs = if qa.abs() < EPSILON {
+            // the ray runs along an asymptote or the axis of a paraboloid
+            if qb.abs() < EPSILON {
+                vec![]
+            } else {
+                vec![-qc / qb]
+            }
+        } else {
+            let disc = qb * qb - 4.0 * qa * qc;
+            if disc < 0.0 {
+                vec![]
+            } else {
+                let root = disc.sqrt();
+                vec![(-qb - root) / (2.0 * qa), (-qb + root) / (2.0 * qa)]
+            }
+        };
+        xs.into_iter()
+            .filter(|t| self.clip.contains(&ray.position(*t)))
+            .collect()
+    }
+    // the gradient of the polynomial
+    fn local_normal_at(&self, p: &Point) -> Vector {
+        let [a, b, c, d, e, f, g, h, i, _] = self.coefficients;
+        Vector::new(
+            2.0 * a * p.x + d * p.y + e * p.z + g,
+            2.0 * b * p.y + d * p.x + f * p.z + h,
+            2.0 * c * p.z + e * p.x + f * p.y + i,
+        )
+    }
+    fn local_bounds(&self) -> Bounds {
+        self.clip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    #[test]
+    fn test_a_quadric_can_be_a_sphere() {
+        let sphere = Quadric::new([1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0]);
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(sphere.local_intersect(&r), [4.0, 6.0]);
+        let n = sphere
+            .local_normal_at(&Point::new(0.0, 0.6, 0.8))
+            .normalize();
+        assert_approx_eq!(n, Vector::new(0.0, 0.6, 0.8));
+    }
+
+    #[test]
+    fn test_intersecting_a_clipped_paraboloid() {
+        // y = x² + z², up to y = 4
+        let bowl = Quadric::new([1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0]).set_clip(
+            Bounds::new(Point::new(-2.0, 0.0, -2.0), Point::new(2.0, 4.0, 2.0)),
+        );
+        // straight down the axis there is a single hit
+        let r = Ray::new(Point::new(0.0, 5.0, 0.0), Vector::new(0.0, -1.0, 0.0));
+        assert_approx_eq!(bowl.local_intersect(&r), [5.0]);
+        let n = bowl.local_normal_at(&Point::new(0.0, 0.0, 0.0)).normalize();
+        assert_approx_eq!(n, Vector::new(0.0, -1.0, 0.0));
+        let r = Ray::new(Point::new(-5.0, 1.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        assert_approx_eq!(bowl.local_intersect(&r), [4.0, 6.0]);
+        // above the rim
+        let r = Ray::new(Point::new(-5.0, 4.5, 0.0), Vector::new(1.0, 0.0, 0.0));
+        assert!(bowl.local_intersect(&r).is_empty());
+    }
+}
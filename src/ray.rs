@@ -1,23 +1,38 @@
 use crate::transform::Affine;
 use crate::point::Point;
+use crate::scalar::Scalar;
 use crate::vector::Vector;
 
+#[derive(Debug)]
 pub struct Ray {
     pub origin: Point,
     pub direction: Vector,
+    // in nanometers, for rays carrying a single wavelength of light
+    pub wavelength: Option<Scalar>,
 }
 
 impl Ray {
     pub fn new(origin: Point, direction: Vector) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            wavelength: None,
+        }
+    }
+    // Rays with a wavelength see the dispersion of the materials they
+    // refract through (see `Material::set_dispersion`), and so do the
+    // reflected and refracted rays they spawn.
+    pub fn set_wavelength(self, wavelength: Option<Scalar>) -> Self {
+        Self { wavelength, ..self }
     }
-    pub fn position(&self, t: f64) -> Point {
+    pub fn position(&self, t: Scalar) -> Point {
         self.origin + &(&self.direction * t)
     }
     pub fn transform(&self, trans: &Affine) -> Self {
         Self {
             origin: trans * &self.origin,
             direction: trans * &self.direction,
+            wavelength: self.wavelength,
         }
     }
 }
@@ -0,0 +1,241 @@
+// A small deterministic random number generator (SplitMix64). Renders seed
+// one sampler per pixel from its coordinates and the frame number, so the
+// sequence a pixel sees never depends on which thread rendered it. Being
+// the pixel's own render state, it also carries the camera's choice of
+// Fresnel term.
+use crate::scalar::consts::PI;
+use crate::scalar::Scalar;
+use crate::vector::Vector;
+use crate::world::Fresnel;
+
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    state: u64,
+    sequence: SampleSequence,
+    fresnel: Fresnel,
+}
+
+// Where the samples of a `PointSet` come from: independent random points, or
+// successive points of a Halton sequence, which cover the unit cube more
+// evenly and so converge faster for the same number of samples.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SampleSequence {
+    #[default]
+    Random,
+    Halton,
+}
+
+const PRIMES: [u64; 8] = [2, 3, 5, 7, 11, 13, 17, 19];
+
+// The digits of `index` in `base` mirrored around the radix point.
+pub fn radical_inverse(base: u64, mut index: u64) -> Scalar {
+    let inv_base = 1.0 / base as Scalar;
+    let mut scale = inv_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as Scalar * scale;
+        index /= base;
+        scale *= inv_base;
+    }
+    result
+}
+
+// The samples for one integral, say a pixel's area or a light's volume. With
+// a Halton sequence every set is shifted by its own random offset (a
+// Cranley-Patterson rotation), so sets stay evenly spread but don't repeat
+// the same pattern from pixel to pixel.
+pub struct PointSet<const D: usize> {
+    offsets: Option<[Scalar; D]>,
+}
+
+impl<const D: usize> PointSet<D> {
+    // The `index`th point in [0, 1)^D.
+    pub fn point(&self, index: u64, sampler: &mut Sampler) -> [Scalar; D] {
+        match &self.offsets {
+            None => [(); D].map(|_| sampler.next_f64()),
+            Some(offsets) => {
+                let mut point = *offsets;
+                for (d, p) in point.iter_mut().enumerate() {
+                    let v = *p + radical_inverse(PRIMES[d], index);
+                    *p = if v >= 1.0 { v - 1.0 } else { v };
+                }
+                point
+            }
+        }
+    }
+}
+
+// A uniformly distributed point in the unit ball, from one in the unit cube.
+pub fn unit_ball([u, v, w]: [Scalar; 3]) -> Vector {
+    let r = w.cbrt();
+    let z = 1.0 - 2.0 * u;
+    let s = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vector::new(r * s * phi.cos(), r * s * phi.sin(), r * z)
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: mix(seed),
+            sequence: SampleSequence::Random,
+            fresnel: Fresnel::Schlick,
+        }
+    }
+    pub fn set_sequence(self, sequence: SampleSequence) -> Self {
+        Self { sequence, ..self }
+    }
+    pub fn set_fresnel(self, fresnel: Fresnel) -> Self {
+        Self { fresnel, ..self }
+    }
+    pub fn get_fresnel(&self) -> Fresnel {
+        self.fresnel
+    }
+    // Random sets draw nothing up front, so they leave the sampler's stream
+    // as it was.
+    pub fn point_set<const D: usize>(&mut self) -> PointSet<D> {
+        assert!(D <= PRIMES.len());
+        PointSet {
+            offsets: match self.sequence {
+                SampleSequence::Random => None,
+                SampleSequence::Halton => Some([(); D].map(|_| self.next_f64())),
+            },
+        }
+    }
+    pub fn for_pixel(x: usize, y: usize, frame: u64) -> Self {
+        Self::new(mix(mix(x as u64) ^ y as u64) ^ frame)
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        mix(self.state)
+    }
+    // uniformly distributed in [0, 1)
+    pub fn next_f64(&mut self) -> Scalar {
+        (self.next_u64() >> (64 - Scalar::MANTISSA_DIGITS)) as Scalar
+            / (1u64 << Scalar::MANTISSA_DIGITS) as Scalar
+    }
+    // uniformly distributed inside the unit sphere
+    pub fn in_unit_sphere(&mut self) -> Vector {
+        loop {
+            let v = Vector::new(
+                2.0 * self.next_f64() - 1.0,
+                2.0 * self.next_f64() - 1.0,
+                2.0 * self.next_f64() - 1.0,
+            );
+            if v.dot(&v) <= 1.0 {
+                return v;
+            }
+        }
+    }
+    // A random unit vector in the hemisphere around `normal`, more likely
+    // near the normal (cosine weighted).
+    pub fn cosine_hemisphere(&mut self, normal: &Vector) -> Vector {
+        let r = self.next_f64().sqrt();
+        let phi = 2.0 * PI * self.next_f64();
+        // an orthonormal basis around the normal
+        let helper = if normal.x.abs() < 0.9 {
+            Vector::new(1.0, 0.0, 0.0)
+        } else {
+            Vector::new(0.0, 1.0, 0.0)
+        };
+        let tangent = normal.cross(&helper).normalize();
+        let bitangent = normal.cross(&tangent);
+        let z = (1.0 - r * r).max(0.0).sqrt();
+        (&(&tangent * (r * phi.cos())) + &(&bitangent * (r * phi.sin()))) + &(normal * z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    #[test]
+    fn test_a_pixel_sampler_is_deterministic() {
+        let mut s1 = Sampler::for_pixel(3, 7, 0);
+        let mut s2 = Sampler::for_pixel(3, 7, 0);
+        for _ in 0..10 {
+            assert_eq!(s1.next_u64(), s2.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_pixel_samplers_differ_between_pixels_and_frames() {
+        let a = Sampler::for_pixel(3, 7, 0).next_u64();
+        assert_ne!(a, Sampler::for_pixel(7, 3, 0).next_u64());
+        assert_ne!(a, Sampler::for_pixel(3, 7, 1).next_u64());
+    }
+
+    #[test]
+    fn test_hemisphere_samples_are_unit_vectors_around_the_normal() {
+        let mut s = Sampler::new(7);
+        let n = Vector::new(0.0, 0.0, -1.0);
+        for _ in 0..100 {
+            let v = s.cosine_hemisphere(&n);
+            assert!((v.magnitude() - 1.0).abs() < 100.0 * Scalar::EPSILON);
+            assert!(v.dot(&n) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_radical_inverses() {
+        assert_eq!(radical_inverse(2, 0), 0.0);
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 6), 0.375);
+        assert_approx_eq!(radical_inverse(3, 5), 7.0 / 9.0);
+    }
+
+    #[test]
+    fn test_halton_points_cover_every_stratum() {
+        // any 6 consecutive points fall in distinct cells of a 2x3 grid
+        let mut cells: Vec<_> = (0..6)
+            .map(|i| (radical_inverse(2, i), radical_inverse(3, i)))
+            .map(|(u, v)| ((u * 2.0) as usize, (v * 3.0) as usize))
+            .collect();
+        cells.sort();
+        cells.dedup();
+        assert_eq!(cells.len(), 6);
+    }
+
+    #[test]
+    fn test_halton_point_sets_are_shifted_by_a_random_offset() {
+        let mut s = Sampler::new(3).set_sequence(SampleSequence::Halton);
+        let set = s.point_set::<2>();
+        let [ou, ov] = set.offsets.unwrap();
+        assert_eq!(set.point(0, &mut s), [ou, ov]);
+        let [u, v] = set.point(1, &mut s);
+        assert_approx_eq!(u, (ou + 0.5).fract());
+        assert_approx_eq!(v, (ov + 1.0 / 3.0).fract());
+        let other = Sampler::new(4)
+            .set_sequence(SampleSequence::Halton)
+            .point_set::<2>();
+        assert_ne!(other.offsets, set.offsets);
+    }
+
+    #[test]
+    fn test_unit_ball_points_are_inside_it() {
+        let mut s = Sampler::new(5).set_sequence(SampleSequence::Halton);
+        let set = s.point_set::<3>();
+        for i in 0..100 {
+            let v = unit_ball(set.point(i, &mut s));
+            assert!(v.magnitude() <= 1.0 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_samples_are_in_the_unit_interval() {
+        let mut s = Sampler::new(42);
+        for _ in 0..1000 {
+            let v = s.next_f64();
+            assert!((0.0..1.0).contains(&v));
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use crate::approx_eq::ApproxEq;
+use std::fmt::Debug;
+
+// The floating point type used throughout the crate; f64 unless the `f32`
+// feature is enabled.
+#[cfg(not(feature = "f32"))]
+pub type Scalar = f64;
+#[cfg(feature = "f32")]
+pub type Scalar = f32;
+
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+
+// What the math core (vectors, points and matrices) needs of its scalar
+// type. f32 and f64 qualify, as would e.g. dual numbers for
+// differentiating a render.
+pub trait Float: num_traits::Float + ApproxEq + Debug {}
+
+impl<T: num_traits::Float + ApproxEq + Debug> Float for T {}
@@ -0,0 +1,261 @@
+use crate::camera::{Camera, RenderSettings};
+use crate::color::{Color, ColorSpace};
+use crate::cube::{Cube, RoundedCube};
+use crate::cylinder::Cylinder;
+use crate::error::Result;
+use crate::light::PointLight;
+use crate::material::{Material, ValidationPolicy};
+use crate::plane::Plane;
+use crate::point::Point;
+use crate::scalar::Scalar;
+use crate::shape::Shape;
+use crate::sphere::Sphere;
+use crate::transform::{Affine, IDENTITY_AFFINE};
+use crate::units::Units;
+use crate::world::World;
+use serde::{Deserialize, Serialize};
+
+// A scene as plain data, built from the primitive shapes. Meshes and
+// anything needing callbacks are left to code.
+#[derive(Serialize, Deserialize)]
+pub struct SceneDescription {
+    pub camera: Camera,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+    #[serde(default)]
+    pub shapes: Vec<ShapeDescription>,
+    #[serde(default)]
+    pub units: Units,
+    #[serde(default = "one")]
+    pub scale: Scalar,
+    // the space material and light colors are given in, and the images
+    // are written in
+    #[serde(default)]
+    pub color_space: ColorSpace,
+    // replaces the render settings given with the camera
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub render: Option<RenderSettings>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LightDescription {
+    pub position: Point,
+    pub intensity: Color,
+    #[serde(default)]
+    pub radius: Scalar,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub visible: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Primitive {
+    Sphere,
+    Plane,
+    Cube,
+    #[serde(rename = "rounded_cube")]
+    RoundedCube {
+        radius: Scalar,
+    },
+    // bounds default to an infinite cylinder, which JSON cannot spell
+    Cylinder {
+        #[serde(default = "negative_infinity")]
+        minimum: Scalar,
+        #[serde(default = "positive_infinity")]
+        maximum: Scalar,
+        #[serde(default)]
+        closed: bool,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShapeDescription {
+    #[serde(flatten)]
+    pub primitive: Primitive,
+    #[serde(default = "default_transform")]
+    pub transform: Affine,
+    #[serde(default)]
+    pub material: Material<'static>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+}
+
+impl SceneDescription {
+    pub fn build(self) -> Result<(World<'static>, Camera)> {
+        let mut world = World::new();
+        world.set_units(self.units);
+        world.set_scale(self.scale);
+        for light in self.lights {
+            let intensity = self.color_space.to_linear(light.intensity);
+            world.add_light(
+                PointLight::new(light.position, intensity)
+                    .set_radius(light.radius)?
+                    .set_visible(light.visible),
+            );
+        }
+        for shape in self.shapes {
+            world.add_shape(shape.build(self.color_space)?);
+        }
+        let camera = self.camera.set_color_space(self.color_space);
+        let camera = match self.render {
+            Some(settings) => camera.with_settings(&settings)?,
+            None => camera,
+        };
+        Ok((world, camera))
+    }
+}
+
+impl ShapeDescription {
+    fn build(self, color_space: ColorSpace) -> Result<Shape<'static>> {
+        let shape = match self.primitive {
+            Primitive::Sphere => Shape::new(Sphere::new()),
+            Primitive::Plane => Shape::new(Plane::new()),
+            Primitive::Cube => Shape::new(Cube::new()),
+            Primitive::RoundedCube { radius } => Shape::new(RoundedCube::new(radius)?),
+            Primitive::Cylinder {
+                minimum,
+                maximum,
+                closed,
+            } => Shape::new(
+                Cylinder::new()
+                    .set_extent(minimum, maximum)
+                    .set_closed(closed),
+            ),
+        };
+        let material = self
+            .material
+            .convert_colors(color_space)
+            .validate(ValidationPolicy::default())?;
+        let shape = self.tags.iter().fold(
+            shape
+                .set_transform(self.transform)?
+                .set_material(material)
+                .set_hidden(self.hidden),
+            |shape, tag| shape.add_tag(tag),
+        );
+        Ok(match self.name {
+            Some(name) => shape.set_name(&name),
+            None => shape,
+        })
+    }
+}
+
+fn default_transform() -> Affine {
+    IDENTITY_AFFINE
+}
+
+fn one() -> Scalar {
+    1.0
+}
+
+fn negative_infinity() -> Scalar {
+    Scalar::NEG_INFINITY
+}
+
+fn positive_infinity() -> Scalar {
+    Scalar::INFINITY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::world::Fresnel;
+
+    const SCENE: &str = r#"{
+        "camera": {"hsize": 11, "vsize": 11, "field_of_view": 0.5,
+                   "transform": {"transform": {"elems": [[-1, 0, 0], [0, 1, 0], [0, 0, -1]]},
+                                 "translate": {"x": 0, "y": 0, "z": -5}}},
+        "lights": [{"position": {"x": -10, "y": 10, "z": -10},
+                    "intensity": {"red": 1, "green": 1, "blue": 1}}],
+        "shapes": [
+            {"type": "sphere", "name": "ball", "tags": ["toy"], "material": {"color": {"red": 1, "green": 0, "blue": 0}}},
+            {"type": "cylinder", "minimum": 2, "maximum": 3, "closed": true}
+        ]
+    }"#;
+
+    #[test]
+    fn test_building_a_world_from_json() {
+        let scene: SceneDescription = serde_json::from_str(SCENE).unwrap();
+        let (world, camera) = scene.build().unwrap();
+        assert_eq!(world.get_shape(0).get_name(), Some("ball"));
+        assert_eq!(world.find_shapes(|s| s.has_tag("toy")), [0]);
+        let center = camera.render(&world).pixel_at(5, 5);
+        assert!(center.red > 0.1);
+        assert!(center.green < 0.1);
+    }
+
+    #[test]
+    fn test_a_scene_can_give_its_units() {
+        let json = SCENE.replacen('{', r#"{"units": "centimeters", "scale": 2,"#, 1);
+        let scene: SceneDescription = serde_json::from_str(&json).unwrap();
+        let (world, _) = scene.build().unwrap();
+        assert_approx_eq!(world.meters_per_unit(), 0.02);
+    }
+
+    #[test]
+    fn test_a_scene_can_give_its_render_settings() {
+        let json = SCENE.replacen(
+            '{',
+            r#"{"render": {"samples": 4, "max_depth": 2, "aovs": true, "fresnel": "Exact"},"#,
+            1,
+        );
+        let scene: SceneDescription = serde_json::from_str(&json).unwrap();
+        let render = scene.render.unwrap();
+        assert_eq!(render.tile_size, RenderSettings::default().tile_size);
+        let (_, camera) = scene.build().unwrap();
+        let settings = camera.get_settings();
+        assert_eq!(settings.fresnel, Fresnel::Exact);
+        assert_eq!(settings.samples, 4);
+        assert_eq!(settings.max_depth, Some(2));
+    }
+
+    #[test]
+    fn test_a_scene_can_give_its_colors_in_srgb() {
+        let json = SCENE.replace(r#""red": 1, "green": 0"#, r#""red": 0.5, "green": 0"#);
+        let linear: SceneDescription = serde_json::from_str(&json).unwrap();
+        let (linear_world, camera) = linear.build().unwrap();
+        let json = json.replacen('{', r#"{"color_space": "srgb","#, 1);
+        let srgb: SceneDescription = serde_json::from_str(&json).unwrap();
+        let (srgb_world, srgb_camera) = srgb.build().unwrap();
+        assert_eq!(srgb_camera.get_color_space(), ColorSpace::Srgb);
+        // a mid red picked in sRGB is darker in linear terms
+        let center = |world: &World| camera.render(world).pixel_at(5, 5).red;
+        assert_approx_eq!(center(&srgb_world), center(&linear_world) * 0.21404 / 0.5);
+    }
+
+    #[test]
+    fn test_rounded_cubes_check_their_radius() {
+        let shape: ShapeDescription =
+            serde_json::from_str(r#"{"type": "rounded_cube", "radius": 0.2}"#).unwrap();
+        assert!(shape.build(ColorSpace::Linear).is_ok());
+        let shape: ShapeDescription =
+            serde_json::from_str(r#"{"type": "rounded_cube", "radius": 2}"#).unwrap();
+        assert!(shape.build(ColorSpace::Linear).is_err());
+    }
+
+    #[test]
+    fn test_scene_materials_are_validated() {
+        let shape: ShapeDescription =
+            serde_json::from_str(r#"{"type": "sphere", "material": {"transparency": 1.5}}"#)
+                .unwrap();
+        assert!(matches!(
+            shape.build(ColorSpace::Linear),
+            Err(crate::error::RayTracerError::InvalidMaterial(_))
+        ));
+    }
+
+    #[test]
+    fn test_cylinders_default_to_infinite_and_open() {
+        let shape: ShapeDescription = serde_json::from_str(r#"{"type": "cylinder"}"#).unwrap();
+        assert!(matches!(
+            shape.primitive,
+            Primitive::Cylinder { minimum, maximum, closed: false }
+                if minimum == Scalar::NEG_INFINITY && maximum == Scalar::INFINITY
+        ));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::approx_eq::EPSILON;
+use crate::bounds::Bounds;
+use crate::error::{RayTracerError, Result};
+use crate::point::Point;
+use crate::ray::Ray;
+use crate::scalar::Scalar;
+use crate::shape::LocalShape;
+use crate::vector::Vector;
+
+type DistanceFn<'a> = Box<dyn Fn(&Point) -> Scalar + Send + Sync + 'a>;
+
+// An implicit surface given by its signed distance function: negative
+// inside, positive outside, and never more than the distance to the
+// surface, so rays can be marched towards it in safe steps. Fractals and
+// blobby shapes with no closed-form intersection fit here.
+pub struct SdfShape<'a> {
+    distance: DistanceFn<'a>,
+    bounds: Bounds,
+    max_steps: usize,
+    epsilon: Scalar,
+}
+
+impl<'a> SdfShape<'a> {
+    // Rays are only marched within `bounds`, which must enclose the surface.
+    pub fn new(
+        distance: impl Fn(&Point) -> Scalar + Send + Sync + 'a,
+        bounds: Bounds,
+    ) -> Result<Self> {
+        if !bounds.is_finite() {
+            return Err(RayTracerError::InvalidShape(
+                "a signed distance shape needs finite bounds".to_string(),
+            ));
+        }
+        Ok(Self {
+            distance: Box::new(distance),
+            bounds,
+            max_steps: 256,
+            epsilon: EPSILON / 10.0,
+        })
+    }
+    // Rays still short of the surface after this many steps miss it.
+    // Grazing rays and fractals take the most.
+    pub fn set_max_steps(self, max_steps: usize) -> Self {
+        Self { max_steps, ..self }
+    }
+    // How close to the surface counts as on it. Normals are estimated by
+    // central differences a hundred times wider, as narrower ones lose
+    // too much precision to rounding.
+    pub fn set_epsilon(self, epsilon: Scalar) -> Self {
+        Self { epsilon, ..self }
+    }
+}
+
+impl LocalShape for SdfShape<'_> {
+    // Marches along the whole ray within the bounds by the distance to the
+    // surface, recording each time it comes within epsilon of it, so rays
+    // get both where they enter and where they leave.
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
+        let (mut t, end) = self.bounds.ray_interval(ray);
+        let speed = ray.direction.magnitude();
+        let mut xs = vec![];
+        let mut on_surface = false;
+        for _ in 0..self.max_steps {
+            if t > end {
+                break;
+            }
+            let d = (self.distance)(&ray.position(t)).abs();
+            if d < self.epsilon {
+                if !on_surface {
+                    xs.push(t);
+                }
+                on_surface = true;
+            } else {
+                on_surface = false;
+            }
+            t += d.max(self.epsilon) / speed;
+        }
+        xs
+    }
+    fn local_normal_at(&self, p: &Point) -> Vector {
+        let h = self.epsilon * 100.0;
+        let slope =
+            |offset: Vector| (self.distance)(&(*p + &offset)) - (self.distance)(&(*p - &offset));
+        Vector::new(
+            slope(Vector::new(h, 0.0, 0.0)),
+            slope(Vector::new(0.0, h, 0.0)),
+            slope(Vector::new(0.0, 0.0, h)),
+        )
+        .normalize()
+    }
+    fn local_bounds(&self) -> Bounds {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::point::ORIGIN;
+
+    fn ball() -> SdfShape<'static> {
+        SdfShape::new(
+            |p: &Point| (*p - &ORIGIN).magnitude() - 1.0,
+            Bounds::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_marching_a_ray_through_a_signed_distance_sphere() {
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(ball().local_intersect(&r), [4.0, 6.0]);
+        let inside = Ray::new(ORIGIN, Vector::new(0.0, 2.0, 0.0));
+        assert_approx_eq!(ball().local_intersect(&inside), [-0.5, 0.5]);
+        let miss = Ray::new(Point::new(0.0, 1.5, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(ball().local_intersect(&miss).is_empty());
+        let n = ball().local_normal_at(&Point::new(0.0, 0.6, 0.8));
+        assert_approx_eq!(n, Vector::new(0.0, 0.6, 0.8));
+    }
+
+    #[test]
+    fn test_signed_distance_shapes_need_steps_and_bounds() {
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(ball().set_max_steps(0).local_intersect(&r).is_empty());
+        assert!(SdfShape::new(|p: &Point| p.y, Bounds::infinite()).is_err());
+    }
+}
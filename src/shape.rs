@@ -1,53 +1,301 @@
+use crate::bounds::Bounds;
+use crate::color::Color;
+use crate::error::{RayTracerError, Result};
 use crate::material::{Material, DEFAULT_MATERIAL};
 use crate::point::Point;
 use crate::ray::Ray;
+use crate::scalar::Scalar;
 use crate::transform::{Affine, IDENTITY_AFFINE};
 use crate::vector::Vector;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Weak};
 
-pub trait LocalShape {
-    fn local_intersect(&self, ray: &Ray) -> Vec<f64>;
+pub trait LocalShape: Send + Sync {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar>;
     fn local_normal_at(&self, p: &Point) -> Vector;
+    // The normal of the surface as it really lies, where the shading normal
+    // differs from it, such as the flat face under interpolated normals.
+    // Points are offset from the surface along it.
+    fn local_geometric_normal_at(&self, p: &Point) -> Vector {
+        self.local_normal_at(p)
+    }
+    fn local_bounds(&self) -> Bounds {
+        Bounds::infinite()
+    }
+    // Shapes with interpolated normals can lift the shadow ray origin off
+    // the flat surface to avoid a faceted shadow terminator.
+    fn local_shadow_origin(&self, p: &Point) -> Point {
+        *p
+    }
+    // How far `p` is from the nearest edge of the face it lies on, as the
+    // smallest barycentric weight, for wireframe views. None for shapes
+    // without faces.
+    fn local_edge_distance(&self, _p: &Point) -> Option<Scalar> {
+        None
+    }
+    // A color carried by the geometry itself, such as interpolated vertex
+    // colors, which tints the material's color.
+    fn local_color_at(&self, _p: &Point) -> Option<Color> {
+        None
+    }
+    // Moves the vertices of a deforming shape to new positions, keeping its
+    // topology. Only meshes have vertices.
+    fn local_deform(&mut self, _vertices: Vec<Point>, _normals: Option<Vec<Vector>>) -> Result<()> {
+        Err(RayTracerError::InvalidShape(
+            "only meshes can be deformed".to_string(),
+        ))
+    }
+}
+
+// A transform with its inverse, shared between shapes placed alike.
+struct Placement {
+    transform: Affine,
+    inverse: Affine,
+}
+
+const IDENTITY_PLACEMENT: Placement = Placement {
+    transform: IDENTITY_AFFINE,
+    inverse: IDENTITY_AFFINE,
+};
+
+// Hands out one shared copy of each distinct transform and solid material,
+// so a world of many alike shapes keeps one of each. Only weak references
+// are kept, so what no shape uses any longer is freed.
+#[derive(Default)]
+pub(crate) struct Interner<'a> {
+    placements: HashMap<[u64; 12], Weak<Placement>>,
+    materials: HashMap<Vec<u64>, Weak<Material<'a>>>,
+}
+
+fn intern<K: Eq + Hash, T>(table: &mut HashMap<K, Weak<T>>, key: K, value: &mut Arc<T>) {
+    match table.get(&key).and_then(Weak::upgrade) {
+        Some(shared) => *value = shared,
+        None => {
+            table.insert(key, Arc::downgrade(value));
+        }
+    }
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn affine_key(a: &Affine) -> [u64; 12] {
+    let m = a.get_transform();
+    let t = a.get_translate();
+    let mut key = [0; 12];
+    for i in 0..3 {
+        for j in 0..3 {
+            key[3 * i + j] = m.at(i, j).to_bits() as u64;
+        }
+        key[9 + i] = t[i].to_bits() as u64;
+    }
+    key
 }
 
 pub struct Shape<'a> {
-    inverse_transform: Affine,
-    material: Material<'a>,
+    placement: Arc<Placement>,
+    material: Arc<Material<'a>>,
+    name: Option<String>,
+    tags: Vec<String>,
+    hidden: bool,
+    casts_shadow: bool,
     local_shape: Box<dyn LocalShape + 'a>,
 }
 
 impl<'a> Shape<'a> {
     pub fn new(local_shape: impl LocalShape + 'a) -> Self {
         Self {
-            inverse_transform: IDENTITY_AFFINE,
-            material: DEFAULT_MATERIAL,
+            placement: Arc::new(IDENTITY_PLACEMENT),
+            material: Arc::new(DEFAULT_MATERIAL),
+            name: None,
+            tags: vec![],
+            hidden: false,
+            casts_shadow: true,
             local_shape: Box::new(local_shape),
         }
     }
-    pub fn set_transform(self, transform: Affine) -> Self {
+    pub fn set_transform(mut self, transform: Affine) -> Result<Self> {
+        self.update_transform(transform)?;
+        Ok(self)
+    }
+    pub(crate) fn update_transform(&mut self, transform: Affine) -> Result<()> {
+        let inverse = transform
+            .inverse()
+            .ok_or(RayTracerError::NonInvertibleTransform)?;
+        self.placement = Arc::new(Placement { transform, inverse });
+        Ok(())
+    }
+    pub fn set_material(self, material: Material<'a>) -> Self {
         Self {
-            inverse_transform: transform.inverse().unwrap(),
+            material: Arc::new(material),
             ..self
         }
     }
-    pub fn set_material(self, material: Material<'a>) -> Self {
-        Self { material, ..self }
+    pub(crate) fn update_material(&mut self, material: Material<'a>) {
+        self.material = Arc::new(material);
+    }
+    // Swaps the transform and material for shared copies of equal ones.
+    pub(crate) fn intern(&mut self, interner: &mut Interner<'a>) {
+        let key = affine_key(&self.placement.transform);
+        intern(&mut interner.placements, key, &mut self.placement);
+        if let Some(key) = self.material.solid_key() {
+            intern(&mut interner.materials, key, &mut self.material);
+        }
+    }
+    // Changes this shape's material alone, copying it first if it is shared.
+    // Returns false, leaving it as it was, for a shared patterned material.
+    pub(crate) fn modify_material(&mut self, f: impl FnOnce(Material<'a>) -> Material<'a>) -> bool {
+        if let Some(material) = Arc::get_mut(&mut self.material) {
+            *material = f(std::mem::take(material));
+            return true;
+        }
+        match self.material.clone_solid() {
+            Some(material) => {
+                self.material = Arc::new(f(material));
+                true
+            }
+            None => false,
+        }
+    }
+    // Whether `modify_material` would succeed.
+    pub(crate) fn can_modify_material(&self) -> bool {
+        Arc::strong_count(&self.material) == 1 && Arc::weak_count(&self.material) == 0
+            || self.material.get_solid_color().is_some()
+    }
+    // For giving many shapes one material without copying it.
+    pub(crate) fn share_material(&mut self, material: &Arc<Material<'a>>) {
+        self.material = Arc::clone(material);
     }
-    pub fn get_material(&self) -> &Material {
+    pub fn get_material(&self) -> &Material<'a> {
         &self.material
     }
+    // Whether the two shapes use the very same material, as shapes given
+    // equal solid materials do once added to a world.
+    pub fn shares_material(&self, other: &Shape<'a>) -> bool {
+        Arc::ptr_eq(&self.material, &other.material)
+    }
+    pub fn set_name(self, name: &str) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            ..self
+        }
+    }
+    pub(crate) fn update_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    // Whether the name matches `pattern`, in which '*' stands for any run
+    // of characters, e.g. "wheel*".
+    pub fn name_matches(&self, pattern: &str) -> bool {
+        self.name
+            .as_deref()
+            .is_some_and(|name| glob_match(pattern, name))
+    }
+    pub fn add_tag(mut self, tag: &str) -> Self {
+        self.update_tags(tag);
+        self
+    }
+    pub(crate) fn update_tags(&mut self, tag: &str) {
+        if !self.has_tag(tag) {
+            self.tags.push(tag.to_string());
+        }
+    }
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+    pub fn get_tags(&self) -> &[String] {
+        &self.tags
+    }
+    // Hidden shapes stay in the world but are left out of rendering, and
+    // neither cast shadows nor show in reflections.
+    pub fn set_hidden(self, hidden: bool) -> Self {
+        Self { hidden, ..self }
+    }
+    pub(crate) fn update_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+    // Shapes that cast no shadow are still seen, but let light and ambient
+    // occlusion rays pass.
+    pub fn set_casts_shadow(self, casts_shadow: bool) -> Self {
+        Self {
+            casts_shadow,
+            ..self
+        }
+    }
+    pub fn casts_shadow(&self) -> bool {
+        self.casts_shadow
+    }
+    pub fn get_transform(&self) -> &Affine {
+        &self.placement.transform
+    }
+    pub(crate) fn deform(
+        &mut self,
+        vertices: Vec<Point>,
+        normals: Option<Vec<Vector>>,
+    ) -> Result<()> {
+        self.local_shape.local_deform(vertices, normals)
+    }
     pub fn get_inverse_transform(&self) -> &Affine {
-        &self.inverse_transform
+        &self.placement.inverse
     }
-    pub fn intersect(&self, ray: &Ray) -> Vec<f64> {
-        let ray = ray.transform(&self.inverse_transform);
+    pub fn intersect(&self, ray: &Ray) -> Vec<Scalar> {
+        let ray = ray.transform(&self.placement.inverse);
         self.local_shape.local_intersect(&ray)
     }
     pub fn normal_at(&self, point: &Point) -> Vector {
-        let local_point = self.inverse_transform * point;
-        let local_normal = self.local_shape.local_normal_at(&local_point);
-        let world_normal = self.inverse_transform.get_transform().transpose() * &local_normal;
+        let local_point = self.placement.inverse * point;
+        self.to_world_normal(&self.local_shape.local_normal_at(&local_point))
+    }
+    pub fn geometric_normal_at(&self, point: &Point) -> Vector {
+        let local_point = self.placement.inverse * point;
+        self.to_world_normal(&self.local_shape.local_geometric_normal_at(&local_point))
+    }
+    fn to_world_normal(&self, local_normal: &Vector) -> Vector {
+        let world_normal = self.placement.inverse.get_transform().transpose() * local_normal;
         world_normal.normalize()
     }
+    pub fn bounds(&self) -> Bounds {
+        self.local_bounds().transform(&self.placement.transform)
+    }
+    pub fn local_bounds(&self) -> Bounds {
+        self.local_shape.local_bounds()
+    }
+    pub fn shadow_origin(&self, point: &Point) -> Point {
+        let local_point = self.placement.inverse * point;
+        self.placement.transform * &self.local_shape.local_shadow_origin(&local_point)
+    }
+    pub fn edge_distance(&self, point: &Point) -> Option<Scalar> {
+        let local_point = self.placement.inverse * point;
+        self.local_shape.local_edge_distance(&local_point)
+    }
+    pub fn color_at(&self, point: &Point) -> Option<Color> {
+        let local_point = self.placement.inverse * point;
+        self.local_shape.local_color_at(&local_point)
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // no wildcard at all
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
 }
 
 #[cfg(test)]
@@ -55,6 +303,7 @@ mod tests {
 
     use super::*;
     use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::color::BLACK;
     use crate::point::ORIGIN;
     use crate::transform::{rotation_z, scaling, translation};
 
@@ -67,7 +316,7 @@ mod tests {
     }
 
     impl LocalShape for TestShape {
-        fn local_intersect(&self, ray: &Ray) -> Vec<f64> {
+        fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
             // hack to get the local ray values out
             vec![
                 ray.origin.x,
@@ -86,20 +335,22 @@ mod tests {
     #[test]
     fn test_a_shapes_default_transformation() {
         let s = Shape::new(TestShape::new());
-        assert_approx_eq!(s.inverse_transform, &IDENTITY_AFFINE);
+        assert_approx_eq!(s.placement.inverse, &IDENTITY_AFFINE);
     }
 
     #[test]
     fn test_changing_a_shapes_transformation() {
         let t = translation(2.0, 3.0, 4.0);
-        let s = Shape::new(TestShape::new()).set_transform(t);
-        assert_approx_eq!(s.inverse_transform, &t.inverse().unwrap());
+        let s = Shape::new(TestShape::new()).set_transform(t).unwrap();
+        assert_approx_eq!(s.placement.inverse, &t.inverse().unwrap());
     }
 
     #[test]
     fn test_intersecting_a_scaled_shape_with_a_ray() {
         let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
-        let s = Shape::new(TestShape::new()).set_transform(scaling(2.0, 2.0, 2.0));
+        let s = Shape::new(TestShape::new())
+            .set_transform(scaling(2.0, 2.0, 2.0))
+            .unwrap();
         let xs = s.intersect(&r);
         assert_approx_eq!(xs, [0.0, 0.0, -2.5, 0.0, 0.0, 0.5]);
     }
@@ -107,14 +358,19 @@ mod tests {
     #[test]
     fn test_intersecting_a_translated_shape_with_a_ray() {
         let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
-        let s = Shape::new(TestShape::new()).set_transform(translation(5.0, 0.0, 0.0));
+        let s = Shape::new(TestShape::new())
+            .set_transform(translation(5.0, 0.0, 0.0))
+            .unwrap();
         let xs = s.intersect(&r);
         assert_approx_eq!(xs, [-5.0, 0.0, -5.0, 0.0, 0.0, 1.0]);
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_computing_the_normal_on_a_translated_shape() {
-        let s = Shape::new(TestShape::new()).set_transform(translation(0.0, 1.0, 0.0));
+        let s = Shape::new(TestShape::new())
+            .set_transform(translation(0.0, 1.0, 0.0))
+            .unwrap();
         let n = s.normal_at(&Point::new(0.0, 1.70711, -0.70711));
         assert_approx_eq!(n, Vector::new(0.0, 0.70711, -0.70711));
     }
@@ -122,8 +378,111 @@ mod tests {
     #[test]
     fn test_computing_the_normal_on_a_transformed_sphere() {
         let s = Shape::new(TestShape::new())
-            .set_transform(scaling(1.0, 0.5, 1.0) * &rotation_z(std::f64::consts::PI / 5.0));
-        let n = s.normal_at(&Point::new(0.0, 2f64.sqrt() / 2.0, -2f64.sqrt() / 2.0));
+            .set_transform(scaling(1.0, 0.5, 1.0) * &rotation_z(crate::scalar::consts::PI / 5.0))
+            .unwrap();
+        let n = s.normal_at(&Point::new(
+            0.0,
+            Scalar::sqrt(2.0) / 2.0,
+            -Scalar::sqrt(2.0) / 2.0,
+        ));
         assert_approx_eq!(n, Vector::new(0.0, 0.97014, -0.24254));
     }
+
+    struct LiftedShape {}
+
+    impl LocalShape for LiftedShape {
+        fn local_intersect(&self, _ray: &Ray) -> Vec<Scalar> {
+            vec![]
+        }
+        fn local_normal_at(&self, _object_point: &Point) -> Vector {
+            Vector::new(0.0, 1.0, 0.0)
+        }
+        fn local_shadow_origin(&self, p: &Point) -> Point {
+            Point::new(p.x, p.y + 1.0, p.z)
+        }
+    }
+
+    #[test]
+    fn test_the_default_shadow_origin_is_the_point_itself() {
+        let s = Shape::new(TestShape::new())
+            .set_transform(translation(0.0, 1.0, 0.0))
+            .unwrap();
+        let p = Point::new(1.0, 2.0, 3.0);
+        assert_approx_eq!(s.shadow_origin(&p), p);
+    }
+
+    #[test]
+    fn test_the_shadow_origin_of_a_transformed_shape() {
+        let s = Shape::new(LiftedShape {})
+            .set_transform(scaling(2.0, 2.0, 2.0))
+            .unwrap();
+        let origin = s.shadow_origin(&Point::new(2.0, 0.0, 0.0));
+        assert_approx_eq!(origin, Point::new(2.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn test_alike_shapes_share_their_transform_and_material() {
+        let mut interner = Interner::default();
+        let mut shapes: Vec<Shape> = [1.0, 1.0, 2.0]
+            .into_iter()
+            .map(|x| {
+                Shape::new(TestShape::new())
+                    .set_transform(translation(x, 0.0, 0.0))
+                    .unwrap()
+                    .set_material(Material::new().set_ambient(x).unwrap())
+            })
+            .collect();
+        for s in &mut shapes {
+            s.intern(&mut interner);
+        }
+        assert!(Arc::ptr_eq(&shapes[0].placement, &shapes[1].placement));
+        assert!(Arc::ptr_eq(&shapes[0].material, &shapes[1].material));
+        assert!(!Arc::ptr_eq(&shapes[0].placement, &shapes[2].placement));
+        assert!(!Arc::ptr_eq(&shapes[0].material, &shapes[2].material));
+    }
+
+    #[test]
+    fn test_patterned_materials_are_not_shared() {
+        let mut interner = Interner::default();
+        let mut shapes: Vec<Shape> = (0..2)
+            .map(|_| {
+                Shape::new(TestShape::new()).set_material(
+                    Material::new()
+                        .set_pattern(|_: &Point| BLACK, IDENTITY_AFFINE)
+                        .unwrap(),
+                )
+            })
+            .collect();
+        for s in &mut shapes {
+            s.intern(&mut interner);
+        }
+        assert!(Arc::ptr_eq(&shapes[0].placement, &shapes[1].placement));
+        assert!(!Arc::ptr_eq(&shapes[0].material, &shapes[1].material));
+    }
+
+    #[test]
+    fn test_matching_names_and_tags() {
+        let s = Shape::new(TestShape::new())
+            .set_name("front wheel")
+            .add_tag("car")
+            .add_tag("car");
+        assert!(s.name_matches("front wheel"));
+        assert!(s.name_matches("*wheel"));
+        assert!(s.name_matches("f*t*"));
+        assert!(!s.name_matches("front"));
+        assert!(!s.name_matches("*wheel*s"));
+        assert!(!Shape::new(TestShape::new()).name_matches("*"));
+        assert!(s.has_tag("car"));
+        assert!(!s.has_tag("wheel"));
+        assert_eq!(s.get_tags(), ["car"]);
+    }
+
+    #[test]
+    fn test_a_non_invertible_transformation_is_an_error() {
+        let result = Shape::new(TestShape::new()).set_transform(scaling(1.0, 0.0, 1.0));
+        assert!(matches!(
+            result,
+            Err(RayTracerError::NonInvertibleTransform)
+        ));
+    }
 }
@@ -0,0 +1,164 @@
+use crate::color::Color;
+use crate::matrix::Matrix;
+use crate::scalar::Scalar;
+use crate::vector::Vector;
+use std::ops::{Add, Mul};
+use std::sync::OnceLock;
+
+// The visible range is split into this many equal bands.
+pub const BANDS: usize = 16;
+pub const MIN_WAVELENGTH: Scalar = 380.0;
+pub const MAX_WAVELENGTH: Scalar = 700.0;
+// Where an RGB color's spectrum switches from blue to green and from green
+// to red, in nanometers.
+const GREEN_FROM: Scalar = 490.0;
+const RED_FROM: Scalar = 590.0;
+
+// CIE XYZ to linear sRGB.
+const XYZ_TO_RGB: [[Scalar; 3]; 3] = [
+    [3.2406, -1.5372, -0.4986],
+    [-0.9689, 1.8758, 0.0415],
+    [0.0557, -0.2040, 1.0570],
+];
+
+// Light as its power in each band of the visible range, for colors that
+// depend on wavelength, such as the fringes of dispersion. Colors turn
+// into spectra by each of red, green and blue covering its own part of the
+// range, and spectra back into colors by how the eye responds to them,
+// calibrated so that a color comes back unchanged.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Spectrum {
+    pub bands: [Scalar; BANDS],
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Self {
+            bands: [0.0; BANDS],
+        }
+    }
+}
+
+// The wavelength in the middle of a band, in nanometers.
+pub fn band_wavelength(band: usize) -> Scalar {
+    let width = (MAX_WAVELENGTH - MIN_WAVELENGTH) / BANDS as Scalar;
+    MIN_WAVELENGTH + (band as Scalar + 0.5) * width
+}
+
+impl Spectrum {
+    pub fn from_color(color: Color) -> Self {
+        Self {
+            bands: std::array::from_fn(|band| {
+                let nm = band_wavelength(band);
+                if nm < GREEN_FROM {
+                    color.blue
+                } else if nm < RED_FROM {
+                    color.green
+                } else {
+                    color.red
+                }
+            }),
+        }
+    }
+    pub fn to_color(&self) -> Color {
+        let v = calibration() * &self.response();
+        Color::new(v.x, v.y, v.z)
+    }
+    // The uncalibrated linear sRGB the eye sees in the spectrum.
+    fn response(&self) -> Vector {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for (band, power) in self.bands.iter().enumerate() {
+            let [cx, cy, cz] = color_matching(band_wavelength(band));
+            x += power * cx;
+            y += power * cy;
+            z += power * cz;
+        }
+        Matrix::new(XYZ_TO_RGB) * &Vector::new(x, y, z)
+    }
+}
+
+// Undoes the response to the spectra of pure red, green and blue, so that
+// colors make the round trip through a spectrum unchanged.
+fn calibration() -> &'static Matrix {
+    static CALIBRATION: OnceLock<Matrix> = OnceLock::new();
+    CALIBRATION.get_or_init(|| {
+        let columns = [
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+        ]
+        .map(|c| Spectrum::from_color(c).response());
+        Matrix::new(std::array::from_fn(|i| columns.map(|v| [v.x, v.y, v.z][i])))
+            .inverse()
+            .unwrap()
+    })
+}
+
+// The CIE 1931 standard observer's color matching functions, by the
+// multi-lobe Gaussian fit of Wyman, Sloan and Shirley (2013).
+fn color_matching(nm: Scalar) -> [Scalar; 3] {
+    let g = |mu: Scalar, below: Scalar, above: Scalar| {
+        let t = (nm - mu) / if nm < mu { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    [
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    ]
+}
+
+impl Add for Spectrum {
+    type Output = Spectrum;
+
+    fn add(self, other: Spectrum) -> Self::Output {
+        Self {
+            bands: std::array::from_fn(|i| self.bands[i] + other.bands[i]),
+        }
+    }
+}
+
+impl Mul<Scalar> for Spectrum {
+    type Output = Spectrum;
+
+    fn mul(self, other: Scalar) -> Self::Output {
+        Self {
+            bands: self.bands.map(|power| power * other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+    use crate::color::WHITE;
+
+    #[test]
+    fn test_colors_survive_the_round_trip_through_a_spectrum() {
+        let c = Color::new(0.8, 0.3, 0.1);
+        assert_approx_eq!(Spectrum::from_color(c).to_color(), c);
+        assert_approx_eq!(Spectrum::from_color(WHITE).to_color(), WHITE);
+        let sum = Spectrum::from_color(c) + Spectrum::from_color(WHITE) * 0.5;
+        assert_approx_eq!(sum.to_color(), Color::new(1.3, 0.8, 0.6));
+    }
+
+    #[test]
+    fn test_single_bands_have_the_hues_of_the_rainbow() {
+        let line = |nm: Scalar| {
+            let band = (0..BANDS)
+                .find(|band| (band_wavelength(*band) - nm).abs() <= 10.0)
+                .unwrap();
+            let mut s = Spectrum::default();
+            s.bands[band] = 1.0;
+            s.to_color()
+        };
+        let violet = line(450.0);
+        assert!(violet.blue > violet.green && violet.blue > violet.red);
+        let green = line(530.0);
+        assert!(green.green > green.red && green.green > green.blue);
+        let red = line(650.0);
+        assert!(red.red > red.green && red.red > red.blue);
+    }
+}
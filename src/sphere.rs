@@ -1,8 +1,11 @@
+use crate::bounds::Bounds;
 use crate::shape::LocalShape;
 use crate::point::{Point, ORIGIN};
 use crate::ray::Ray;
+use crate::scalar::Scalar;
 use crate::vector::Vector;
 
+#[derive(Default)]
 pub struct Sphere {}
 
 impl Sphere {
@@ -12,7 +15,7 @@ impl Sphere {
 }
 
 impl LocalShape for Sphere {
-    fn local_intersect(&self, ray: &Ray) -> Vec<f64> {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Scalar> {
         let sphere_to_ray = ray.origin - &ORIGIN;
 
         let a = ray.direction.dot(&ray.direction);
@@ -33,6 +36,9 @@ impl LocalShape for Sphere {
     fn local_normal_at(&self, object_point: &Point) -> Vector {
         object_point - &ORIGIN
     }
+    fn local_bounds(&self) -> Bounds {
+        Bounds::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0))
+    }
 }
 
 #[cfg(test)]
@@ -105,7 +111,7 @@ mod tests {
     #[test]
     fn test_the_normal_on_a_sphere_at_a_nonaxial_point() {
         let s = Sphere::new();
-        let c = 3f64.sqrt() / 3.0;
+        let c = Scalar::sqrt(3.0) / 3.0;
         let n = s.local_normal_at(&Point::new(c, c, c));
         assert_approx_eq!(n, Vector::new(c, c, c));
     }
@@ -0,0 +1,264 @@
+use crate::canvas::{Canvas, PixelFormat};
+use crate::color::{Color, ColorSpace};
+use crate::scalar::Scalar;
+
+pub const DEFAULT_TILE_SIZE: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+// Splits a width x height image into square tiles in row-major order,
+// clipping those at the right and bottom edges.
+pub fn tile_rects(width: usize, height: usize, tile_size: usize) -> Vec<Rect> {
+    let tiles_x = width.div_ceil(tile_size);
+    let tiles_y = height.div_ceil(tile_size);
+    (0..tiles_x * tiles_y)
+        .map(|index| {
+            let x = (index % tiles_x) * tile_size;
+            let y = (index / tiles_x) * tile_size;
+            Rect::new(x, y, tile_size.min(width - x), tile_size.min(height - y))
+        })
+        .collect()
+}
+
+// The order tiles are rendered in. Spiral starts at the center of the
+// image and works outwards ring by ring, so a preview shows the subject
+// first. Hilbert follows a space-filling curve, keeping consecutive tiles
+// next to each other, which is kinder to caches than jumping back to the
+// start of each row.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TileOrder {
+    #[default]
+    Scanline,
+    Spiral,
+    Hilbert,
+}
+
+// The indices of the tiles of `tile_rects(width, height, tile_size)` in the
+// order they are to be rendered.
+pub fn tile_order(width: usize, height: usize, tile_size: usize, order: TileOrder) -> Vec<usize> {
+    let tiles_x = width.div_ceil(tile_size);
+    let tiles_y = height.div_ceil(tile_size);
+    let mut indices: Vec<usize> = (0..tiles_x * tiles_y).collect();
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            // the offset of each tile's center from the image's, in tiles
+            let offset = |index: usize| {
+                let center = |i: usize, tiles: usize| i as Scalar + 0.5 - tiles as Scalar / 2.0;
+                (
+                    center(index % tiles_x, tiles_x),
+                    center(index / tiles_x, tiles_y),
+                )
+            };
+            let key = |index: &usize| {
+                let (dx, dy) = offset(*index);
+                (dx.abs().max(dy.abs()).round(), dy.atan2(dx))
+            };
+            indices.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        }
+        TileOrder::Hilbert => {
+            let n = tiles_x.max(tiles_y).next_power_of_two();
+            indices.sort_by_key(|index| hilbert_index(n, index % tiles_x, index / tiles_x));
+        }
+    }
+    indices
+}
+
+// The distance along a Hilbert curve filling an n x n grid, n a power of
+// two, to the cell (x, y).
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        // rotate the quadrant so the curve continues where it left off
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+// A canvas split into square tiles, remembering which tiles have been
+// written to since the last call to `take_dirty_rects`.
+pub struct TiledCanvas {
+    canvas: Canvas,
+    tile_size: usize,
+    tiles_x: usize,
+    tiles_y: usize,
+    dirty: Vec<bool>,
+}
+
+impl TiledCanvas {
+    pub fn new(width: usize, height: usize, tile_size: usize) -> Self {
+        Self::with_format(width, height, tile_size, PixelFormat::default())
+    }
+    pub fn with_format(width: usize, height: usize, tile_size: usize, format: PixelFormat) -> Self {
+        let tiles_x = width.div_ceil(tile_size);
+        let tiles_y = height.div_ceil(tile_size);
+        Self {
+            canvas: Canvas::with_format(width, height, format),
+            tile_size,
+            tiles_x,
+            tiles_y,
+            dirty: vec![false; tiles_x * tiles_y],
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.canvas.width()
+    }
+    pub fn height(&self) -> usize {
+        self.canvas.height()
+    }
+    pub fn tile_size(&self) -> usize {
+        self.tile_size
+    }
+    pub fn tile_count(&self) -> usize {
+        self.tiles_x * self.tiles_y
+    }
+    pub fn tile_rect(&self, index: usize) -> Rect {
+        let x = (index % self.tiles_x) * self.tile_size;
+        let y = (index / self.tiles_x) * self.tile_size;
+        Rect::new(
+            x,
+            y,
+            self.tile_size.min(self.width() - x),
+            self.tile_size.min(self.height() - y),
+        )
+    }
+    pub fn tiles(&self) -> Vec<Rect> {
+        tile_rects(self.width(), self.height(), self.tile_size)
+    }
+    pub fn pixel_at(&self, x: usize, y: usize) -> Color {
+        self.canvas.pixel_at(x, y)
+    }
+    pub fn write_pixel(&mut self, x: usize, y: usize, c: Color) {
+        self.canvas.write_pixel(x, y, c);
+        self.mark_dirty(x, y);
+    }
+    pub fn write_alpha(&mut self, x: usize, y: usize, a: Scalar) {
+        self.canvas.write_alpha(x, y, a);
+        self.mark_dirty(x, y);
+    }
+    fn mark_dirty(&mut self, x: usize, y: usize) {
+        let index = (y / self.tile_size) * self.tiles_x + x / self.tile_size;
+        self.dirty[index] = true;
+    }
+    // Returns the tiles changed since the previous call, in row-major order.
+    pub fn take_dirty_rects(&mut self) -> Vec<Rect> {
+        let rects = (0..self.tile_count())
+            .filter(|i| self.dirty[*i])
+            .map(|i| self.tile_rect(i))
+            .collect();
+        self.dirty.fill(false);
+        rects
+    }
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.canvas.set_color_space(color_space);
+    }
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+    pub fn into_canvas(self) -> Canvas {
+        self.canvas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::color::WHITE;
+
+    #[test]
+    fn test_a_canvas_is_divided_into_tiles_clipped_at_the_edges() {
+        let c = TiledCanvas::new(10, 5, 4);
+        assert_eq!(c.tile_count(), 6);
+        assert_eq!(c.tile_rect(0), Rect::new(0, 0, 4, 4));
+        assert_eq!(c.tile_rect(2), Rect::new(8, 0, 2, 4));
+        assert_eq!(c.tile_rect(5), Rect::new(8, 4, 2, 1));
+    }
+
+    #[test]
+    fn test_tiles_in_scanline_order() {
+        assert_eq!(
+            tile_order(10, 5, 4, TileOrder::Scanline),
+            vec![0, 1, 2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn test_tiles_in_spiral_order_start_at_the_center() {
+        let order = tile_order(20, 20, 4, TileOrder::Spiral);
+        assert_eq!(order[0], 12);
+        // the ring around the center comes next
+        let mut ring = order[1..9].to_vec();
+        ring.sort();
+        assert_eq!(ring, vec![6, 7, 8, 11, 13, 16, 17, 18]);
+        let mut all = order.clone();
+        all.sort();
+        assert_eq!(all, (0..25).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_tiles_in_hilbert_order_are_always_adjacent() {
+        let order = tile_order(32, 32, 4, TileOrder::Hilbert);
+        assert_eq!(order.len(), 64);
+        for pair in order.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let distance = (a % 8).abs_diff(b % 8) + (a / 8).abs_diff(b / 8);
+            assert_eq!(distance, 1);
+        }
+    }
+
+    #[test]
+    fn test_a_new_tiled_canvas_has_no_dirty_tiles() {
+        let mut c = TiledCanvas::new(10, 5, 4);
+        assert!(c.take_dirty_rects().is_empty());
+    }
+
+    #[test]
+    fn test_writing_a_pixel_marks_its_tile_dirty() {
+        let mut c = TiledCanvas::new(10, 5, 4);
+        c.write_pixel(9, 4, WHITE);
+        c.write_pixel(1, 1, WHITE);
+        c.write_pixel(2, 3, WHITE);
+        assert_eq!(
+            c.take_dirty_rects(),
+            vec![Rect::new(0, 0, 4, 4), Rect::new(8, 4, 2, 1)]
+        );
+    }
+
+    #[test]
+    fn test_taking_dirty_rects_clears_them() {
+        let mut c = TiledCanvas::new(10, 5, 4);
+        c.write_pixel(5, 0, WHITE);
+        assert_eq!(c.take_dirty_rects(), vec![Rect::new(4, 0, 4, 4)]);
+        assert!(c.take_dirty_rects().is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::scalar::Scalar;
+use crate::tile::Rect;
+use std::time::Duration;
+
+// The heatmap's ramp from the quickest pixels to the slowest.
+const HEAT: [Color; 5] = [
+    Color::new(0.0, 0.0, 0.0),
+    Color::new(0.0, 0.0, 1.0),
+    Color::new(1.0, 0.0, 0.0),
+    Color::new(1.0, 1.0, 0.0),
+    Color::new(1.0, 1.0, 1.0),
+];
+
+// How long each pixel took to render, to find what blows the render budget.
+pub struct RenderTimes {
+    width: usize,
+    height: usize,
+    times: Vec<Duration>,
+}
+
+impl RenderTimes {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            times: vec![Duration::ZERO; width * height],
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn time_at(&self, x: usize, y: usize) -> Duration {
+        self.times[y * self.width + x]
+    }
+    pub fn write_time(&mut self, x: usize, y: usize, time: Duration) {
+        self.times[y * self.width + x] = time;
+    }
+    // The time spent on the pixels of `tile`, e.g. to compare tile sizes or
+    // find the tiles that hold up a render.
+    pub fn tile_time(&self, tile: &Rect) -> Duration {
+        (tile.y..tile.y + tile.height)
+            .flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.time_at(x, y))
+            .sum()
+    }
+    pub fn total(&self) -> Duration {
+        self.times.iter().sum()
+    }
+    // Each pixel's time relative to the slowest pixel's, from black for no
+    // time through blue, red and yellow to white for the slowest.
+    pub fn heatmap(&self) -> Canvas {
+        let slowest = self.times.iter().max().copied().unwrap_or_default();
+        let mut image = Canvas::new(self.width, self.height);
+        if slowest.is_zero() {
+            return image;
+        }
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let t = self.time_at(x, y).as_secs_f64() / slowest.as_secs_f64();
+                image.write_pixel(x, y, heat(t as Scalar));
+            }
+        }
+        image
+    }
+}
+
+fn heat(t: Scalar) -> Color {
+    let position = t.clamp(0.0, 1.0) * (HEAT.len() - 1) as Scalar;
+    let i = (position.floor() as usize).min(HEAT.len() - 2);
+    HEAT[i] + (HEAT[i + 1] - HEAT[i]) * (position - i as Scalar)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::approx_eq::{assert_approx_eq, ApproxEq};
+
+    #[test]
+    fn test_a_heatmap_of_render_times() {
+        let mut times = RenderTimes::new(4, 2);
+        times.write_time(0, 0, Duration::from_millis(8));
+        times.write_time(1, 0, Duration::from_millis(4));
+        times.write_time(3, 1, Duration::from_millis(2));
+        assert_eq!(times.total(), Duration::from_millis(14));
+        assert_eq!(
+            times.tile_time(&Rect::new(0, 0, 2, 2)),
+            Duration::from_millis(12)
+        );
+        let heatmap = times.heatmap();
+        assert_approx_eq!(heatmap.pixel_at(0, 0), Color::new(1.0, 1.0, 1.0));
+        assert_approx_eq!(heatmap.pixel_at(1, 0), Color::new(1.0, 0.0, 0.0));
+        assert_approx_eq!(heatmap.pixel_at(3, 1), Color::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(heatmap.pixel_at(2, 0), Color::new(0.0, 0.0, 0.0));
+    }
+}
@@ -1,10 +1,14 @@
-use crate::approx_eq::ApproxEq;
+use crate::approx_eq::{ApproxEq, EPSILON};
+use crate::error::{RayTracerError, Result};
 use crate::matrix::{Matrix, IDENTITY_MATRIX};
 use crate::point::Point;
+use crate::scalar::consts::PI;
+use crate::scalar::Scalar;
 use crate::vector::{Vector, ZERO};
 use std::ops::Mul;
 
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Affine {
     transform: Matrix,
     translate: Vector,
@@ -25,6 +29,37 @@ impl Affine {
     pub fn get_transform(&self) -> Matrix {
         self.transform
     }
+    pub fn get_translate(&self) -> Vector {
+        self.translate
+    }
+    // Places an object at `from` and rotates it so that its local `axis`
+    // points towards `to`. Fails if `to` is `from` or `axis` has no length.
+    pub fn look_at(from: &Point, to: &Point, axis: &Vector) -> Result<Self> {
+        let v = to - from;
+        if !(v.magnitude() >= EPSILON && axis.magnitude() >= EPSILON) {
+            return Err(RayTracerError::InvalidShape(
+                "look_at needs two distinct points and an axis".to_string(),
+            ));
+        }
+        let a = axis.normalize();
+        let d = v.normalize();
+        let cos = a.dot(&d).clamp(-1.0, 1.0);
+        let cross = a.cross(&d);
+        let rotation = if cross.magnitude() >= EPSILON {
+            rotation_axis_angle(&cross, cos.acos())
+        } else if cos > 0.0 {
+            IDENTITY_AFFINE
+        } else {
+            // pointing the opposite way; any axis perpendicular to `a` will do
+            let other = if a.x.abs() < 0.9 {
+                Vector::new(1.0, 0.0, 0.0)
+            } else {
+                Vector::new(0.0, 1.0, 0.0)
+            };
+            rotation_axis_angle(&a.cross(&other), PI)
+        };
+        Ok(translation(from.x, from.y, from.z) * &rotation)
+    }
     pub fn inverse(&self) -> Option<Self> {
         self.transform.inverse().map(|inv_trans| Self {
             transform: inv_trans,
@@ -43,7 +78,7 @@ impl Mul<&Point> for &Affine {
     type Output = Point;
 
     fn mul(self, rhs: &Point) -> Self::Output {
-        self.transform * rhs + &self.translate
+        *self * rhs
     }
 }
 
@@ -51,7 +86,7 @@ impl Mul<&Point> for Affine {
     type Output = Point;
 
     fn mul(self, rhs: &Point) -> Self::Output {
-        &self * rhs
+        self.transform * rhs + &self.translate
     }
 }
 
@@ -59,7 +94,7 @@ impl Mul<&Vector> for &Affine {
     type Output = Vector;
 
     fn mul(self, rhs: &Vector) -> Self::Output {
-        self.transform * rhs
+        *self * rhs
     }
 }
 
@@ -67,7 +102,7 @@ impl Mul<&Vector> for Affine {
     type Output = Vector;
 
     fn mul(self, rhs: &Vector) -> Self::Output {
-        &self * rhs
+        self.transform * rhs
     }
 }
 
@@ -75,10 +110,7 @@ impl Mul<&Affine> for &Affine {
     type Output = Affine;
 
     fn mul(self, rhs: &Affine) -> Self::Output {
-        Self::Output {
-            transform: self.transform * &rhs.transform,
-            translate: self.transform * &rhs.translate + &self.translate,
-        }
+        *self * rhs
     }
 }
 
@@ -86,22 +118,25 @@ impl Mul<&Affine> for Affine {
     type Output = Affine;
 
     fn mul(self, rhs: &Affine) -> Self::Output {
-        &self * rhs
+        Self::Output {
+            transform: self.transform * &rhs.transform,
+            translate: self.transform * &rhs.translate + &self.translate,
+        }
     }
 }
 
-pub fn translation(x: f64, y: f64, z: f64) -> Affine {
+pub fn translation(x: Scalar, y: Scalar, z: Scalar) -> Affine {
     Affine::new(IDENTITY_MATRIX, Vector::new(x, y, z))
 }
 
-pub fn scaling(x: f64, y: f64, z: f64) -> Affine {
+pub fn scaling(x: Scalar, y: Scalar, z: Scalar) -> Affine {
     Affine::new(
         Matrix::new([[x, 0.0, 0.0], [0.0, y, 0.0], [0.0, 0.0, z]]),
         ZERO,
     )
 }
 
-pub fn rotation_x(r: f64) -> Affine {
+pub fn rotation_x(r: Scalar) -> Affine {
     let cr = r.cos();
     let sr = r.sin();
     Affine::new(
@@ -110,7 +145,7 @@ pub fn rotation_x(r: f64) -> Affine {
     )
 }
 
-pub fn rotation_y(r: f64) -> Affine {
+pub fn rotation_y(r: Scalar) -> Affine {
     let cr = r.cos();
     let sr = r.sin();
     Affine::new(
@@ -119,7 +154,7 @@ pub fn rotation_y(r: f64) -> Affine {
     )
 }
 
-pub fn rotation_z(r: f64) -> Affine {
+pub fn rotation_z(r: Scalar) -> Affine {
     let cr = r.cos();
     let sr = r.sin();
     Affine::new(
@@ -128,32 +163,100 @@ pub fn rotation_z(r: f64) -> Affine {
     )
 }
 
-pub fn shearing(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Affine {
+pub fn rotation_axis_angle(axis: &Vector, r: Scalar) -> Affine {
+    let Vector { x, y, z } = axis.normalize();
+    let cr = r.cos();
+    let sr = r.sin();
+    let t = 1.0 - cr;
+    Affine::new(
+        Matrix::new([
+            [t * x * x + cr, t * x * y - sr * z, t * x * z + sr * y],
+            [t * x * y + sr * z, t * y * y + cr, t * y * z - sr * x],
+            [t * x * z - sr * y, t * y * z + sr * x, t * z * z + cr],
+        ]),
+        ZERO,
+    )
+}
+
+// Yaw turns about the y axis, pitch about the x axis and roll about the z
+// axis; roll is applied first and yaw last.
+pub fn rotation_euler(yaw: Scalar, pitch: Scalar, roll: Scalar) -> Affine {
+    rotation_y(yaw) * &rotation_x(pitch) * &rotation_z(roll)
+}
+
+pub fn shearing(xy: Scalar, xz: Scalar, yx: Scalar, yz: Scalar, zx: Scalar, zy: Scalar) -> Affine {
     Affine::new(
         Matrix::new([[1.0, xy, xz], [yx, 1.0, yz], [zx, zy, 1.0]]),
         ZERO,
     )
 }
 
-pub fn view_transform(from: &Point, to: &Point, up: &Vector) -> Affine {
-    let forward = (to - from).normalize();
-    let upn = up.normalize();
-    let left = forward.cross(&upn);
+// Fails if `from` and `to` are the same point. An `up` along the view
+// direction, or without length, is replaced by the y axis, or the z axis
+// when looking mostly up or down.
+pub fn view_transform(from: &Point, to: &Point, up: &Vector) -> Result<Affine> {
+    let v = to - from;
+    let distance = v.magnitude();
+    if distance.is_nan() || distance < EPSILON {
+        return Err(RayTracerError::InvalidCamera(
+            "the view must look at a point other than its own".to_string(),
+        ));
+    }
+    let forward = v.normalize();
+    let left = forward.cross(&up.normalize());
+    let left = if left.magnitude() >= EPSILON {
+        left
+    } else if forward.y.abs() > 0.9 {
+        forward.cross(&Vector::new(0.0, 0.0, 1.0))
+    } else {
+        forward.cross(&Vector::new(0.0, 1.0, 0.0))
+    };
     let true_up = left.cross(&forward);
-    Affine::new(
+    Ok(Affine::new(
         Matrix::new([
             [left.x, left.y, left.z],
             [true_up.x, true_up.y, true_up.z],
             [-forward.x, -forward.y, -forward.z],
         ]),
         ZERO
-    ) * &translation(-from.x, -from.y, -from.z)
+    ) * &translation(-from.x, -from.y, -from.z))
+}
+
+// The frame a transform given to a shape is expressed in. World applies it
+// after the shape's own transform, along the world's axes; Local applies it
+// before, along the shape's own axes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Space {
+    World,
+    Local,
+}
+
+// The point a transform is done about, in the coordinates of the space it is
+// applied in. Center is the middle of the bounds of whatever is transformed.
+#[derive(Copy, Clone, Debug)]
+pub enum Pivot {
+    Origin,
+    Center,
+    At(Point),
+}
+
+// `transform` done about `pivot` instead of the origin.
+pub fn about(pivot: &Point, transform: &Affine) -> Affine {
+    translation(pivot.x, pivot.y, pivot.z) * transform * &translation(-pivot.x, -pivot.y, -pivot.z)
+}
+
+// `current` followed by `transform`, given in `space`.
+pub fn compose_in(space: Space, current: &Affine, transform: &Affine) -> Affine {
+    match space {
+        Space::World => *transform * current,
+        Space::Local => *current * transform,
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use std::f64::consts::PI;
+    use crate::scalar::consts::PI;
     use super::*;
     use crate::approx_eq::{assert_approx_eq, ApproxEq};
     use crate::point::ORIGIN;
@@ -216,7 +319,7 @@ mod tests {
         let full_quarter = rotation_x(PI / 2.0);
         assert_approx_eq!(
             half_quarter * &p,
-            Point::new(0.0, 2f64.sqrt() / 2.0, 2f64.sqrt() / 2.0)
+            Point::new(0.0, Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0)
         );
         assert_approx_eq!(full_quarter * &p, Point::new(0.0, 0.0, 1.0));
     }
@@ -228,7 +331,7 @@ mod tests {
         let full_quarter = rotation_y(PI / 2.0);
         assert_approx_eq!(
             half_quarter * &p,
-            Point::new(2f64.sqrt() / 2.0, 0.0, 2f64.sqrt() / 2.0)
+            Point::new(Scalar::sqrt(2.0) / 2.0, 0.0, Scalar::sqrt(2.0) / 2.0)
         );
         assert_approx_eq!(full_quarter * &p, Point::new(1.0, 0.0, 0.0));
     }
@@ -240,11 +343,101 @@ mod tests {
         let full_quarter = rotation_z(PI / 2.0);
         assert_approx_eq!(
             half_quarter * &p,
-            Point::new(-2f64.sqrt() / 2.0, 2f64.sqrt() / 2.0, 0.0)
+            Point::new(-Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0, 0.0)
         );
         assert_approx_eq!(full_quarter * &p, Point::new(-1.0, 0.0, 0.0));
     }
 
+    #[test]
+    fn test_rotating_around_a_coordinate_axis_matches_the_axis_rotations() {
+        let r = PI / 3.0;
+        assert_approx_eq!(
+            rotation_axis_angle(&Vector::new(1.0, 0.0, 0.0), r),
+            rotation_x(r)
+        );
+        assert_approx_eq!(
+            rotation_axis_angle(&Vector::new(0.0, 2.0, 0.0), r),
+            rotation_y(r)
+        );
+        assert_approx_eq!(
+            rotation_axis_angle(&Vector::new(0.0, 0.0, 1.0), r),
+            rotation_z(r)
+        );
+    }
+
+    #[test]
+    fn test_rotating_a_point_around_an_arbitrary_axis() {
+        let transform = rotation_axis_angle(&Vector::new(1.0, 1.0, 1.0), 2.0 * PI / 3.0);
+        assert_approx_eq!(
+            transform * &Point::new(1.0, 0.0, 0.0),
+            Point::new(0.0, 1.0, 0.0)
+        );
+        assert_approx_eq!(
+            transform * &Point::new(0.0, 1.0, 0.0),
+            Point::new(0.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_euler_angles_apply_roll_then_pitch_then_yaw() {
+        let transform = rotation_euler(PI / 2.0, PI / 2.0, PI / 2.0);
+        let p = Point::new(1.0, 0.0, 0.0);
+        // roll: (0, 1, 0), pitch: (0, 0, 1), yaw: (1, 0, 0)
+        assert_approx_eq!(transform * &p, Point::new(1.0, 0.0, 0.0));
+        assert_approx_eq!(rotation_euler(PI / 4.0, 0.0, 0.0), rotation_y(PI / 4.0));
+    }
+
+    #[test]
+    fn test_look_at_points_the_local_y_axis_at_the_target() {
+        let from = Point::new(1.0, 2.0, 3.0);
+        let to = Point::new(1.0, 2.0, 8.0);
+        let t = Affine::look_at(&from, &to, &Vector::new(0.0, 1.0, 0.0)).unwrap();
+        assert_approx_eq!(t * &ORIGIN, from);
+        assert_approx_eq!(t * &Vector::new(0.0, 1.0, 0.0), Vector::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_look_at_along_the_axis_is_a_translation() {
+        let t = Affine::look_at(
+            &ORIGIN,
+            &Point::new(0.0, 3.0, 0.0),
+            &Vector::new(0.0, 1.0, 0.0),
+        )
+        .unwrap();
+        assert_approx_eq!(t, IDENTITY_AFFINE);
+    }
+
+    #[test]
+    fn test_look_at_opposite_the_axis_flips_it() {
+        let t = Affine::look_at(
+            &ORIGIN,
+            &Point::new(0.0, -3.0, 0.0),
+            &Vector::new(0.0, 1.0, 0.0),
+        )
+        .unwrap();
+        assert_approx_eq!(t * &Vector::new(0.0, 1.0, 0.0), Vector::new(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn test_look_at_with_a_custom_axis() {
+        let t = Affine::look_at(
+            &ORIGIN,
+            &Point::new(2.0, 2.0, 0.0),
+            &Vector::new(0.0, 0.0, 1.0),
+        )
+        .unwrap();
+        let v = t * &Vector::new(0.0, 0.0, 1.0);
+        assert_approx_eq!(v, Vector::new(Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0, 0.0));
+    }
+
+    #[test]
+    fn test_look_at_needs_a_direction() {
+        let up = Vector::new(0.0, 1.0, 0.0);
+        let p = Point::new(1.0, 2.0, 3.0);
+        assert!(Affine::look_at(&p, &p, &up).is_err());
+        assert!(Affine::look_at(&ORIGIN, &p, &ZERO).is_err());
+    }
+
     #[test]
     fn test_a_shearing_transformation_moves_x_in_proportion_to_y() {
         let transform = shearing(1.0, 0.0, 0.0, 0.0, 0.0, 0.0);
@@ -292,7 +485,7 @@ mod tests {
         let from = ORIGIN;
         let to = Point::new(0.0, 0.0, -1.0);
         let up = Vector::new(0.0, 1.0, 0.0);
-        let t = view_transform(&from, &to, &up);
+        let t = view_transform(&from, &to, &up).unwrap();
         assert_approx_eq!(t, IDENTITY_AFFINE);
     }
 
@@ -301,7 +494,7 @@ mod tests {
         let from = ORIGIN;
         let to = Point::new(0.0, 0.0, 1.0);
         let up = Vector::new(0.0, 1.0, 0.0);
-        let t = view_transform(&from, &to, &up);
+        let t = view_transform(&from, &to, &up).unwrap();
         assert_approx_eq!(t, scaling(-1.0, 1.0, -1.0));
     }
 
@@ -310,7 +503,7 @@ mod tests {
         let from = Point::new(0.0, 0.0, 8.0);
         let to = ORIGIN;
         let up = Vector::new(0.0, 1.0, 0.0);
-        let t = view_transform(&from, &to, &up);
+        let t = view_transform(&from, &to, &up).unwrap();
         assert_approx_eq!(t, translation(0.0, 0.0, -8.0));
     }
 
@@ -319,7 +512,7 @@ mod tests {
         let from = Point::new(1.0, 3.0, 2.0);
         let to = Point::new(4.0, -2.0, 8.0);
         let up = Vector::new(1.0, 1.0, 0.0);
-        let t = view_transform(&from, &to, &up);
+        let t = view_transform(&from, &to, &up).unwrap();
         assert_approx_eq!(
             t,
             Affine::new(
@@ -332,4 +525,48 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_a_view_needs_distinct_points() {
+        let p = Point::new(1.0, 3.0, 2.0);
+        let result = view_transform(&p, &p, &Vector::new(0.0, 1.0, 0.0));
+        assert!(matches!(result, Err(RayTracerError::InvalidCamera(_))));
+    }
+
+    #[test]
+    fn test_an_up_vector_along_the_view_is_replaced() {
+        let from = Point::new(0.0, 5.0, 0.0);
+        let t = view_transform(&from, &ORIGIN, &Vector::new(0.0, 1.0, 0.0)).unwrap();
+        let expected = view_transform(&from, &ORIGIN, &Vector::new(0.0, 0.0, 1.0)).unwrap();
+        assert_approx_eq!(t, expected);
+        let t = view_transform(&ORIGIN, &Point::new(0.0, 0.0, -1.0), &ZERO).unwrap();
+        assert_approx_eq!(t, IDENTITY_AFFINE);
+    }
+
+    #[test]
+    fn test_rotating_about_a_pivot() {
+        let t = about(&Point::new(1.0, 0.0, 0.0), &rotation_z(PI / 2.0));
+        assert_approx_eq!(t * &Point::new(1.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0));
+        assert_approx_eq!(t * &Point::new(2.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_composing_in_world_and_local_space() {
+        let current = translation(5.0, 0.0, 0.0);
+        let turn = rotation_z(PI / 2.0);
+        // about the world's origin, swinging the shape round it
+        let world = compose_in(Space::World, &current, &turn);
+        assert_approx_eq!(world * &ORIGIN, Point::new(0.0, 5.0, 0.0));
+        // about the shape's own origin, turning it in place
+        let local = compose_in(Space::Local, &current, &turn);
+        assert_approx_eq!(local * &ORIGIN, Point::new(5.0, 0.0, 0.0));
+        assert_approx_eq!(local * &Point::new(1.0, 0.0, 0.0), Point::new(5.0, 1.0, 0.0));
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_an_affine_transform_round_trips_through_json() {
+        let t = rotation_euler(0.1, 0.2, 0.3) * &translation(1.0, 2.0, 3.0);
+        let json = serde_json::to_string(&t).unwrap();
+        assert_approx_eq!(serde_json::from_str::<Affine>(&json).unwrap(), t);
+    }
 }
@@ -0,0 +1,31 @@
+use crate::scalar::Scalar;
+
+// The length of one unit of scene coordinates, so that parameters measured
+// in meters (fog density, focal distance, ambient occlusion radius) keep
+// their meaning whatever units a model was authored in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Units {
+    #[default]
+    Meters,
+    Centimeters,
+    Millimeters,
+    Inches,
+    Feet,
+}
+
+impl Units {
+    pub fn meters(self) -> Scalar {
+        match self {
+            Units::Meters => 1.0,
+            Units::Centimeters => 0.01,
+            Units::Millimeters => 0.001,
+            Units::Inches => 0.0254,
+            Units::Feet => 0.3048,
+        }
+    }
+}
@@ -1,25 +1,29 @@
 use crate::approx_eq::ApproxEq;
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use crate::scalar::{Float, Scalar};
+use std::ops::{Add, Div, Index, IndexMut, Mul, Neg, Sub};
 
 #[derive(Debug, Copy, Clone)]
-pub struct Vector {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vector3<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
 }
 
+pub type Vector = Vector3<Scalar>;
+
 pub const ZERO: Vector = Vector {
     x: 0.0,
     y: 0.0,
     z: 0.0,
 };
 
-impl Vector {
-    pub fn new(x: f64, y: f64, z: f64) -> Self {
+impl<T: Float> Vector3<T> {
+    pub fn new(x: T, y: T, z: T) -> Self {
         Self { x, y, z }
     }
 
-    pub fn magnitude(&self) -> f64 {
+    pub fn magnitude(&self) -> T {
         self.dot(self).sqrt()
     }
 
@@ -27,7 +31,7 @@ impl Vector {
         self / self.magnitude()
     }
 
-    pub fn dot(&self, other: &Self) -> f64 {
+    pub fn dot(&self, other: &Self) -> T {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
 
@@ -40,16 +44,72 @@ impl Vector {
     }
 }
 
-impl ApproxEq for Vector {
+impl<T: Float> ApproxEq for Vector3<T> {
     fn approx_eq(&self, other: &Self) -> bool {
         self.x.approx_eq(&other.x) && self.y.approx_eq(&other.y) && self.z.approx_eq(&other.z)
     }
 }
 
-impl Add<&Vector> for &Vector {
-    type Output = Vector;
+impl<T> From<[T; 3]> for Vector3<T> {
+    fn from([x, y, z]: [T; 3]) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl<T> From<(T, T, T)> for Vector3<T> {
+    fn from((x, y, z): (T, T, T)) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl<T> From<Vector3<T>> for [T; 3] {
+    fn from(v: Vector3<T>) -> Self {
+        [v.x, v.y, v.z]
+    }
+}
+
+impl<T> From<Vector3<T>> for (T, T, T) {
+    fn from(v: Vector3<T>) -> Self {
+        (v.x, v.y, v.z)
+    }
+}
+
+impl<T> Index<usize> for Vector3<T> {
+    type Output = T;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("axis index out of range: {}", axis),
+        }
+    }
+}
+
+impl<T> IndexMut<usize> for Vector3<T> {
+    fn index_mut(&mut self, axis: usize) -> &mut Self::Output {
+        match axis {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("axis index out of range: {}", axis),
+        }
+    }
+}
+
+impl<T: Float> Add<&Vector3<T>> for &Vector3<T> {
+    type Output = Vector3<T>;
+
+    fn add(self, rhs: &Vector3<T>) -> Self::Output {
+        *self + rhs
+    }
+}
+
+impl<T: Float> Add<&Vector3<T>> for Vector3<T> {
+    type Output = Vector3<T>;
 
-    fn add(self, rhs: &Vector) -> Self::Output {
+    fn add(self, rhs: &Self) -> Self::Output {
         Self::Output {
             x: self.x + rhs.x,
             y: self.y + rhs.y,
@@ -58,18 +118,18 @@ impl Add<&Vector> for &Vector {
     }
 }
 
-impl Add<&Vector> for Vector {
-    type Output = Vector;
+impl<T: Float> Sub<&Vector3<T>> for &Vector3<T> {
+    type Output = Vector3<T>;
 
-    fn add(self, rhs: &Self) -> Self::Output {
-        &self + rhs
+    fn sub(self, rhs: &Vector3<T>) -> Self::Output {
+        *self - rhs
     }
 }
 
-impl Sub<&Vector> for &Vector {
-    type Output = Vector;
+impl<T: Float> Sub<&Vector3<T>> for Vector3<T> {
+    type Output = Vector3<T>;
 
-    fn sub(self, rhs: &Vector) -> Self::Output {
+    fn sub(self, rhs: &Self) -> Self::Output {
         Self::Output {
             x: self.x - rhs.x,
             y: self.y - rhs.y,
@@ -78,16 +138,8 @@ impl Sub<&Vector> for &Vector {
     }
 }
 
-impl Sub<&Vector> for Vector {
-    type Output = Vector;
-
-    fn sub(self, rhs: &Self) -> Self::Output {
-        &self - rhs
-    }
-}
-
-impl Neg for Vector {
-    type Output = Vector;
+impl<T: Float> Neg for Vector3<T> {
+    type Output = Vector3<T>;
 
     fn neg(self) -> Self::Output {
         Self::Output {
@@ -98,10 +150,10 @@ impl Neg for Vector {
     }
 }
 
-impl Mul<f64> for &Vector {
-    type Output = Vector;
+impl<T: Float> Mul<T> for &Vector3<T> {
+    type Output = Vector3<T>;
 
-    fn mul(self, other: f64) -> Self::Output {
+    fn mul(self, other: T) -> Self::Output {
         Self::Output {
             x: self.x * other,
             y: self.y * other,
@@ -110,7 +162,8 @@ impl Mul<f64> for &Vector {
     }
 }
 
-impl Mul<&Vector> for f64 {
+// The orphan rule rules out a generic scalar on the left.
+impl Mul<&Vector> for Scalar {
     type Output = Vector;
 
     fn mul(self, other: &Vector) -> Self::Output {
@@ -118,10 +171,10 @@ impl Mul<&Vector> for f64 {
     }
 }
 
-impl Div<f64> for &Vector {
-    type Output = Vector;
+impl<T: Float> Div<T> for &Vector3<T> {
+    type Output = Vector3<T>;
 
-    fn div(self, other: f64) -> Self::Output {
+    fn div(self, other: T) -> Self::Output {
         Self::Output {
             x: self.x / other,
             y: self.y / other,
@@ -148,6 +201,32 @@ mod tests {
         assert_approx_eq!(p.z, 3.0);
     }
 
+    #[test]
+    fn test_converting_vectors_to_and_from_arrays_and_tuples() {
+        let v = Vector::from([1.0, 2.0, 3.0]);
+        assert_approx_eq!(v, Vector::from((1.0, 2.0, 3.0)));
+        assert_eq!(<[Scalar; 3]>::from(v), [1.0, 2.0, 3.0]);
+        assert_eq!(<(Scalar, Scalar, Scalar)>::from(v), (1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn test_vectors_of_another_scalar_type() {
+        let v = Vector3::<f32>::new(3.0, 0.0, 4.0);
+        assert_eq!(v.magnitude(), 5.0);
+        assert_approx_eq!(
+            v.cross(&Vector3::new(0.0, 1.0, 0.0)),
+            Vector3::new(-4.0, 0.0, 3.0)
+        );
+    }
+
+    #[test]
+    fn test_indexing_a_vector_by_axis() {
+        let mut v = Vector::new(1.0, 2.0, 3.0);
+        assert_eq!([v[0], v[1], v[2]], [1.0, 2.0, 3.0]);
+        v[2] = -3.0;
+        assert_approx_eq!(v, Vector::new(1.0, 2.0, -3.0));
+    }
+
     // #[test]
     // fn test_adding_two_tuples() {
     //     let a1 = Tuple::new(3.0, -2.0, 5.0, 1.0);
@@ -228,13 +307,13 @@ mod tests {
     #[test]
     fn test_the_magnitude_of_vector4() {
         let v = Vector::new(1.0, 2.0, 3.0);
-        assert_approx_eq!(v.magnitude(), (14f64).sqrt());
+        assert_approx_eq!(v.magnitude(), Scalar::sqrt(14.0));
     }
 
     #[test]
     fn test_the_magnitude_of_vector5() {
         let v = Vector::new(-1.0, -2.0, -3.0);
-        assert_approx_eq!(v.magnitude(), (14f64).sqrt());
+        assert_approx_eq!(v.magnitude(), Scalar::sqrt(14.0));
     }
 
     #[test]
@@ -248,7 +327,11 @@ mod tests {
         let v = Vector::new(1.0, 2.0, 3.0);
         assert_approx_eq!(
             v.normalize(),
-            Vector::new(1.0 / 14f64.sqrt(), 2.0 / 14f64.sqrt(), 3.0 / 14f64.sqrt())
+            Vector::new(
+                1.0 / Scalar::sqrt(14.0),
+                2.0 / Scalar::sqrt(14.0),
+                3.0 / Scalar::sqrt(14.0)
+            )
         );
     }
 
@@ -285,7 +368,7 @@ mod tests {
     #[test]
     fn test_reflecting_a_vector_off_a_slanted_surface() {
         let v = Vector::new(0.0, -1.0, 0.0);
-        let n = Vector::new(2f64.sqrt() / 2.0, 2f64.sqrt() / 2.0, 0.0);
+        let n = Vector::new(Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0, 0.0);
         let r = reflect(&v, &n);
         assert_approx_eq!(r, Vector::new(1.0, 0.0, 0.0));
     }
@@ -0,0 +1,38 @@
+use crate::scene::SceneDescription;
+use wasm_bindgen::prelude::*;
+
+// Renders a JSON `SceneDescription` at the given size and returns its pixels
+// as RGBA bytes, ready for an ImageData. Errors come back as JS strings.
+#[wasm_bindgen]
+pub fn render(scene_json: &str, width: usize, height: usize) -> Result<Vec<u8>, JsValue> {
+    render_rgba(scene_json, width, height).map_err(|message| JsValue::from_str(&message))
+}
+
+// No threads in a plain wasm32 module, so everything renders on the caller's.
+fn render_rgba(scene_json: &str, width: usize, height: usize) -> Result<Vec<u8>, String> {
+    let scene: SceneDescription = serde_json::from_str(scene_json).map_err(|e| e.to_string())?;
+    let (world, camera) = scene.build().map_err(|e| e.to_string())?;
+    let camera = camera
+        .set_size(width, height)
+        .map_err(|e| e.to_string())?
+        .set_threads(1);
+    Ok(camera.render(&world).to_rgba8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rendering_a_json_scene_to_rgba() {
+        let scene = r#"{"camera": {"hsize": 100, "vsize": 100, "field_of_view": 1.0}}"#;
+        let rgba = render_rgba(scene, 4, 3).unwrap();
+        assert_eq!(rgba.len(), 4 * 4 * 3);
+        assert_eq!(&rgba[..4], &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_invalid_json_is_reported() {
+        assert!(render_rgba("{", 4, 3).is_err());
+    }
+}
@@ -1,39 +1,295 @@
+use crate::accelerator::{Accelerator, AcceleratorKind, TraversalCounters, TraversalStats};
+use crate::aov::Aovs;
 use crate::approx_eq::EPSILON;
-use crate::color::{Color, BLACK};
+use crate::bounds::Bounds;
+use crate::bvh::BvhBuilder;
+use crate::camera::Camera;
+use crate::color::{Color, BLACK, MAGENTA, WHITE};
+use crate::error::{RayTracerError, Result};
 use crate::intersection::{Intersection, Intersections};
-use crate::light::PointLight;
-use crate::point::Point;
+use crate::light::{OccluderCache, PointLight};
+use crate::material::{Material, MaterialField, ValidationPolicy};
+use crate::packet::{lanes, RayPacket};
+use crate::point::{Point, ORIGIN};
 use crate::ray::Ray;
-use crate::shape::Shape;
+use crate::sampler::Sampler;
+use crate::scalar::Scalar;
+use crate::shape::{Interner, Shape};
+use crate::sphere::Sphere;
+use crate::transform::{about, compose_in, scaling, translation, Affine, Pivot, Space};
+use crate::units::Units;
 use crate::vector::{reflect, Vector};
+use std::cell::RefCell;
+use std::sync::{Arc, OnceLock};
 
 pub const RECURSION_LIMIT: isize = 5;
+// With Russian roulette rays end at random, this only guards the stack.
+pub const ROULETTE_RECURSION_LIMIT: isize = 100;
+
+thread_local! {
+    // One occluder cache per render thread, kept across all the pixels and
+    // tiles it renders. It only saves intersections, so which thread renders
+    // a pixel still doesn't change its color.
+    static OCCLUDERS: RefCell<OccluderCache> = RefCell::new(OccluderCache::new());
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct AmbientOcclusion {
+    pub radius: Scalar,
+    pub samples: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ToonShading {
+    pub bands: usize,
+    pub edge_threshold: Scalar,
+}
+
+impl ToonShading {
+    // Rounds the cosine of the light's angle up to the next band, so any lit
+    // surface stays lit.
+    fn band(&self, light_dot_normal: Scalar) -> Scalar {
+        (light_dot_normal * self.bands as Scalar).ceil() / self.bands as Scalar
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RussianRoulette {
+    pub start_depth: usize,
+    pub min_survival: Scalar,
+}
+
+// Borrows the world to adjust a shape after it has been added, e.g.
+// `world.add_shape(shape).set_name("floor").set_transform(t)?`.
+pub struct ShapeHandle<'w, 'a> {
+    world: &'w mut World<'a>,
+    id: usize,
+}
+
+impl<'a> ShapeHandle<'_, 'a> {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    pub fn set_transform(self, transform: Affine) -> Result<Self> {
+        self.world.move_shapes(&[(self.id, transform)])?;
+        self.world.shape_moved(self.id);
+        Ok(self)
+    }
+    pub fn set_material(self, material: Material<'a>) -> Self {
+        self.world.shapes[self.id].update_material(material);
+        self.world.intern_shape(self.id);
+        self
+    }
+    pub fn set_name(self, name: &str) -> Self {
+        self.world.shapes[self.id].update_name(name);
+        self
+    }
+    pub fn add_tag(self, tag: &str) -> Self {
+        self.world.shapes[self.id].update_tags(tag);
+        self
+    }
+    pub fn set_hidden(self, hidden: bool) -> Self {
+        self.world.set_hidden(self.id, hidden);
+        self
+    }
+}
+
+// The shapes picked by `World::select`, to adjust them all at once, e.g.
+// `world.select(|s| s.has_tag("wheel")).transform(&t)?.set_hidden(false)`.
+pub struct Selection<'w, 'a> {
+    world: &'w mut World<'a>,
+    ids: Vec<usize>,
+}
+
+impl<'a> Selection<'_, 'a> {
+    pub fn ids(&self) -> &[usize] {
+        &self.ids
+    }
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+    pub fn set_hidden(self, hidden: bool) -> Self {
+        for id in &self.ids {
+            self.world.set_hidden(*id, hidden);
+        }
+        self
+    }
+    // Every selected shape gets the one material, shared rather than copied.
+    pub fn set_material(self, material: Material<'a>) -> Self {
+        let material = Arc::new(material);
+        for id in &self.ids {
+            self.world.shapes[*id].share_material(&material);
+            self.world.intern_shape(*id);
+        }
+        self
+    }
+    pub fn add_tag(self, tag: &str) -> Self {
+        for id in &self.ids {
+            self.world.shapes[*id].update_tags(tag);
+        }
+        self
+    }
+    // Applies `transform` on top of each shape's own, moving them as one.
+    pub fn transform(self, transform: &Affine) -> Result<Self> {
+        self.transform_in(transform, Space::World, Pivot::Origin)
+    }
+    // Applies `transform` about `pivot` in `space`. In world space the pivot
+    // is shared, so Center turns the selection about the middle of its
+    // bounds; in local space each shape is turned about its own. Either
+    // every shape moves or, if any would get a singular transform, none do.
+    pub fn transform_in(self, transform: &Affine, space: Space, pivot: Pivot) -> Result<Self> {
+        let selection_bounds = self
+            .ids
+            .iter()
+            .map(|id| self.world.shapes[*id].bounds())
+            .filter(|bounds| bounds.is_finite())
+            .fold(Bounds::empty(), |acc, bounds| acc.merge(&bounds));
+        let moves: Vec<_> = self
+            .ids
+            .iter()
+            .map(|id| {
+                let shape = &self.world.shapes[*id];
+                let pivot = match (pivot, space) {
+                    (Pivot::Origin, _) => ORIGIN,
+                    (Pivot::At(p), _) => p,
+                    (Pivot::Center, Space::World) => center(&selection_bounds),
+                    (Pivot::Center, Space::Local) => center(&shape.local_bounds()),
+                };
+                let t = compose_in(space, shape.get_transform(), &about(&pivot, transform));
+                (*id, t)
+            })
+            .collect();
+        self.world.move_shapes(&moves)?;
+        for id in &self.ids {
+            self.world.shape_moved(*id);
+        }
+        Ok(self)
+    }
+}
+
+// The middle of finite bounds, or else the origin.
+fn center(bounds: &Bounds) -> Point {
+    if bounds.is_finite() {
+        bounds.centroid()
+    } else {
+        ORIGIN
+    }
+}
+
+// Places the unit sphere showing a visible light.
+fn light_transform(light: &PointLight) -> Affine {
+    let p = light.get_position();
+    let r = light.get_radius();
+    translation(p.x, p.y, p.z) * &scaling(r, r, r)
+}
+
+// A change to a scene between two frames of an animation. Moving shapes
+// this way refits the acceleration structures instead of rebuilding them.
+#[derive(Copy, Clone, Debug)]
+pub enum SceneDelta {
+    // a shape's new transform, by object id
+    MoveShape(usize, Affine),
+    SetMaterialField(usize, MaterialField),
+    // a light's new position, by its index in `World::get_lights`
+    MoveLight(usize, Point),
+    MoveCamera(Affine),
+}
+
+// Called for every hit before it is shaded; a color it returns is used
+// instead of the lighting (and of any reflected or refracted rays).
+pub type ShadingHook<'a> = Box<dyn Fn(&ShadingInfo) -> Option<Color> + Send + Sync + 'a>;
 
 pub struct World<'a> {
     lights: Vec<PointLight>,
+    // the shape showing each visible light
+    light_shapes: Vec<Option<usize>>,
     shapes: Vec<Shape<'a>>,
+    // Static shapes never move, so their tree is only rebuilt when shapes
+    // are added. Dynamic shapes get their own small tree that is cheap to
+    // rebuild after `set_shape_transform`; a static shape moved once its
+    // tree is built joins them.
+    dynamic: Vec<bool>,
+    // shares equal transforms and materials between shapes
+    interner: Interner<'a>,
+    accelerator: AcceleratorKind,
+    bvh_builder: BvhBuilder,
+    static_accelerator: OnceLock<Accelerator>,
+    dynamic_accelerator: OnceLock<Accelerator>,
+    traversal_counters: Option<TraversalCounters>,
     handle_shadows: bool,
+    surface_offset: Scalar,
+    min_hit_distance: Scalar,
+    shadow_ray_budget: Option<usize>,
+    ambient_occlusion: Option<AmbientOcclusion>,
+    toon_shading: Option<ToonShading>,
+    russian_roulette: Option<RussianRoulette>,
+    nan_guard: bool,
+    shading_hook: Option<ShadingHook<'a>>,
+    units: Units,
+    scale: Scalar,
+    colored_shadows: bool,
+    contact_hardening: Scalar,
 }
 
-struct Computations {
-    object_id: usize,
-    over_point: Point,
-    under_point: Point,
-    eyev: Vector,
-    normalv: Vector,
-    reflectv: Vector,
-    n1: f64,
-    n2: f64,
-    #[cfg(test)]
-    t: f64,
-    #[cfg(test)]
-    point: Point,
-    #[cfg(test)]
-    inside: bool,
+// Everything known about a ray's hit, for shading it. Returned publicly by
+// `World::shade_ray` so custom integrators can build on the intersection code.
+pub struct ShadingInfo {
+    pub object_id: usize,
+    pub t: Scalar,
+    pub point: Point,
+    pub over_point: Point,
+    pub under_point: Point,
+    pub shadow_point: Point,
+    pub eyev: Vector,
+    // the normal to shade with, facing the eye
+    pub normalv: Vector,
+    // the normal of the surface itself, on the eye's side, which the over,
+    // under and shadow points are offset along
+    pub geometric_normalv: Vector,
+    pub reflectv: Vector,
+    // at the ray's wavelength, if it has one
+    pub n1: Scalar,
+    pub n2: Scalar,
+    pub inside: bool,
+    pub wavelength: Option<Scalar>,
 }
 
-impl Computations {
-    fn schlick(&self) -> f64 {
+// How reflection and refraction share the light at the surface of a
+// transparent, reflective material. Schlick's approximation is the book's
+// and cheaper; the exact Fresnel equations (averaged over both
+// polarizations) differ at grazing angles on high index materials.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Fresnel {
+    #[default]
+    Schlick,
+    Exact,
+}
+
+impl ShadingInfo {
+    pub fn reflectance(&self, fresnel: Fresnel) -> Scalar {
+        match fresnel {
+            Fresnel::Schlick => self.schlick(),
+            Fresnel::Exact => self.fresnel(),
+        }
+    }
+    pub fn fresnel(&self) -> Scalar {
+        let cos_i = self.eyev.dot(&self.normalv);
+        let n = self.n1 / self.n2;
+        let sin2_t = n * n * (1.0 - cos_i * cos_i);
+        if sin2_t > 1.0 {
+            return 1.0;
+        }
+        let cos_t = (1.0 - sin2_t).sqrt();
+        let (n1, n2) = (self.n1, self.n2);
+        let s = (n1 * cos_i - n2 * cos_t) / (n1 * cos_i + n2 * cos_t);
+        let p = (n1 * cos_t - n2 * cos_i) / (n1 * cos_t + n2 * cos_i);
+        (s * s + p * p) / 2.0
+    }
+    pub fn schlick(&self) -> Scalar {
         // find the cosine of the angle between the eye and normal vectors
         let mut cos = self.eyev.dot(&self.normalv);
         // total internal reflection can only occur if n1 > n2
@@ -53,155 +309,929 @@ impl Computations {
     }
 }
 
+// Enters the volume of `object_id`, or leaves it if already inside.
+fn toggle_container(containers: &mut Vec<usize>, object_id: usize) {
+    match containers.iter().position(|c| *c == object_id) {
+        Some(p) => {
+            containers.remove(p);
+        }
+        None => containers.push(object_id),
+    };
+}
+
+impl Default for World<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a> World<'a> {
     pub fn new() -> Self {
         Self {
             lights: vec![],
+            light_shapes: vec![],
             shapes: vec![],
+            dynamic: vec![],
+            interner: Interner::default(),
+            accelerator: AcceleratorKind::default(),
+            bvh_builder: BvhBuilder::new(),
+            static_accelerator: OnceLock::new(),
+            dynamic_accelerator: OnceLock::new(),
+            traversal_counters: None,
             handle_shadows: true,
+            surface_offset: EPSILON,
+            min_hit_distance: 0.0,
+            shadow_ray_budget: None,
+            ambient_occlusion: None,
+            toon_shading: None,
+            russian_roulette: None,
+            nan_guard: false,
+            shading_hook: None,
+            units: Units::Meters,
+            scale: 1.0,
+            colored_shadows: false,
+            contact_hardening: 0.0,
         }
     }
+    // What the scene's coordinates are measured in, for parameters given in
+    // meters.
+    pub fn set_units(&mut self, units: Units) {
+        self.units = units;
+    }
+    // Multiplies the units further, e.g. 10 for a model built at a tenth of
+    // its real size.
+    pub fn set_scale(&mut self, scale: Scalar) {
+        self.scale = scale;
+    }
+    pub fn meters_per_unit(&self) -> Scalar {
+        self.units.meters() * self.scale
+    }
+    // Distance rays leaving a surface start off it, along the geometric
+    // normal: reflected and shadow rays above it, refracted rays below.
+    // Raise it when coarse geometry self-shadows.
+    pub fn set_surface_offset(&mut self, offset: Scalar) {
+        self.surface_offset = offset;
+    }
+    // Intersections closer than this to a ray's origin are ignored, both for
+    // finding the hit and for shadow rays. Unlike the surface offset this
+    // applies along the ray, so it also catches rays that leave a surface at
+    // a grazing angle, which the offset barely lifts off it; and as it skips
+    // hits on every ray, it is 0 unless set.
+    pub fn set_min_hit_distance(&mut self, distance: Scalar) {
+        self.min_hit_distance = distance;
+    }
+    // Caps the number of lights tested for shadows at each shading point.
+    // The lights with the largest estimated contribution (intensity over
+    // squared distance) are tested; the rest are assumed to be unblocked,
+    // so shadows come out brighter than they should by up to what those
+    // lights add. Black lights are never tested, as they add nothing.
+    pub fn set_shadow_ray_budget(&mut self, budget: Option<usize>) {
+        self.shadow_ray_budget = budget;
+    }
+    // Lets transparent shapes pass light on to the shadows behind them,
+    // filtered channel by channel by their transparency and transmission
+    // tint, so a red glass pane casts a red shadow. Otherwise every shape
+    // blocks light alike.
+    pub fn set_colored_shadows(&mut self, colored_shadows: bool) {
+        self.colored_shadows = colored_shadows;
+    }
+    // Lets lights without a radius cast shadows as if they had this one, so
+    // a shadow is sharp where it touches its occluder and its penumbra
+    // widens with the distance between occluder and receiver. The light
+    // itself still shines from a point. 0 keeps their shadows hard.
+    pub fn set_contact_hardening(&mut self, radius: Scalar) {
+        self.contact_hardening = radius.max(0.0);
+    }
+    // The radius shadow rays towards `light` spread over.
+    fn shadow_radius(&self, light: &PointLight) -> Scalar {
+        if light.get_radius() > 0.0 {
+            light.get_radius()
+        } else {
+            self.contact_hardening
+        }
+    }
+    // Scale the ambient term by the fraction of `samples` short rays (within
+    // `radius` meters) leaving the hemisphere above a hit without striking
+    // anything.
+    pub fn set_ambient_occlusion(&mut self, radius: Scalar, samples: usize) {
+        self.ambient_occlusion = if samples > 0 && radius > 0.0 {
+            Some(AmbientOcclusion { radius, samples })
+        } else {
+            None
+        };
+    }
+    // Non-photorealistic shading: diffuse light falls off in `bands` flat
+    // steps instead of smoothly, and surfaces seen at a grazing angle (the
+    // cosine between normal and eye below `edge_threshold`) are drawn black
+    // as an outline. Zero bands turns it off.
+    pub fn set_toon_shading(&mut self, bands: usize, edge_threshold: Scalar) {
+        self.toon_shading = if bands > 0 {
+            Some(ToonShading {
+                bands,
+                edge_threshold,
+            })
+        } else {
+            None
+        };
+    }
+    // Stops secondary rays at random instead of after a fixed number of
+    // bounces. From `start_depth` bounces on, a reflected or refracted ray
+    // is traced with a probability equal to the fraction of color it
+    // carries, but at least `min_survival`, and the rays that survive are
+    // weighted up so the image stays unbiased.
+    pub fn set_russian_roulette(&mut self, start_depth: usize, min_survival: Scalar) {
+        self.russian_roulette = Some(RussianRoulette {
+            start_depth,
+            min_survival,
+        });
+    }
+    pub fn clear_russian_roulette(&mut self) {
+        self.russian_roulette = None;
+    }
+    // How many bounces a camera ray may take, to pass to `trace`.
+    pub fn recursion_limit(&self) -> isize {
+        if self.russian_roulette.is_some() {
+            ROULETTE_RECURSION_LIMIT
+        } else {
+            RECURSION_LIMIT
+        }
+    }
+    // Debugging aid: report every shading point whose own lighting isn't
+    // finite on stderr, and return magenta from `trace` for rays whose color
+    // isn't, instead of letting NaNs spread through the image.
+    pub fn set_nan_guard(&mut self, nan_guard: bool) {
+        self.nan_guard = nan_guard;
+    }
+    // Lets a callback override the color of any hit, e.g. to flat color
+    // objects by id for a coverage (clown) pass or to highlight one object.
+    pub fn set_shading_hook(
+        &mut self,
+        hook: impl Fn(&ShadingInfo) -> Option<Color> + Send + Sync + 'a,
+    ) {
+        self.shading_hook = Some(Box::new(hook));
+    }
+    pub fn clear_shading_hook(&mut self) {
+        self.shading_hook = None;
+    }
     pub fn add_light(&mut self, light: PointLight) {
+        let shape = if light.is_visible() && light.get_radius() > 0.0 {
+            let sphere = Shape::new(Sphere::new())
+                .set_transform(light_transform(&light))
+                .unwrap()
+                .set_casts_shadow(false);
+            Some(self.add_dynamic_shape(sphere).id())
+        } else {
+            None
+        };
+        self.light_shapes.push(shape);
         self.lights.push(light);
     }
-    pub fn add_shape(&mut self, object: Shape<'a>) -> usize {
+    pub fn add_shape(&mut self, object: Shape<'a>) -> ShapeHandle<'_, 'a> {
+        self.push_shape(object, false)
+    }
+    pub fn add_dynamic_shape(&mut self, object: Shape<'a>) -> ShapeHandle<'_, 'a> {
+        self.push_shape(object, true)
+    }
+    fn push_shape(&mut self, object: Shape<'a>, dynamic: bool) -> ShapeHandle<'_, 'a> {
         let id = self.shapes.len();
         self.shapes.push(object);
-        id
+        self.dynamic.push(dynamic);
+        self.intern_shape(id);
+        self.shape_moved(id);
+        ShapeHandle { world: self, id }
+    }
+    pub fn shape(&mut self, object_id: usize) -> ShapeHandle<'_, 'a> {
+        ShapeHandle {
+            world: self,
+            id: object_id,
+        }
+    }
+    pub fn shape_count(&self) -> usize {
+        self.shapes.len()
+    }
+    pub fn get_shape(&self, object_id: usize) -> &Shape<'a> {
+        &self.shapes[object_id]
+    }
+    pub fn get_lights(&self) -> &[PointLight] {
+        &self.lights
+    }
+    // The index of the light the shape shows, if it is a visible light's.
+    pub fn light_showing(&self, object_id: usize) -> Option<usize> {
+        self.light_shapes
+            .iter()
+            .position(|id| *id == Some(object_id))
+    }
+    // The bounds of every shape; planes and other unbounded shapes are
+    // left out, as are hidden ones and visible lights.
+    pub fn bounds(&self) -> Bounds {
+        self.shapes
+            .iter()
+            .enumerate()
+            .filter(|(id, shape)| !shape.is_hidden() && self.light_showing(*id).is_none())
+            .map(|(_, shape)| shape.bounds())
+            .filter(|bounds| bounds.is_finite())
+            .fold(Bounds::empty(), |acc, bounds| acc.merge(&bounds))
+    }
+    // Applies the changes in order. Static shapes that move become dynamic,
+    // as with `set_shape_transform`; only the trees of the shapes that moved
+    // are updated. Every change is checked first, so if any is invalid none
+    // are applied.
+    pub fn apply_deltas(&mut self, camera: &mut Camera, deltas: &[SceneDelta]) -> Result<()> {
+        for delta in deltas {
+            self.check_delta(camera, delta)?;
+        }
+        let mut moved = [false; 2];
+        for delta in deltas {
+            match *delta {
+                SceneDelta::MoveShape(id, transform) => {
+                    self.move_shapes(&[(id, transform)])?;
+                    moved[self.dynamic[id] as usize] = true;
+                }
+                SceneDelta::SetMaterialField(id, field) => {
+                    if !self.shapes[id].modify_material(|m| m.apply_field(field)) {
+                        return Err(RayTracerError::InvalidMaterial(format!(
+                            "shape {} shares a patterned material, which can't be copied",
+                            id
+                        )));
+                    }
+                    self.intern_shape(id);
+                }
+                SceneDelta::MoveLight(index, position) => {
+                    self.lights[index].update_position(position);
+                    if let Some(id) = self.light_shapes[index] {
+                        self.shapes[id].update_transform(light_transform(&self.lights[index]))?;
+                        self.intern_shape(id);
+                        moved[1] = true;
+                    }
+                }
+                SceneDelta::MoveCamera(transform) => {
+                    *camera = camera.set_transform(transform)?;
+                }
+            }
+        }
+        self.refit_accelerators(moved);
+        Ok(())
+    }
+    fn check_delta(&self, camera: &Camera, delta: &SceneDelta) -> Result<()> {
+        let shape = |id: usize| {
+            self.shapes
+                .get(id)
+                .ok_or_else(|| RayTracerError::InvalidShape(format!("no shape {}", id)))
+        };
+        match *delta {
+            SceneDelta::MoveShape(id, transform) => {
+                shape(id)?;
+                transform
+                    .inverse()
+                    .ok_or(RayTracerError::NonInvertibleTransform)?;
+            }
+            SceneDelta::SetMaterialField(id, field) => {
+                field.validate(ValidationPolicy::Error)?;
+                if !shape(id)?.can_modify_material() {
+                    return Err(RayTracerError::InvalidMaterial(format!(
+                        "shape {} shares a patterned material, which can't be copied",
+                        id
+                    )));
+                }
+            }
+            SceneDelta::MoveLight(index, _) => {
+                if index >= self.lights.len() {
+                    return Err(RayTracerError::InvalidLight(format!("no light {}", index)));
+                }
+            }
+            SceneDelta::MoveCamera(transform) => {
+                camera.set_transform(transform)?;
+            }
+        }
+        Ok(())
+    }
+    // Moves the vertices of a mesh, e.g. one skinned or morphing between
+    // frames; see `Mesh::set_vertices`. The mesh's own BVH and the world's
+    // are refitted.
+    pub fn deform_shape(
+        &mut self,
+        object_id: usize,
+        vertices: Vec<Point>,
+        normals: Option<Vec<Vector>>,
+    ) -> Result<()> {
+        self.shapes[object_id].deform(vertices, normals)?;
+        let mut moved = [false; 2];
+        moved[self.dynamic[object_id] as usize] = true;
+        self.refit_accelerators(moved);
+        Ok(())
+    }
+    // Refits the static and dynamic trees, as flagged, to the shapes' bounds,
+    // dropping them to be rebuilt when that isn't possible.
+    fn refit_accelerators(&mut self, moved: [bool; 2]) {
+        let shapes = &self.shapes;
+        let accelerators = [&mut self.static_accelerator, &mut self.dynamic_accelerator];
+        for (accelerator, moved) in accelerators.into_iter().zip(moved) {
+            if !moved {
+                continue;
+            }
+            let refitted = accelerator
+                .get_mut()
+                .is_none_or(|a| a.refit(|id| shapes[id].bounds()));
+            if !refitted {
+                accelerator.take();
+            }
+        }
+    }
+    pub fn find_shape(&self, name: &str) -> Option<usize> {
+        self.shapes.iter().position(|s| s.get_name() == Some(name))
+    }
+    // The ids of the shapes for which `predicate` holds, e.g.
+    // `|s| s.name_matches("wheel*")` or `|s| s.get_material().is_reflective()`.
+    pub fn find_shapes(&self, predicate: impl Fn(&Shape<'a>) -> bool) -> Vec<usize> {
+        (0..self.shapes.len())
+            .filter(|id| predicate(&self.shapes[*id]))
+            .collect()
+    }
+    pub fn select(&mut self, predicate: impl Fn(&Shape<'a>) -> bool) -> Selection<'_, 'a> {
+        let ids = self.find_shapes(predicate);
+        Selection { world: self, ids }
+    }
+    fn set_hidden(&mut self, object_id: usize, hidden: bool) {
+        if self.shapes[object_id].is_hidden() != hidden {
+            self.shapes[object_id].update_hidden(hidden);
+            self.shape_moved(object_id);
+        }
+    }
+    fn intern_shape(&mut self, object_id: usize) {
+        self.shapes[object_id].intern(&mut self.interner);
+    }
+    fn shape_moved(&mut self, object_id: usize) {
+        if self.dynamic[object_id] {
+            self.dynamic_accelerator.take();
+        } else {
+            self.static_accelerator.take();
+        }
+    }
+    // Gives shapes new transforms, all of them or, if any is singular, none.
+    // A static shape that moves once its tree has been built becomes dynamic,
+    // so the static tree never has to follow it; while the scene is still
+    // being assembled it simply stays static.
+    fn move_shapes(&mut self, moves: &[(usize, Affine)]) -> Result<()> {
+        if moves.iter().any(|(_, t)| t.inverse().is_none()) {
+            return Err(RayTracerError::NonInvertibleTransform);
+        }
+        let static_built = self.static_accelerator.get().is_some();
+        for (id, transform) in moves {
+            if static_built && !self.dynamic[*id] {
+                self.dynamic[*id] = true;
+                self.static_accelerator.take();
+                self.dynamic_accelerator.take();
+            }
+            self.shapes[*id].update_transform(*transform)?;
+            self.intern_shape(*id);
+        }
+        Ok(())
+    }
+    pub fn set_shape_transform(&mut self, object_id: usize, transform: Affine) -> Result<()> {
+        self.move_shapes(&[(object_id, transform)])?;
+        self.shape_moved(object_id);
+        Ok(())
+    }
+    // Which structure finds the shapes a ray may hit; both give the same
+    // images, at different speeds.
+    pub fn set_accelerator(&mut self, kind: AcceleratorKind) {
+        self.accelerator = kind;
+        self.static_accelerator.take();
+        self.dynamic_accelerator.take();
+    }
+    // How the BVH over the world's shapes is built, when it is the
+    // accelerator in use.
+    pub fn set_bvh_builder(&mut self, builder: BvhBuilder) {
+        self.bvh_builder = builder;
+        self.static_accelerator.take();
+        self.dynamic_accelerator.take();
+    }
+    // Counts the work done by the accelerator from now on, to compare
+    // them; turning it on again restarts the counts.
+    pub fn set_traversal_stats(&mut self, enabled: bool) {
+        self.traversal_counters = enabled.then(TraversalCounters::default);
+    }
+    pub fn traversal_stats(&self) -> Option<TraversalStats> {
+        self.traversal_counters.as_ref().map(|c| c.get())
+    }
+    fn accelerators(&self) -> [&Accelerator; 2] {
+        let build = |dynamic: bool| {
+            Accelerator::new(
+                self.accelerator,
+                &self.bvh_builder,
+                self.shapes
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, shape)| self.dynamic[*i] == dynamic && !shape.is_hidden())
+                    .map(|(i, shape)| (i, shape.bounds())),
+            )
+        };
+        [
+            self.static_accelerator.get_or_init(|| build(false)),
+            self.dynamic_accelerator.get_or_init(|| build(true)),
+        ]
+    }
+    fn count_traversal(&self, stats: &TraversalStats) {
+        if let Some(counters) = &self.traversal_counters {
+            counters.add(stats);
+        }
     }
     fn intersect(&self, ray: &Ray) -> Intersections {
-        Intersections::new(self.shapes.iter().enumerate().flat_map(|(i, obj)| {
-            obj.intersect(ray)
-                .iter()
-                .map(|t| Intersection::new(*t, i))
-                .collect::<Vec<_>>()
-        }))
+        let mut intersections = vec![];
+        let mut stats = TraversalStats {
+            rays: 1,
+            ..TraversalStats::default()
+        };
+        for accelerator in self.accelerators() {
+            accelerator.for_each(ray, &mut stats, |i| {
+                let xs = self.shapes[i].intersect(ray);
+                intersections.extend(xs.iter().map(|t| Intersection::new(*t, i)));
+            });
+        }
+        self.count_traversal(&stats);
+        Intersections::new(intersections)
+    }
+    // The intersections of each of the packet's rays, as `intersect` finds
+    // them.
+    fn intersect_packet(&self, packet: &RayPacket) -> Vec<Intersections> {
+        let rays = packet.rays();
+        let mut intersections = vec![vec![]; rays.len()];
+        let mut stats = TraversalStats {
+            rays: rays.len(),
+            ..TraversalStats::default()
+        };
+        for accelerator in self.accelerators() {
+            accelerator.for_each_packet(packet, &mut stats, |i, mask| {
+                for lane in lanes(mask) {
+                    let xs = self.shapes[i].intersect(&rays[lane]);
+                    intersections[lane].extend(xs.iter().map(|t| Intersection::new(*t, i)));
+                }
+            });
+        }
+        self.count_traversal(&stats);
+        intersections.into_iter().map(Intersections::new).collect()
+    }
+    // Finds any shape hit by the ray in [min_hit_distance, distance).
+    fn occluder(&self, ray: &Ray, distance: Scalar) -> Option<usize> {
+        let mut occluder = None;
+        let mut stats = TraversalStats {
+            rays: 1,
+            ..TraversalStats::default()
+        };
+        for accelerator in self.accelerators() {
+            let found = accelerator.any(ray, &mut stats, |i| {
+                if !self.shapes[i].casts_shadow() {
+                    return false;
+                }
+                let xs = self.shapes[i].intersect(ray);
+                let blocks = xs
+                    .iter()
+                    .any(|t| *t >= self.min_hit_distance && *t < distance);
+                if blocks {
+                    occluder = Some(i);
+                }
+                blocks
+            });
+            if found {
+                break;
+            }
+        }
+        self.count_traversal(&stats);
+        occluder
     }
     fn prepare_computations(
         &self,
         intersections: Intersections,
         intersection_index: usize,
         ray: &Ray,
-    ) -> Computations {
+    ) -> ShadingInfo {
         let intersections: Vec<Intersection> = Vec::from(intersections);
         let intersection = intersections[intersection_index];
         let point = ray.position(intersection.t);
         let eyev = -ray.direction;
-        let nv = self.shapes[intersection.object_id].normal_at(&point);
+        let shape = &self.shapes[intersection.object_id];
+        let nv = shape.normal_at(&point);
         let inside = nv.dot(&eyev) < 0.0;
         let normalv = if inside { -nv } else { nv };
+        let gv = shape.geometric_normal_at(&point);
+        let geometric_normalv = if gv.dot(&eyev) < 0.0 { -gv } else { gv };
         let reflectv = reflect(&ray.direction, &normalv);
-        let over_point = point + &(&normalv * EPSILON);
-        let under_point = point - &(&normalv * EPSILON);
+        let offset = &geometric_normalv * self.surface_offset;
+        let over_point = point + &offset;
+        let under_point = point - &offset;
+        let shadow_point = shape.shadow_origin(&point) + &offset;
         let mut containers: Vec<usize> = vec![];
-        let mut n1 = 1.0;
-        let mut n2 = 1.0;
-        for (index, i) in intersections.iter().enumerate() {
-            if index == intersection_index {
-                if let Some(object_id) = containers.last() {
-                    n1 = self.shapes[*object_id]
-                        .get_material()
-                        .get_refractive_index();
-                }
-            }
-            match containers.iter().position(|c| *c == i.object_id) {
-                Some(p) => {
-                    containers.remove(p);
-                }
-                None => containers.push(i.object_id),
-            };
-            if index == intersection_index {
-                if let Some(object_id) = containers.last() {
-                    n2 = self.shapes[*object_id]
-                        .get_material()
-                        .get_refractive_index();
-                }
-                break;
-            }
+        for i in &intersections[..intersection_index] {
+            toggle_container(&mut containers, i.object_id);
         }
-        Computations {
+        let n1 = self.medium_index(&containers, ray.wavelength);
+        toggle_container(&mut containers, intersection.object_id);
+        let n2 = self.medium_index(&containers, ray.wavelength);
+        ShadingInfo {
             object_id: intersection.object_id,
             over_point,
             under_point,
+            shadow_point,
             eyev,
             normalv,
+            geometric_normalv,
             reflectv,
             n1,
             n2,
-            #[cfg(test)]
             t: intersection.t,
-            #[cfg(test)]
             point,
-            #[cfg(test)]
             inside,
+            wavelength: ray.wavelength,
+        }
+    }
+    // The volume a ray inside `containers` travels through: the one with the
+    // highest priority, or the one entered last among equals.
+    fn medium_index(&self, containers: &[usize], wavelength: Option<Scalar>) -> Scalar {
+        containers
+            .iter()
+            .map(|id| self.shapes[*id].get_material())
+            .max_by_key(|material| material.get_priority())
+            .map_or(1.0, |material| material.refractive_index_at(wavelength))
+    }
+    // The first hit from `first` on that isn't inside a volume of higher
+    // priority than the surface hit, such as the part of a glass's wall
+    // where the water it holds overlaps.
+    fn true_hit_index(&self, intersections: &Intersections, first: usize) -> Option<usize> {
+        let priority = |id: usize| self.shapes[id].get_material().get_priority();
+        let mut containers: Vec<usize> = vec![];
+        for (index, i) in intersections.iter().enumerate() {
+            let hidden = containers
+                .iter()
+                .any(|c| *c != i.object_id && priority(*c) > priority(i.object_id));
+            if index >= first && !hidden {
+                return Some(index);
+            }
+            toggle_container(&mut containers, i.object_id);
         }
+        None
     }
-    fn shade_hit(&self, comps: &Computations, remaining: isize) -> Color {
+    fn shade_hit(&self, comps: &ShadingInfo, remaining: isize, sampler: &mut Sampler) -> Color {
+        self.shade_hit_aovs(comps, remaining, sampler).total()
+    }
+    fn shade_hit_aovs(&self, comps: &ShadingInfo, remaining: isize, sampler: &mut Sampler) -> Aovs {
+        if let Some(color) = self.shading_hook.as_ref().and_then(|hook| hook(comps)) {
+            return Aovs {
+                diffuse: color,
+                ..Aovs::new()
+            };
+        }
+        if let Some(light) = self.light_showing(comps.object_id) {
+            return Aovs {
+                diffuse: self.lights[light].get_intensity(),
+                ..Aovs::new()
+            };
+        }
+        if let Some(toon) = self.toon_shading {
+            if comps.normalv.dot(&comps.eyev) < toon.edge_threshold {
+                return Aovs::new();
+            }
+        }
         let shape = &self.shapes[comps.object_id];
-        let material = shape.get_material();
+        let material = self.material_at(comps);
+        let occlusion = self.ambient_visibility(comps, sampler);
+        let vertex_color = shape.color_at(&comps.point);
 
-        let mut surface = BLACK;
-        for light in &self.lights {
-            let shadowed = self.handle_shadows && self.is_shadowed(light, &comps.over_point);
-            let color = material.lighting(
-                &light,
+        let casts_shadow = self.shadow_casting_lights(&comps.shadow_point);
+        let mut aovs = Aovs::new();
+        for (index, (light, casts_shadow)) in self.lights.iter().zip(casts_shadow).enumerate() {
+            let visibility = if casts_shadow {
+                self.light_filter(light, index, &comps.shadow_point, sampler)
+            } else {
+                WHITE
+            };
+            let mut c = material.light_contribution(
+                light,
                 shape.get_inverse_transform(),
                 &comps.over_point,
                 &comps.eyev,
                 &comps.normalv,
-                shadowed,
+                false,
             );
-            surface = surface + color;
+            if let Some(tint) = &vertex_color {
+                c.ambient = c.ambient * tint;
+                c.diffuse = c.diffuse * tint;
+            }
+            if let Some(toon) = self.toon_shading {
+                let light_dot_normal = light
+                    .vector_from(&comps.over_point)
+                    .normalize()
+                    .dot(&comps.normalv);
+                if light_dot_normal > 0.0 {
+                    c.diffuse = c.diffuse * (toon.band(light_dot_normal) / light_dot_normal);
+                }
+            }
+            aovs.ambient = aovs.ambient + c.ambient * occlusion;
+            aovs.diffuse = aovs.diffuse + c.diffuse * &visibility;
+            aovs.specular = aovs.specular + c.specular * &visibility;
+            aovs.rim = aovs.rim + c.rim * &visibility;
+            aovs.shadow = aovs.shadow + (c.diffuse + c.specular + c.rim) * &(WHITE - visibility);
         }
 
-        let reflected = self.reflected_color(comps, remaining);
-        let refracted = self.refracted_color(comps, remaining);
+        if self.nan_guard {
+            self.check_finite(comps, &aovs);
+        }
+
+        aovs.reflection = self.reflected_color(comps, remaining, sampler);
+        aovs.refraction = self.refracted_color(comps, remaining, sampler);
 
-        if material.is_reflective() && material.is_transparent() {
-            let reflectance = comps.schlick();
-            return surface + reflected * reflectance + refracted * (1.0 - reflectance);
+        if let Some(film) = material.get_thin_film() {
+            let cos = comps.eyev.dot(&comps.normalv);
+            let reflectance = film.reflectance(comps.n1, comps.n2, cos, comps.wavelength);
+            aovs.reflection = aovs.reflection * &reflectance;
+            aovs.refraction = aovs.refraction * &(WHITE - reflectance);
+        } else if material.is_reflective() && material.is_transparent() {
+            let reflectance = comps.reflectance(sampler.get_fresnel());
+            aovs.reflection = aovs.reflection * reflectance;
+            aovs.refraction = aovs.refraction * (1.0 - reflectance);
         }
 
-        surface + reflected + refracted
+        aovs
     }
-    pub fn color_at(&self, ray: &Ray, remaining: isize) -> Color {
-        let intersections = self.intersect(ray);
-        if let Some(intersection_index) = intersections.hit_index() {
-            let comps = self.prepare_computations(intersections, intersection_index, ray);
-            self.shade_hit(&comps, remaining)
-        } else {
-            BLACK
+    // Reports a shading point whose surface terms (everything but the
+    // reflected and refracted rays, which report themselves) aren't finite.
+    fn check_finite(&self, comps: &ShadingInfo, surface: &Aovs) {
+        if surface.total().is_finite() {
+            return;
+        }
+        let ray = Ray::new(comps.point + &(&comps.eyev * comps.t), -comps.eyev);
+        eprintln!(
+            "non-finite color {:?} on object {} for ray {:?}",
+            surface.total(),
+            comps.object_id,
+            ray
+        );
+    }
+    pub fn color_at(&self, ray: &Ray, remaining: isize, sampler: &mut Sampler) -> Color {
+        match self.shade_ray(ray) {
+            Some(comps) => self.shade_hit(&comps, remaining, sampler),
+            None => BLACK,
+        }
+    }
+    // Like color_at, but None when the ray hits nothing.
+    pub fn trace(&self, ray: &Ray, remaining: isize, sampler: &mut Sampler) -> Option<Color> {
+        self.trace_aovs(ray, remaining, sampler)
+            .map(|aovs| aovs.total())
+    }
+    // Like trace, but with the color split into its components. Reflected
+    // and refracted rays contribute their full color to those passes.
+    pub fn trace_aovs(&self, ray: &Ray, remaining: isize, sampler: &mut Sampler) -> Option<Aovs> {
+        let comps = self.shade_ray(ray)?;
+        Some(self.hit_aovs(&comps, remaining, sampler))
+    }
+    // The components of a hit from `shade_ray` or `shade_packet`, as
+    // `trace_aovs` gives them.
+    pub fn hit_aovs(&self, comps: &ShadingInfo, remaining: isize, sampler: &mut Sampler) -> Aovs {
+        let aovs = self.shade_hit_aovs(comps, remaining, sampler);
+        if self.nan_guard && !aovs.total().is_finite() {
+            // all in one pass so the beauty pass shows magenta too
+            return Aovs {
+                diffuse: MAGENTA,
+                ..Aovs::new()
+            };
+        }
+        aovs
+    }
+    pub fn shade_ray(&self, ray: &Ray) -> Option<ShadingInfo> {
+        self.shade_intersections(self.intersect(ray), ray)
+    }
+    // `shade_ray` for up to PACKET_SIZE coherent rays at once, such as
+    // camera rays through neighbouring pixels.
+    pub fn shade_packet(&self, rays: &[Ray]) -> Vec<Option<ShadingInfo>> {
+        self.intersect_packet(&RayPacket::new(rays))
+            .into_iter()
+            .zip(rays)
+            .map(|(intersections, ray)| self.shade_intersections(intersections, ray))
+            .collect()
+    }
+    fn shade_intersections(&self, intersections: Intersections, ray: &Ray) -> Option<ShadingInfo> {
+        let first = intersections.hit_index_after(self.min_hit_distance)?;
+        let intersection_index = self.true_hit_index(&intersections, first)?;
+        Some(self.prepare_computations(intersections, intersection_index, ray))
+    }
+    fn shadow_casting_lights(&self, point: &Point) -> Vec<bool> {
+        let brightness = |light: &PointLight| {
+            let i = light.get_intensity();
+            i.red + i.green + i.blue
+        };
+        let mut casts_shadow: Vec<bool> = self.lights.iter().map(|l| brightness(l) > 0.0).collect();
+        let mut order: Vec<usize> = (0..self.lights.len())
+            .filter(|i| casts_shadow[*i])
+            .collect();
+        let Some(budget) = self.shadow_ray_budget else {
+            return casts_shadow;
+        };
+        if budget >= order.len() {
+            return casts_shadow;
+        }
+        // a light right at the point would otherwise divide by zero
+        let estimate = |light: &PointLight| {
+            let v = light.vector_from(point);
+            brightness(light) / v.dot(&v).max(EPSILON)
+        };
+        order.sort_by(|a, b| {
+            let (ea, eb) = (estimate(&self.lights[*a]), estimate(&self.lights[*b]));
+            eb.total_cmp(&ea)
+        });
+        for i in &order[budget..] {
+            casts_shadow[*i] = false;
         }
+        casts_shadow
     }
-    fn is_shadowed(&self, light: &PointLight, point: &Point) -> bool {
+    // Fraction of the light reaching `point`; only lights with a radius give
+    // values between 0 and 1. `index` is the light's place in `lights`.
+    fn light_visibility(
+        &self,
+        light: &PointLight,
+        index: usize,
+        point: &Point,
+        sampler: &mut Sampler,
+    ) -> Scalar {
+        if !self.handle_shadows {
+            return 1.0;
+        }
+        let shadowed = |v: &Vector| {
+            OCCLUDERS
+                .with_borrow_mut(|occluders| self.is_shadowed_along(index, point, v, occluders))
+        };
+        let radius = self.shadow_radius(light);
+        if radius <= 0.0 {
+            return if shadowed(&light.vector_from(point)) {
+                0.0
+            } else {
+                1.0
+            };
+        }
+        let samples = light.get_shadow_samples();
+        let set = sampler.point_set();
+        let lit = (0..samples)
+            .filter(|&i| {
+                let v = light.sample_vector_within(point, radius, set.point(i as u64, sampler));
+                !shadowed(&v)
+            })
+            .count();
+        lit as Scalar / samples as Scalar
+    }
+    // The share of the light's color reaching `point`: its visibility, or
+    // with colored shadows what transparent shapes on the way let through.
+    fn light_filter(
+        &self,
+        light: &PointLight,
+        index: usize,
+        point: &Point,
+        sampler: &mut Sampler,
+    ) -> Color {
+        if !self.colored_shadows || !self.handle_shadows {
+            return WHITE * self.light_visibility(light, index, point, sampler);
+        }
+        let radius = self.shadow_radius(light);
+        if radius <= 0.0 {
+            return self.transmittance_along(point, &light.vector_from(point));
+        }
+        let samples = light.get_shadow_samples();
+        let set = sampler.point_set();
+        let total = (0..samples).fold(BLACK, |total, i| {
+            let v = light.sample_vector_within(point, radius, set.point(i as u64, sampler));
+            total + self.transmittance_along(point, &v)
+        });
+        total * (1.0 / samples as Scalar)
+    }
+    // The product of the transmission of every shape crossed by `v` from
+    // `point`, black as soon as an opaque one is.
+    fn transmittance_along(&self, point: &Point, v: &Vector) -> Color {
+        let distance = v.magnitude();
+        let ray = Ray::new(*point, v.normalize());
+        let mut filter = WHITE;
+        let mut stats = TraversalStats {
+            rays: 1,
+            ..TraversalStats::default()
+        };
+        for accelerator in self.accelerators() {
+            let blocked = accelerator.any(&ray, &mut stats, |i| {
+                if !self.shapes[i].casts_shadow() {
+                    return false;
+                }
+                let xs = self.shapes[i].intersect(&ray);
+                if !xs
+                    .iter()
+                    .any(|t| *t >= self.min_hit_distance && *t < distance)
+                {
+                    return false;
+                }
+                let material = self.shapes[i].get_material();
+                if !material.is_transparent() {
+                    return true;
+                }
+                filter = material.scale_transparency(&filter);
+                false
+            });
+            if blocked {
+                filter = BLACK;
+                break;
+            }
+        }
+        self.count_traversal(&stats);
+        filter
+    }
+    pub fn is_shadowed(&self, light: &PointLight, point: &Point) -> bool {
         let v = light.vector_from(point);
+        // an empty cache has nothing for any light index
+        self.is_shadowed_along(0, point, &v, &mut OccluderCache::new())
+    }
+    fn is_shadowed_along(
+        &self,
+        light: usize,
+        point: &Point,
+        v: &Vector,
+        occluders: &mut OccluderCache,
+    ) -> bool {
         let distance = v.magnitude();
         let direction = v.normalize();
 
         let r = Ray::new(*point, direction);
-        let intersections = self.intersect(&r);
 
-        if let Some(intersection_index) = intersections.hit_index() {
-            let intersection = Vec::from(intersections)[intersection_index];
-            if intersection.t < distance {
+        // the cached shape must pass the same tests as in `occluder`, where
+        // hidden shapes are left out of the accelerators
+        let cached = occluders.get(light).and_then(|id| self.shapes.get(id));
+        if let Some(shape) = cached {
+            let blocks = |t: &Scalar| *t >= self.min_hit_distance && *t < distance;
+            if !shape.is_hidden() && shape.casts_shadow() && shape.intersect(&r).iter().any(blocks)
+            {
                 return true;
             }
         }
+
+        if let Some(object_id) = self.occluder(&r, distance) {
+            occluders.set(light, object_id);
+            return true;
+        }
         false
     }
-    fn reflected_color(&self, comps: &Computations, remaining: isize) -> Color {
-        let material = self.shapes[comps.object_id].get_material();
-        if !material.is_reflective() || remaining <= 0 {
+    fn ambient_visibility(&self, comps: &ShadingInfo, sampler: &mut Sampler) -> Scalar {
+        let Some(ao) = self.ambient_occlusion else {
+            return 1.0;
+        };
+        let radius = ao.radius / self.meters_per_unit();
+        let unoccluded = (0..ao.samples)
+            .filter(|_| {
+                let r = Ray::new(comps.over_point, sampler.cosine_hemisphere(&comps.normalv));
+                self.occluder(&r, radius).is_none()
+            })
+            .count();
+        unoccluded as Scalar / ao.samples as Scalar
+    }
+    // The color of the surface hit, unlit, as a denoiser's albedo guide.
+    pub fn albedo_at(&self, comps: &ShadingInfo) -> Color {
+        let shape = &self.shapes[comps.object_id];
+        shape.get_material().color_at(
+            shape.get_inverse_transform(),
+            &comps.over_point,
+            &comps.eyev,
+            &comps.normalv,
+        )
+    }
+    // The material of the surface hit, as it is at the hit point.
+    fn material_at(&self, comps: &ShadingInfo) -> Material<'_> {
+        let shape = &self.shapes[comps.object_id];
+        shape.get_material().at(
+            shape.get_inverse_transform(),
+            &comps.over_point,
+            &comps.eyev,
+            &comps.normalv,
+        )
+    }
+    fn reflected_color(
+        &self,
+        comps: &ShadingInfo,
+        remaining: isize,
+        sampler: &mut Sampler,
+    ) -> Color {
+        let material = self.material_at(comps);
+        if !material.is_reflective() {
             return BLACK;
         }
-        let reflect_ray = Ray::new(comps.over_point, comps.reflectv);
-        let color = self.color_at(&reflect_ray, remaining - 1);
+        let Some(weight) = self.survival_weight(remaining, material.get_reflective(), sampler)
+        else {
+            return BLACK;
+        };
+        let reflect_ray =
+            Ray::new(comps.over_point, comps.reflectv).set_wavelength(comps.wavelength);
+        let color = self.color_at(&reflect_ray, remaining - 1, sampler);
 
-        material.reflected_color(&color)
+        material.reflected_color_at(&color, comps.eyev.dot(&comps.normalv)) * weight
     }
-    fn refracted_color(&self, comps: &Computations, remaining: isize) -> Color {
-        let material = self.shapes[comps.object_id].get_material();
-        if !material.is_transparent() || remaining <= 0 {
+    fn refracted_color(
+        &self,
+        comps: &ShadingInfo,
+        remaining: isize,
+        sampler: &mut Sampler,
+    ) -> Color {
+        let material = self.material_at(comps);
+        if !material.is_transparent() {
             return BLACK;
         }
         // Find the ratio of first index of refraction to the second.
@@ -216,11 +1246,34 @@ impl<'a> World<'a> {
         let cos_t = (1.0 - sin2_t).sqrt();
         // Compute the direction of the refracted ray
         let direction = (n_ratio * cos_i - cos_t) * &comps.normalv - &(n_ratio * &comps.eyev);
+        let Some(weight) = self.survival_weight(remaining, material.get_transparency(), sampler)
+        else {
+            return BLACK;
+        };
         // Create the refracted ray
-        let refract_ray = Ray::new(comps.under_point, direction);
+        let refract_ray = Ray::new(comps.under_point, direction).set_wavelength(comps.wavelength);
         // Find the color of the refracted ray, making sure to multiply
         // by the transparency value to account for any opacity
-        material.scale_transparency(&self.color_at(&refract_ray, remaining - 1))
+        material.scale_transparency(&self.color_at(&refract_ray, remaining - 1, sampler)) * weight
+    }
+    // Whether a secondary ray carrying `fraction` of the color is traced, and
+    // if so what its color is scaled by.
+    fn survival_weight(
+        &self,
+        remaining: isize,
+        fraction: Scalar,
+        sampler: &mut Sampler,
+    ) -> Option<Scalar> {
+        if remaining <= 0 {
+            return None;
+        }
+        match self.russian_roulette {
+            Some(rr) if self.recursion_limit() - remaining >= rr.start_depth as isize => {
+                let p = fraction.clamp(rr.min_survival, 1.0);
+                (sampler.next_f64() < p).then(|| 1.0 / p)
+            }
+            _ => Some(1.0),
+        }
     }
 }
 
@@ -230,16 +1283,19 @@ mod tests {
     use super::*;
     use crate::approx_eq::{assert_approx_eq, ApproxEq};
     use crate::color::WHITE;
-    use crate::material::Material;
-    use crate::pattern::Pattern;
+    use crate::material::{BlendedMaterial, Material};
+    use crate::mesh::Mesh;
     use crate::plane::Plane;
     use crate::point::ORIGIN;
+    use crate::sampler::SampleSequence;
+    use crate::scalar::consts::PI;
     use crate::sphere::Sphere;
-    use crate::transform::{scaling, translation, Affine, IDENTITY_AFFINE};
+    use crate::transform::{rotation_z, scaling, translation, Affine, IDENTITY_AFFINE};
 
     impl<'a> World<'a> {
         fn clear_lights(&mut self) {
             self.lights.clear();
+            self.light_shapes.clear();
         }
     }
 
@@ -254,54 +1310,429 @@ mod tests {
             Shape::new(Sphere::new()).set_material(
                 Material::new()
                     .set_color(Color::new(0.8, 1.0, 0.6))
+                    .unwrap()
                     .set_diffuse(0.7)
-                    .set_specular(0.2),
+                    .unwrap()
+                    .set_specular(0.2)
+                    .unwrap(),
             ),
         );
-        world.add_shape(Shape::new(Sphere::new()).set_transform(scaling(0.5, 0.5, 0.5)));
+        world.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(scaling(0.5, 0.5, 0.5))
+                .unwrap(),
+        );
         world
     }
 
-    fn new_glass_sphere<'a>(transform: Affine, refractive_index: f64) -> Shape<'a> {
+    fn new_glass_sphere<'a>(transform: Affine, refractive_index: Scalar) -> Shape<'a> {
         Shape::new(Sphere::new())
             .set_material(
                 Material::new()
                     .set_transparency(1.0)
-                    .set_refractive_index(refractive_index),
+                    .unwrap()
+                    .set_refractive_index(refractive_index)
+                    .unwrap(),
             )
             .set_transform(transform)
+            .unwrap()
+    }
+
+    fn test_pattern(point: &Point) -> Color {
+        Color::new(point.x, point.y, point.z)
     }
 
-    struct TestPattern {}
+    #[test]
+    fn test_intersect_a_world_with_a_ray() {
+        let w = default_world();
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let xs = w.intersect(&r);
+        assert_approx_eq!(
+            Vec::from(xs).iter().map(|i| i.t).collect::<Vec<_>>(),
+            [4.0, 4.5, 5.5, 6.0]
+        );
+    }
 
-    impl TestPattern {
-        pub fn new() -> Self {
-            Self {}
+    #[test]
+    fn test_intersecting_many_static_shapes() {
+        let mut w = World::new();
+        for i in 0..50 {
+            w.add_shape(
+                Shape::new(Sphere::new())
+                    .set_transform(translation(3.0 * i as Scalar, 0.0, 0.0))
+                    .unwrap(),
+            );
         }
+        w.add_shape(
+            Shape::new(Plane::new())
+                .set_transform(translation(0.0, -1.0, 0.0))
+                .unwrap(),
+        );
+        let r = Ray::new(Point::new(30.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let xs = Vec::from(w.intersect(&r));
+        assert_eq!(xs.len(), 2);
+        assert!(xs.iter().all(|i| i.object_id == 10));
+    }
+
+    #[test]
+    fn test_a_kd_tree_finds_the_same_hits_and_counts_its_work() {
+        let mut w = World::new();
+        for i in 0..50 {
+            w.add_shape(
+                Shape::new(Sphere::new())
+                    .set_transform(translation(3.0 * i as Scalar, 0.0, 0.0))
+                    .unwrap(),
+            );
+        }
+        w.set_accelerator(AcceleratorKind::KdTree);
+        w.set_traversal_stats(true);
+        let r = Ray::new(Point::new(30.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let xs = Vec::from(w.intersect(&r));
+        assert_eq!(xs.len(), 2);
+        assert!(xs.iter().all(|i| i.object_id == 10));
+        let stats = w.traversal_stats().unwrap();
+        assert_eq!(stats.rays, 1);
+        assert_eq!(stats.candidates, 1);
+        assert!(stats.nodes > 0);
+    }
+
+    #[test]
+    fn test_a_packet_of_rays_finds_the_same_hits_as_single_rays() {
+        let w = default_world();
+        let rays: Vec<Ray> = (0..8)
+            .map(|i| {
+                let x = i as Scalar * 0.4 - 1.4;
+                Ray::new(
+                    Point::new(0.0, 0.0, -5.0),
+                    Vector::new(x, 0.1, 1.0).normalize(),
+                )
+            })
+            .collect();
+        let hits = w.shade_packet(&rays);
+        assert!(hits.iter().any(|hit| hit.is_none()));
+        for (ray, hit) in rays.iter().zip(hits) {
+            let single = w.shade_ray(ray);
+            assert_eq!(
+                hit.map(|h| (h.object_id, h.t)),
+                single.map(|h| (h.object_id, h.t))
+            );
+        }
+    }
+
+    #[test]
+    fn test_moving_a_dynamic_shape_between_frames() {
+        let mut w = default_world();
+        let id = w
+            .add_dynamic_shape(
+                Shape::new(Sphere::new())
+                    .set_transform(translation(0.0, 0.0, -3.0))
+                    .unwrap(),
+            )
+            .id();
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let xs = Vec::from(w.intersect(&r));
+        assert_eq!(xs[0].object_id, id);
+        assert_approx_eq!(xs[0].t, 1.0);
+
+        w.set_shape_transform(id, translation(0.0, 5.0, 0.0))
+            .unwrap();
+        let xs = Vec::from(w.intersect(&r));
+        assert_approx_eq!(
+            xs.iter().map(|i| i.t).collect::<Vec<_>>(),
+            [4.0, 4.5, 5.5, 6.0]
+        );
+    }
+
+    #[test]
+    fn test_adjusting_a_shape_through_its_handle() {
+        let mut w = World::new();
+        let id = w
+            .add_shape(Shape::new(Sphere::new()))
+            .set_name("ball")
+            .set_material(Material::new().set_reflective(0.5).unwrap())
+            .set_transform(translation(0.0, 0.0, 3.0))
+            .unwrap()
+            .id();
+        assert_eq!(w.find_shape("ball"), Some(id));
+        assert_eq!(w.find_shape("floor"), None);
+        assert!(w.shapes[id].get_material().is_reflective());
+
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let xs = Vec::from(w.intersect(&r));
+        assert_approx_eq!(xs[0].t, 7.0);
+
+        // moving a static shape while assembling the scene rebuilds the BVH
+        w.shape(id).set_transform(IDENTITY_AFFINE).unwrap();
+        let xs = Vec::from(w.intersect(&r));
+        assert_approx_eq!(xs[0].t, 4.0);
+    }
+
+    #[test]
+    fn test_adjusting_shapes_selected_by_a_query() {
+        let mut w = World::new();
+        for x in [-2.0, 0.0, 2.0] {
+            w.add_shape(Shape::new(Sphere::new()))
+                .set_name(&format!("ball at {}", x))
+                .add_tag(if x < 0.0 { "left" } else { "right" })
+                .set_transform(translation(x, 0.0, 0.0))
+                .unwrap();
+        }
+        assert_eq!(w.find_shapes(|s| s.name_matches("ball*")), [0, 1, 2]);
+        assert_eq!(w.find_shapes(|s| s.has_tag("right")), [1, 2]);
+        let r = Ray::new(Point::new(2.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(Vec::from(w.intersect(&r))[0].object_id, 2);
+
+        // hidden shapes are not hit, nor counted in the bounds
+        let right = w.select(|s| s.has_tag("right")).set_hidden(true);
+        assert_eq!(right.len(), 2);
+        assert!(Vec::from(w.intersect(&r)).is_empty());
+        assert_approx_eq!(w.bounds().max, Point::new(-1.0, 1.0, 1.0));
+        w.select(|s| s.is_hidden()).set_hidden(false);
+
+        // moving the selection as a whole
+        w.select(|s| s.has_tag("right"))
+            .transform(&translation(0.0, 0.0, 1.0))
+            .unwrap();
+        assert_approx_eq!(Vec::from(w.intersect(&r))[0].t, 5.0);
+
+        // one material shared by the whole selection
+        let left = w.find_shapes(|s| s.has_tag("left"));
+        let mirrors = w
+            .select(|s| s.has_tag("right"))
+            .set_material(Material::new().set_reflective(1.0).unwrap())
+            .add_tag("mirror");
+        assert_eq!(mirrors.ids(), [1, 2]);
+        assert!(w.shapes[1].shares_material(&w.shapes[2]));
+        assert!(!w.shapes[1].shares_material(&w.shapes[left[0]]));
+        let shiny = w.find_shapes(|s| s.get_material().is_reflective());
+        assert_eq!(shiny, w.find_shapes(|s| s.has_tag("mirror")));
+    }
+
+    #[test]
+    fn test_transforming_a_selection_about_a_pivot() {
+        let mut w = World::new();
+        for x in [1.0, 3.0] {
+            w.add_shape(Shape::new(Sphere::new()))
+                .set_transform(translation(x, 0.0, 0.0))
+                .unwrap();
+        }
+        let centers = |w: &World| -> Vec<Point> {
+            (0..2)
+                .map(|id| w.get_shape(id).bounds().centroid())
+                .collect()
+        };
+        // half a turn about the middle of the pair swaps them
+        w.select(|_| true)
+            .transform_in(&rotation_z(PI), Space::World, Pivot::Center)
+            .unwrap();
+        assert_approx_eq!(centers(&w)[0], Point::new(3.0, 0.0, 0.0));
+        assert_approx_eq!(centers(&w)[1], Point::new(1.0, 0.0, 0.0));
+        // while turning each about its own center leaves them in place
+        w.select(|_| true)
+            .transform_in(&rotation_z(PI / 3.0), Space::Local, Pivot::Center)
+            .unwrap();
+        assert_approx_eq!(centers(&w)[1], Point::new(1.0, 0.0, 0.0));
+        // a local pivot is in the shape's own coordinates
+        let mut w = World::new();
+        w.add_shape(Shape::new(Sphere::new()))
+            .set_transform(translation(5.0, 0.0, 0.0))
+            .unwrap();
+        let pivot = Pivot::At(Point::new(-1.0, 0.0, 0.0));
+        w.select(|_| true)
+            .transform_in(&scaling(2.0, 2.0, 2.0), Space::Local, pivot)
+            .unwrap();
+        let bounds = w.get_shape(0).bounds();
+        assert_approx_eq!(bounds.min, Point::new(4.0, -2.0, -2.0));
+        assert_approx_eq!(bounds.max, Point::new(8.0, 2.0, 2.0));
+        // a transform that would shrink one shape to nothing moves none
+        w.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(scaling(0.1, 0.1, 0.1))
+                .unwrap(),
+        );
+        let result = w.select(|_| true).transform(&scaling(0.1, 0.1, 0.1));
+        assert!(result.is_err());
+        assert_approx_eq!(w.get_shape(0).bounds().max, Point::new(8.0, 2.0, 2.0));
     }
 
-    impl Pattern for TestPattern {
-        fn get_color(&self, point: &Point) -> Color {
-            Color::new(point.x, point.y, point.z)
+    #[test]
+    fn test_applying_scene_deltas_between_frames() {
+        let mut w = World::new();
+        w.add_light(PointLight::new(Point::new(-10.0, 10.0, -10.0), WHITE));
+        for x in [-3.0, 0.0, 3.0] {
+            w.add_dynamic_shape(Shape::new(Sphere::new()))
+                .set_transform(translation(x, 0.0, 0.0))
+                .unwrap();
         }
+        let mut camera = Camera::new(10, 10, PI / 2.0).unwrap();
+        let r = Ray::new(Point::new(6.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(Vec::from(w.intersect(&r)).is_empty());
+
+        w.apply_deltas(
+            &mut camera,
+            &[
+                SceneDelta::MoveShape(0, translation(6.0, 0.0, 0.0)),
+                SceneDelta::SetMaterialField(0, MaterialField::Reflective(0.5)),
+                SceneDelta::MoveLight(0, Point::new(0.0, 10.0, 0.0)),
+                SceneDelta::MoveCamera(translation(0.0, 0.0, -5.0)),
+            ],
+        )
+        .unwrap();
+        // the dynamic tree was refitted rather than thrown away
+        assert!(w.dynamic_accelerator.get().is_some());
+        assert_eq!(Vec::from(w.intersect(&r))[0].object_id, 0);
+        // the shapes shared a material until it changed for one of them
+        assert!(w.shapes[0].get_material().is_reflective());
+        assert!(!w.shapes[1].get_material().is_reflective());
+        assert!(w.shapes[1].shares_material(&w.shapes[2]));
+        assert_approx_eq!(
+            w.lights[0].vector_from(&ORIGIN),
+            Vector::new(0.0, 10.0, 0.0)
+        );
+        assert_approx_eq!(camera.get_transform(), translation(0.0, 0.0, -5.0));
+
+        // a kd-tree is rebuilt instead
+        w.set_accelerator(AcceleratorKind::KdTree);
+        w.intersect(&r);
+        let moved = [SceneDelta::MoveShape(0, IDENTITY_AFFINE)];
+        w.apply_deltas(&mut camera, &moved).unwrap();
+        assert!(w.dynamic_accelerator.get().is_none());
+        assert!(Vec::from(w.intersect(&r)).is_empty());
+    }
+
+    #[test]
+    fn test_a_batch_of_deltas_with_an_invalid_one_changes_nothing() {
+        let mut w = World::new();
+        w.add_dynamic_shape(Shape::new(Sphere::new()));
+        let mut camera = Camera::new(10, 10, PI / 2.0).unwrap();
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(w.shade_ray(&r).is_some());
+
+        let moved = SceneDelta::MoveShape(0, translation(6.0, 0.0, 0.0));
+        let flattened = SceneDelta::MoveShape(0, scaling(1.0, 0.0, 1.0));
+        assert!(w.apply_deltas(&mut camera, &[moved, flattened]).is_err());
+        assert_approx_eq!(w.shade_ray(&r).unwrap().t, 4.0);
+
+        let result = w.apply_deltas(
+            &mut camera,
+            &[moved, SceneDelta::MoveShape(3, IDENTITY_AFFINE)],
+        );
+        assert!(matches!(result, Err(RayTracerError::InvalidShape(_))));
+        let result = w.apply_deltas(&mut camera, &[moved, SceneDelta::MoveLight(0, ORIGIN)]);
+        assert!(matches!(result, Err(RayTracerError::InvalidLight(_))));
+        let murky = SceneDelta::SetMaterialField(0, MaterialField::Transparency(Scalar::NAN));
+        let result = w.apply_deltas(&mut camera, &[moved, murky]);
+        assert!(matches!(result, Err(RayTracerError::InvalidMaterial(_))));
+        assert_approx_eq!(w.shade_ray(&r).unwrap().t, 4.0);
+    }
+
+    #[test]
+    fn test_a_visible_light_glows_without_casting_shadows() {
+        let mut w = World::new();
+        let intensity = Color::new(2.0, 2.0, 1.0);
+        w.add_light(
+            PointLight::new(Point::new(0.0, 5.0, 0.0), intensity)
+                .set_radius(0.5)
+                .unwrap()
+                .set_visible(true),
+        );
+        let floor = w
+            .add_shape(
+                Shape::new(Plane::new()).set_material(Material::new().set_reflective(1.0).unwrap()),
+            )
+            .id();
+        assert_eq!(w.light_showing(0), Some(0));
+        assert_eq!(w.light_showing(floor), None);
+        assert!(!w.bounds().is_finite());
+
+        let mut sampler = Sampler::new(0);
+        let r = Ray::new(Point::new(0.0, 5.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(w.color_at(&r, 4, &mut sampler), intensity);
+        // the floor below is lit through the light's own sphere, and shows
+        // it in reflection
+        assert!(!w.is_shadowed(&w.lights[0], &Point::new(0.0, 0.01, 0.0)));
+        let r = Ray::new(Point::new(0.0, 3.0, 0.0), Vector::new(0.0, -1.0, 0.0));
+        let aovs = w.trace_aovs(&r, 4, &mut sampler).unwrap();
+        assert_approx_eq!(aovs.reflection, intensity);
+
+        // the sphere follows the light
+        let mut camera = Camera::new(10, 10, PI / 2.0).unwrap();
+        w.apply_deltas(
+            &mut camera,
+            &[SceneDelta::MoveLight(0, Point::new(0.0, 7.0, 0.0))],
+        )
+        .unwrap();
+        let r = Ray::new(Point::new(0.0, 5.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(w.color_at(&r, 4, &mut sampler), BLACK);
+    }
+
+    #[test]
+    fn test_deforming_a_mesh_in_the_world() {
+        let mut w = World::new();
+        let vertices = vec![
+            Point::new(0.0, 1.0, 0.0),
+            Point::new(-1.0, 0.0, 0.0),
+            Point::new(1.0, 0.0, 0.0),
+        ];
+        let mesh = Mesh::new(vertices.clone(), vec![[0, 1, 2]]).unwrap();
+        let id = w.add_shape(Shape::new(mesh)).id();
+        w.add_shape(Shape::new(Sphere::new()));
+        let r = Ray::new(Point::new(0.0, 0.5, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let mesh_hit = |w: &World| {
+            let xs = Vec::from(w.intersect(&r));
+            xs.iter().find(|i| i.object_id == id).map(|i| i.t)
+        };
+        assert_approx_eq!(mesh_hit(&w).unwrap(), 5.0);
+        // pushed back along z, out past the sphere
+        let moved = vertices.iter().map(|v| Point::new(v.x, v.y, 3.0)).collect();
+        w.deform_shape(id, moved, None).unwrap();
+        assert!(w.static_accelerator.get().is_some());
+        assert_approx_eq!(mesh_hit(&w).unwrap(), 8.0);
+        assert!(w.deform_shape(1, vec![], None).is_err());
+    }
+
+    #[test]
+    fn test_the_bounds_of_a_world_leave_out_planes() {
+        let mut w = World::new();
+        w.add_shape(Shape::new(Plane::new()));
+        assert!(!w.bounds().is_finite());
+        w.add_shape(Shape::new(Sphere::new()));
+        w.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(translation(3.0, 0.0, 0.0))
+                .unwrap(),
+        );
+        let b = w.bounds();
+        assert_approx_eq!(b.min, Point::new(-1.0, -1.0, -1.0));
+        assert_approx_eq!(b.max, Point::new(4.0, 1.0, 1.0));
     }
 
     #[test]
-    fn test_intersect_a_world_with_a_ray() {
-        let w = default_world();
+    fn test_static_shapes_become_dynamic_when_moved() {
+        let mut w = default_world();
+        // placing shapes while assembling the scene leaves them static
+        w.shape(0).set_transform(IDENTITY_AFFINE).unwrap();
+        assert!(!w.dynamic[0]);
         let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
-        let xs = w.intersect(&r);
-        assert_approx_eq!(
-            Vec::from(xs).iter().map(|i| i.t).collect::<Vec<_>>(),
-            [4.0, 4.5, 5.5, 6.0]
-        );
+        w.intersect(&r);
+        // every way of moving a shape once its tree is built makes it dynamic
+        w.set_shape_transform(0, translation(0.0, 5.0, 0.0))
+            .unwrap();
+        assert!(w.dynamic[0]);
+        assert!(!w.dynamic[1]);
+        w.intersect(&r);
+        w.shape(1)
+            .set_transform(translation(0.0, 5.0, 0.0))
+            .unwrap();
+        assert!(w.dynamic[1]);
+        assert!(Vec::from(w.intersect(&r)).is_empty());
     }
 
     #[test]
     fn test_precomputing_the_state_of_an_intersection() {
         let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
         let mut world = World::new();
-        let shape = world.add_shape(Shape::new(Sphere::new()));
+        let shape = world.add_shape(Shape::new(Sphere::new())).id();
         let i = Intersection::new(4.0, shape);
         let comp = world.prepare_computations(Intersections::new([i]), 0, &r);
         assert_approx_eq!(comp.t, 4.0);
@@ -311,11 +1742,27 @@ mod tests {
         assert_approx_eq!(comp.normalv, Vector::new(0.0, 0.0, -1.0));
     }
 
+    #[test]
+    fn test_shading_information_for_a_ray() {
+        let w = default_world();
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let info = w.shade_ray(&r).unwrap();
+        assert_eq!(info.object_id, 0);
+        assert_approx_eq!(info.t, 4.0);
+        assert_approx_eq!(info.point, Point::new(0.0, 0.0, -1.0));
+        assert_approx_eq!(info.normalv, Vector::new(0.0, 0.0, -1.0));
+        assert!(!info.inside);
+        assert!(!w.get_shape(info.object_id).get_material().is_reflective());
+
+        let miss = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 1.0, 0.0));
+        assert!(w.shade_ray(&miss).is_none());
+    }
+
     #[test]
     fn test_the_hit_when_an_intersection_occurs_on_the_outside() {
         let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
         let mut world = World::new();
-        let shape = world.add_shape(Shape::new(Sphere::new()));
+        let shape = world.add_shape(Shape::new(Sphere::new())).id();
         let i = Intersection::new(4.0, shape);
         let comp = world.prepare_computations(Intersections::new([i]), 0, &r);
         assert!(!comp.inside);
@@ -325,7 +1772,7 @@ mod tests {
     fn test_the_hit_when_an_intersection_occurs_on_the_inside() {
         let r = Ray::new(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0));
         let mut world = World::new();
-        let shape = world.add_shape(Shape::new(Sphere::new()));
+        let shape = world.add_shape(Shape::new(Sphere::new())).id();
         let i = Intersection::new(1.0, shape);
         let comp = world.prepare_computations(Intersections::new([i]), 0, &r);
         assert_approx_eq!(comp.point, Point::new(0.0, 0.0, 1.0));
@@ -334,6 +1781,56 @@ mod tests {
         assert_approx_eq!(comp.normalv, Vector::new(0.0, 0.0, -1.0));
     }
 
+    #[test]
+    fn test_hits_closer_than_the_minimum_distance_are_skipped() {
+        let mut w = default_world();
+        // starts on the surface of the outer sphere, looking inwards
+        let r = Ray::new(Point::new(0.0, 0.0, -1.0), Vector::new(0.0, 0.0, 1.0));
+        assert_eq!(w.shade_ray(&r).unwrap().t, 0.0);
+        w.set_min_hit_distance(0.01);
+        assert_approx_eq!(w.shade_ray(&r).unwrap().t, 0.5);
+    }
+
+    #[test]
+    fn test_points_leaving_the_surface_are_offset_by_one_distance() {
+        let mut w = default_world();
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let i = Intersection::new(4.0, 0);
+        let comps = w.prepare_computations(Intersections::new([i]), 0, &r);
+        assert!(comps.shadow_point.z < -1.0);
+        assert!(comps.shadow_point.z > -1.0 - 2.0 * EPSILON);
+
+        w.set_surface_offset(0.5);
+        let comps = w.prepare_computations(Intersections::new([i]), 0, &r);
+        assert_approx_eq!(comps.shadow_point, Point::new(0.0, 0.0, -1.5));
+        assert_approx_eq!(comps.over_point, Point::new(0.0, 0.0, -1.5));
+        assert_approx_eq!(comps.under_point, Point::new(0.0, 0.0, -0.5));
+    }
+
+    #[test]
+    fn test_points_are_offset_along_the_geometric_normal() {
+        let tilted = Vector::new(1.0, 0.0, -1.0);
+        let triangle = Mesh::new(
+            vec![
+                Point::new(0.0, 1.0, 0.0),
+                Point::new(-1.0, 0.0, 0.0),
+                Point::new(1.0, 0.0, 0.0),
+            ],
+            vec![[0, 1, 2]],
+        )
+        .unwrap()
+        .set_normals(vec![tilted; 3])
+        .unwrap();
+        let mut w = World::new();
+        let id = w.add_shape(Shape::new(triangle)).id();
+        let r = Ray::new(Point::new(0.0, 0.5, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let comps = w.prepare_computations(Intersections::new([Intersection::new(5.0, id)]), 0, &r);
+        assert_approx_eq!(comps.normalv, tilted.normalize());
+        assert_approx_eq!(comps.geometric_normalv, Vector::new(0.0, 0.0, -1.0));
+        assert_approx_eq!(comps.over_point, Point::new(0.0, 0.5, -EPSILON));
+        assert_approx_eq!(comps.under_point, Point::new(0.0, 0.5, EPSILON));
+    }
+
     #[test]
     fn test_shading_an_intersection() {
         let w = default_world();
@@ -341,7 +1838,7 @@ mod tests {
         let object_id = 0;
         let i = Intersection::new(4.0, object_id);
         let comps = w.prepare_computations(Intersections::new([i]), 0, &r);
-        let c = w.shade_hit(&comps, RECURSION_LIMIT);
+        let c = w.shade_hit(&comps, RECURSION_LIMIT, &mut Sampler::new(0));
         assert_approx_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
     }
 
@@ -357,7 +1854,7 @@ mod tests {
         let object_id = 1;
         let i = Intersection::new(0.5, object_id);
         let comps = w.prepare_computations(Intersections::new([i]), 0, &r);
-        let c = w.shade_hit(&comps, RECURSION_LIMIT);
+        let c = w.shade_hit(&comps, RECURSION_LIMIT, &mut Sampler::new(0));
         assert_approx_eq!(c, Color::new(0.90498, 0.90498, 0.90498));
     }
 
@@ -365,7 +1862,7 @@ mod tests {
     fn test_the_color_when_a_ray_misses() {
         let w = default_world();
         let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 1.0, 0.0));
-        let c = w.color_at(&r, RECURSION_LIMIT);
+        let c = w.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
         assert_approx_eq!(c, BLACK);
     }
 
@@ -373,10 +1870,92 @@ mod tests {
     fn test_the_color_when_a_ray_hits() {
         let w = default_world();
         let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
-        let c = w.color_at(&r, RECURSION_LIMIT);
+        let c = w.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
         assert_approx_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
     }
 
+    #[test]
+    fn test_a_shading_hook_overrides_the_color_of_a_hit() {
+        let mut w = default_world();
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        w.set_shading_hook(|comps| (comps.object_id == 1).then_some(MAGENTA));
+        let c = w.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
+        assert_approx_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
+
+        w.set_shading_hook(|comps| (comps.object_id == 0).then_some(MAGENTA));
+        let c = w.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
+        assert_approx_eq!(c, MAGENTA);
+
+        w.clear_shading_hook();
+        let c = w.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
+        assert_approx_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn test_toon_shading_lights_a_band_evenly() {
+        let mut w = World::new();
+        w.add_light(PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE));
+        w.add_shape(Shape::new(Sphere::new()));
+        let diffuse_at = |w: &World, x: Scalar| {
+            let r = Ray::new(Point::new(x, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+            let comps = w.shade_ray(&r).unwrap();
+            w.shade_hit_aovs(&comps, 0, &mut Sampler::new(0)).diffuse
+        };
+        assert!(diffuse_at(&w, 0.5).red < diffuse_at(&w, 0.0).red - 0.1);
+
+        w.set_toon_shading(2, 0.2);
+        assert_approx_eq!(diffuse_at(&w, 0.5), diffuse_at(&w, 0.0));
+    }
+
+    #[test]
+    fn test_toon_shading_outlines_silhouettes() {
+        let mut w = World::new();
+        w.add_light(PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE));
+        w.add_shape(Shape::new(Sphere::new()));
+        w.set_toon_shading(2, 0.2);
+        let r = Ray::new(Point::new(0.99, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let c = w.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
+        assert_approx_eq!(c, BLACK);
+        let r = Ray::new(Point::new(0.9, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let c = w.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
+        assert!(c.red > 0.5);
+    }
+
+    #[test]
+    fn test_the_shadow_pass_holds_the_blocked_light() {
+        let mut w = World::new();
+        w.add_light(PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE));
+        w.add_shape(Shape::new(Sphere::new()));
+        w.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(translation(0.0, 0.0, 10.0))
+                .unwrap(),
+        );
+        let r = Ray::new(Point::new(0.0, 0.0, 5.0), Vector::new(0.0, 0.0, 1.0));
+        let aovs = w
+            .trace_aovs(&r, RECURSION_LIMIT, &mut Sampler::new(0))
+            .unwrap();
+        assert_approx_eq!(aovs.diffuse, BLACK);
+        assert_approx_eq!(aovs.total(), Color::new(0.1, 0.1, 0.1));
+        assert_approx_eq!(aovs.shadow, Color::new(1.8, 1.8, 1.8));
+    }
+
+    #[test]
+    fn test_the_nan_guard_paints_non_finite_colors_magenta() {
+        let mut w = World::new();
+        w.add_light(PointLight::new(
+            Point::new(-10.0, 10.0, -10.0),
+            Color::new(Scalar::NAN, 1.0, 1.0),
+        ));
+        w.add_shape(Shape::new(Sphere::new()));
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let c = w.trace(&r, RECURSION_LIMIT, &mut Sampler::new(0)).unwrap();
+        assert!(!c.is_finite());
+        w.set_nan_guard(true);
+        let c = w.trace(&r, RECURSION_LIMIT, &mut Sampler::new(0)).unwrap();
+        assert_approx_eq!(c, MAGENTA);
+    }
+
     #[test]
     fn test_the_color_with_an_intersection_behind_the_ray() {
         let mut world = World::new();
@@ -385,19 +1964,82 @@ mod tests {
             Shape::new(Sphere::new()).set_material(
                 Material::new()
                     .set_color(Color::new(0.8, 1.0, 0.6))
+                    .unwrap()
                     .set_diffuse(0.7)
+                    .unwrap()
                     .set_specular(0.2)
-                    .set_ambient(1.0),
+                    .unwrap()
+                    .set_ambient(1.0)
+                    .unwrap(),
             ),
         );
         world.add_shape(
             Shape::new(Sphere::new())
                 .set_transform(scaling(0.5, 0.5, 0.5))
-                .set_material(Material::new().set_ambient(1.0)),
+                .unwrap()
+                .set_material(Material::new().set_ambient(1.0).unwrap()),
         );
 
         let r = Ray::new(Point::new(0.0, 0.0, 0.75), Vector::new(0.0, 0.0, -1.0));
-        let c = world.color_at(&r, RECURSION_LIMIT);
+        let c = world.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
+        assert_approx_eq!(c, WHITE);
+    }
+
+    fn ambient_only_floor<'a>() -> World<'a> {
+        let mut world = World::new();
+        world.add_light(PointLight::new(Point::new(0.0, 10.0, 0.0), WHITE));
+        world.add_shape(
+            Shape::new(Plane::new()).set_material(
+                Material::new()
+                    .set_ambient(1.0)
+                    .unwrap()
+                    .set_diffuse(0.0)
+                    .unwrap()
+                    .set_specular(0.0)
+                    .unwrap(),
+            ),
+        );
+        world
+    }
+
+    #[test]
+    fn test_ambient_occlusion_of_an_open_surface() {
+        let mut world = ambient_only_floor();
+        world.set_ambient_occlusion(1.0, 16);
+        let r = Ray::new(Point::new(0.0, 1.0, 0.0), Vector::new(0.0, -1.0, 0.0));
+        let c = world.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
+        assert_approx_eq!(c, WHITE);
+    }
+
+    #[test]
+    fn test_ambient_occlusion_darkens_a_surface_under_a_nearby_object() {
+        let mut world = ambient_only_floor();
+        world.add_shape(
+            Shape::new(Plane::new())
+                .set_transform(translation(0.0, 0.5, 0.0))
+                .unwrap(),
+        );
+        let r = Ray::new(Point::new(0.0, 0.25, 0.0), Vector::new(0.0, -1.0, 0.0));
+
+        let c = world.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
+        assert_approx_eq!(c, WHITE);
+
+        world.set_ambient_occlusion(1.0, 16);
+        let c = world.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
+        assert!(c.red < 1.0);
+    }
+
+    #[test]
+    fn test_ambient_occlusion_ignores_objects_beyond_the_radius() {
+        let mut world = ambient_only_floor();
+        world.add_shape(
+            Shape::new(Plane::new())
+                .set_transform(translation(0.0, 5.0, 0.0))
+                .unwrap(),
+        );
+        world.set_ambient_occlusion(1.0, 16);
+        let r = Ray::new(Point::new(0.0, 1.0, 0.0), Vector::new(0.0, -1.0, 0.0));
+        let c = world.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
         assert_approx_eq!(c, WHITE);
     }
 
@@ -429,19 +2071,253 @@ mod tests {
         assert!(!w.is_shadowed(&default_light(), &p));
     }
 
+    #[test]
+    fn test_colored_glass_casts_a_colored_shadow() {
+        let mut w = World::new();
+        let light = PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE);
+        let red_glass = || {
+            Material::new()
+                .set_transparency(0.5)
+                .unwrap()
+                .set_transmission_tint(Color::new(1.0, 0.2, 0.0))
+                .unwrap()
+        };
+        w.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(translation(0.0, 0.0, -5.0))
+                .unwrap()
+                .set_material(red_glass()),
+        );
+        w.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(translation(0.0, 0.0, -2.0))
+                .unwrap()
+                .set_material(red_glass()),
+        );
+        let mut sampler = Sampler::new(0);
+        let behind_glass = Point::new(0.0, 0.0, 0.0);
+        assert_approx_eq!(
+            w.light_filter(&light, 0, &behind_glass, &mut sampler),
+            BLACK
+        );
+
+        w.set_colored_shadows(true);
+        let filter = w.light_filter(&light, 0, &behind_glass, &mut sampler);
+        assert_approx_eq!(filter, Color::new(0.25, 0.01, 0.0));
+        let beside = Point::new(5.0, 0.0, 0.0);
+        assert_approx_eq!(w.light_filter(&light, 0, &beside, &mut sampler), WHITE);
+        // an opaque shape still blocks everything
+        w.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(translation(0.0, 0.0, -8.0))
+                .unwrap(),
+        );
+        assert_approx_eq!(
+            w.light_filter(&light, 0, &behind_glass, &mut sampler),
+            BLACK
+        );
+    }
+
+    #[test]
+    fn test_a_light_with_a_radius_casts_a_penumbra() {
+        let mut w = World::new();
+        w.add_shape(Shape::new(Sphere::new()));
+        let light = PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE)
+            .set_radius(5.0)
+            .unwrap()
+            .set_shadow_samples(64);
+        let mut sampler = Sampler::new(0);
+
+        let umbra = w.light_visibility(&light, 0, &Point::new(0.0, 0.0, 2.0), &mut sampler);
+        assert_approx_eq!(umbra, 0.0);
+        let penumbra = w.light_visibility(&light, 0, &Point::new(1.0, 0.0, 10.0), &mut sampler);
+        assert!(penumbra > 0.0 && penumbra < 1.0);
+        let lit = w.light_visibility(&light, 0, &Point::new(10.0, 0.0, 0.0), &mut sampler);
+        assert_approx_eq!(lit, 1.0);
+    }
+
+    #[test]
+    fn test_contact_hardened_shadows_widen_away_from_the_occluder() {
+        let mut w = World::new();
+        w.add_shape(Shape::new(Sphere::new()));
+        let light = PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE).set_shadow_samples(64);
+        let penumbra_width = |w: &World, z: Scalar| {
+            let mut sampler = Sampler::new(0);
+            let soft = (0..400)
+                .filter(|i| {
+                    let p = Point::new(*i as Scalar * 0.025, 0.0, z);
+                    let v = w.light_visibility(&light, 0, &p, &mut sampler);
+                    v > 0.0 && v < 1.0
+                })
+                .count();
+            soft as Scalar * 0.025
+        };
+        assert_eq!(penumbra_width(&w, 2.0), 0.0);
+
+        w.set_contact_hardening(0.5);
+        let near = penumbra_width(&w, 2.0);
+        let far = penumbra_width(&w, 8.0);
+        assert!(near > 0.0);
+        assert!(far > 2.0 * near);
+    }
+
+    #[test]
+    fn test_halton_shadow_samples_converge_faster_than_random_ones() {
+        let mut w = World::new();
+        w.add_shape(Shape::new(Sphere::new()));
+        let light = |samples| {
+            PointLight::new(Point::new(0.0, 0.0, -10.0), WHITE)
+                .set_radius(5.0)
+                .unwrap()
+                .set_shadow_samples(samples)
+        };
+        let p = Point::new(1.0, 0.0, 10.0);
+        let reference = w.light_visibility(
+            &light(1 << 14),
+            0,
+            &p,
+            &mut Sampler::new(0).set_sequence(SampleSequence::Halton),
+        );
+        let light = light(16);
+        let error = |sequence| {
+            (0..200)
+                .map(|seed| {
+                    let mut sampler = Sampler::new(seed).set_sequence(sequence);
+                    (w.light_visibility(&light, 0, &p, &mut sampler) - reference).abs()
+                })
+                .sum::<Scalar>()
+        };
+        assert!(error(SampleSequence::Halton) < 0.7 * error(SampleSequence::Random));
+    }
+
+    #[test]
+    fn test_russian_roulette_converges_to_the_untruncated_color() {
+        // between two facing mirrors every bounce adds 1 and then 0.8 times
+        // the next one, 5 in the limit
+        let mut w = World::new();
+        w.add_light(PointLight::new(ORIGIN, WHITE));
+        let mirror = || {
+            Material::new()
+                .set_ambient(1.0)
+                .unwrap()
+                .set_diffuse(0.0)
+                .unwrap()
+                .set_specular(0.0)
+                .unwrap()
+                .set_reflective(0.8)
+                .unwrap()
+        };
+        for y in [-1.0, 1.0] {
+            w.add_shape(
+                Shape::new(Plane::new())
+                    .set_transform(translation(0.0, y, 0.0))
+                    .unwrap()
+                    .set_material(mirror()),
+            );
+        }
+        let r = Ray::new(ORIGIN, Vector::new(0.0, 1.0, 0.0));
+        let truncated = w.color_at(&r, w.recursion_limit(), &mut Sampler::new(0));
+        assert_approx_eq!(truncated.red, 3.68928);
+
+        w.set_russian_roulette(2, 0.1);
+        let n = 4000;
+        let mean = (0..n)
+            .map(|seed| {
+                w.color_at(&r, w.recursion_limit(), &mut Sampler::new(seed))
+                    .red
+            })
+            .sum::<Scalar>()
+            / n as Scalar;
+        assert!((mean - 5.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_a_shadow_ray_budget_picks_the_strongest_lights() {
+        let mut w = World::new();
+        w.add_light(PointLight::new(Point::new(0.0, 10.0, 0.0), WHITE));
+        w.add_light(PointLight::new(Point::new(0.0, 2.0, 0.0), WHITE));
+        w.add_light(PointLight::new(
+            Point::new(0.0, 20.0, 0.0),
+            Color::new(10.0, 10.0, 10.0),
+        ));
+        assert_eq!(w.shadow_casting_lights(&ORIGIN), vec![true, true, true]);
+        w.set_shadow_ray_budget(Some(2));
+        assert_eq!(w.shadow_casting_lights(&ORIGIN), vec![false, true, true]);
+        w.set_shadow_ray_budget(Some(0));
+        assert_eq!(w.shadow_casting_lights(&ORIGIN), vec![false, false, false]);
+        // a light right at the point ranks first
+        w.add_light(PointLight::new(ORIGIN, WHITE));
+        w.set_shadow_ray_budget(Some(2));
+        let casting = w.shadow_casting_lights(&ORIGIN);
+        assert_eq!(casting, vec![false, true, false, true]);
+    }
+
+    #[test]
+    fn test_black_lights_cast_no_shadows() {
+        let mut w = World::new();
+        w.add_light(PointLight::new(Point::new(0.0, 10.0, 0.0), BLACK));
+        w.add_light(PointLight::new(Point::new(0.0, 20.0, 0.0), WHITE));
+        assert_eq!(w.shadow_casting_lights(&ORIGIN), vec![false, true]);
+        // nor do they use up the budget
+        w.set_shadow_ray_budget(Some(1));
+        assert_eq!(w.shadow_casting_lights(&ORIGIN), vec![false, true]);
+    }
+
+    #[test]
+    fn test_a_shadowed_point_remembers_its_occluder() {
+        let w = default_world();
+        let light = default_light();
+        let mut occluders = OccluderCache::new();
+        assert_eq!(occluders.get(0), None);
+        let p = Point::new(10.0, -10.0, 10.0);
+        assert!(w.is_shadowed_along(0, &p, &light.vector_from(&p), &mut occluders));
+        assert_eq!(occluders.get(0), Some(0));
+    }
+
+    #[test]
+    fn test_a_stale_occluder_falls_back_to_intersecting_the_world() {
+        let world = |near_casts_shadow: bool| {
+            let mut w = World::new();
+            w.add_shape(Shape::new(Sphere::new()).set_casts_shadow(near_casts_shadow));
+            w.add_shape(
+                Shape::new(Sphere::new())
+                    .set_transform(translation(0.0, 0.0, 50.0))
+                    .unwrap(),
+            );
+            w
+        };
+        let (near, far) = (0, 1);
+        let w = world(true);
+        let light = default_light();
+        let (lit, shadowed) = (Point::new(0.0, 10.0, 0.0), Point::new(10.0, -10.0, 10.0));
+        let mut occluders = OccluderCache::new();
+        occluders.set(0, far);
+        let v = light.vector_from(&lit);
+        assert!(!w.is_shadowed_along(0, &lit, &v, &mut occluders));
+        let v = light.vector_from(&shadowed);
+        assert!(w.is_shadowed_along(0, &shadowed, &v, &mut occluders));
+        assert_eq!(occluders.get(0), Some(near));
+        // the cached shape casts no shadow in another world
+        let w = world(false);
+        assert!(!w.is_shadowed_along(0, &shadowed, &v, &mut occluders));
+        // or isn't in it at all
+        occluders.set(0, 7);
+        assert!(!w.is_shadowed_along(0, &shadowed, &v, &mut occluders));
+    }
+
     #[test]
     fn test_precomputing_the_reflection_vector() {
         let mut w = World::new();
-        let id = w.add_shape(Shape::new(Plane::new()));
+        let id = w.add_shape(Shape::new(Plane::new())).id();
         let r = Ray::new(
             Point::new(0.0, 1.0, -1.0),
-            Vector::new(0.0, -2f64.sqrt() / 2.0, 2f64.sqrt() / 2.0),
+            Vector::new(0.0, -Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0),
         );
-        let i = Intersection::new(2f64.sqrt(), id);
+        let i = Intersection::new(Scalar::sqrt(2.0), id);
         let comps = w.prepare_computations(Intersections::new([i]), 0, &r);
         assert_approx_eq!(
             &comps.reflectv,
-            Vector::new(0.0, 2f64.sqrt() / 2.0, 2f64.sqrt() / 2.0)
+            Vector::new(0.0, Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0)
         )
     }
 
@@ -453,55 +2329,96 @@ mod tests {
             Shape::new(Sphere::new()).set_material(
                 Material::new()
                     .set_color(Color::new(0.8, 1.0, 0.6))
+                    .unwrap()
                     .set_diffuse(0.7)
-                    .set_specular(0.2),
+                    .unwrap()
+                    .set_specular(0.2)
+                    .unwrap(),
             ),
         );
-        let id2 = world.add_shape(
-            Shape::new(Sphere::new())
-                .set_transform(scaling(0.5, 0.5, 0.5))
-                .set_material(Material::new().set_ambient(1.0)),
-        );
+        let id2 = world
+            .add_shape(
+                Shape::new(Sphere::new())
+                    .set_transform(scaling(0.5, 0.5, 0.5))
+                    .unwrap()
+                    .set_material(Material::new().set_ambient(1.0).unwrap()),
+            )
+            .id();
         let r = Ray::new(ORIGIN, Vector::new(0.0, 0.0, 1.0));
         let i = Intersection::new(1.0, id2);
         let comps = world.prepare_computations(Intersections::new([i]), 0, &r);
-        let color = world.reflected_color(&comps, RECURSION_LIMIT);
+        let color = world.reflected_color(&comps, RECURSION_LIMIT, &mut Sampler::new(0));
         assert_approx_eq!(color, BLACK);
     }
 
     #[test]
     fn test_the_reflected_color_for_a_reflective_material() {
         let mut w = default_world();
-        let id3 = w.add_shape(
-            Shape::new(Plane::new())
-                .set_material(Material::new().set_reflective(0.5))
-                .set_transform(translation(0.0, -1.0, 0.0)),
+        let id3 = w
+            .add_shape(
+                Shape::new(Plane::new())
+                    .set_material(Material::new().set_reflective(0.5).unwrap())
+                    .set_transform(translation(0.0, -1.0, 0.0))
+                    .unwrap(),
+            )
+            .id();
+        let r = Ray::new(
+            Point::new(0.0, 0.0, -3.0),
+            Vector::new(0.0, -Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0),
         );
+        let i = Intersection::new(Scalar::sqrt(2.0), id3);
+        let comps = w.prepare_computations(Intersections::new([i]), 0, &r);
+        let color = w.reflected_color(&comps, RECURSION_LIMIT, &mut Sampler::new(0));
+        assert_approx_eq!(color, Color::new(0.19033, 0.23792, 0.14275));
+    }
+
+    #[test]
+    fn test_the_reflected_color_for_a_blended_material() {
+        let mut w = default_world();
+        // half of a fully reflective material
+        let material = BlendedMaterial::new(
+            Material::new(),
+            Material::new().set_reflective(1.0).unwrap(),
+            |_: &Point| WHITE * 0.5,
+            IDENTITY_AFFINE,
+        )
+        .unwrap();
+        let id3 = w
+            .add_shape(
+                Shape::new(Plane::new())
+                    .set_material(material.into())
+                    .set_transform(translation(0.0, -1.0, 0.0))
+                    .unwrap(),
+            )
+            .id();
         let r = Ray::new(
             Point::new(0.0, 0.0, -3.0),
-            Vector::new(0.0, -2f64.sqrt() / 2.0, 2f64.sqrt() / 2.0),
+            Vector::new(0.0, -Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0),
         );
-        let i = Intersection::new(2f64.sqrt(), id3);
+        let i = Intersection::new(Scalar::sqrt(2.0), id3);
         let comps = w.prepare_computations(Intersections::new([i]), 0, &r);
-        let color = w.reflected_color(&comps, RECURSION_LIMIT);
+        let color = w.reflected_color(&comps, RECURSION_LIMIT, &mut Sampler::new(0));
         assert_approx_eq!(color, Color::new(0.19033, 0.23792, 0.14275));
     }
 
     #[test]
     fn test_shade_hit_with_a_reflective_material() {
         let mut w = default_world();
-        let id3 = w.add_shape(
-            Shape::new(Plane::new())
-                .set_material(Material::new().set_reflective(0.5))
-                .set_transform(translation(0.0, -1.0, 0.0)),
-        );
+        let id3 = w
+            .add_shape(
+                Shape::new(Plane::new())
+                    .set_material(Material::new().set_reflective(0.5).unwrap())
+                    .set_transform(translation(0.0, -1.0, 0.0))
+                    .unwrap(),
+            )
+            .id();
         let r = Ray::new(
             Point::new(0.0, 0.0, -3.0),
-            Vector::new(0.0, -2f64.sqrt() / 2.0, 2f64.sqrt() / 2.0),
+            Vector::new(0.0, -Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0),
         );
-        let i = Intersection::new(2f64.sqrt(), id3);
+        let i = Intersection::new(Scalar::sqrt(2.0), id3);
         let comps = w.prepare_computations(Intersections::new([i]), 0, &r);
-        let color = w.shade_hit(&comps, RECURSION_LIMIT);
+        let color = w.shade_hit(&comps, RECURSION_LIMIT, &mut Sampler::new(0));
         assert_approx_eq!(color, Color::new(0.87676, 0.92434, 0.82917));
     }
 
@@ -511,42 +2428,53 @@ mod tests {
         w.add_light(PointLight::new(ORIGIN, WHITE));
         w.add_shape(
             Shape::new(Plane::new())
-                .set_material(Material::new().set_reflective(1.0))
-                .set_transform(translation(0.0, -1.0, 0.0)),
+                .set_material(Material::new().set_reflective(1.0).unwrap())
+                .set_transform(translation(0.0, -1.0, 0.0))
+                .unwrap(),
         );
         w.add_shape(
             Shape::new(Plane::new())
-                .set_material(Material::new().set_reflective(1.0))
-                .set_transform(translation(0.0, 1.0, 0.0)),
+                .set_material(Material::new().set_reflective(1.0).unwrap())
+                .set_transform(translation(0.0, 1.0, 0.0))
+                .unwrap(),
         );
         let r = Ray::new(ORIGIN, Vector::new(0.0, 1.0, 0.0));
-        w.color_at(&r, RECURSION_LIMIT);
+        w.color_at(&r, RECURSION_LIMIT, &mut Sampler::new(0));
     }
 
     #[test]
     fn test_the_reflected_color_at_the_maximum_recursive_depth() {
         let mut w = default_world();
-        let id3 = w.add_shape(
-            Shape::new(Plane::new())
-                .set_material(Material::new().set_reflective(0.5))
-                .set_transform(translation(0.0, -1.0, 0.0)),
-        );
+        let id3 = w
+            .add_shape(
+                Shape::new(Plane::new())
+                    .set_material(Material::new().set_reflective(0.5).unwrap())
+                    .set_transform(translation(0.0, -1.0, 0.0))
+                    .unwrap(),
+            )
+            .id();
         let r = Ray::new(
             Point::new(0.0, 0.0, -3.0),
-            Vector::new(0.0, -2f64.sqrt() / 2.0, 2f64.sqrt() / 2.0),
+            Vector::new(0.0, -Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0),
         );
-        let i = Intersection::new(2f64.sqrt(), id3);
+        let i = Intersection::new(Scalar::sqrt(2.0), id3);
         let comps = w.prepare_computations(Intersections::new([i]), 0, &r);
-        let color = w.reflected_color(&comps, 0);
+        let color = w.reflected_color(&comps, 0, &mut Sampler::new(0));
         assert_approx_eq!(color, BLACK);
     }
 
     #[test]
     fn test_finding_n1_and_n2_at_various_intersections() {
         let mut world = World::new();
-        let a = world.add_shape(new_glass_sphere(scaling(2.0, 2.0, 2.0), 1.5));
-        let b = world.add_shape(new_glass_sphere(translation(0.0, 0.0, -0.25), 2.0));
-        let c = world.add_shape(new_glass_sphere(translation(0.0, 0.0, 0.25), 2.5));
+        let a = world
+            .add_shape(new_glass_sphere(scaling(2.0, 2.0, 2.0), 1.5))
+            .id();
+        let b = world
+            .add_shape(new_glass_sphere(translation(0.0, 0.0, -0.25), 2.0))
+            .id();
+        let c = world
+            .add_shape(new_glass_sphere(translation(0.0, 0.0, 0.25), 2.5))
+            .id();
         let r = Ray::new(Point::new(0.0, 0.0, -4.0), Vector::new(0.0, 0.0, 1.0));
         let xs = Intersections::new([
             Intersection::new(2.0, a),
@@ -576,13 +2504,61 @@ mod tests {
         assert_approx_eq!(comps.n2, 1.0);
     }
 
+    #[test]
+    fn test_overlapping_volumes_refract_by_priority() {
+        let mut world = World::new();
+        let water = world
+            .add_shape(
+                Shape::new(Sphere::new()).set_material(
+                    Material::new()
+                        .set_refractive_index(1.33)
+                        .unwrap()
+                        .set_priority(1),
+                ),
+            )
+            .id();
+        let ice = world
+            .add_shape(
+                Shape::new(Sphere::new())
+                    .set_transform(translation(0.0, 0.0, 1.0))
+                    .unwrap()
+                    .set_material(
+                        Material::new()
+                            .set_refractive_index(1.31)
+                            .unwrap()
+                            .set_priority(2),
+                    ),
+            )
+            .id();
+        let r = Ray::new(Point::new(0.0, 0.0, -4.0), Vector::new(0.0, 0.0, 1.0));
+        let xs = Intersections::new([
+            Intersection::new(3.0, water),
+            Intersection::new(4.0, ice),
+            Intersection::new(5.0, water),
+            Intersection::new(6.0, ice),
+        ]);
+
+        // the water's surface inside the ice is skipped
+        assert_eq!(world.true_hit_index(&xs, 0), Some(0));
+        assert_eq!(world.true_hit_index(&xs, 2), Some(3));
+        let comps = world.prepare_computations(xs.clone(), 1, &r);
+        assert_approx_eq!(comps.n1, 1.33);
+        assert_approx_eq!(comps.n2, 1.31);
+        let comps = world.prepare_computations(xs.clone(), 3, &r);
+        assert_approx_eq!(comps.n1, 1.31);
+        assert_approx_eq!(comps.n2, 1.0);
+
+        let r = Ray::new(Point::new(0.0, 0.0, 0.5), Vector::new(0.0, 0.0, 1.0));
+        assert_approx_eq!(world.shade_ray(&r).unwrap().t, 1.5);
+    }
+
     #[test]
     fn test_the_refracted_color_with_an_opaque_surface() {
         let w = default_world();
         let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, -1.0));
         let xs = Intersections::new([Intersection::new(4.0, 0), Intersection::new(6.0, 0)]);
         let comps = w.prepare_computations(xs, 0, &r);
-        let c = w.refracted_color(&comps, 5);
+        let c = w.refracted_color(&comps, 5, &mut Sampler::new(0));
         assert_approx_eq!(c, BLACK);
     }
 
@@ -590,27 +2566,38 @@ mod tests {
     fn test_the_refracted_color_under_total_internal_reflection() {
         let mut world = World::new();
         world.add_light(default_light());
-        let o1 = world.add_shape(
-            Shape::new(Sphere::new()).set_material(
-                Material::new()
-                    .set_color(Color::new(0.8, 1.0, 0.6))
-                    .set_diffuse(0.7)
-                    .set_specular(0.2)
-                    .set_transparency(1.0)
-                    .set_refractive_index(1.5),
-            ),
+        let o1 = world
+            .add_shape(
+                Shape::new(Sphere::new()).set_material(
+                    Material::new()
+                        .set_color(Color::new(0.8, 1.0, 0.6))
+                        .unwrap()
+                        .set_diffuse(0.7)
+                        .unwrap()
+                        .set_specular(0.2)
+                        .unwrap()
+                        .set_transparency(1.0)
+                        .unwrap()
+                        .set_refractive_index(1.5)
+                        .unwrap(),
+                ),
+            )
+            .id();
+        world.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(scaling(0.5, 0.5, 0.5))
+                .unwrap(),
         );
-        world.add_shape(Shape::new(Sphere::new()).set_transform(scaling(0.5, 0.5, 0.5)));
         let r = Ray::new(
-            Point::new(0.0, 0.0, 2f64.sqrt() / 2.0),
+            Point::new(0.0, 0.0, Scalar::sqrt(2.0) / 2.0),
             Vector::new(0.0, 1.0, 0.0),
         );
         let xs = Intersections::new([
-            Intersection::new(-2f64.sqrt() / 2.0, o1),
-            Intersection::new(2f64.sqrt() / 2.0, o1),
+            Intersection::new(-Scalar::sqrt(2.0) / 2.0, o1),
+            Intersection::new(Scalar::sqrt(2.0) / 2.0, o1),
         ]);
         let comps = world.prepare_computations(xs, 1, &r);
-        let color = world.refracted_color(&comps, 5);
+        let color = world.refracted_color(&comps, 5, &mut Sampler::new(0));
         assert_approx_eq!(color, BLACK);
     }
 
@@ -618,24 +2605,35 @@ mod tests {
     fn test_the_refracted_color_with_a_refracted_ray() {
         let mut world = World::new();
         world.add_light(default_light());
-        let a = world.add_shape(
-            Shape::new(Sphere::new()).set_material(
-                Material::new()
-                    .set_pattern(TestPattern::new(), IDENTITY_AFFINE)
-                    .set_diffuse(0.7)
-                    .set_specular(0.2)
-                    .set_ambient(1.0),
-            ),
-        );
-        let b = world.add_shape(
-            Shape::new(Sphere::new())
-                .set_transform(scaling(0.5, 0.5, 0.5))
-                .set_material(
+        let a = world
+            .add_shape(
+                Shape::new(Sphere::new()).set_material(
                     Material::new()
-                        .set_transparency(1.0)
-                        .set_refractive_index(1.5),
+                        .set_pattern(test_pattern, IDENTITY_AFFINE)
+                        .unwrap()
+                        .set_diffuse(0.7)
+                        .unwrap()
+                        .set_specular(0.2)
+                        .unwrap()
+                        .set_ambient(1.0)
+                        .unwrap(),
                 ),
-        );
+            )
+            .id();
+        let b = world
+            .add_shape(
+                Shape::new(Sphere::new())
+                    .set_transform(scaling(0.5, 0.5, 0.5))
+                    .unwrap()
+                    .set_material(
+                        Material::new()
+                            .set_transparency(1.0)
+                            .unwrap()
+                            .set_refractive_index(1.5)
+                            .unwrap(),
+                    ),
+            )
+            .id();
         let r = Ray::new(Point::new(0.0, 0.0, 0.1), Vector::new(0.0, 1.0, 0.0));
         let xs = Intersections::new([
             Intersection::new(-0.9899, a),
@@ -644,7 +2642,7 @@ mod tests {
             Intersection::new(0.9899, a),
         ]);
         let comps = world.prepare_computations(xs, 2, &r);
-        let color = world.refracted_color(&comps, 5);
+        let color = world.refracted_color(&comps, 5, &mut Sampler::new(0));
         assert_approx_eq!(color, Color::new(0.0, 0.99887, 0.04722));
     }
 
@@ -656,51 +2654,66 @@ mod tests {
             Shape::new(Sphere::new()).set_material(
                 Material::new()
                     .set_color(Color::new(0.8, 1.0, 0.6))
+                    .unwrap()
                     .set_diffuse(0.7)
-                    .set_specular(0.2),
+                    .unwrap()
+                    .set_specular(0.2)
+                    .unwrap(),
             ),
         );
-        w.add_shape(Shape::new(Sphere::new()).set_transform(scaling(0.5, 0.5, 0.5)));
-
-        let floor = w.add_shape(
-            Shape::new(Plane::new())
-                .set_transform(translation(0.0, -1.0, 0.0))
-                .set_material(
-                    Material::new()
-                        .set_transparency(0.5)
-                        .set_refractive_index(1.5),
-                ),
+        w.add_shape(
+            Shape::new(Sphere::new())
+                .set_transform(scaling(0.5, 0.5, 0.5))
+                .unwrap(),
         );
+
+        let floor = w
+            .add_shape(
+                Shape::new(Plane::new())
+                    .set_transform(translation(0.0, -1.0, 0.0))
+                    .unwrap()
+                    .set_material(
+                        Material::new()
+                            .set_transparency(0.5)
+                            .unwrap()
+                            .set_refractive_index(1.5)
+                            .unwrap(),
+                    ),
+            )
+            .id();
         w.add_shape(
             Shape::new(Sphere::new())
                 .set_transform(translation(0.0, -3.5, -0.5))
+                .unwrap()
                 .set_material(
                     Material::new()
                         .set_color(Color::new(1.0, 0.0, 0.0))
-                        .set_ambient(0.5),
+                        .unwrap()
+                        .set_ambient(0.5)
+                        .unwrap(),
                 ),
         );
         let r = Ray::new(
             Point::new(0.0, 0.0, -3.0),
-            Vector::new(0.0, -2f64.sqrt() / 2.0, 2f64.sqrt() / 2.0),
+            Vector::new(0.0, -Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0),
         );
-        let xs = Intersections::new([Intersection::new(2f64.sqrt(), floor)]);
+        let xs = Intersections::new([Intersection::new(Scalar::sqrt(2.0), floor)]);
         let comps = w.prepare_computations(xs, 0, &r);
-        let color = w.shade_hit(&comps, 5);
+        let color = w.shade_hit(&comps, 5, &mut Sampler::new(0));
         assert_approx_eq!(color, Color::new(0.93642, 0.68642, 0.68642))
     }
 
     #[test]
     fn test_the_schlick_approximation_under_total_internal_reflection() {
         let mut w = World::new();
-        let shape = w.add_shape(new_glass_sphere(IDENTITY_AFFINE, 1.5));
+        let shape = w.add_shape(new_glass_sphere(IDENTITY_AFFINE, 1.5)).id();
         let r = Ray::new(
-            Point::new(0.0, 0.0, 2f64.sqrt() / 2.0),
+            Point::new(0.0, 0.0, Scalar::sqrt(2.0) / 2.0),
             Vector::new(0.0, 1.0, 0.0),
         );
         let xs = Intersections::new([
-            Intersection::new(-2f64.sqrt() / 2.0, shape),
-            Intersection::new(2f64.sqrt() / 2.0, shape),
+            Intersection::new(-Scalar::sqrt(2.0) / 2.0, shape),
+            Intersection::new(Scalar::sqrt(2.0) / 2.0, shape),
         ]);
         let comps = w.prepare_computations(xs, 1, &r);
         let reflectance = comps.schlick();
@@ -710,7 +2723,7 @@ mod tests {
     #[test]
     fn test_the_schlick_approximation_with_a_perpendicular_viewing_angle() {
         let mut w = World::new();
-        let shape = w.add_shape(new_glass_sphere(IDENTITY_AFFINE, 1.5));
+        let shape = w.add_shape(new_glass_sphere(IDENTITY_AFFINE, 1.5)).id();
         let r = Ray::new(ORIGIN, Vector::new(0.0, 1.0, 0.0));
         let xs = Intersections::new([
             Intersection::new(-1.0, shape),
@@ -724,42 +2737,70 @@ mod tests {
     #[test]
     fn test_the_schlick_approximation_with_small_angle_and_n2_over_n1() {
         let mut w = World::new();
-        let shape = w.add_shape(new_glass_sphere(IDENTITY_AFFINE, 1.5));
+        let shape = w.add_shape(new_glass_sphere(IDENTITY_AFFINE, 1.5)).id();
         let r = Ray::new(Point::new(0.0, 0.99, -2.0), Vector::new(0.0, 0.0, 1.0));
         let xs = Intersections::new([Intersection::new(1.8589, shape)]);
         let comps = w.prepare_computations(xs, 0, &r);
         let reflectance = comps.schlick();
         assert_approx_eq!(reflectance, 0.48873);
     }
+
+    #[test]
+    fn test_the_exact_fresnel_reflectance() {
+        let mut w = World::new();
+        let shape = w.add_shape(new_glass_sphere(IDENTITY_AFFINE, 1.5)).id();
+        let r = Ray::new(ORIGIN, Vector::new(0.0, 1.0, 0.0));
+        let xs = Intersections::new([
+            Intersection::new(-1.0, shape),
+            Intersection::new(1.0, shape),
+        ]);
+        let comps = w.prepare_computations(xs, 1, &r);
+        assert_approx_eq!(comps.reflectance(Fresnel::Exact), 0.04);
+        // Schlick overestimates near grazing angles
+        let r = Ray::new(Point::new(0.0, 0.99, -2.0), Vector::new(0.0, 0.0, 1.0));
+        let xs = Intersections::new([Intersection::new(1.8589, shape)]);
+        let comps = w.prepare_computations(xs, 0, &r);
+        assert_approx_eq!(comps.reflectance(Fresnel::Exact), 0.45924);
+        assert_approx_eq!(comps.reflectance(Fresnel::Schlick), 0.48873);
+    }
     #[test]
     fn test_shade_hit_with_a_reflective_transparent_material() {
         let mut w = default_world();
-        let floor = w.add_shape(
-            Shape::new(Plane::new())
-                .set_transform(translation(0.0, -1.0, 0.0))
-                .set_material(
-                    Material::new()
-                        .set_reflective(0.5)
-                        .set_transparency(0.5)
-                        .set_refractive_index(1.5),
-                ),
-        );
+        let floor = w
+            .add_shape(
+                Shape::new(Plane::new())
+                    .set_transform(translation(0.0, -1.0, 0.0))
+                    .unwrap()
+                    .set_material(
+                        Material::new()
+                            .set_reflective(0.5)
+                            .unwrap()
+                            .set_transparency(0.5)
+                            .unwrap()
+                            .set_refractive_index(1.5)
+                            .unwrap(),
+                    ),
+            )
+            .id();
         w.add_shape(
             Shape::new(Sphere::new())
                 .set_transform(translation(0.0, -3.5, -0.5))
+                .unwrap()
                 .set_material(
                     Material::new()
                         .set_color(Color::new(1.0, 0.0, 0.0))
-                        .set_ambient(0.5),
+                        .unwrap()
+                        .set_ambient(0.5)
+                        .unwrap(),
                 ),
         );
         let r = Ray::new(
             Point::new(0.0, 0.0, -3.0),
-            Vector::new(0.0, -2f64.sqrt() / 2.0, 2f64.sqrt() / 2.0),
+            Vector::new(0.0, -Scalar::sqrt(2.0) / 2.0, Scalar::sqrt(2.0) / 2.0),
         );
-        let xs = Intersections::new([Intersection::new(2f64.sqrt(), floor)]);
+        let xs = Intersections::new([Intersection::new(Scalar::sqrt(2.0), floor)]);
         let comps = w.prepare_computations(xs, 0, &r);
-        let color = w.shade_hit(&comps, 5);
+        let color = w.shade_hit(&comps, 5, &mut Sampler::new(0));
         assert_approx_eq!(color, Color::new(0.93391, 0.69643, 0.69243))
     }
 }